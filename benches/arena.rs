@@ -0,0 +1,55 @@
+//! compares parsing into `Vec<Located<SExpr>>` against the arena-backed `Ast`
+//!
+//! run with `cargo bench --features arena`
+use lerp_lib::{arena, parser};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const RUNS: u32 = 20;
+
+/// a large generated program of deeply nested calls
+fn generate(functions: usize, depth: usize) -> String {
+    let mut code = String::new();
+    for i in 0..functions {
+        code.push_str(&format!("(defn f{i} ((a i32) (b i32)) i32 "));
+        for _ in 0..depth {
+            code.push_str("(+ a [b {:x 1 \"s\"}] ");
+        }
+        code.push_str(&")".repeat(depth));
+        code.push_str(")\n");
+    }
+    code
+}
+
+/// the time one parse takes on average
+fn bench(name: &str, code: &str, parse: impl Fn(&str)) -> Duration {
+    parse(code);
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        parse(code);
+    }
+    let time = start.elapsed() / RUNS;
+    println!("{name:>6}: {time:?} per parse");
+    time
+}
+
+fn main() {
+    let code = generate(2000, 32);
+    println!("parsing {} bytes", code.len());
+    let vec = bench("vec", &code, |code| {
+        black_box(parser::parse(code).unwrap());
+    });
+    let arena = bench("arena", &code, |code| {
+        black_box(arena::parse(code).unwrap());
+    });
+    println!(
+        "the arena takes {:.2}x the time",
+        arena.as_secs_f64() / vec.as_secs_f64()
+    );
+    assert_eq!(
+        arena::parse(&code).map(|ast| ast.to_sexprs(&ast.roots)),
+        parser::parse(&code)
+    );
+}
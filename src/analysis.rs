@@ -0,0 +1,525 @@
+use crate::{
+    code::{
+        Address, Destination, Function, Instruction, Register, RegisterName, RegisterSize, Source,
+    },
+    intern::Symbol,
+};
+use std::collections::{BTreeSet, HashMap};
+
+/// a run of instructions which is only entered at its first one and only left after its last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// the index of the first instruction in the function body
+    pub start: usize,
+    /// the index after the last instruction
+    pub end: usize,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+}
+/// the control flow graph of a function, its first block being the entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: Vec<Block>,
+}
+impl Cfg {
+    /// splits `function` into blocks at labels and after jumps and returns
+    ///
+    /// an indirect jump may go to any label of the function's jump tables, calls return to the
+    /// instruction after them
+    pub fn build(function: &Function) -> Self {
+        let body = &function.body;
+        let mut starts = vec![0];
+        for (idx, instr) in body.iter().enumerate() {
+            match instr {
+                Instruction::Label(_) => starts.push(idx),
+                Instruction::Jmp { .. }
+                | Instruction::JOp { .. }
+                | Instruction::JmpIndirect(_)
+                | Instruction::Ret => starts.push(idx + 1),
+                _ => {}
+            }
+        }
+        starts.retain(|start| *start < body.len());
+        starts.dedup();
+        let mut blocks: Vec<Block> = starts
+            .iter()
+            .enumerate()
+            .map(|(idx, start)| Block {
+                start: *start,
+                end: starts.get(idx + 1).copied().unwrap_or(body.len()),
+                successors: vec![],
+                predecessors: vec![],
+            })
+            .collect();
+        let labels: HashMap<Symbol, usize> = blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, block)| match body[block.start] {
+                Instruction::Label(label) => Some((label, idx)),
+                _ => None,
+            })
+            .collect();
+        let tables: Vec<usize> = function
+            .tables
+            .iter()
+            .flatten()
+            .filter_map(|label| labels.get(label).copied())
+            .collect();
+        for idx in 0..blocks.len() {
+            let next = (idx + 1 < blocks.len()).then_some(idx + 1);
+            let successors: Vec<usize> = match &body[blocks[idx].end - 1] {
+                Instruction::Jmp { label } => labels.get(label).copied().into_iter().collect(),
+                Instruction::JOp { label, .. } => {
+                    labels.get(label).copied().into_iter().chain(next).collect()
+                }
+                Instruction::JmpIndirect(_) => tables.clone(),
+                Instruction::Ret => vec![],
+                _ => next.into_iter().collect(),
+            };
+            for successor in successors {
+                if !blocks[idx].successors.contains(&successor) {
+                    blocks[idx].successors.push(successor);
+                    blocks[successor].predecessors.push(idx);
+                }
+            }
+        }
+        Self { blocks }
+    }
+    /// the block the instruction at `idx` is in
+    pub fn block_of(&self, idx: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| (block.start..block.end).contains(&idx))
+    }
+}
+
+/// the registers an instruction reads and the ones it overwrites completely
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Effects {
+    pub uses: BTreeSet<RegisterName>,
+    pub defs: BTreeSet<RegisterName>,
+}
+impl Effects {
+    fn read(&mut self, register: RegisterName) {
+        self.uses.insert(register);
+    }
+    fn read_address(&mut self, address: &Address) {
+        self.uses.extend(address.base.map(|base| base.name));
+        self.uses.extend(address.index.map(|(index, _)| index.name));
+    }
+    fn read_source(&mut self, src: &Source) {
+        match src {
+            Source::Register(register) => self.read(register.name),
+            Source::Memory(memory) => self.read_address(&memory.address),
+            Source::Int(_) | Source::Int64(_) | Source::Name(_) | Source::Amount(_) => {}
+        }
+    }
+    /// `dest` is an operand read before it is written if `read`
+    fn write(&mut self, dest: &Destination, read: bool) {
+        match dest {
+            Destination::Register(register) => self.write_register(*register, read),
+            Destination::Memory(memory) => self.read_address(&memory.address),
+        }
+    }
+    fn write_register(&mut self, register: Register, read: bool) {
+        if read {
+            self.read(register.name);
+        }
+        // writing the low bytes keeps the rest of the register
+        if register.size.bytes() >= RegisterSize::S32.bytes() {
+            self.defs.insert(register.name);
+        }
+    }
+    fn clobber(&mut self, registers: &[RegisterName]) {
+        self.defs.extend(registers);
+    }
+}
+
+/// the registers the caller can't rely on after a cdecl call
+const CALLER_SAVED: &[RegisterName] = &[RegisterName::A, RegisterName::C, RegisterName::D];
+/// the registers a function returns to its caller, its result and the callee-saved ones
+const RETURNED: &[RegisterName] = &[
+    RegisterName::A,
+    RegisterName::B,
+    RegisterName::SP,
+    RegisterName::BP,
+    RegisterName::SI,
+    RegisterName::DI,
+];
+
+/// the registers `instr` reads and writes, including the ones it only uses implicitly
+pub fn effects(instr: &Instruction) -> Effects {
+    use RegisterName::{A, BP, C, D, DI, SI, SP};
+    let mut effects = Effects::default();
+    match instr {
+        Instruction::Mov { dest, src } => {
+            effects.read_source(src);
+            effects.write(dest, false);
+        }
+        Instruction::Movzx { dest, src } | Instruction::Movsx { dest, src } => {
+            effects.read_source(src);
+            effects.write_register(*dest, false);
+        }
+        Instruction::Lea { dest, addr } => {
+            effects.read_address(addr);
+            effects.write_register(*dest, false);
+        }
+        Instruction::Push { src } => {
+            effects.read_source(src);
+            effects.read(SP);
+            effects.clobber(&[SP]);
+        }
+        Instruction::Pop { dest } => {
+            effects.read(SP);
+            effects.clobber(&[SP]);
+            effects.write(dest, false);
+        }
+        Instruction::Call { .. } => {
+            effects.read(SP);
+            effects.clobber(CALLER_SAVED);
+        }
+        Instruction::CallIndirect(src) => {
+            effects.read_source(src);
+            effects.read(SP);
+            effects.clobber(CALLER_SAVED);
+        }
+        Instruction::Leave => {
+            effects.read(BP);
+            effects.clobber(&[SP, BP]);
+        }
+        Instruction::Ret => effects.uses.extend(RETURNED),
+        Instruction::JmpIndirect(src) => effects.read_source(src),
+        Instruction::Cmp { a, b } => {
+            effects.read_source(a);
+            effects.read_source(b);
+        }
+        Instruction::Set { dest, .. } => effects.write(dest, false),
+        // a conditional move keeps what was there otherwise
+        Instruction::Cmov { dest, src, .. } => {
+            effects.read_source(src);
+            effects.read(dest.name);
+        }
+        Instruction::Add { dest, src }
+        | Instruction::Sub { dest, src }
+        | Instruction::And { dest, src } => {
+            effects.read_source(src);
+            effects.write(dest, true);
+        }
+        Instruction::Sar { dest, .. }
+        | Instruction::Shl { dest, .. }
+        | Instruction::Shr { dest, .. } => effects.write(dest, true),
+        Instruction::Xchg { dest, src } | Instruction::Xadd { dest, src, .. } => {
+            effects.write(dest, true);
+            effects.write_register(*src, true);
+        }
+        Instruction::Cmpxchg { dest, src, .. } => {
+            effects.read(A);
+            effects.read(src.name);
+            effects.write(dest, true);
+            effects.clobber(&[A]);
+        }
+        Instruction::Mul { src } => {
+            effects.read_source(src);
+            effects.read(A);
+            effects.clobber(&[A, D]);
+        }
+        Instruction::Div { src } | Instruction::Idiv { src } => {
+            effects.read_source(src);
+            effects.uses.extend([A, D]);
+            effects.clobber(&[A, D]);
+        }
+        Instruction::Cdq => {
+            effects.read(A);
+            effects.clobber(&[D]);
+        }
+        Instruction::RepMovsb => {
+            effects.uses.extend([SI, DI, C]);
+            effects.clobber(&[SI, DI, C]);
+        }
+        Instruction::RepStosb => {
+            effects.uses.extend([A, DI, C]);
+            effects.clobber(&[DI, C]);
+        }
+        Instruction::Fld { src } => effects.read_address(&src.address),
+        Instruction::Fstp { dest } => effects.read_address(&dest.address),
+        // the system call number and its arguments
+        Instruction::Interrupt(_) => {
+            effects.uses.extend([A, RegisterName::B, C, D]);
+            effects.clobber(&[A]);
+        }
+        Instruction::NOp
+        | Instruction::Label(_)
+        | Instruction::Jmp { .. }
+        | Instruction::JOp { .. } => {}
+    }
+    effects
+}
+
+/// which registers hold a value that is read later, see `liveness`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LivenessInfo {
+    pub cfg: Cfg,
+    /// the registers live at the start of every block
+    pub live_in: Vec<BTreeSet<RegisterName>>,
+    /// the registers live at the end of every block
+    pub live_out: Vec<BTreeSet<RegisterName>>,
+    /// the instructions reading a register for the last time before it is overwritten or
+    /// the function returns, with those registers
+    pub last_uses: Vec<(usize, BTreeSet<RegisterName>)>,
+}
+impl LivenessInfo {
+    /// the registers live right after the instruction at `idx`
+    pub fn live_after(&self, body: &[Instruction], idx: usize) -> BTreeSet<RegisterName> {
+        let Some(block) = self.cfg.block_of(idx) else {
+            return BTreeSet::new();
+        };
+        let mut live = self.live_out[block].clone();
+        for instr in body[idx + 1..self.cfg.blocks[block].end].iter().rev() {
+            transfer(&mut live, instr);
+        }
+        live
+    }
+}
+/// turns the registers live after `instr` into the ones live before it
+fn transfer(live: &mut BTreeSet<RegisterName>, instr: &Instruction) {
+    let effects = effects(instr);
+    for def in &effects.defs {
+        live.remove(def);
+    }
+    live.extend(effects.uses);
+}
+
+/// the registers live into and out of every block of `function` and where each value held in
+/// a register is read for the last time
+pub fn liveness(function: &Function) -> LivenessInfo {
+    let cfg = Cfg::build(function);
+    let body = &function.body;
+    let blocks = cfg.blocks.len();
+    let mut live_in = vec![BTreeSet::new(); blocks];
+    let mut live_out: Vec<BTreeSet<RegisterName>> = vec![BTreeSet::new(); blocks];
+    // the blocks are visited backwards until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in (0..blocks).rev() {
+            let block = &cfg.blocks[idx];
+            let out: BTreeSet<RegisterName> = block
+                .successors
+                .iter()
+                .flat_map(|successor| live_in[*successor].iter().copied())
+                .collect();
+            let mut live = out.clone();
+            for instr in body[block.start..block.end].iter().rev() {
+                transfer(&mut live, instr);
+            }
+            if live != live_in[idx] || out != live_out[idx] {
+                live_in[idx] = live;
+                live_out[idx] = out;
+                changed = true;
+            }
+        }
+    }
+    let mut last_uses = vec![];
+    for (idx, block) in cfg.blocks.iter().enumerate() {
+        let mut live = live_out[idx].clone();
+        let mut block_uses = vec![];
+        for at in (block.start..block.end).rev() {
+            let effects = effects(&body[at]);
+            // the stack pointer is read by every push and never dies
+            let last: BTreeSet<RegisterName> = effects
+                .uses
+                .iter()
+                .filter(|register| **register != RegisterName::SP)
+                .filter(|register| !live.contains(register) || effects.defs.contains(register))
+                .copied()
+                .collect();
+            transfer(&mut live, &body[at]);
+            if !last.is_empty() {
+                block_uses.push((at, last));
+            }
+        }
+        last_uses.extend(block_uses.into_iter().rev());
+    }
+    LivenessInfo {
+        cfg,
+        live_in,
+        live_out,
+        last_uses,
+    }
+}
+
+/// the immediate dominator of every block of a `Cfg`, the last block every path from the entry
+/// to it passes through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomTree {
+    /// the immediate dominator of every block, the entry being its own and unreachable blocks
+    /// having none
+    idoms: Vec<Option<usize>>,
+    /// the reachable blocks in reverse postorder
+    order: Vec<usize>,
+}
+impl DomTree {
+    /// computes the dominators of `cfg` by iterating over its blocks in reverse postorder until
+    /// the immediate dominators settle, as Cooper, Harvey and Kennedy describe
+    pub fn compute(cfg: &Cfg) -> Self {
+        let blocks = cfg.blocks.len();
+        let mut order = Vec::with_capacity(blocks);
+        let mut visited = vec![false; blocks];
+        // the successors left to visit of every block on the path from the entry
+        let mut stack = vec![];
+        if blocks > 0 {
+            visited[0] = true;
+            stack.push((0, 0));
+        }
+        while let Some((block, next)) = stack.last_mut() {
+            match cfg.blocks[*block].successors.get(*next) {
+                Some(&successor) => {
+                    *next += 1;
+                    if !visited[successor] {
+                        visited[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => {
+                    order.push(*block);
+                    stack.pop();
+                }
+            }
+        }
+        order.reverse();
+        let mut position = vec![usize::MAX; blocks];
+        for (idx, block) in order.iter().enumerate() {
+            position[*block] = idx;
+        }
+        let mut idoms = vec![None; blocks];
+        if blocks > 0 {
+            idoms[0] = Some(0);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().skip(1) {
+                let mut processed = cfg.blocks[block]
+                    .predecessors
+                    .iter()
+                    .copied()
+                    .filter(|predecessor| idoms[*predecessor].is_some());
+                let Some(first) = processed.next() else {
+                    continue;
+                };
+                let idom = processed.fold(first, |mut a, mut b| {
+                    // walks up from both blocks until they meet
+                    while a != b {
+                        while position[a] > position[b] {
+                            a = idoms[a].expect("processed blocks have dominators");
+                        }
+                        while position[b] > position[a] {
+                            b = idoms[b].expect("processed blocks have dominators");
+                        }
+                    }
+                    a
+                });
+                if idoms[block] != Some(idom) {
+                    idoms[block] = Some(idom);
+                    changed = true;
+                }
+            }
+        }
+        Self { idoms, order }
+    }
+    /// the immediate dominator of `block`, none for the entry and unreachable blocks
+    pub fn idom(&self, block: usize) -> Option<usize> {
+        self.idoms[block].filter(|idom| *idom != block)
+    }
+    /// whether the entry reaches `block`
+    pub fn reachable(&self, block: usize) -> bool {
+        self.idoms[block].is_some()
+    }
+    /// the reachable blocks in reverse postorder, every block before the ones it dominates
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+    /// the blocks `block` immediately dominates
+    pub fn children(&self, block: usize) -> Vec<usize> {
+        (0..self.idoms.len())
+            .filter(|child| self.idom(*child) == Some(block))
+            .collect()
+    }
+    /// whether every path from the entry to `b` passes through `a`, which holds for `b` itself
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        if !self.reachable(a) || !self.reachable(b) {
+            return false;
+        }
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom(b) {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// a loop a single block, its header, enters, and which a jump back to the header repeats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NaturalLoop {
+    pub header: usize,
+    /// the blocks jumping back to the header
+    pub latches: Vec<usize>,
+    /// every block of the loop, including the header
+    pub blocks: BTreeSet<usize>,
+}
+impl NaturalLoop {
+    /// the blocks outside of the loop it may be left to
+    pub fn exits(&self, cfg: &Cfg) -> BTreeSet<usize> {
+        self.blocks
+            .iter()
+            .flat_map(|block| cfg.blocks[*block].successors.iter().copied())
+            .filter(|successor| !self.blocks.contains(successor))
+            .collect()
+    }
+    /// whether `other` is nested in this loop
+    pub fn contains(&self, other: &NaturalLoop) -> bool {
+        other.blocks.is_subset(&self.blocks)
+    }
+}
+
+/// the natural loops of `cfg`, one for every header, outer loops before the ones nested in them
+///
+/// an edge is a back edge if its target dominates its source, irreducible cycles have none and
+/// aren't found
+pub fn natural_loops(cfg: &Cfg, dom: &DomTree) -> Vec<NaturalLoop> {
+    let mut loops: Vec<NaturalLoop> = vec![];
+    for &header in dom.order() {
+        let latches: Vec<usize> = cfg.blocks[header]
+            .predecessors
+            .iter()
+            .copied()
+            .filter(|predecessor| dom.dominates(header, *predecessor))
+            .collect();
+        if latches.is_empty() {
+            continue;
+        }
+        // everything reaching a latch without passing through the header
+        let mut blocks = BTreeSet::from([header]);
+        let mut stack = latches.clone();
+        while let Some(block) = stack.pop() {
+            if blocks.insert(block) {
+                stack.extend(
+                    cfg.blocks[block]
+                        .predecessors
+                        .iter()
+                        .filter(|predecessor| dom.reachable(**predecessor)),
+                );
+            }
+        }
+        loops.push(NaturalLoop {
+            header,
+            latches,
+            blocks,
+        });
+    }
+    loops
+}
@@ -0,0 +1,150 @@
+use crate::{
+    intern::Symbol,
+    parser::{Lexer, Located, ParseError, ParseErrorKind, Position, QuoteKind, SExpr, Token},
+    typ::Type,
+};
+use std::ops::Range;
+
+/// index of a node in an `Ast`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(pub u32);
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// range into `Ast::lists`
+    Expr(Range<u32>),
+    Bracket(Range<u32>),
+    Brace(Range<u32>),
+    Word(Symbol),
+    Int(i128, Option<Type>),
+    Float(f64, Option<Type>),
+    String(String),
+    ByteString(String),
+    Keyword(Symbol),
+    Quoted(QuoteKind, NodeId),
+}
+/// an s-expression tree stored in flat vectors instead of a `Vec` per group
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ast {
+    pub nodes: Vec<Located<Node>>,
+    /// the children of every group, each group occupying a contiguous range
+    pub lists: Vec<NodeId>,
+    pub roots: Vec<NodeId>,
+}
+impl Ast {
+    pub fn get(&self, id: NodeId) -> &Located<Node> {
+        &self.nodes[id.0 as usize]
+    }
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        match &self.get(id).value {
+            Node::Expr(range) | Node::Bracket(range) | Node::Brace(range) => {
+                &self.lists[range.start as usize..range.end as usize]
+            }
+            _ => &[],
+        }
+    }
+    fn push(&mut self, value: Node, pos: Position) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(Located { value, pos });
+        id
+    }
+    /// converts the node `id` back into an owned `SExpr` tree
+    pub fn to_sexpr(&self, id: NodeId) -> Located<SExpr> {
+        let Located { value, pos } = self.get(id);
+        let value = match value {
+            Node::Expr(_) => SExpr::Expr(self.to_sexprs(self.children(id))),
+            Node::Bracket(_) => SExpr::Bracket(self.to_sexprs(self.children(id))),
+            Node::Brace(_) => SExpr::Brace(self.to_sexprs(self.children(id))),
+            Node::Word(word) => SExpr::Word(*word),
+            Node::Int(int, typ) => SExpr::Int(*int, typ.clone()),
+            Node::Float(float, typ) => SExpr::Float(*float, typ.clone()),
+            Node::String(string) => SExpr::String(string.clone()),
+            Node::ByteString(string) => SExpr::ByteString(string.clone()),
+            Node::Keyword(keyword) => SExpr::Keyword(*keyword),
+            Node::Quoted(kind, id) => SExpr::Quoted(*kind, Box::new(self.to_sexpr(*id))),
+        };
+        Located { value, pos: *pos }
+    }
+    pub fn to_sexprs(&self, ids: &[NodeId]) -> Vec<Located<SExpr>> {
+        ids.iter().map(|id| self.to_sexpr(*id)).collect()
+    }
+}
+
+/// parses `code` into an arena, the equivalent of `parser::parse`
+pub fn parse(code: &str) -> Result<Ast, ParseError> {
+    let mut lexer = Lexer::from(code);
+    let mut ast = Ast::default();
+    // children of the groups which are still open
+    let mut stack = vec![];
+    while let Some(token) = lexer.next_token()? {
+        let id = parse_token(&mut lexer, &mut ast, &mut stack, token)?;
+        ast.roots.push(id);
+    }
+    Ok(ast)
+}
+fn parse_token(
+    lexer: &mut Lexer,
+    ast: &mut Ast,
+    stack: &mut Vec<NodeId>,
+    Located { value: token, pos }: Located<Token>,
+) -> Result<NodeId, ParseError> {
+    let node = match token {
+        Token::Open(open) => {
+            let close = match open {
+                '(' => ')',
+                '[' => ']',
+                _ => '}',
+            };
+            let start = stack.len();
+            loop {
+                let Some(token) = lexer.next_token()? else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::Unclosed(open),
+                        pos,
+                    });
+                };
+                match token.value {
+                    Token::Close(c) if c == close => break,
+                    Token::Close(_) => {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::Unclosed(open),
+                            pos,
+                        })
+                    }
+                    _ => {
+                        let id = parse_token(lexer, ast, stack, token)?;
+                        stack.push(id);
+                    }
+                }
+            }
+            let range = ast.lists.len() as u32..(ast.lists.len() + stack.len() - start) as u32;
+            ast.lists.extend(stack.drain(start..));
+            match open {
+                '(' => Node::Expr(range),
+                '[' => Node::Bracket(range),
+                _ => Node::Brace(range),
+            }
+        }
+        Token::Close(c) => {
+            return Err(ParseError {
+                kind: ParseErrorKind::Unexpected(c),
+                pos,
+            })
+        }
+        Token::Quote(kind) => {
+            let Some(token) = lexer.next_token()? else {
+                return Err(ParseError {
+                    kind: ParseErrorKind::Unexpected(kind.symbol()),
+                    pos,
+                });
+            };
+            Node::Quoted(kind, parse_token(lexer, ast, stack, token)?)
+        }
+        Token::Word(word) => Node::Word(word),
+        Token::Int(int, typ) => Node::Int(int, typ),
+        Token::Float(float, typ) => Node::Float(float, typ),
+        Token::String(string) => Node::String(string),
+        Token::ByteString(string) => Node::ByteString(string),
+        Token::Keyword(keyword) => Node::Keyword(keyword),
+    };
+    Ok(ast.push(node, pos))
+}
@@ -0,0 +1,131 @@
+use crate::{
+    code::{
+        Address, DataType, Destination, Instruction, Memory, Register, RegisterName, RegisterSize,
+        Source,
+    },
+    compiler::{CompileError, Compiler},
+    parser::{Located, Position, SExpr},
+    typ::{IntType, Type},
+    visit::{walk, SExprVisitor},
+};
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const ECX: Register = Register {
+    name: RegisterName::C,
+    size: RegisterSize::S32,
+};
+const EBP: Register = Register {
+    name: RegisterName::BP,
+    size: RegisterSize::S32,
+};
+
+/// where `main` keeps the arguments it is called with for `argc` and `argv`
+const ARGC: &str = "lerp_argc";
+const ARGV: &str = "lerp_argv";
+
+/// finds `(argc)` and `(argv i)` forms
+#[derive(Default)]
+struct UsesArgs(bool);
+impl SExprVisitor for UsesArgs {
+    fn visit(&mut self, sexpr: &Located<SExpr>) {
+        if let SExpr::Expr(sexprs) = &sexpr.value {
+            if matches!(sexprs.first(), Some(Located { value: SExpr::Word(head), .. }) if head == "argc" || head == "argv")
+            {
+                self.0 = true;
+            }
+        }
+        walk(self, sexpr)
+    }
+}
+/// whether `program` reads the command-line arguments anywhere
+pub fn uses_args(program: &[Located<SExpr>]) -> bool {
+    let mut visitor = UsesArgs::default();
+    visitor.visit_all(program);
+    visitor.0
+}
+
+fn dword(address: Address) -> Memory {
+    Memory {
+        data_type: DataType::DoubleWord,
+        address,
+    }
+}
+
+impl Compiler {
+    /// stores the `argc` and `argv` `main` is called with where every function can read them,
+    /// has to be compiled at the start of `main`
+    pub fn save_args(&mut self) {
+        for (idx, label) in [ARGC, ARGV].into_iter().enumerate() {
+            self.program
+                .bss
+                .push((label.to_string(), RegisterSize::S32.bytes()));
+            // above the return address and the saved base pointer
+            let offset = (idx + 2) * RegisterSize::S32.bytes();
+            self.write(Instruction::Mov {
+                dest: EAX.into(),
+                src: Source::Memory(dword(Address::offset(EBP, offset as i32))),
+            });
+            self.write(Instruction::Mov {
+                dest: Destination::Memory(dword(Address::label(label.to_string()))),
+                src: Source::Register(EAX),
+            });
+        }
+    }
+    /// `(argc)`: how many command-line arguments the program was called with, its own name
+    /// included
+    pub fn compile_argc(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if !sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(0),
+                pos,
+            });
+        }
+        self.write(Instruction::Mov {
+            dest: EAX.into(),
+            src: Source::Memory(dword(Address::label(ARGC.to_string()))),
+        });
+        Ok(Type::Int(IntType::Size))
+    }
+    /// `(argv i)`: the command-line argument at the index `i`, where 0 is the name of the
+    /// program and `(argc)` a null pointer
+    pub fn compile_argv(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([idx]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        let idx_pos = idx.pos;
+        let typ = self.compile(idx)?;
+        if !matches!(typ, Type::Int(_) | Type::UInt(_)) || self.widen(&typ) != Some(EAX.size) {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: idx_pos,
+            });
+        }
+        self.write(Instruction::Mov {
+            dest: ECX.into(),
+            src: Source::Memory(dword(Address::label(ARGV.to_string()))),
+        });
+        self.write(Instruction::Mov {
+            dest: EAX.into(),
+            src: Source::Memory(dword(Address::element(
+                ECX,
+                EAX,
+                RegisterSize::S32.bytes() as u8,
+            ))),
+        });
+        Ok(Type::Pointer(Box::new(Type::UInt(IntType::S8))))
+    }
+}
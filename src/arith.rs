@@ -0,0 +1,808 @@
+use crate::{
+    code::{
+        Address, ComparisonOperator, DataType, Destination, Instruction, Memory, Register,
+        RegisterName, RegisterSize, Source,
+    },
+    compiler::{CompileError, Compiler},
+    const_eval::const_eval,
+    intern::Symbol,
+    parser::{Located, Position, SExpr},
+    typ::{IntType, Type},
+};
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const EBX: Register = Register {
+    name: RegisterName::B,
+    size: RegisterSize::S32,
+};
+const ECX: Register = Register {
+    name: RegisterName::C,
+    size: RegisterSize::S32,
+};
+const EDX: Register = Register {
+    name: RegisterName::D,
+    size: RegisterSize::S32,
+};
+const ESP: Register = Register {
+    name: RegisterName::SP,
+    size: RegisterSize::S32,
+};
+
+/// the exponent of `value` if it is a power of two
+fn log2(value: i64) -> Option<u8> {
+    (value > 0 && value & (value - 1) == 0).then(|| value.trailing_zeros() as u8)
+}
+/// the smallest number of bits `value` can be rounded up to a power of two with
+fn ceil_log2(value: u32) -> u32 {
+    32 - (value - 1).leading_zeros()
+}
+/// a multiplier fitting into 32 bits and a shift with `n / divisor == (n * m) >> (32 + shift)`
+/// for every unsigned 32-bit `n`, if there is one
+fn unsigned_magic(divisor: u32) -> Option<(u32, u8)> {
+    (0..ceil_log2(divisor)).find_map(|shift| {
+        let power = 1u128 << (32 + shift);
+        let magic = power.div_ceil(divisor as u128);
+        // the error of rounding the multiplier up stays below a whole quotient
+        (magic < 1 << 32 && magic * divisor as u128 - power <= 1 << shift)
+            .then_some((magic as u32, shift as u8))
+    })
+}
+
+/// the inverse of the odd `value` modulo 2^32
+fn inverse(value: u32) -> u32 {
+    // every step doubles the correct low bits, `value` is its own inverse modulo 8
+    let mut inverse = value;
+    for _ in 0..4 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(value.wrapping_mul(inverse)));
+    }
+    inverse
+}
+
+impl Compiler {
+    /// the size of what a pointer of type `typ` points to
+    fn pointee_size(&self, typ: &Type, pos: Position) -> Result<usize, Located<CompileError>> {
+        let Type::Pointer(pointee) = typ else {
+            return Err(Located {
+                value: CompileError::Internal("not a pointer"),
+                pos,
+            });
+        };
+        match pointee.size() {
+            Some(size) if size > 0 => Ok(size),
+            _ => Err(Located {
+                value: CompileError::UnknownSize(*pointee.clone()),
+                pos,
+            }),
+        }
+    }
+    /// multiplies the A register by `size`
+    fn scale(&mut self, size: usize) {
+        if let Some(shift) = log2(size as i64) {
+            self.shift_left(EAX, shift);
+            return;
+        }
+        self.write(Instruction::Mov {
+            dest: ECX.into(),
+            src: Source::Amount(size),
+        });
+        self.write(Instruction::Mul {
+            src: Source::Register(ECX),
+        });
+    }
+    /// moves the right operand of `size` from the A register into the B register and pops the
+    /// left one pushed before it into the A register, a 64-bit right operand going from the D
+    /// and A register pair into the C and B one and the left one into the D and A pair
+    fn pop_operands(&mut self, size: RegisterSize) {
+        if size == RegisterSize::S64 {
+            self.write(Instruction::Mov {
+                dest: ECX.into(),
+                src: Source::Register(EDX),
+            });
+        }
+        self.write(Instruction::Mov {
+            dest: EBX.into(),
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Pop { dest: EAX.into() });
+        if size == RegisterSize::S64 {
+            self.write(Instruction::Pop { dest: EDX.into() });
+        }
+    }
+    /// multiplies `register` by 2 to the power of `shift`
+    pub fn shift_left(&mut self, register: Register, shift: u8) {
+        if shift > 0 {
+            self.write(Instruction::Shl {
+                dest: register.into(),
+                amount: shift,
+            });
+        }
+    }
+    /// calls the panic runtime if the arithmetic `form` before overflowed `typ`, which needs
+    /// the C library
+    fn check_overflow(
+        &mut self,
+        form: &str,
+        typ: &Type,
+        pos: Position,
+    ) -> Result<(), Located<CompileError>> {
+        let op = match typ {
+            Type::Int(_) => ComparisonOperator::NotOverflow,
+            Type::UInt(_) => ComparisonOperator::GreaterEqualUnsigned,
+            _ => return Ok(()),
+        };
+        if self.options.freestanding {
+            return Err(Located {
+                value: CompileError::NeedsLibc(form.to_string()),
+                pos,
+            });
+        }
+        if self.options.check_only {
+            return Ok(());
+        }
+        let label = Symbol::intern(&format!("checked{}", self.new_labels()));
+        self.write(Instruction::JOp { op, label });
+        let message = self.new_string(format!(
+            "arithmetic overflow at {}:{}",
+            pos.ln + 1,
+            pos.col + 1
+        ));
+        self.write(Instruction::Push {
+            src: Source::Name(message),
+        });
+        let panic = self.panic_function();
+        self.call(panic, RegisterSize::S32.bytes());
+        self.write(Instruction::Label(label));
+        Ok(())
+    }
+
+    /// `(+ a b)` and `(- a b)`: integers of the same type, a pointer offset by an integer
+    /// counting elements of the pointee, or with `-` the elements between two pointers as `isz`
+    ///
+    /// integers which don't fit into their type trap if `checked`, an unsigned result below zero
+    /// included, pointers are never checked
+    pub fn compile_additive(
+        &mut self,
+        subtract: bool,
+        checked: bool,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([left, right]) = <[Located<SExpr>; 2]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(2),
+                pos,
+            });
+        };
+        let (left_pos, right_pos) = (left.pos, right.pos);
+
+        let hint = self.operand_hint(&left, &right);
+        let left_typ = self.compile_expecting(left, hint.as_ref())?;
+        if let Type::Float(_) = left_typ {
+            return Err(Located {
+                value: CompileError::Unsupported("float operands"),
+                pos: left_pos,
+            });
+        }
+        let Some(size) = RegisterSize::typ(&left_typ) else {
+            return Err(Located {
+                value: CompileError::InvalidType(left_typ),
+                pos: left_pos,
+            });
+        };
+        let Some(push_size) = self.widen(&left_typ) else {
+            return Err(Located {
+                value: CompileError::InvalidType(left_typ),
+                pos: left_pos,
+            });
+        };
+        self.push_value(push_size);
+
+        let right_typ = self.compile_expecting(right, Some(&left_typ))?;
+        // an offset has to fit into a pointer
+        let offset = matches!(left_typ, Type::Pointer(_))
+            && matches!(right_typ, Type::Int(_) | Type::UInt(_))
+            && self.widen(&right_typ) == Some(EAX.size);
+        let op = |dest: Register, src: Register| {
+            let (dest, src) = (Destination::Register(dest), Source::Register(src));
+            match subtract {
+                true => Instruction::Sub { dest, src },
+                false => Instruction::Add { dest, src },
+            }
+        };
+        let typ = match (&left_typ, &right_typ) {
+            (Type::Pointer(_), Type::Pointer(_)) if subtract && left_typ == right_typ => {
+                let element = self.pointee_size(&left_typ, left_pos)?;
+                self.write(Instruction::Mov {
+                    dest: EBX.into(),
+                    src: Source::Register(EAX),
+                });
+                self.write(Instruction::Pop { dest: EAX.into() });
+                self.write(op(EAX, EBX));
+                // the difference is a multiple of the size, which divides exactly by shifting
+                // out the power of two and multiplying by the inverse of the odd rest
+                let shift = element.trailing_zeros();
+                if shift > 0 {
+                    self.write(Instruction::Sar {
+                        dest: EAX.into(),
+                        amount: shift as u8,
+                    });
+                }
+                let odd = (element >> shift) as u32;
+                if odd > 1 {
+                    self.write(Instruction::Mov {
+                        dest: ECX.into(),
+                        src: Source::Int(inverse(odd) as i32),
+                    });
+                    self.write(Instruction::Mul {
+                        src: Source::Register(ECX),
+                    });
+                }
+                Type::Int(IntType::Size)
+            }
+            (Type::Pointer(_), _) if offset => {
+                let element = self.pointee_size(&left_typ, left_pos)?;
+                self.scale(element);
+                self.write(Instruction::Mov {
+                    dest: EBX.into(),
+                    src: Source::Register(EAX),
+                });
+                self.write(Instruction::Pop { dest: EAX.into() });
+                self.write(op(EAX, EBX));
+                left_typ
+            }
+            (_, Type::Pointer(_))
+                if !subtract && matches!(left_typ, Type::Int(_) | Type::UInt(_)) =>
+            {
+                if push_size != EAX.size {
+                    return Err(Located {
+                        value: CompileError::InvalidType(left_typ),
+                        pos: left_pos,
+                    });
+                }
+                let element = self.pointee_size(&right_typ, right_pos)?;
+                self.write(Instruction::Mov {
+                    dest: EBX.into(),
+                    src: Source::Register(EAX),
+                });
+                self.write(Instruction::Pop { dest: EAX.into() });
+                self.scale(element);
+                self.write(op(EAX, EBX));
+                right_typ
+            }
+            (Type::Pointer(_), _) | (_, Type::Pointer(_)) => {
+                return Err(Located {
+                    value: CompileError::InvalidPointerArithmetic {
+                        left: left_typ,
+                        right: right_typ,
+                    },
+                    pos,
+                })
+            }
+            _ if right_typ != left_typ => {
+                return Err(Located {
+                    value: CompileError::InvalidTypeExpected {
+                        expected: left_typ,
+                        got: right_typ,
+                    },
+                    pos: right_pos,
+                })
+            }
+            // the carry or borrow out of the low halves is taken with `setb` and added to what
+            // is added to or subtracted from the high half, as there is no `adc` or `sbb`
+            _ if size == RegisterSize::S64 => {
+                if checked {
+                    return Err(Located {
+                        value: CompileError::Unsupported("checked 64-bit arithmetic"),
+                        pos,
+                    });
+                }
+                self.pop_operands(size);
+                self.write(op(EAX, EBX));
+                let bl = Register {
+                    size: RegisterSize::S8,
+                    ..EBX
+                };
+                self.write(Instruction::Set {
+                    op: ComparisonOperator::LessUnsigned,
+                    dest: bl.into(),
+                });
+                self.write(Instruction::Movzx {
+                    dest: EBX,
+                    src: Source::Register(bl),
+                });
+                self.write(Instruction::Add {
+                    dest: ECX.into(),
+                    src: Source::Register(EBX),
+                });
+                self.write(op(EDX, ECX));
+                left_typ
+            }
+            _ => {
+                let (a, b) = (Register { size, ..EAX }, Register { size, ..EBX });
+                self.write(Instruction::Mov {
+                    dest: b.into(),
+                    src: Source::Register(a),
+                });
+                self.write(Instruction::Pop {
+                    dest: Register {
+                        size: push_size,
+                        ..EAX
+                    }
+                    .into(),
+                });
+                self.write(op(a, b));
+                if checked {
+                    self.check_overflow(if subtract { "-" } else { "+" }, &left_typ, pos)?;
+                }
+                left_typ
+            }
+        };
+        Ok(typ)
+    }
+
+    /// compiles the operands of an intrinsic into the A and B registers, extended to 32 bits,
+    /// and returns their type, with `wide` taking 64-bit ones as well, which `pop_operands`
+    /// leaves in the D and A and the C and B register pairs
+    fn integer_operands(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        wide: bool,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([left, right]) = <[Located<SExpr>; 2]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(2),
+                pos,
+            });
+        };
+        let (left_pos, right_pos) = (left.pos, right.pos);
+        let hint = self.operand_hint(&left, &right);
+        let typ = self.compile_expecting(left, hint.as_ref())?;
+        let size = self
+            .widen(&typ)
+            .filter(|size| *size == RegisterSize::S32 || wide && *size == RegisterSize::S64);
+        let (Type::Int(_) | Type::UInt(_), Some(size)) = (&typ, size) else {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: left_pos,
+            });
+        };
+        self.push_value(size);
+        let right_typ = self.compile_expecting(right, Some(&typ))?;
+        if right_typ != typ {
+            return Err(Located {
+                value: CompileError::InvalidTypeExpected {
+                    expected: typ,
+                    got: right_typ,
+                },
+                pos: right_pos,
+            });
+        }
+        self.widen(&right_typ);
+        self.pop_operands(size);
+        Ok(typ)
+    }
+
+    /// `(wrapping-mul a b)`: the product of two integers of the same type, wrapping around
+    /// even with checked arithmetic
+    pub fn compile_wrapping_mul(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let factor = |compiler: &Self, sexpr: &Located<SExpr>| {
+            const_eval(compiler, sexpr).ok().and_then(log2)
+        };
+        if let [left, right] = sexprs.as_slice() {
+            if factor(self, right).is_none() && factor(self, left).is_some() {
+                sexprs.swap(0, 1);
+            }
+        }
+        // multiplying by a constant power of two is shifting the other operand
+        if let Some(shift) = sexprs.get(1).and_then(|right| factor(self, right)) {
+            let right_pos = sexprs[1].pos;
+            let left = sexprs.remove(0);
+            let left_pos = left.pos;
+            let typ = self.compile(left)?;
+            if !matches!(typ, Type::Int(_) | Type::UInt(_)) {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ),
+                    pos: left_pos,
+                });
+            }
+            // the constant is an `i32` like every integer literal
+            if typ != Type::Int(IntType::S32) {
+                return Err(Located {
+                    value: CompileError::InvalidTypeExpected {
+                        expected: typ,
+                        got: Type::Int(IntType::S32),
+                    },
+                    pos: right_pos,
+                });
+            }
+            self.shift_left(EAX, shift);
+            return Ok(typ);
+        }
+        let typ = self.integer_operands(sexprs, true, pos)?;
+        if RegisterSize::typ(&typ) == Some(RegisterSize::S64) {
+            self.wide_mul();
+            return Ok(typ);
+        }
+        // the low half of the product is the same for signed integers
+        self.write(Instruction::Mul {
+            src: Source::Register(EBX),
+        });
+        Ok(typ)
+    }
+    /// multiplies the D and A register pair by the C and B one, keeping the low 64 bits
+    ///
+    /// the product of the low halves is the only one reaching into the high half of the
+    /// result, the high halves only add the low halves of their products with the other low one
+    fn wide_mul(&mut self) {
+        let low = Source::Memory(Memory {
+            data_type: DataType::DoubleWord,
+            address: Address::offset(ESP, 0),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Mov {
+            dest: EAX.into(),
+            src: Source::Register(EDX),
+        });
+        self.write(Instruction::Mul {
+            src: Source::Register(EBX),
+        });
+        self.write(Instruction::Xchg {
+            dest: EAX.into(),
+            src: ECX,
+        });
+        self.write(Instruction::Mul { src: low });
+        self.write(Instruction::Add {
+            dest: ECX.into(),
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Pop { dest: EAX.into() });
+        self.write(Instruction::Mul {
+            src: Source::Register(EBX),
+        });
+        self.write(Instruction::Add {
+            dest: EDX.into(),
+            src: Source::Register(ECX),
+        });
+    }
+
+    /// `(/ a b)`: the quotient of two integers of the same type, rounded towards zero
+    ///
+    /// a constant divisor takes the type of `a` and is lowered to shifts and a multiplication
+    /// by its scaled inverse, any other one to `div`, which only divides unsigned integers
+    pub fn compile_divide(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([left, right]) = <[Located<SExpr>; 2]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(2),
+                pos,
+            });
+        };
+        let Ok(divisor) = const_eval(self, &right) else {
+            let typ = self.integer_operands(vec![left, right], false, pos)?;
+            let src = Source::Register(EBX);
+            if let Type::Int(_) = typ {
+                self.write(Instruction::Cdq);
+                self.write(Instruction::Idiv { src });
+            } else {
+                self.write(Instruction::Mov {
+                    dest: EDX.into(),
+                    src: Source::Int(0),
+                });
+                self.write(Instruction::Div { src });
+            }
+            return Ok(typ);
+        };
+        let left_pos = left.pos;
+        let hint = self.operand_hint(&left, &right);
+        let typ = self.compile_expecting(left, hint.as_ref())?;
+        if let SExpr::Int(_, Some(divisor_typ)) = &right.value {
+            if *divisor_typ != typ {
+                return Err(Located {
+                    value: CompileError::InvalidTypeExpected {
+                        expected: typ,
+                        got: divisor_typ.clone(),
+                    },
+                    pos: right.pos,
+                });
+            }
+        }
+        let signed = match typ {
+            Type::Int(_) => true,
+            Type::UInt(_) => false,
+            _ => {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ),
+                    pos: left_pos,
+                })
+            }
+        };
+        if self.widen(&typ) != Some(EAX.size) {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: left_pos,
+            });
+        }
+        if divisor == 0 {
+            return Err(Located {
+                value: CompileError::DivisionByZero,
+                pos: right.pos,
+            });
+        }
+        let in_range = match signed {
+            true => i32::try_from(divisor).is_ok(),
+            false => u32::try_from(divisor).is_ok(),
+        };
+        if !in_range {
+            return Err(Located {
+                value: CompileError::OutOfRange(divisor.into()),
+                pos: right.pos,
+            });
+        }
+        match signed {
+            true => self.divide_signed(divisor as i32),
+            false => self.divide_unsigned(divisor as u32),
+        }
+        Ok(typ)
+    }
+    /// divides the unsigned A register by the constant `divisor`
+    fn divide_unsigned(&mut self, divisor: u32) {
+        if let Some(shift) = log2(divisor as i64) {
+            if shift > 0 {
+                self.write(Instruction::Shr {
+                    dest: EAX.into(),
+                    amount: shift,
+                });
+            }
+            return;
+        }
+        if let Some((magic, shift)) = unsigned_magic(divisor) {
+            self.multiply_high(magic);
+            self.write(Instruction::Mov {
+                dest: EAX.into(),
+                src: Source::Register(EDX),
+            });
+            if shift > 0 {
+                self.write(Instruction::Shr {
+                    dest: EAX.into(),
+                    amount: shift,
+                });
+            }
+            return;
+        }
+        // the multiplier takes 33 bits, its top bit is added back as the dividend itself,
+        // halving the difference first so it doesn't overflow
+        let bits = ceil_log2(divisor);
+        let magic = ((1u64 << 32) * ((1u64 << bits) - divisor as u64) / divisor as u64 + 1) as u32;
+        self.write(Instruction::Mov {
+            dest: EBX.into(),
+            src: Source::Register(EAX),
+        });
+        self.multiply_high(magic);
+        self.write(Instruction::Sub {
+            dest: EBX.into(),
+            src: Source::Register(EDX),
+        });
+        self.write(Instruction::Shr {
+            dest: EBX.into(),
+            amount: 1,
+        });
+        self.write(Instruction::Add {
+            dest: EBX.into(),
+            src: Source::Register(EDX),
+        });
+        self.write(Instruction::Mov {
+            dest: EAX.into(),
+            src: Source::Register(EBX),
+        });
+        if bits > 1 {
+            self.write(Instruction::Shr {
+                dest: EAX.into(),
+                amount: bits as u8 - 1,
+            });
+        }
+    }
+    /// divides the signed A register by the constant `divisor`, rounding towards zero
+    fn divide_signed(&mut self, divisor: i32) {
+        let magnitude = divisor.unsigned_abs();
+        if magnitude > 1 {
+            self.write(Instruction::Mov {
+                dest: EBX.into(),
+                src: Source::Register(EAX),
+            });
+        }
+        if let Some(shift) = log2(magnitude as i64).filter(|shift| *shift > 0) {
+            // a negative dividend is rounded towards zero by adding the divisor minus one
+            self.write(Instruction::Sar {
+                dest: EBX.into(),
+                amount: 31,
+            });
+            self.write(Instruction::Shr {
+                dest: EBX.into(),
+                amount: 32 - shift,
+            });
+            self.write(Instruction::Add {
+                dest: EAX.into(),
+                src: Source::Register(EBX),
+            });
+            self.write(Instruction::Sar {
+                dest: EAX.into(),
+                amount: shift,
+            });
+        } else if magnitude > 1 {
+            // the multiplier is above 2^31, which makes the unsigned high half of the product
+            // too large by the multiplier for a negative dividend
+            let bits = ceil_log2(magnitude);
+            let magic = ((1u64 << (31 + bits)) / magnitude as u64 + 1) as u32;
+            self.multiply_high(magic);
+            self.write(Instruction::Mov {
+                dest: EAX.into(),
+                src: Source::Register(EBX),
+            });
+            self.write(Instruction::Sar {
+                dest: EAX.into(),
+                amount: 31,
+            });
+            self.write(Instruction::And {
+                dest: EAX.into(),
+                src: Source::Register(ECX),
+            });
+            self.write(Instruction::Sub {
+                dest: EDX.into(),
+                src: Source::Register(EAX),
+            });
+            if bits > 1 {
+                self.write(Instruction::Sar {
+                    dest: EDX.into(),
+                    amount: bits as u8 - 1,
+                });
+            }
+            // the quotient is rounded down, and up by one for a negative dividend
+            self.write(Instruction::Sar {
+                dest: EBX.into(),
+                amount: 31,
+            });
+            self.write(Instruction::Sub {
+                dest: EDX.into(),
+                src: Source::Register(EBX),
+            });
+            self.write(Instruction::Mov {
+                dest: EAX.into(),
+                src: Source::Register(EDX),
+            });
+        }
+        if divisor < 0 {
+            self.write(Instruction::Mov {
+                dest: EBX.into(),
+                src: Source::Register(EAX),
+            });
+            self.write(Instruction::Mov {
+                dest: EAX.into(),
+                src: Source::Int(0),
+            });
+            self.write(Instruction::Sub {
+                dest: EAX.into(),
+                src: Source::Register(EBX),
+            });
+        }
+    }
+    /// the high half of the unsigned product of the A register and `magic` into the D
+    /// register, with `magic` left in the C register
+    fn multiply_high(&mut self, magic: u32) {
+        self.write(Instruction::Mov {
+            dest: ECX.into(),
+            src: Source::Int(magic as i32),
+        });
+        self.write(Instruction::Mul {
+            src: Source::Register(ECX),
+        });
+    }
+
+    /// `(saturating-add a b)` and `(saturating-sub a b)`: the sum or difference of two integers
+    /// of the same type, clamped to the range of the type instead of overflowing
+    pub fn compile_saturating(
+        &mut self,
+        subtract: bool,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let typ = self.integer_operands(sexprs, false, pos)?;
+        let op = match subtract {
+            true => Instruction::Sub {
+                dest: EAX.into(),
+                src: Source::Register(EBX),
+            },
+            false => Instruction::Add {
+                dest: EAX.into(),
+                src: Source::Register(EBX),
+            },
+        };
+        let clamp = |compiler: &mut Self, op, bound: i64| {
+            compiler.write(Instruction::Mov {
+                dest: ECX.into(),
+                src: Source::Int(bound as i32),
+            });
+            compiler.write(Instruction::Cmp {
+                a: Source::Register(EAX),
+                b: Source::Register(ECX),
+            });
+            compiler.write(Instruction::Cmov {
+                op,
+                dest: EAX,
+                src: Source::Register(ECX),
+            });
+        };
+        match (&typ, typ.size()) {
+            // the exact result of narrower integers fits into 32 bits
+            (Type::Int(_), Some(bytes @ (1 | 2))) => {
+                let max = (1i64 << (bytes * 8 - 1)) - 1;
+                self.write(op);
+                clamp(self, ComparisonOperator::Greater, max);
+                clamp(self, ComparisonOperator::Less, -max - 1);
+            }
+            (_, Some(bytes @ (1 | 2))) => {
+                self.write(op);
+                match subtract {
+                    true => clamp(self, ComparisonOperator::Less, 0),
+                    false => clamp(self, ComparisonOperator::Greater, (1i64 << (bytes * 8)) - 1),
+                }
+            }
+            // an unsigned result wraps around exactly when it carries
+            (Type::UInt(_), _) => {
+                self.write(Instruction::Mov {
+                    dest: ECX.into(),
+                    src: Source::Int(if subtract { 0 } else { -1 }),
+                });
+                self.write(op);
+                self.write(Instruction::Cmov {
+                    op: ComparisonOperator::LessUnsigned,
+                    dest: EAX,
+                    src: Source::Register(ECX),
+                });
+            }
+            // a signed result overflows towards the sign of `a`
+            _ => {
+                let edx = Register {
+                    name: RegisterName::D,
+                    ..EAX
+                };
+                self.write(Instruction::Mov {
+                    dest: ECX.into(),
+                    src: Source::Int(i32::MAX),
+                });
+                self.write(Instruction::Mov {
+                    dest: edx.into(),
+                    src: Source::Int(i32::MIN),
+                });
+                self.write(Instruction::Cmp {
+                    a: Source::Register(EAX),
+                    b: Source::Int(0),
+                });
+                self.write(Instruction::Cmov {
+                    op: ComparisonOperator::Less,
+                    dest: ECX,
+                    src: Source::Register(edx),
+                });
+                self.write(op);
+                self.write(Instruction::Cmov {
+                    op: ComparisonOperator::Overflow,
+                    dest: EAX,
+                    src: Source::Register(ECX),
+                });
+            }
+        }
+        Ok(typ)
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    code::{
+        Address, Destination, Instruction, Memory, Register, RegisterName, RegisterSize, Source,
+    },
+    compiler::{CompileError, Compiler},
+    parser::{Located, Position, SExpr},
+    typ::Type,
+};
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const ECX: Register = Register {
+    name: RegisterName::C,
+    size: RegisterSize::S32,
+};
+const EDX: Register = Register {
+    name: RegisterName::D,
+    size: RegisterSize::S32,
+};
+
+/// the value the C register points to
+fn target(size: RegisterSize) -> Destination {
+    Destination::Memory(Memory {
+        data_type: size.into(),
+        address: Address::register(ECX),
+    })
+}
+
+impl Compiler {
+    /// compiles the pointer and the `count` values of an atomic operation, pushing all but the
+    /// last one, which is left in the A register
+    ///
+    /// the pointer has to point to an integer or pointer of at most 32 bits, and the values
+    /// have to have its type
+    fn atomic_operands(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        count: usize,
+        pos: Position,
+    ) -> Result<(Type, RegisterSize), Located<CompileError>> {
+        if sexprs.len() != count + 1 {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(count + 1),
+                pos,
+            });
+        }
+        let ptr = sexprs.remove(0);
+        let ptr_pos = ptr.pos;
+        let typ = self.compile(ptr)?;
+        let pointee = match &typ {
+            Type::Pointer(pointee)
+                if matches!(**pointee, Type::Int(_) | Type::UInt(_) | Type::Pointer(_)) =>
+            {
+                RegisterSize::typ(pointee)
+                    .filter(|size| size.bytes() <= EAX.size.bytes())
+                    .map(|size| (*pointee.clone(), size))
+            }
+            _ => None,
+        };
+        let Some((pointee, size)) = pointee else {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: ptr_pos,
+            });
+        };
+        for sexpr in sexprs {
+            self.write(Instruction::Push {
+                src: Source::Register(EAX),
+            });
+            let value_pos = sexpr.pos;
+            let typ = self.compile(sexpr)?;
+            if typ != pointee {
+                return Err(Located {
+                    value: CompileError::InvalidTypeExpected {
+                        expected: pointee,
+                        got: typ,
+                    },
+                    pos: value_pos,
+                });
+            }
+        }
+        Ok((pointee, size))
+    }
+
+    /// `(atomic-load p)`: the value `p` points to, read at once
+    pub fn compile_atomic_load(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let (typ, size) = self.atomic_operands(sexprs, 0, pos)?;
+        // aligned loads are atomic on x86
+        self.load(
+            &typ,
+            Source::Memory(Memory {
+                data_type: size.into(),
+                address: Address::register(EAX),
+            }),
+        );
+        Ok(typ)
+    }
+    /// `(atomic-store p v)`: stores `v` where `p` points, ordered with every other atomic
+    /// operation
+    pub fn compile_atomic_store(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let (_, size) = self.atomic_operands(sexprs, 1, pos)?;
+        self.write(Instruction::Pop { dest: ECX.into() });
+        // unlike `mov`, `xchg` with memory is a full barrier
+        self.write(Instruction::Xchg {
+            dest: target(size),
+            src: Register { size, ..EAX },
+        });
+        Ok(Type::None)
+    }
+    /// `(atomic-add p n)`: adds the integer `n` to the one `p` points to and returns what it was
+    pub fn compile_atomic_add(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let ptr = sexprs.first().map_or(pos, |ptr| ptr.pos);
+        let (typ, size) = self.atomic_operands(sexprs, 1, pos)?;
+        if !matches!(typ, Type::Int(_) | Type::UInt(_)) {
+            return Err(Located {
+                value: CompileError::InvalidType(Type::Pointer(Box::new(typ))),
+                pos: ptr,
+            });
+        }
+        self.write(Instruction::Pop { dest: ECX.into() });
+        self.write(Instruction::Xadd {
+            lock: true,
+            dest: target(size),
+            src: Register { size, ..EAX },
+        });
+        Ok(typ)
+    }
+    /// `(cas p old new)`: stores `new` where `p` points if the value there is `old`, returning
+    /// the value that was there either way
+    pub fn compile_cas(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let (typ, size) = self.atomic_operands(sexprs, 2, pos)?;
+        self.write(Instruction::Mov {
+            dest: EDX.into(),
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Pop { dest: EAX.into() });
+        self.write(Instruction::Pop { dest: ECX.into() });
+        self.write(Instruction::Cmpxchg {
+            lock: true,
+            dest: target(size),
+            src: Register { size, ..EDX },
+        });
+        Ok(typ)
+    }
+}
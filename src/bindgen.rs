@@ -0,0 +1,388 @@
+use crate::typ::{IntType, Type};
+use std::fmt::Display;
+
+/// qualifiers and storage classes which don't change how a function is called
+const IGNORED: &[&str] = &[
+    "const",
+    "volatile",
+    "restrict",
+    "extern",
+    "inline",
+    "register",
+    "_Noreturn",
+    "auto",
+];
+
+/// a C prototype as a typed lerp extern
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub name: String,
+    pub params: Vec<(String, Type)>,
+    pub ret: Type,
+    /// any amount of arguments can follow `params`, written as a trailing `...`
+    pub variadic: bool,
+}
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(extern ({} (", self.name)?;
+        for (idx, (name, typ)) in self.params.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "({name} {typ})")?;
+        }
+        if self.variadic {
+            let separator = if self.params.is_empty() { "" } else { " " };
+            write!(f, "{separator}...")?;
+        }
+        write!(f, ") {}))", self.ret)
+    }
+}
+/// a function declaration which couldn't be turned into a binding
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub declaration: String,
+    pub reason: String,
+}
+/// the externs generated from a header, written out as lerp code one per line
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bindings {
+    pub bindings: Vec<Binding>,
+    pub skipped: Vec<Skipped>,
+}
+impl Display for Bindings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for binding in &self.bindings {
+            writeln!(f, "{binding}")?;
+        }
+        Ok(())
+    }
+}
+
+/// `header` without comments and preprocessor directives
+fn strip(header: &str) -> String {
+    let mut code = String::new();
+    let mut chars = header.chars().peekable();
+    let mut line_start = true;
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+                code.push(' ');
+                continue;
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|c| *c != '\n').is_some() {}
+                continue;
+            }
+            // directives end at the first line break which isn't escaped
+            '#' if line_start => {
+                let mut last = ' ';
+                while let Some(c) = chars.next_if(|c| *c != '\n' || last == '\\') {
+                    last = c;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if c == '\n' {
+            line_start = true;
+        } else if !c.is_whitespace() {
+            line_start = false;
+        }
+        code.push(c);
+    }
+    code
+}
+fn tokenize(code: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let mut word = String::from(c);
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                word.push(c);
+            }
+            tokens.push(word);
+        } else if c == '"' {
+            let mut string = String::from(c);
+            for c in chars.by_ref() {
+                string.push(c);
+                if c == '"' {
+                    break;
+                }
+            }
+            tokens.push(string);
+        } else if c == '.' && chars.peek() == Some(&'.') {
+            while chars.next_if_eq(&'.').is_some() {}
+            tokens.push("...".into());
+        } else {
+            tokens.push(c.to_string());
+        }
+    }
+    tokens
+}
+/// splits the tokens into top-level declarations, dropping the bodies of structs and inline
+/// functions and the braces of `extern "C"` blocks
+fn declarations(tokens: Vec<String>) -> Vec<Vec<String>> {
+    let mut declarations = vec![];
+    let mut declaration: Vec<String> = vec![];
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            ";" => declarations.push(std::mem::take(&mut declaration)),
+            "{" if declaration.len() == 2 && declaration[1].starts_with('"') => {
+                declaration.clear();
+            }
+            "{" => {
+                let mut depth = 1;
+                for token in tokens.by_ref() {
+                    match token.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                // a function definition ends at its body
+                if declaration.iter().any(|token| token == "(") {
+                    declarations.push(std::mem::take(&mut declaration));
+                } else {
+                    declaration.push("{}".into());
+                }
+            }
+            // the end of an `extern "C"` block
+            "}" => {}
+            _ => declaration.push(token),
+        }
+    }
+    declarations
+}
+/// `tokens` without qualifiers and compiler extensions like `__attribute__((...))`
+fn clean(tokens: Vec<String>) -> Vec<String> {
+    let mut cleaned = vec![];
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        // linkage specifications like `extern "C++"`
+        if IGNORED.contains(&token.as_str()) || token.starts_with('"') {
+            continue;
+        }
+        if token.starts_with("__") {
+            if tokens.next_if(|token| token == "(").is_some() {
+                let mut depth = 1;
+                for token in tokens.by_ref() {
+                    match token.as_str() {
+                        "(" => depth += 1,
+                        ")" => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            }
+            continue;
+        }
+        cleaned.push(token);
+    }
+    cleaned
+}
+/// the words which make up a type, as opposed to a name
+fn is_type_word(word: &str) -> bool {
+    matches!(
+        word,
+        "void"
+            | "char"
+            | "short"
+            | "int"
+            | "long"
+            | "signed"
+            | "unsigned"
+            | "float"
+            | "double"
+            | "_Bool"
+            | "bool"
+            | "struct"
+            | "union"
+            | "enum"
+    )
+}
+/// the lerp type of the C type spelled `words` with `stars` levels of pointers
+fn c_type(words: &[String], stars: usize) -> Result<Type, String> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let int = |signed: bool, size| {
+        if signed {
+            Type::Int(size)
+        } else {
+            Type::UInt(size)
+        }
+    };
+    let signed = !words.contains(&"unsigned");
+    let longs = words.iter().filter(|word| **word == "long").count();
+    let base = match words.as_slice() {
+        ["void"] => Some(Type::None),
+        ["struct" | "union", ..] => None,
+        ["enum", ..] => Some(Type::Int(IntType::S32)),
+        ["_Bool"] | ["bool"] => Some(Type::UInt(IntType::S8)),
+        ["float"] | ["double"] | ["long", "double"] => {
+            return Err(String::from("floating point values aren't supported"))
+        }
+        ["size_t"] | ["uintptr_t"] => Some(Type::UInt(IntType::Size)),
+        ["ssize_t"] | ["ptrdiff_t"] | ["intptr_t"] => Some(Type::Int(IntType::Size)),
+        ["int8_t"] => Some(Type::Int(IntType::S8)),
+        ["int16_t"] => Some(Type::Int(IntType::S16)),
+        ["int32_t"] => Some(Type::Int(IntType::S32)),
+        ["int64_t"] => Some(Type::Int(IntType::S64)),
+        ["uint8_t"] => Some(Type::UInt(IntType::S8)),
+        ["uint16_t"] => Some(Type::UInt(IntType::S16)),
+        ["uint32_t"] => Some(Type::UInt(IntType::S32)),
+        ["uint64_t"] => Some(Type::UInt(IntType::S64)),
+        // plain `char` holds the bytes of strings, which are `*u8` in lerp
+        _ if words.contains(&"char") => Some(int(words.contains(&"signed"), IntType::S8)),
+        _ if words.contains(&"short") => Some(int(signed, IntType::S16)),
+        // `long` is 32 bits wide on x86
+        _ if longs >= 2 => Some(int(signed, IntType::S64)),
+        _ if !words.is_empty() && words.iter().all(|word| is_type_word(word)) => {
+            Some(int(signed, IntType::S32))
+        }
+        _ => None,
+    };
+    match (base, stars) {
+        (Some(typ), 0) => Ok(typ),
+        (None, 0) if words.is_empty() => Err(String::from("missing type")),
+        (None, 0) => Err(format!("unknown type `{}`", words.join(" "))),
+        // structs and typedefs behind pointers are opaque
+        (Some(Type::None) | None, _) => Ok((1..stars).fold(
+            Type::Pointer(Box::new(Type::UInt(IntType::S8))),
+            |typ, _| Type::Pointer(Box::new(typ)),
+        )),
+        (Some(typ), _) => Ok((0..stars).fold(typ, |typ, _| Type::Pointer(Box::new(typ)))),
+    }
+}
+/// splits a parameter or return type into the type's words, its pointer depth and a name
+fn declarator(tokens: &[String]) -> Result<(Vec<String>, usize, Option<String>), String> {
+    let mut words = vec![];
+    let mut stars = 0;
+    let mut tokens = tokens.to_vec();
+    // array parameters are pointers
+    if let Some(open) = tokens.iter().position(|token| token == "[") {
+        tokens.truncate(open);
+        stars += 1;
+    }
+    for token in tokens {
+        match token.as_str() {
+            "*" => stars += 1,
+            "(" | ")" => return Err(String::from("function pointers aren't supported")),
+            "{}" => return Err(String::from("inline struct definitions aren't supported")),
+            _ if token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+                words.push(token)
+            }
+            _ => return Err(format!("unexpected `{token}`")),
+        }
+    }
+    let named = words.len() > 1
+        && !is_type_word(words.last().unwrap())
+        && !matches!(words[words.len() - 2].as_str(), "struct" | "union" | "enum");
+    let name = if named { words.pop() } else { None };
+    Ok((words, stars, name))
+}
+fn binding(tokens: &[String]) -> Result<Binding, String> {
+    let (Some(open), Some(")")) = (
+        tokens.iter().position(|token| token == "("),
+        tokens.last().map(String::as_str),
+    ) else {
+        return Err(String::from("not a function prototype"));
+    };
+    let (ret, stars, name) = declarator(&tokens[..open])?;
+    let Some(name) = name else {
+        return Err(String::from("missing return type"));
+    };
+    let ret = c_type(&ret, stars)?;
+    let mut params = vec![];
+    let mut variadic = false;
+    let inner = &tokens[open + 1..tokens.len() - 1];
+    let no_params = inner.is_empty() || (inner.len() == 1 && inner[0] == "void");
+    if !no_params {
+        let mut depth = 0;
+        let mut split = vec![vec![]];
+        for token in inner {
+            match token.as_str() {
+                "(" => depth += 1,
+                ")" => depth -= 1,
+                "," if depth == 0 => {
+                    split.push(vec![]);
+                    continue;
+                }
+                _ => {}
+            }
+            split.last_mut().unwrap().push(token.clone());
+        }
+        for (idx, param) in split.iter().enumerate() {
+            if param.len() == 1 && param[0] == "..." {
+                variadic = true;
+                continue;
+            }
+            let (words, stars, param_name) = declarator(param)?;
+            let typ = c_type(&words, stars)?;
+            if typ == Type::None {
+                return Err(String::from("void parameters aren't supported"));
+            }
+            params.push((param_name.unwrap_or_else(|| format!("a{idx}")), typ));
+        }
+    }
+    Ok(Binding {
+        name,
+        params,
+        ret,
+        variadic,
+    })
+}
+
+/// generates externs for the function prototypes in the C header `header`, skipping the ones
+/// using types lerp can't express
+///
+/// the header isn't preprocessed, macros and conditionally compiled declarations are taken as
+/// they are written
+pub fn bindgen(header: &str) -> Bindings {
+    let mut bindings = Bindings::default();
+    for declaration in declarations(tokenize(&strip(header))) {
+        let tokens = clean(declaration);
+        // types, variables and functions without external linkage
+        if !tokens.iter().any(|token| token == "(")
+            || matches!(
+                tokens.first().map(String::as_str),
+                Some("typedef" | "static")
+            )
+        {
+            continue;
+        }
+        match binding(&tokens) {
+            Ok(binding) => {
+                if !bindings
+                    .bindings
+                    .iter()
+                    .any(|other| other.name == binding.name)
+                {
+                    bindings.bindings.push(binding)
+                }
+            }
+            Err(reason) => bindings.skipped.push(Skipped {
+                declaration: tokens.join(" "),
+                reason,
+            }),
+        }
+    }
+    bindings
+}
@@ -0,0 +1,41 @@
+use std::{path::Path, process::Command};
+
+/// runs `command`, turning a failure into its stderr
+fn tool(command: &mut Command) -> Result<(), String> {
+    let name = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|err| format!("couldn't run {name}: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{name} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// assembles `asm` with nasm and links it with `gcc -m32` into `binary`, passing `-l` for every
+/// library in `libraries`
+///
+/// the executable isn't position independent, the compiler addresses data and calls externs
+/// by their absolute addresses
+pub fn assemble_and_link(asm: &Path, binary: &Path, libraries: &[String]) -> Result<(), String> {
+    let object = binary.with_extension("o");
+    tool(
+        Command::new("nasm")
+            .arg("-f")
+            .arg("elf")
+            .arg(asm)
+            .arg("-o")
+            .arg(&object),
+    )?;
+    tool(
+        Command::new("gcc")
+            .args(["-m32", "-no-pie"])
+            .arg(&object)
+            .arg("-o")
+            .arg(binary)
+            .args(libraries.iter().map(|library| format!("-l{library}"))),
+    )
+}
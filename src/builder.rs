@@ -0,0 +1,400 @@
+use crate::{
+    code::{
+        Address, ComparisonOperator, DataType, Destination, Function, Instruction, Memory, Program,
+        Register, RegisterName, RegisterSize, Source,
+    },
+    intern::Symbol,
+    typ::Type,
+};
+use std::fmt::Display;
+
+macro_rules! registers {
+    ($($fn:ident: $name:ident $size:ident),* $(,)?) => {
+        $(
+            pub fn $fn() -> Register {
+                Register {
+                    name: RegisterName::$name,
+                    size: RegisterSize::$size,
+                }
+            }
+        )*
+    };
+}
+registers! {
+    rax: A S64, eax: A S32, ax: A S16, al: A S8,
+    rbx: B S64, ebx: B S32, bx: B S16, bl: B S8,
+    rcx: C S64, ecx: C S32, cx: C S16, cl: C S8,
+    rdx: D S64, edx: D S32, dx: D S16, dl: D S8,
+    rsi: SI S64, esi: SI S32, rdi: DI S64, edi: DI S32,
+    rsp: SP S64, esp: SP S32, rbp: BP S64, ebp: BP S32,
+}
+/// an immediate operand
+pub fn imm(value: i32) -> Source {
+    Source::Int(value)
+}
+/// a 64-bit immediate operand, which only a `mov` into a 64-bit register takes
+pub fn imm64(value: i64) -> Source {
+    Source::Int64(value)
+}
+/// a sized memory operand
+pub fn mem(data_type: DataType, address: Address) -> Memory {
+    Memory { data_type, address }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildError {
+    pub function: String,
+    /// index of the offending instruction in the function body
+    pub index: usize,
+    pub kind: BuildErrorKind,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildErrorKind {
+    NoFunction,
+    SizeMismatch {
+        dest: RegisterSize,
+        src: RegisterSize,
+    },
+    MemoryToMemory,
+    ImmediateTooLarge(i64, RegisterSize),
+    /// `movzx`/`movsx` need a source smaller than the destination
+    NotWidening {
+        dest: RegisterSize,
+        src: RegisterSize,
+    },
+    InvalidOperand(Source),
+}
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{}: {}", self.function, self.index, self.kind)
+    }
+}
+impl std::error::Error for BuildError {}
+impl Display for BuildErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildErrorKind::NoFunction => write!(f, "no function to add the instruction to"),
+            BuildErrorKind::SizeMismatch { dest, src } => write!(
+                f,
+                "operand size mismatch: {} byte destination, {} byte source",
+                dest.bytes(),
+                src.bytes()
+            ),
+            BuildErrorKind::MemoryToMemory => write!(f, "both operands are memory"),
+            BuildErrorKind::ImmediateTooLarge(value, size) => {
+                write!(
+                    f,
+                    "immediate {value} doesn't fit into {} bytes",
+                    size.bytes()
+                )
+            }
+            BuildErrorKind::NotWidening { dest, src } => write!(
+                f,
+                "can't extend {} bytes into {} bytes",
+                src.bytes(),
+                dest.bytes()
+            ),
+            BuildErrorKind::InvalidOperand(src) => write!(f, "invalid operand {src}"),
+        }
+    }
+}
+impl std::error::Error for BuildErrorKind {}
+
+/// checks that `src` can be used together with a `dest` sized destination
+pub fn check_operands(dest: &Destination, src: &Source) -> Result<(), BuildErrorKind> {
+    let size = dest.size();
+    match src {
+        Source::Memory(_) if matches!(dest, Destination::Memory(_)) => {
+            Err(BuildErrorKind::MemoryToMemory)
+        }
+        Source::Int(value) => {
+            let fits = match size {
+                RegisterSize::S8 => i8::try_from(*value).is_ok() || u8::try_from(*value).is_ok(),
+                RegisterSize::S16 => i16::try_from(*value).is_ok() || u16::try_from(*value).is_ok(),
+                RegisterSize::S32 | RegisterSize::S64 => true,
+            };
+            if fits {
+                Ok(())
+            } else {
+                Err(BuildErrorKind::ImmediateTooLarge((*value).into(), size))
+            }
+        }
+        Source::Int64(value) => match dest {
+            Destination::Register(_) if size == RegisterSize::S64 => Ok(()),
+            _ => Err(BuildErrorKind::ImmediateTooLarge(*value, size)),
+        },
+        src => match src.size() {
+            Some(src) if src != size => Err(BuildErrorKind::SizeMismatch { dest: size, src }),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// builds a `Program` instruction by instruction, validating operand sizes along the way:
+/// `ProgramBuilder::function("main").mov(eax(), imm(0)).ret().build()`
+#[derive(Debug, Default)]
+pub struct ProgramBuilder {
+    pub program: Program,
+    pub current: Option<Function>,
+    /// the first invalid instruction, reported by `build`
+    pub error: Option<BuildError>,
+}
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// starts a builder with the function `name`
+    pub fn function(name: impl Into<String>) -> Self {
+        Self::new().begin(name)
+    }
+    /// finishes the current function and starts the function `name`
+    pub fn begin(mut self, name: impl Into<String>) -> Self {
+        self.finish();
+        self.current = Some(Function {
+            name: name.into(),
+            registers: 0,
+            return_type: Type::default(),
+            body: vec![],
+            strings: vec![],
+            tables: vec![],
+            floats: vec![],
+            bytes: vec![],
+            comments: vec![],
+            lines: vec![],
+            local_offsets: vec![],
+        });
+        self
+    }
+    pub fn declare_extern(mut self, name: impl Into<String>) -> Self {
+        self.program.externs.push(name.into());
+        self
+    }
+    fn finish(&mut self) {
+        if let Some(function) = self.current.take() {
+            self.program.functions.push(function);
+        }
+    }
+    fn fail(&mut self, kind: BuildErrorKind) {
+        if self.error.is_some() {
+            return;
+        }
+        let (function, index) = match &self.current {
+            Some(function) => (function.name.clone(), function.body.len()),
+            None => (String::new(), 0),
+        };
+        self.error = Some(BuildError {
+            function,
+            index,
+            kind,
+        });
+    }
+    /// appends `instr` if `check` passed
+    fn emit(mut self, check: Result<(), BuildErrorKind>, instr: Instruction) -> Self {
+        if let Err(kind) = check {
+            self.fail(kind);
+            return self;
+        }
+        match &mut self.current {
+            Some(function) => function.body.push(instr),
+            None => self.fail(BuildErrorKind::NoFunction),
+        }
+        self
+    }
+    /// appends `instr` without validating it
+    pub fn instr(self, instr: Instruction) -> Self {
+        self.emit(Ok(()), instr)
+    }
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        if let Some(function) = &mut self.current {
+            function
+                .comments
+                .push((function.body.len(), comment.into()));
+        }
+        self
+    }
+    pub fn mov(self, dest: impl Into<Destination>, src: impl Into<Source>) -> Self {
+        let (dest, src) = (dest.into(), src.into());
+        self.emit(check_operands(&dest, &src), Instruction::Mov { dest, src })
+    }
+    fn extend(dest: Register, src: &Source) -> Result<(), BuildErrorKind> {
+        match src {
+            Source::Register(_) | Source::Memory(_) => {}
+            src => return Err(BuildErrorKind::InvalidOperand(src.clone())),
+        }
+        match src.size() {
+            Some(size) if size.bytes() < dest.size.bytes() => Ok(()),
+            Some(size) => Err(BuildErrorKind::NotWidening {
+                dest: dest.size,
+                src: size,
+            }),
+            None => Ok(()),
+        }
+    }
+    pub fn movzx(self, dest: Register, src: impl Into<Source>) -> Self {
+        let src = src.into();
+        self.emit(Self::extend(dest, &src), Instruction::Movzx { dest, src })
+    }
+    pub fn movsx(self, dest: Register, src: impl Into<Source>) -> Self {
+        let src = src.into();
+        self.emit(Self::extend(dest, &src), Instruction::Movsx { dest, src })
+    }
+    pub fn lea(self, dest: Register, addr: Address) -> Self {
+        self.instr(Instruction::Lea { dest, addr })
+    }
+    pub fn push(self, src: impl Into<Source>) -> Self {
+        let src = src.into();
+        let check = match src.size() {
+            Some(RegisterSize::S8) => Err(BuildErrorKind::InvalidOperand(src.clone())),
+            _ => Ok(()),
+        };
+        self.emit(check, Instruction::Push { src })
+    }
+    pub fn pop(self, dest: impl Into<Destination>) -> Self {
+        let dest = dest.into();
+        let check = match dest.size() {
+            RegisterSize::S8 => Err(BuildErrorKind::InvalidOperand(dest.clone().into())),
+            _ => Ok(()),
+        };
+        self.emit(check, Instruction::Pop { dest })
+    }
+    pub fn call(self, func: impl Into<Symbol>) -> Self {
+        self.instr(Instruction::Call { func: func.into() })
+    }
+    pub fn leave(self) -> Self {
+        self.instr(Instruction::Leave)
+    }
+    pub fn rep_movsb(self) -> Self {
+        self.instr(Instruction::RepMovsb)
+    }
+    pub fn rep_stosb(self) -> Self {
+        self.instr(Instruction::RepStosb)
+    }
+    pub fn int(self, vector: u8) -> Self {
+        self.instr(Instruction::Interrupt(vector))
+    }
+    pub fn ret(self) -> Self {
+        self.instr(Instruction::Ret)
+    }
+    pub fn label(self, label: impl Into<Symbol>) -> Self {
+        self.instr(Instruction::Label(label.into()))
+    }
+    pub fn jmp(self, label: impl Into<Symbol>) -> Self {
+        self.instr(Instruction::Jmp {
+            label: label.into(),
+        })
+    }
+    pub fn j(self, op: ComparisonOperator, label: impl Into<Symbol>) -> Self {
+        self.instr(Instruction::JOp {
+            op,
+            label: label.into(),
+        })
+    }
+    pub fn cmp(self, a: impl Into<Destination>, b: impl Into<Source>) -> Self {
+        let (a, b) = (a.into(), b.into());
+        self.emit(check_operands(&a, &b), Instruction::Cmp { a: a.into(), b })
+    }
+    pub fn set(self, op: ComparisonOperator, dest: impl Into<Destination>) -> Self {
+        let dest = dest.into();
+        let check = match dest.size() {
+            RegisterSize::S8 => Ok(()),
+            size => Err(BuildErrorKind::SizeMismatch {
+                dest: size,
+                src: RegisterSize::S8,
+            }),
+        };
+        self.emit(check, Instruction::Set { op, dest })
+    }
+    pub fn cmov(self, op: ComparisonOperator, dest: Register, src: impl Into<Source>) -> Self {
+        let src = src.into();
+        let check = check_operands(&dest.into(), &src).and_then(|_| match src {
+            Source::Register(_) | Source::Memory(_) => Ok(()),
+            _ => Err(BuildErrorKind::InvalidOperand(src.clone())),
+        });
+        self.emit(check, Instruction::Cmov { op, dest, src })
+    }
+    pub fn add(self, dest: impl Into<Destination>, src: impl Into<Source>) -> Self {
+        let (dest, src) = (dest.into(), src.into());
+        self.emit(check_operands(&dest, &src), Instruction::Add { dest, src })
+    }
+    pub fn sub(self, dest: impl Into<Destination>, src: impl Into<Source>) -> Self {
+        let (dest, src) = (dest.into(), src.into());
+        self.emit(check_operands(&dest, &src), Instruction::Sub { dest, src })
+    }
+    pub fn and(self, dest: impl Into<Destination>, src: impl Into<Source>) -> Self {
+        let (dest, src) = (dest.into(), src.into());
+        self.emit(check_operands(&dest, &src), Instruction::And { dest, src })
+    }
+    pub fn xchg(self, dest: impl Into<Destination>, src: Register) -> Self {
+        let dest = dest.into();
+        self.emit(
+            check_operands(&dest, &src.into()),
+            Instruction::Xchg { dest, src },
+        )
+    }
+    pub fn xadd(self, lock: bool, dest: impl Into<Destination>, src: Register) -> Self {
+        let dest = dest.into();
+        let check = check_operands(&dest, &src.into());
+        self.emit(check, Instruction::Xadd { lock, dest, src })
+    }
+    pub fn cmpxchg(self, lock: bool, dest: impl Into<Destination>, src: Register) -> Self {
+        let dest = dest.into();
+        let check = check_operands(&dest, &src.into());
+        self.emit(check, Instruction::Cmpxchg { lock, dest, src })
+    }
+    pub fn sar(self, dest: impl Into<Destination>, amount: u8) -> Self {
+        self.instr(Instruction::Sar {
+            dest: dest.into(),
+            amount,
+        })
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn shl(self, dest: impl Into<Destination>, amount: u8) -> Self {
+        self.instr(Instruction::Shl {
+            dest: dest.into(),
+            amount,
+        })
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn shr(self, dest: impl Into<Destination>, amount: u8) -> Self {
+        self.instr(Instruction::Shr {
+            dest: dest.into(),
+            amount,
+        })
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn mul(self, src: impl Into<Source>) -> Self {
+        let src = src.into();
+        let check = match src {
+            Source::Register(_) | Source::Memory(_) => Ok(()),
+            _ => Err(BuildErrorKind::InvalidOperand(src.clone())),
+        };
+        self.emit(check, Instruction::Mul { src })
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn div(self, src: impl Into<Source>) -> Self {
+        let src = src.into();
+        let check = match src {
+            Source::Register(_) | Source::Memory(_) => Ok(()),
+            _ => Err(BuildErrorKind::InvalidOperand(src.clone())),
+        };
+        self.emit(check, Instruction::Div { src })
+    }
+    pub fn idiv(self, src: impl Into<Source>) -> Self {
+        let src = src.into();
+        let check = match src {
+            Source::Register(_) | Source::Memory(_) => Ok(()),
+            _ => Err(BuildErrorKind::InvalidOperand(src.clone())),
+        };
+        self.emit(check, Instruction::Idiv { src })
+    }
+    pub fn cdq(self) -> Self {
+        self.instr(Instruction::Cdq)
+    }
+    pub fn build(mut self) -> Result<Program, BuildError> {
+        self.finish();
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.program),
+        }
+    }
+}
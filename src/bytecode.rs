@@ -0,0 +1,891 @@
+use crate::{
+    code::{
+        string_bytes, Address, ComparisonOperator, Destination, Instruction, Memory, Program,
+        Register, RegisterName, RegisterSize, Source,
+    },
+    intern::Symbol,
+};
+use std::{collections::HashMap, fmt::Display};
+
+/// start of the data image in the VM's address space, everything below is unmapped
+pub const DATA_BASE: u32 = 0x1000;
+/// functions are referred to by `FUNCTION_BASE + index` when their address is taken
+pub const FUNCTION_BASE: u32 = 0xF000_0000;
+/// externs are referred to by `EXTERN_BASE + index` when their address is taken
+pub const EXTERN_BASE: u32 = 0xF800_0000;
+const MAGIC: &[u8; 4] = b"LBC\x01";
+
+/// an operation of the stack machine, operating on an operand stack of 64-bit values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Int(i64),
+    /// pushes the value of a register, zero-extended
+    Reg(Register),
+    /// pops a value into a register, keeping the bytes outside of its size like x86 does
+    SetReg(Register),
+    Dup,
+    Add,
+    /// pops `b` and `a` and pushes `a - b`
+    Sub,
+    And,
+    Mul,
+    /// pops an amount and arithmetically shifts the value below, sign-extended from the size
+    Sar(RegisterSize),
+    /// pops an amount and shifts the value below left
+    Shl,
+    /// pops an amount and shifts the zero-extended value below right
+    Shr,
+    /// sign-extends the top of the stack from the given size
+    SignExtend(RegisterSize),
+    /// pops an address and pushes the zero-extended value stored there
+    Load(RegisterSize),
+    /// pops a value and an address and stores the value there
+    Store(RegisterSize),
+    /// pops a value onto the machine stack at `esp`
+    Push(RegisterSize),
+    /// pushes the value at `esp` and releases it
+    Pop(RegisterSize),
+    /// pops `b` and `a` and compares them for the next `JumpIf` or `Set`
+    Cmp(RegisterSize),
+    /// pops `b` and `a` and pushes `b` if the comparison holds, else `a`
+    Select(ComparisonOperator),
+    /// pops `b` and `a` and sets the flags of adding them, where `Cmp` sets the ones of
+    /// subtracting them
+    AddFlags(RegisterSize),
+    Set(ComparisonOperator),
+    Jmp(u32),
+    JumpIf(ComparisonOperator, u32),
+    /// pops the index of the op to jump to, as stored in a jump table
+    JumpIndirect,
+    /// x86 `mul`, multiplying the accumulator by the popped value
+    WideMul(RegisterSize),
+    /// x86 `div`, dividing the accumulator and `edx` by the popped value
+    WideDiv(RegisterSize),
+    /// x86 `idiv`, `WideDiv` of signed integers
+    SignedDiv(RegisterSize),
+    Call(u32),
+    CallExtern(u32),
+    /// pops a function address as produced by taking the address of a function or extern
+    CallIndirect,
+    /// x86 `int`, with 0x80 making a system call
+    Interrupt(u8),
+    /// pops an address and pushes the float of the size stored there onto the x87 stack
+    FloatLoad(RegisterSize),
+    /// pops an address and pops the top of the x87 stack into it, rounded to the size
+    FloatStore(RegisterSize),
+    Ret,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BytecodeFunction {
+    pub name: String,
+    pub code: Vec<Op>,
+}
+/// a program lowered to stack machine code, with its data laid out at `DATA_BASE`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bytecode {
+    pub functions: Vec<BytecodeFunction>,
+    pub externs: Vec<String>,
+    pub data: Vec<u8>,
+    /// zeroed bytes following the data
+    pub bss: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytecodeError {
+    UnknownSymbol(Symbol),
+    UnknownLabel(Symbol),
+    /// the bytes aren't a valid `.lbc` file
+    Malformed(&'static str),
+}
+impl Display for BytecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BytecodeError::UnknownSymbol(symbol) => write!(f, "unknown symbol {symbol}"),
+            BytecodeError::UnknownLabel(label) => write!(f, "unknown label {label}"),
+            BytecodeError::Malformed(reason) => write!(f, "malformed bytecode: {reason}"),
+        }
+    }
+}
+impl std::error::Error for BytecodeError {}
+
+/// lowers the instructions of a function, with `symbols` holding the value of every name
+struct Lowering<'a> {
+    symbols: &'a HashMap<Symbol, u32>,
+    functions: &'a HashMap<Symbol, u32>,
+    externs: &'a HashMap<Symbol, u32>,
+    code: Vec<Op>,
+}
+impl Lowering<'_> {
+    fn symbol(&self, name: &str) -> Result<i64, BytecodeError> {
+        let symbol = Symbol::intern(name);
+        self.symbols
+            .get(&symbol)
+            .map(|value| *value as i64)
+            .ok_or(BytecodeError::UnknownSymbol(symbol))
+    }
+    fn address(&mut self, address: &Address) -> Result<(), BytecodeError> {
+        let label = match &address.label {
+            Some(label) => self.symbol(label)?,
+            None => 0,
+        };
+        self.code.push(Op::Int(label + address.displacement as i64));
+        if let Some(base) = address.base {
+            self.code.extend([Op::Reg(base), Op::Add]);
+        }
+        if let Some((index, scale)) = address.index {
+            self.code
+                .extend([Op::Reg(index), Op::Int(scale as i64), Op::Mul, Op::Add]);
+        }
+        Ok(())
+    }
+    fn source(&mut self, src: &Source) -> Result<(), BytecodeError> {
+        match src {
+            Source::Register(register) => self.code.push(Op::Reg(*register)),
+            Source::Memory(Memory { data_type, address }) => {
+                self.address(address)?;
+                self.code.push(Op::Load((*data_type).into()));
+            }
+            Source::Int(int) => self.code.push(Op::Int(*int as i64)),
+            Source::Int64(int) => self.code.push(Op::Int(*int)),
+            Source::Amount(amount) => self.code.push(Op::Int(*amount as i64)),
+            Source::Name(name) => {
+                let value = self.symbol(name)?;
+                self.code.push(Op::Int(value));
+            }
+        }
+        Ok(())
+    }
+    /// writes the result of `value` into `dest`
+    fn write(
+        &mut self,
+        dest: &Destination,
+        value: impl FnOnce(&mut Self) -> Result<(), BytecodeError>,
+    ) -> Result<(), BytecodeError> {
+        match dest {
+            Destination::Register(register) => {
+                value(self)?;
+                self.code.push(Op::SetReg(*register));
+            }
+            Destination::Memory(Memory { data_type, address }) => {
+                self.address(address)?;
+                value(self)?;
+                self.code.push(Op::Store((*data_type).into()));
+            }
+        }
+        Ok(())
+    }
+    /// sets the flags `instr` sets if it's arithmetic, only needed when the next instruction
+    /// reads them
+    fn flags(&mut self, instr: &Instruction) -> Result<(), BytecodeError> {
+        let (dest, src, op) = match instr {
+            Instruction::Add { dest, src } => (dest, src, Op::AddFlags(dest.size())),
+            Instruction::Sub { dest, src } => (dest, src, Op::Cmp(dest.size())),
+            _ => return Ok(()),
+        };
+        self.source(&dest.clone().into())?;
+        self.source(src)?;
+        self.code.push(op);
+        Ok(())
+    }
+    /// `dest = dest <op> src`
+    fn binary(&mut self, dest: &Destination, src: &Source, op: Op) -> Result<(), BytecodeError> {
+        match dest {
+            Destination::Register(register) => {
+                self.code.push(Op::Reg(*register));
+                self.source(src)?;
+                self.code.extend([op, Op::SetReg(*register)]);
+            }
+            Destination::Memory(Memory { data_type, address }) => {
+                let size = (*data_type).into();
+                self.address(address)?;
+                self.code.extend([Op::Dup, Op::Load(size)]);
+                self.source(src)?;
+                self.code.extend([op, Op::Store(size)]);
+            }
+        }
+        Ok(())
+    }
+    fn call(&mut self, func: Symbol) -> Result<(), BytecodeError> {
+        if let Some(index) = self.functions.get(&func) {
+            self.code.push(Op::Call(*index));
+        } else if let Some(index) = self.externs.get(&func) {
+            self.code.push(Op::CallExtern(*index));
+        } else {
+            return Err(BytecodeError::UnknownSymbol(func));
+        }
+        Ok(())
+    }
+    fn instruction(&mut self, instr: &Instruction) -> Result<(), BytecodeError> {
+        match instr {
+            Instruction::NOp | Instruction::Label(_) => {}
+            Instruction::Mov { dest, src } => self.write(dest, |lower| lower.source(src))?,
+            Instruction::Movzx { dest, src } => {
+                self.source(src)?;
+                self.code.push(Op::SetReg(*dest));
+            }
+            Instruction::Movsx { dest, src } => {
+                self.source(src)?;
+                let size = src.size().unwrap_or(RegisterSize::S32);
+                self.code.extend([Op::SignExtend(size), Op::SetReg(*dest)]);
+            }
+            Instruction::Lea { dest, addr } => {
+                self.address(addr)?;
+                self.code.push(Op::SetReg(*dest));
+            }
+            Instruction::Push { src } => {
+                self.source(src)?;
+                let size = src.size().unwrap_or(RegisterSize::S32);
+                self.code.push(Op::Push(size));
+            }
+            Instruction::Pop { dest } => {
+                let size = dest.size();
+                self.write(dest, |lower| {
+                    lower.code.push(Op::Pop(size));
+                    Ok(())
+                })?
+            }
+            Instruction::Call { func } => self.call(*func)?,
+            Instruction::CallIndirect(src) => {
+                self.source(src)?;
+                self.code.push(Op::CallIndirect);
+            }
+            Instruction::Leave => self.code.extend([
+                Op::Reg(STACK_BASE),
+                Op::SetReg(STACK_POINTER),
+                Op::Pop(RegisterSize::S32),
+                Op::SetReg(STACK_BASE),
+            ]),
+            Instruction::Ret => self.code.push(Op::Ret),
+            // the targets are patched in once every label is known
+            Instruction::Jmp { .. } => self.code.push(Op::Jmp(0)),
+            Instruction::JOp { op, .. } => self.code.push(Op::JumpIf(*op, 0)),
+            Instruction::JmpIndirect(src) => {
+                self.source(src)?;
+                self.code.push(Op::JumpIndirect);
+            }
+            Instruction::Cmp { a, b } => {
+                self.source(a)?;
+                self.source(b)?;
+                let size = a.size().or(b.size()).unwrap_or(RegisterSize::S32);
+                self.code.push(Op::Cmp(size));
+            }
+            // every operand is read before anything is written, like the instructions do
+            Instruction::Xchg { dest, src } => {
+                self.source(&dest.clone().into())?;
+                self.write(dest, |lower| {
+                    lower.code.push(Op::Reg(*src));
+                    Ok(())
+                })?;
+                self.code.push(Op::SetReg(*src));
+            }
+            Instruction::Xadd { dest, src, .. } => {
+                self.source(&dest.clone().into())?;
+                self.write(dest, |lower| {
+                    lower.source(&dest.clone().into())?;
+                    lower.code.extend([Op::Reg(*src), Op::Add]);
+                    Ok(())
+                })?;
+                self.code.push(Op::SetReg(*src));
+            }
+            Instruction::Cmpxchg { dest, src, .. } => {
+                let size = dest.size();
+                let accumulator = Register {
+                    name: RegisterName::A,
+                    size,
+                };
+                let current: Source = dest.clone().into();
+                self.code.push(Op::Reg(accumulator));
+                self.source(&current)?;
+                self.code.push(Op::Cmp(size));
+                // the accumulator is loaded and `dest` is stored only if they differ
+                self.code.push(Op::Reg(accumulator));
+                self.source(&current)?;
+                self.code.push(Op::Select(ComparisonOperator::NotEqual));
+                self.write(dest, |lower| {
+                    lower.source(&current)?;
+                    lower
+                        .code
+                        .extend([Op::Reg(*src), Op::Select(ComparisonOperator::Equal)]);
+                    Ok(())
+                })?;
+                self.code.push(Op::SetReg(accumulator));
+            }
+            Instruction::Cmov { op, dest, src } => {
+                self.code.push(Op::Reg(*dest));
+                self.source(src)?;
+                self.code.extend([Op::Select(*op), Op::SetReg(*dest)]);
+            }
+            Instruction::Set { op, dest } => self.write(dest, |lower| {
+                lower.code.push(Op::Set(*op));
+                Ok(())
+            })?,
+            Instruction::Add { dest, src } => self.binary(dest, src, Op::Add)?,
+            Instruction::Sub { dest, src } => self.binary(dest, src, Op::Sub)?,
+            Instruction::And { dest, src } => self.binary(dest, src, Op::And)?,
+            Instruction::Sar { dest, amount } => {
+                self.binary(dest, &Source::Int(*amount as i32), Op::Sar(dest.size()))?
+            }
+            Instruction::Shl { dest, amount } => {
+                self.binary(dest, &Source::Int(*amount as i32), Op::Shl)?
+            }
+            Instruction::Shr { dest, amount } => {
+                self.binary(dest, &Source::Int(*amount as i32), Op::Shr)?
+            }
+            Instruction::Mul { src } | Instruction::Div { src } | Instruction::Idiv { src } => {
+                self.source(src)?;
+                let size = src.size().unwrap_or(RegisterSize::S32);
+                self.code.push(match instr {
+                    Instruction::Mul { .. } => Op::WideMul(size),
+                    Instruction::Div { .. } => Op::WideDiv(size),
+                    _ => Op::SignedDiv(size),
+                });
+            }
+            Instruction::Cdq => {
+                let [eax, edx] = [RegisterName::A, RegisterName::D].map(|name| Register {
+                    name,
+                    size: RegisterSize::S32,
+                });
+                self.code.extend([
+                    Op::Reg(eax),
+                    Op::Int(31),
+                    Op::Sar(RegisterSize::S32),
+                    Op::SetReg(edx),
+                ]);
+            }
+            Instruction::RepMovsb | Instruction::RepStosb => self.rep(instr),
+            Instruction::Interrupt(vector) => self.code.push(Op::Interrupt(*vector)),
+            Instruction::Fld { src } => {
+                self.address(&src.address)?;
+                self.code.push(Op::FloatLoad(src.data_type.into()));
+            }
+            Instruction::Fstp { dest } => {
+                self.address(&dest.address)?;
+                self.code.push(Op::FloatStore(dest.data_type.into()));
+            }
+        }
+        Ok(())
+    }
+    /// lowers a `rep` string instruction to a loop, which unlike the instruction sets the flags
+    fn rep(&mut self, instr: &Instruction) {
+        let [count, dest, src] =
+            [RegisterName::C, RegisterName::DI, RegisterName::SI].map(|name| Register {
+                name,
+                size: RegisterSize::S32,
+            });
+        let head = self.code.len() as u32;
+        self.code
+            .extend([Op::Reg(count), Op::Int(0), Op::Cmp(RegisterSize::S32)]);
+        let exit = self.code.len();
+        self.code
+            .extend([Op::JumpIf(ComparisonOperator::Equal, 0), Op::Reg(dest)]);
+        let advanced = match instr {
+            Instruction::RepMovsb => {
+                self.code.extend([Op::Reg(src), Op::Load(RegisterSize::S8)]);
+                vec![dest, src]
+            }
+            _ => {
+                self.code.push(Op::Reg(Register {
+                    name: RegisterName::A,
+                    size: RegisterSize::S8,
+                }));
+                vec![dest]
+            }
+        };
+        self.code.push(Op::Store(RegisterSize::S8));
+        for register in advanced {
+            self.code
+                .extend([Op::Reg(register), Op::Int(1), Op::Add, Op::SetReg(register)]);
+        }
+        self.code
+            .extend([Op::Reg(count), Op::Int(1), Op::Sub, Op::SetReg(count)]);
+        self.code.push(Op::Jmp(head));
+        self.code[exit] = Op::JumpIf(ComparisonOperator::Equal, self.code.len() as u32);
+    }
+}
+const STACK_POINTER: Register = Register {
+    name: RegisterName::SP,
+    size: RegisterSize::S32,
+};
+const STACK_BASE: Register = Register {
+    name: RegisterName::BP,
+    size: RegisterSize::S32,
+};
+
+impl Bytecode {
+    /// lowers a compiled program, which is expected to follow the 32-bit cdecl conventions of
+    /// the compiler
+    pub fn lower(program: &Program) -> Result<Self, BytecodeError> {
+        let mut bytecode = Bytecode {
+            externs: program.externs.clone(),
+            ..Default::default()
+        };
+        let mut symbols = HashMap::new();
+        let mut functions = HashMap::new();
+        let mut externs = HashMap::new();
+        for (index, function) in program.functions.iter().enumerate() {
+            let name = Symbol::intern(&function.name);
+            functions.insert(name, index as u32);
+            symbols.insert(name, FUNCTION_BASE + index as u32);
+            for (idx, string) in function.strings.iter().enumerate() {
+                let label = Symbol::intern(&format!("{}_c{idx}", function.name));
+                symbols.insert(label, DATA_BASE + bytecode.data.len() as u32);
+                bytecode.data.extend(string_bytes(string));
+                bytecode.data.push(0);
+            }
+            for (idx, (data_type, bits)) in function.floats.iter().enumerate() {
+                let label = Symbol::intern(&format!("{}_f{idx}", function.name));
+                symbols.insert(label, DATA_BASE + bytecode.data.len() as u32);
+                let bytes = RegisterSize::from(*data_type).bytes();
+                bytecode
+                    .data
+                    .extend_from_slice(&bits.to_le_bytes()[..bytes]);
+            }
+            for (idx, bytes) in function.bytes.iter().enumerate() {
+                let label = Symbol::intern(&format!("{}_b{idx}", function.name));
+                symbols.insert(label, DATA_BASE + bytecode.data.len() as u32);
+                bytecode.data.extend_from_slice(bytes);
+            }
+        }
+        // jump tables hold code indices, which are only known once their function is lowered
+        let mut tables = vec![];
+        for function in &program.functions {
+            for (idx, table) in function.tables.iter().enumerate() {
+                let label = Symbol::intern(&format!("{}_t{idx}", function.name));
+                symbols.insert(label, DATA_BASE + bytecode.data.len() as u32);
+                tables.push(bytecode.data.len());
+                bytecode
+                    .data
+                    .resize(bytecode.data.len() + table.len() * 4, 0);
+            }
+        }
+        let mut tables = tables.into_iter();
+        for (index, name) in program.externs.iter().enumerate() {
+            let name = Symbol::intern(name);
+            externs.insert(name, index as u32);
+            symbols.insert(name, EXTERN_BASE + index as u32);
+        }
+        for data in &program.data {
+            let bytes = RegisterSize::from(data.data_type).bytes();
+            symbols.insert(
+                Symbol::intern(&data.label),
+                DATA_BASE + bytecode.data.len() as u32,
+            );
+            for value in &data.values {
+                bytecode
+                    .data
+                    .extend_from_slice(&value.to_le_bytes()[..bytes]);
+            }
+        }
+        for (label, bytes) in &program.bss {
+            let address = DATA_BASE + bytecode.data.len() as u32 + bytecode.bss;
+            symbols.insert(Symbol::intern(label), address);
+            bytecode.bss += *bytes as u32;
+        }
+        for function in &program.functions {
+            let mut lowering = Lowering {
+                symbols: &symbols,
+                functions: &functions,
+                externs: &externs,
+                code: vec![],
+            };
+            let mut labels = HashMap::new();
+            // index of every jump with the label it targets
+            let mut jumps = vec![];
+            for (idx, instr) in function.body.iter().enumerate() {
+                let next = function.body.get(idx + 1);
+                if matches!(
+                    next,
+                    Some(
+                        Instruction::JOp { .. }
+                            | Instruction::Set { .. }
+                            | Instruction::Cmov { .. }
+                    )
+                ) {
+                    lowering.flags(instr)?;
+                }
+                match instr {
+                    Instruction::Label(label) => {
+                        labels.insert(*label, lowering.code.len() as u32);
+                    }
+                    Instruction::Jmp { label } | Instruction::JOp { label, .. } => {
+                        jumps.push((lowering.code.len(), *label))
+                    }
+                    _ => {}
+                }
+                lowering.instruction(instr)?;
+            }
+            for (index, label) in jumps {
+                let target = *labels
+                    .get(&label)
+                    .ok_or(BytecodeError::UnknownLabel(label))?;
+                match &mut lowering.code[index] {
+                    Op::Jmp(to) | Op::JumpIf(_, to) => *to = target,
+                    _ => unreachable!(),
+                }
+            }
+            for table in &function.tables {
+                let offset = tables.next().unwrap();
+                for (idx, label) in table.iter().enumerate() {
+                    let target = *labels
+                        .get(label)
+                        .ok_or(BytecodeError::UnknownLabel(*label))?;
+                    let at = offset + idx * 4;
+                    bytecode.data[at..at + 4].copy_from_slice(&target.to_le_bytes());
+                }
+            }
+            bytecode.functions.push(BytecodeFunction {
+                name: function.name.clone(),
+                code: lowering.code,
+            });
+        }
+        Ok(bytecode)
+    }
+    /// the index of the function `name`
+    pub fn function(&self, name: &str) -> Option<u32> {
+        self.functions
+            .iter()
+            .position(|function| function.name == name)
+            .map(|index| index as u32)
+    }
+
+    /// serializes into the `.lbc` format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.blob(&self.data);
+        writer.unsigned(self.bss as u64);
+        writer.unsigned(self.externs.len() as u64);
+        for name in &self.externs {
+            writer.blob(name.as_bytes());
+        }
+        writer.unsigned(self.functions.len() as u64);
+        for function in &self.functions {
+            writer.blob(function.name.as_bytes());
+            writer.unsigned(function.code.len() as u64);
+            for op in &function.code {
+                writer.op(op);
+            }
+        }
+        writer.bytes
+    }
+    /// deserializes the `.lbc` format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+            return Err(BytecodeError::Malformed("not a lerp bytecode file"));
+        };
+        let mut reader = Reader { bytes };
+        let data = reader.blob()?.to_vec();
+        let bss = reader.unsigned()? as u32;
+        let mut externs = vec![];
+        for _ in 0..reader.unsigned()? {
+            externs.push(reader.string()?);
+        }
+        let mut functions = vec![];
+        for _ in 0..reader.unsigned()? {
+            let name = reader.string()?;
+            let mut code = vec![];
+            for _ in 0..reader.unsigned()? {
+                code.push(reader.op()?);
+            }
+            functions.push(BytecodeFunction { name, code });
+        }
+        if !reader.bytes.is_empty() {
+            return Err(BytecodeError::Malformed("trailing bytes"));
+        }
+        Ok(Self {
+            functions,
+            externs,
+            data,
+            bss,
+        })
+    }
+}
+
+const COMPARISONS: [ComparisonOperator; 12] = [
+    ComparisonOperator::Equal,
+    ComparisonOperator::NotEqual,
+    ComparisonOperator::Less,
+    ComparisonOperator::Greater,
+    ComparisonOperator::LessEqual,
+    ComparisonOperator::GreaterEqual,
+    ComparisonOperator::LessUnsigned,
+    ComparisonOperator::GreaterUnsigned,
+    ComparisonOperator::LessEqualUnsigned,
+    ComparisonOperator::GreaterEqualUnsigned,
+    ComparisonOperator::Overflow,
+    ComparisonOperator::NotOverflow,
+];
+const REGISTER_NAMES: [RegisterName; 16] = [
+    RegisterName::A,
+    RegisterName::C,
+    RegisterName::D,
+    RegisterName::B,
+    RegisterName::SP,
+    RegisterName::BP,
+    RegisterName::SI,
+    RegisterName::DI,
+    RegisterName::R8,
+    RegisterName::R9,
+    RegisterName::R10,
+    RegisterName::R11,
+    RegisterName::R12,
+    RegisterName::R13,
+    RegisterName::R14,
+    RegisterName::R15,
+];
+const REGISTER_SIZES: [RegisterSize; 4] = [
+    RegisterSize::S64,
+    RegisterSize::S32,
+    RegisterSize::S16,
+    RegisterSize::S8,
+];
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+impl Writer {
+    /// LEB128
+    fn unsigned(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+            self.bytes.push(byte | 0x80);
+        }
+    }
+    /// zigzag encoded LEB128
+    fn signed(&mut self, value: i64) {
+        self.unsigned(((value << 1) ^ (value >> 63)) as u64);
+    }
+    fn blob(&mut self, bytes: &[u8]) {
+        self.unsigned(bytes.len() as u64);
+        self.bytes.extend_from_slice(bytes);
+    }
+    fn size(&mut self, size: RegisterSize) {
+        self.bytes.push(size as u8);
+    }
+    fn register(&mut self, register: Register) {
+        self.bytes
+            .push((register.name as u8) << 2 | register.size as u8);
+    }
+    fn comparison(&mut self, op: ComparisonOperator) {
+        let index = COMPARISONS.iter().position(|cmp| *cmp == op).unwrap();
+        self.bytes.push(index as u8);
+    }
+    fn op(&mut self, op: &Op) {
+        match *op {
+            Op::Int(int) => {
+                self.bytes.push(0);
+                self.signed(int);
+            }
+            Op::Reg(register) => {
+                self.bytes.push(1);
+                self.register(register);
+            }
+            Op::SetReg(register) => {
+                self.bytes.push(2);
+                self.register(register);
+            }
+            Op::Dup => self.bytes.push(3),
+            Op::Add => self.bytes.push(4),
+            Op::And => self.bytes.push(5),
+            Op::Mul => self.bytes.push(6),
+            Op::SignExtend(size) => {
+                self.bytes.push(7);
+                self.size(size);
+            }
+            Op::Load(size) => {
+                self.bytes.push(8);
+                self.size(size);
+            }
+            Op::Store(size) => {
+                self.bytes.push(9);
+                self.size(size);
+            }
+            Op::Push(size) => {
+                self.bytes.push(10);
+                self.size(size);
+            }
+            Op::Pop(size) => {
+                self.bytes.push(11);
+                self.size(size);
+            }
+            Op::Cmp(size) => {
+                self.bytes.push(12);
+                self.size(size);
+            }
+            Op::Set(op) => {
+                self.bytes.push(13);
+                self.comparison(op);
+            }
+            Op::Jmp(target) => {
+                self.bytes.push(14);
+                self.unsigned(target as u64);
+            }
+            Op::JumpIf(op, target) => {
+                self.bytes.push(15);
+                self.comparison(op);
+                self.unsigned(target as u64);
+            }
+            Op::WideMul(size) => {
+                self.bytes.push(16);
+                self.size(size);
+            }
+            Op::WideDiv(size) => {
+                self.bytes.push(17);
+                self.size(size);
+            }
+            Op::Call(index) => {
+                self.bytes.push(18);
+                self.unsigned(index as u64);
+            }
+            Op::CallExtern(index) => {
+                self.bytes.push(19);
+                self.unsigned(index as u64);
+            }
+            Op::CallIndirect => self.bytes.push(20),
+            Op::Ret => self.bytes.push(21),
+            Op::JumpIndirect => self.bytes.push(22),
+            Op::Sub => self.bytes.push(23),
+            Op::Sar(size) => {
+                self.bytes.push(24);
+                self.size(size);
+            }
+            Op::AddFlags(size) => {
+                self.bytes.push(25);
+                self.size(size);
+            }
+            Op::Select(op) => {
+                self.bytes.push(26);
+                self.comparison(op);
+            }
+            Op::Interrupt(vector) => {
+                self.bytes.push(27);
+                self.bytes.push(vector);
+            }
+            Op::Shl => self.bytes.push(28),
+            Op::Shr => self.bytes.push(29),
+            Op::FloatLoad(size) => {
+                self.bytes.push(30);
+                self.size(size);
+            }
+            Op::FloatStore(size) => {
+                self.bytes.push(31);
+                self.size(size);
+            }
+            Op::SignedDiv(size) => {
+                self.bytes.push(32);
+                self.size(size);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, BytecodeError> {
+        let (byte, rest) = self
+            .bytes
+            .split_first()
+            .ok_or(BytecodeError::Malformed("unexpected end of file"))?;
+        self.bytes = rest;
+        Ok(*byte)
+    }
+    fn unsigned(&mut self) -> Result<u64, BytecodeError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(BytecodeError::Malformed("integer too long"))
+    }
+    fn signed(&mut self) -> Result<i64, BytecodeError> {
+        let value = self.unsigned()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+    fn blob(&mut self) -> Result<&[u8], BytecodeError> {
+        let len = self.unsigned()? as usize;
+        if len > self.bytes.len() {
+            return Err(BytecodeError::Malformed("unexpected end of file"));
+        }
+        let (blob, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(blob)
+    }
+    fn string(&mut self) -> Result<String, BytecodeError> {
+        String::from_utf8(self.blob()?.to_vec())
+            .map_err(|_| BytecodeError::Malformed("invalid utf-8 in a name"))
+    }
+    fn size(&mut self) -> Result<RegisterSize, BytecodeError> {
+        REGISTER_SIZES
+            .get(self.byte()? as usize)
+            .copied()
+            .ok_or(BytecodeError::Malformed("invalid register size"))
+    }
+    fn register(&mut self) -> Result<Register, BytecodeError> {
+        let byte = self.byte()?;
+        Ok(Register {
+            name: *REGISTER_NAMES
+                .get(byte as usize >> 2)
+                .ok_or(BytecodeError::Malformed("invalid register"))?,
+            size: REGISTER_SIZES[byte as usize & 3],
+        })
+    }
+    fn comparison(&mut self) -> Result<ComparisonOperator, BytecodeError> {
+        COMPARISONS
+            .get(self.byte()? as usize)
+            .copied()
+            .ok_or(BytecodeError::Malformed("invalid comparison"))
+    }
+    fn op(&mut self) -> Result<Op, BytecodeError> {
+        Ok(match self.byte()? {
+            0 => Op::Int(self.signed()?),
+            1 => Op::Reg(self.register()?),
+            2 => Op::SetReg(self.register()?),
+            3 => Op::Dup,
+            4 => Op::Add,
+            5 => Op::And,
+            6 => Op::Mul,
+            7 => Op::SignExtend(self.size()?),
+            8 => Op::Load(self.size()?),
+            9 => Op::Store(self.size()?),
+            10 => Op::Push(self.size()?),
+            11 => Op::Pop(self.size()?),
+            12 => Op::Cmp(self.size()?),
+            13 => Op::Set(self.comparison()?),
+            14 => Op::Jmp(self.unsigned()? as u32),
+            15 => {
+                let op = self.comparison()?;
+                Op::JumpIf(op, self.unsigned()? as u32)
+            }
+            16 => Op::WideMul(self.size()?),
+            17 => Op::WideDiv(self.size()?),
+            18 => Op::Call(self.unsigned()? as u32),
+            19 => Op::CallExtern(self.unsigned()? as u32),
+            20 => Op::CallIndirect,
+            21 => Op::Ret,
+            22 => Op::JumpIndirect,
+            23 => Op::Sub,
+            24 => Op::Sar(self.size()?),
+            25 => Op::AddFlags(self.size()?),
+            26 => Op::Select(self.comparison()?),
+            27 => Op::Interrupt(self.byte()?),
+            28 => Op::Shl,
+            29 => Op::Shr,
+            30 => Op::FloatLoad(self.size()?),
+            31 => Op::FloatStore(self.size()?),
+            32 => Op::SignedDiv(self.size()?),
+            _ => return Err(BytecodeError::Malformed("invalid opcode")),
+        })
+    }
+}
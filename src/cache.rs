@@ -0,0 +1,51 @@
+use crate::code::Program;
+use std::{fs, path::PathBuf};
+
+/// the directory the CLI keeps its cache in
+pub const CACHE_DIR: &str = ".lerp-cache";
+
+/// FNV-1a, which unlike `DefaultHasher` hashes the same on every run and every platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StableHasher(u64);
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+impl StableHasher {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+        // separates consecutive writes so `ab`, `c` and `a`, `bc` hash differently
+        self.0 = self.0.wrapping_mul(0x100000001b3);
+    }
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// compiled functions on disk, keyed by a hash of their definition and everything before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cache {
+    pub dir: PathBuf,
+}
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.asm"))
+    }
+    /// the output stored for `key`, an unreadable entry counts as missing
+    pub fn load(&self, key: u64) -> Option<Program> {
+        fs::read_to_string(self.path(key)).ok()?.parse().ok()
+    }
+    /// stores `output` for `key`, the cache only saves work so failing to write it is ignored
+    pub fn store(&self, key: u64, output: &Program) {
+        if fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(self.path(key), output.to_string());
+        }
+    }
+}
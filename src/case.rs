@@ -0,0 +1,351 @@
+use crate::{
+    code::{
+        string_bytes, Address, ComparisonOperator, DataType, Instruction, Memory, Register,
+        RegisterName, RegisterSize, Source,
+    },
+    compiler::{CompileError, Compiler},
+    const_eval::const_eval,
+    intern::Symbol,
+    parser::{Located, Position, SExpr},
+    typ::Type,
+};
+use std::{collections::BTreeMap, fmt::Debug};
+
+/// the fewest arms a jump table is used for, a few comparisons are cheaper than the bounds check
+const MIN_TABLE_ARMS: usize = 4;
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const ESP: Register = Register {
+    name: RegisterName::SP,
+    size: RegisterSize::S32,
+};
+
+/// an arm of a `case`, matching a key or everything the other arms don't
+struct Arm<K> {
+    key: Option<K>,
+    body: Vec<Located<SExpr>>,
+    pos: Position,
+}
+
+impl Compiler {
+    /// the arms of a `case` form, with the `else` arm last if there is one
+    fn parse_arms<K: PartialEq + Debug>(
+        &self,
+        form: &'static str,
+        sexprs: Vec<Located<SExpr>>,
+        key: impl Fn(&Self, Located<SExpr>) -> Result<K, Located<CompileError>>,
+    ) -> Result<Vec<Arm<K>>, Located<CompileError>> {
+        let mut arms: Vec<Arm<K>> = vec![];
+        for sexpr in sexprs {
+            let invalid = Located {
+                value: CompileError::InvalidForm(form),
+                pos: sexpr.pos,
+            };
+            let SExpr::Expr(mut body) = sexpr.value else {
+                return Err(invalid);
+            };
+            if body.is_empty() || arms.last().is_some_and(|arm| arm.key.is_none()) {
+                return Err(invalid);
+            }
+            let head = body.remove(0);
+            if matches!(&head.value, SExpr::Word(word) if word == "else") {
+                arms.push(Arm {
+                    key: None,
+                    body,
+                    pos: sexpr.pos,
+                });
+                continue;
+            }
+            let head_pos = head.pos;
+            let value = key(self, head)?;
+            if arms.iter().any(|arm| arm.key.as_ref() == Some(&value)) {
+                return Err(Located {
+                    value: CompileError::DuplicateCase(format!("{value:?}")),
+                    pos: head_pos,
+                });
+            }
+            arms.push(Arm {
+                key: Some(value),
+                body,
+                pos: sexpr.pos,
+            });
+        }
+        Ok(arms)
+    }
+    /// compiles each arm after its label, running `entry` first and jumping to `end` after it,
+    /// the last arm falls through to `end`
+    ///
+    /// the arms have to agree on the type of their value if `valued`, else the value is none
+    fn compile_arms<K>(
+        &mut self,
+        arms: Vec<(Symbol, Arm<K>)>,
+        end: Symbol,
+        entry: &[Instruction],
+        valued: bool,
+    ) -> Result<Type, Located<CompileError>> {
+        let mut result: Option<Type> = None;
+        let last = arms.len().saturating_sub(1);
+        // every arm starts with the stack as it is here
+        let depth = self.frame().depth;
+        for (idx, (label, arm)) in arms.into_iter().enumerate() {
+            self.write(Instruction::Label(label));
+            self.frame_mut().depth = depth;
+            for instr in entry {
+                self.write(instr.clone());
+            }
+            let typ = self.compile_scoped(arm.body)?;
+            match &result {
+                _ if typ == Type::Never || !valued => {}
+                Some(expected) if *expected != typ => {
+                    return Err(Located {
+                        value: CompileError::InvalidTypeExpected {
+                            expected: expected.clone(),
+                            got: typ,
+                        },
+                        pos: arm.pos,
+                    })
+                }
+                _ => result = Some(typ),
+            }
+            if idx != last {
+                self.write(Instruction::Jmp { label: end });
+            }
+        }
+        self.write(Instruction::Label(end));
+        if !valued {
+            return Ok(Type::None);
+        }
+        Ok(result.unwrap_or(Type::Never))
+    }
+
+    /// `(case x (1 expr...) (2 expr...) (else expr...))`: the value of the arm matching the
+    /// integer `x`, or none without an `else` arm
+    ///
+    /// dense arms jump through a table indexed by `x`, sparse ones are compared one by one
+    pub fn compile_case(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::InvalidForm("case"),
+                pos,
+            });
+        }
+        let scrutinee = sexprs.remove(0);
+        let scrutinee_pos = scrutinee.pos;
+        let arms = self.parse_arms("case", sexprs, |compiler, key| {
+            let value = const_eval(compiler, &key)?;
+            match i32::try_from(value) {
+                Ok(value) => Ok(value),
+                Err(_) => Err(Located {
+                    value: CompileError::OutOfRange(value.into()),
+                    pos: key.pos,
+                }),
+            }
+        })?;
+
+        let typ = self.compile(scrutinee)?;
+        if !matches!(typ, Type::Int(_) | Type::UInt(_)) || self.widen(&typ) != Some(EAX.size) {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: scrutinee_pos,
+            });
+        }
+        let group = self.new_labels();
+        let label = |arm: &str| Symbol::intern(&format!("case{group}_{arm}"));
+        let end = label("end");
+        let has_default = arms.last().is_some_and(|arm| arm.key.is_none());
+        let otherwise = if has_default { label("else") } else { end };
+        let values: Vec<i64> = arms
+            .iter()
+            .filter_map(|arm| arm.key)
+            .map(i64::from)
+            .collect();
+        let (min, max) = (
+            values.iter().copied().min().unwrap_or(0),
+            values.iter().copied().max().unwrap_or(0),
+        );
+        let range = (max - min + 1) as usize;
+        if values.len() >= MIN_TABLE_ARMS && range <= 2 * values.len() {
+            let mut table = vec![otherwise; range];
+            for (idx, value) in values.iter().enumerate() {
+                table[(value - min) as usize] = label(&idx.to_string());
+            }
+            let table = self.new_table(table);
+            if min != 0 {
+                self.write(Instruction::Add {
+                    dest: EAX.into(),
+                    src: Source::Int((-min) as i32),
+                });
+            }
+            // below the minimum wraps around to a large unsigned value
+            self.write(Instruction::Cmp {
+                a: Source::Register(EAX),
+                b: Source::Int(range as i32 - 1),
+            });
+            self.write(Instruction::JOp {
+                op: ComparisonOperator::GreaterUnsigned,
+                label: otherwise,
+            });
+            self.write(Instruction::JmpIndirect(Source::Memory(Memory {
+                data_type: DataType::DoubleWord,
+                address: Address {
+                    index: Some((EAX, 4)),
+                    ..Address::label(table)
+                },
+            })));
+        } else {
+            for (idx, value) in values.iter().enumerate() {
+                self.write(Instruction::Cmp {
+                    a: Source::Register(EAX),
+                    b: Source::Int(*value as i32),
+                });
+                self.write(Instruction::JOp {
+                    op: ComparisonOperator::Equal,
+                    label: label(&idx.to_string()),
+                });
+            }
+            self.write(Instruction::Jmp { label: otherwise });
+        }
+        let arms = arms
+            .into_iter()
+            .enumerate()
+            .map(|(idx, arm)| match arm.key {
+                Some(_) => (label(&idx.to_string()), arm),
+                None => (otherwise, arm),
+            })
+            .collect();
+        self.compile_arms(arms, end, &[], has_default)
+    }
+
+    /// `(case-str s ("add" expr...) ("sub" expr...) (else expr...))`: the value of the arm
+    /// whose string equals `s`, or none without an `else` arm
+    ///
+    /// the length of `s` picks the arms worth comparing, which `memcmp` then compares
+    pub fn compile_case_str(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::InvalidForm("case-str"),
+                pos,
+            });
+        }
+        let scrutinee = sexprs.remove(0);
+        let scrutinee_pos = scrutinee.pos;
+        let mut arms = self.parse_arms("case-str", sexprs, |_, key| match key.value {
+            SExpr::String(string) => Ok(string),
+            _ => Err(Located {
+                value: CompileError::InvalidForm("case-str"),
+                pos: key.pos,
+            }),
+        })?;
+
+        let typ = self.compile(scrutinee)?;
+        if !typ.is_string() {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: scrutinee_pos,
+            });
+        }
+        let group = self.new_labels();
+        let label = |arm: &str| Symbol::intern(&format!("case{group}_{arm}"));
+        let (end, otherwise) = (label("end"), label("else"));
+        let has_default = arms.last().is_some_and(|arm| arm.key.is_none());
+        if !has_default {
+            // the string still has to be released when nothing matches
+            arms.push(Arm {
+                key: None,
+                body: vec![],
+                pos,
+            });
+        }
+        // `s` stays on the stack until an arm is chosen
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        self.use_extern("strlen");
+        self.call("strlen", 4);
+        let mut lengths: BTreeMap<usize, Vec<(usize, String)>> = BTreeMap::new();
+        for (idx, arm) in arms.iter().enumerate() {
+            if let Some(string) = &arm.key {
+                lengths
+                    .entry(string_bytes(string).len())
+                    .or_default()
+                    .push((idx, string.clone()));
+            }
+        }
+        if !lengths.is_empty() {
+            self.use_extern("memcmp");
+        }
+        for (len, strings) in lengths {
+            // every empty string is equal, the other ones have to be compared
+            let (op, target) = match strings.as_slice() {
+                [(idx, _)] if len == 0 => (ComparisonOperator::Equal, label(&idx.to_string())),
+                _ => (ComparisonOperator::NotEqual, label(&format!("not{len}"))),
+            };
+            self.write(Instruction::Cmp {
+                a: Source::Register(EAX),
+                b: Source::Int(len as i32),
+            });
+            self.write(Instruction::JOp { op, label: target });
+            if len == 0 {
+                continue;
+            }
+            for (idx, string) in strings {
+                let arm = label(&idx.to_string());
+                let constant = self.new_string(string);
+                self.write(Instruction::Push {
+                    src: Source::Int(len as i32),
+                });
+                self.write(Instruction::Push {
+                    src: Source::Name(constant),
+                });
+                self.write(Instruction::Push {
+                    src: Source::Memory(Memory {
+                        data_type: DataType::DoubleWord,
+                        address: Address {
+                            base: Some(ESP),
+                            ..Address::at(8)
+                        },
+                    }),
+                });
+                self.call("memcmp", 12);
+                self.write(Instruction::Cmp {
+                    a: Source::Register(EAX),
+                    b: Source::Int(0),
+                });
+                self.write(Instruction::JOp {
+                    op: ComparisonOperator::Equal,
+                    label: arm,
+                });
+            }
+            self.write(Instruction::Jmp { label: otherwise });
+            self.write(Instruction::Label(target));
+        }
+        self.write(Instruction::Jmp { label: otherwise });
+        let arms = arms
+            .into_iter()
+            .enumerate()
+            .map(|(idx, arm)| match arm.key {
+                Some(_) => (label(&idx.to_string()), arm),
+                None => (otherwise, arm),
+            })
+            .collect();
+        let release = Instruction::Add {
+            dest: ESP.into(),
+            src: Source::Amount(4),
+        };
+        self.compile_arms(arms, end, &[release], has_default)
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    compiler::CompileError,
+    options::CompileOptions,
+    parser::{Located, SExpr},
+};
+
+/// the condition and body of `(cfg condition body...)` if `sexpr` is one
+fn cfg_form(sexpr: &Located<SExpr>) -> Option<(&Located<SExpr>, &[Located<SExpr>])> {
+    let SExpr::Expr(sexprs) = &sexpr.value else {
+        return None;
+    };
+    match sexprs.as_slice() {
+        [Located {
+            value: SExpr::Word(head),
+            ..
+        }, condition, body @ ..]
+            if head == "cfg" =>
+        {
+            Some((condition, body))
+        }
+        _ => None,
+    }
+}
+
+/// whether the `cfg` condition `sexpr` holds: `(target name)` for the architecture or operating
+/// system, `(feature "name")` for a feature enabled with `-D`, and `not`, `all` and `any` of
+/// other conditions
+pub fn holds(
+    sexpr: &Located<SExpr>,
+    options: &CompileOptions,
+) -> Result<bool, Located<CompileError>> {
+    let invalid = Located {
+        value: CompileError::InvalidForm("cfg"),
+        pos: sexpr.pos,
+    };
+    let SExpr::Expr(sexprs) = &sexpr.value else {
+        return Err(invalid);
+    };
+    let Some((
+        Located {
+            value: SExpr::Word(head),
+            ..
+        },
+        args,
+    )) = sexprs.split_first()
+    else {
+        return Err(invalid);
+    };
+    match (head.as_str(), args) {
+        (
+            "target",
+            [Located {
+                value: SExpr::Word(name),
+                ..
+            }],
+        ) => Ok(options.target.matches(name.as_str())),
+        (
+            "feature",
+            [Located {
+                value: SExpr::String(name),
+                ..
+            }],
+        ) => Ok(options.features.contains(name)),
+        ("not", [condition]) => Ok(!holds(condition, options)?),
+        ("all", conditions) => conditions
+            .iter()
+            .try_fold(true, |all, condition| Ok(all && holds(condition, options)?)),
+        ("any", conditions) => {
+            conditions.iter().try_fold(
+                false,
+                |any, condition| Ok(any || holds(condition, options)?),
+            )
+        }
+        _ => Err(invalid),
+    }
+}
+
+/// replaces every `(cfg condition body...)` in `sexprs` with its body if the condition holds
+/// and drops it otherwise, quoted expressions are left alone
+pub fn prune(
+    sexprs: Vec<Located<SExpr>>,
+    options: &CompileOptions,
+) -> Result<Vec<Located<SExpr>>, Located<CompileError>> {
+    let mut pruned = vec![];
+    for sexpr in sexprs {
+        if let Some((condition, body)) = cfg_form(&sexpr) {
+            if holds(condition, options)? {
+                pruned.extend(prune(body.to_vec(), options)?);
+            }
+            continue;
+        }
+        let Located { value, pos } = sexpr;
+        let value = match value {
+            SExpr::Expr(sexprs) => SExpr::Expr(prune(sexprs, options)?),
+            SExpr::Bracket(sexprs) => SExpr::Bracket(prune(sexprs, options)?),
+            SExpr::Brace(sexprs) => SExpr::Brace(prune(sexprs, options)?),
+            value => value,
+        };
+        pruned.push(Located { value, pos });
+    }
+    Ok(pruned)
+}
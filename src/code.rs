@@ -1,24 +1,164 @@
-use crate::typ::{FloatType, IntType, Type};
+use crate::{
+    intern::Symbol,
+    parser::{FileId, Position},
+    typ::{FloatType, IntType, Type},
+};
 use std::{fmt::Display, str::FromStr};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Program {
     pub functions: Vec<Function>,
     pub externs: Vec<String>,
+    /// libraries the program is linked against, from `(link name)`
+    pub libraries: Vec<String>,
+    /// functions visible to other objects besides `main`, from `(export name)`
+    pub exports: Vec<String>,
+    /// source files referenced by `%line` directives when debug info is emitted, indexed by
+    /// `FileId`
+    pub sources: Vec<String>,
+    /// initialized data emitted into `.data`
+    pub data: Vec<Data>,
+    /// zero-initialized data reserved in `.bss` as `(label, bytes)`
+    pub bss: Vec<(String, usize)>,
 }
 impl Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // nasm ignores these, they tell whoever links the object what it needs
+        for library in &self.libraries {
+            writeln!(f, "; link {library}")?;
+        }
         for name in &self.externs {
             writeln!(f, "extern {name}")?;
         }
         writeln!(f, "global main")?;
+        for name in &self.exports {
+            writeln!(f, "global {name}")?;
+        }
         writeln!(f, "section .text")?;
         for function in &self.functions {
-            write!(f, "{function}")?;
+            function.fmt_with_source(f, &self.sources)?;
+        }
+        self.fmt_data(f)
+    }
+}
+impl Program {
+    /// adds the source line every run of instructions was generated from as a comment above
+    /// it, `texts` being the source files indexed by `FileId`, see `--asm-source`
+    pub fn interleave_source(&mut self, texts: &[String]) {
+        for function in &mut self.functions {
+            let mut comments: Vec<(usize, String)> = vec![];
+            for (addr, pos) in &function.lines {
+                let Some(text) = texts
+                    .get(pos.file.0 as usize)
+                    .and_then(|text| text.lines().nth(pos.ln))
+                else {
+                    continue;
+                };
+                // a line generating no instructions is followed by the one which does
+                if comments.last().is_some_and(|(last, _)| last == addr) {
+                    comments.pop();
+                }
+                comments.push((*addr, format!("{}: {}", pos.ln + 1, text.trim())));
+            }
+            // a source line goes above the comments of its first instruction
+            comments.append(&mut function.comments);
+            comments.sort_by_key(|(addr, _)| *addr);
+            function.comments = comments;
+        }
+    }
+    /// writes the sections after `.text`
+    fn fmt_data(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.functions.iter().any(|function| {
+            !function.tables.is_empty() || !function.floats.is_empty() || !function.bytes.is_empty()
+        }) {
+            writeln!(f, "section .rodata")?;
+            for function in &self.functions {
+                for (idx, table) in function.tables.iter().enumerate() {
+                    let labels = table
+                        .iter()
+                        .map(|label| format!("{}.{label}", function.name))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    writeln!(f, "{}_t{idx} dd {labels}", function.name)?;
+                }
+                for (idx, (data_type, bits)) in function.floats.iter().enumerate() {
+                    let directive = data_type.directive();
+                    writeln!(f, "{}_f{idx} {directive} {bits:#x}", function.name)?;
+                }
+                for (idx, bytes) in function.bytes.iter().enumerate() {
+                    // an empty byte string is just its label
+                    if bytes.is_empty() {
+                        writeln!(f, "{}_b{idx}:", function.name)?;
+                        continue;
+                    }
+                    let bytes = bytes
+                        .iter()
+                        .map(|byte| byte.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    writeln!(f, "{}_b{idx} db {bytes}", function.name)?;
+                }
+            }
+        }
+        if !self.data.is_empty() {
+            writeln!(f, "section .data")?;
+            for data in &self.data {
+                writeln!(f, "{data}")?;
+            }
+        }
+        if !self.bss.is_empty() {
+            writeln!(f, "section .bss")?;
+            for (label, bytes) in &self.bss {
+                writeln!(f, "{label} resb {bytes}")?;
+            }
         }
         Ok(())
     }
 }
+/// a program written for `nasm -f bin`, for boot sectors and kernels: loaded at `org` and
+/// entered at its first byte, which is `main`, without externs or symbols for a linker
+#[derive(Debug, Clone, Copy)]
+pub struct FlatBinary<'a> {
+    pub program: &'a Program,
+    pub org: u32,
+}
+impl Display for FlatBinary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "bits 32")?;
+        writeln!(f, "org {:#x}", self.org)?;
+        writeln!(f, "section .text")?;
+        let (main, functions): (Vec<&Function>, Vec<&Function>) = self
+            .program
+            .functions
+            .iter()
+            .partition(|function| function.name == "main");
+        for function in main.into_iter().chain(functions) {
+            function.fmt_with_source(f, &self.program.sources)?;
+        }
+        self.program.fmt_data(f)
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Data {
+    pub label: String,
+    pub data_type: DataType,
+    pub values: Vec<i64>,
+}
+impl Display for Data {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.label,
+            self.data_type.directive(),
+            self.values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
 #[derive(Debug, Clone, PartialEq)]
 pub struct Function {
     pub name: String,
@@ -26,19 +166,122 @@ pub struct Function {
     pub return_type: Type,
     pub body: Vec<Instruction>,
     pub strings: Vec<String>,
+    /// jump tables of labels in the function, read by `JmpIndirect`s through `{name}_t{idx}`
+    pub tables: Vec<Vec<Symbol>>,
+    /// float constants in `.rodata` as their bits, read through `{name}_f{idx}`
+    pub floats: Vec<(DataType, u64)>,
+    /// byte strings in `.rodata` without a terminating zero, read through `{name}_b{idx}`
+    pub bytes: Vec<Vec<u8>>,
+    /// comments emitted above the instruction at the given body index
+    pub comments: Vec<(usize, String)>,
+    /// source lines the instructions starting at the given body index were generated from
+    pub lines: Vec<(usize, Position)>,
+    /// locals as `{name}_v{idx}` labels with their offsets from the base pointer, defined with
+    /// `equ`
+    ///
+    /// these are plain symbols, not DWARF variable locations: a debugger can't print a local by
+    /// its name, only read it at `$ebp` plus the value of its symbol
+    pub local_offsets: Vec<(String, i32)>,
 }
-impl Display for Function {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Function {
+    /// whether the function has strings or other constants of its own, which the labels of are
+    /// named after it
+    pub fn has_constants(&self) -> bool {
+        !self.strings.is_empty() || !self.floats.is_empty() || !self.bytes.is_empty()
+    }
+    /// writes the function, mapping instructions back to `source` with `%line` directives
+    pub fn fmt_with_source(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        sources: &[String],
+    ) -> std::fmt::Result {
         writeln!(f, "{}:", self.name)?;
-        for instr in &self.body {
+        let mut comments = self.comments.iter().peekable();
+        let mut lines = self.lines.iter().peekable();
+        for (addr, instr) in self.body.iter().enumerate() {
+            let mut line = None;
+            while let Some((_, pos)) = lines.next_if(|(at, _)| *at == addr) {
+                line = Some(pos);
+            }
+            let source = line.and_then(|pos| sources.get(pos.file.0 as usize));
+            if let (Some(pos), Some(source)) = (line, source) {
+                writeln!(f, "%line {}+0 {source}", pos.ln + 1)?;
+            }
+            while let Some((_, comment)) = comments.next_if(|(at, _)| *at == addr) {
+                writeln!(f, "\t; {comment}")?;
+            }
             writeln!(f, "{instr}")?;
         }
+        for (label, offset) in &self.local_offsets {
+            writeln!(f, "{label} equ {offset}")?;
+        }
         for (idx, string) in self.strings.iter().enumerate() {
-            write!(f, "{}_c{idx} db `{string}`, 0", self.name)?;
+            // raw strings may contain line breaks which have to be escaped for nasm
+            let string = string
+                .replace('`', "\\`")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")
+                .replace('\t', "\\t");
+            writeln!(f, "{}_c{idx} db `{string}`, 0", self.name)?;
         }
         Ok(())
     }
 }
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with_source(f, &[])
+    }
+}
+/// the bytes nasm assembles a string constant into, resolving its backslash escapes
+pub fn string_bytes(string: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut chars = string.bytes().peekable();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            bytes.push(byte);
+            break;
+        };
+        bytes.push(match escape {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b't' => b'\t',
+            b'n' => b'\n',
+            b'v' => 0x0B,
+            b'f' => 0x0C,
+            b'r' => b'\r',
+            b'e' => 0x1B,
+            b'x' => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| (*c as char).to_digit(16)) {
+                        Some(digit) => {
+                            value = value * 16 + digit as u8;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                value
+            }
+            b'0'..=b'7' => {
+                let mut value = escape - b'0';
+                for _ in 0..2 {
+                    match chars.next_if(|c| (b'0'..=b'7').contains(c)) {
+                        Some(digit) => value = value.wrapping_mul(8) + (digit - b'0'),
+                        None => break,
+                    }
+                }
+                value
+            }
+            other => other,
+        });
+    }
+    bytes
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[repr(u8)]
@@ -133,7 +376,8 @@ impl RegisterSize {
                 FloatType::S32 => Some(Self::S32),
                 FloatType::S64 => Some(Self::S64),
             },
-            Type::Array { typ, size: _ } => Self::typ(typ.as_ref()),
+            // arrays are passed around by their address
+            Type::Array { .. } | Type::Func { .. } | Type::Pointer(_) => Some(Self::S32),
             _ => None,
         }
     }
@@ -178,16 +422,22 @@ impl Display for Register {
             | RegisterName::R13
             | RegisterName::R14
             | RegisterName::R15 => match self.size {
-                RegisterSize::S64 => write!(f, "r{}", self.name),
-                RegisterSize::S32 => write!(f, "r{}d", self.name),
-                RegisterSize::S16 => write!(f, "r{}w", self.name),
-                RegisterSize::S8 => write!(f, "r{}b", self.name),
+                RegisterSize::S64 => write!(f, "{}", self.name),
+                RegisterSize::S32 => write!(f, "{}d", self.name),
+                RegisterSize::S16 => write!(f, "{}w", self.name),
+                RegisterSize::S8 => write!(f, "{}b", self.name),
             },
         }
     }
 }
 #[derive(Debug, Clone, PartialEq)]
 pub struct InvalidRegister;
+impl Display for InvalidRegister {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid register")
+    }
+}
+impl std::error::Error for InvalidRegister {}
 impl FromStr for Register {
     type Err = InvalidRegister;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -320,6 +570,22 @@ impl FromStr for Register {
                 name: RegisterName::DI,
                 size: RegisterSize::S8,
             }),
+            "r8" => Ok(Self {
+                name: RegisterName::R8,
+                size: RegisterSize::S64,
+            }),
+            "r8d" => Ok(Self {
+                name: RegisterName::R8,
+                size: RegisterSize::S32,
+            }),
+            "r8w" => Ok(Self {
+                name: RegisterName::R8,
+                size: RegisterSize::S16,
+            }),
+            "r8b" => Ok(Self {
+                name: RegisterName::R8,
+                size: RegisterSize::S8,
+            }),
             "r9" => Ok(Self {
                 name: RegisterName::R9,
                 size: RegisterSize::S64,
@@ -436,60 +702,103 @@ impl FromStr for Register {
         }
     }
 }
+/// a memory address of the form `[base + index*scale + label + displacement]`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Address {
+    pub base: Option<Register>,
+    pub index: Option<(Register, u8)>,
+    pub label: Option<String>,
+    pub displacement: i32,
+}
+impl Address {
+    pub fn at(displacement: i32) -> Self {
+        Self {
+            displacement,
+            ..Default::default()
+        }
+    }
+    pub fn register(base: Register) -> Self {
+        Self {
+            base: Some(base),
+            ..Default::default()
+        }
+    }
+    pub fn offset(base: Register, displacement: i32) -> Self {
+        Self {
+            base: Some(base),
+            displacement,
+            ..Default::default()
+        }
+    }
+    pub fn label(label: String) -> Self {
+        Self {
+            label: Some(label),
+            ..Default::default()
+        }
+    }
+    pub fn element(base: Register, index: Register, scale: u8) -> Self {
+        Self {
+            base: Some(base),
+            index: Some((index, scale)),
+            ..Default::default()
+        }
+    }
+}
+impl Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if let Some(base) = self.base {
+            parts.push(base.to_string());
+        }
+        if let Some((index, scale)) = self.index {
+            parts.push(format!("{index}*{scale}"));
+        }
+        if let Some(label) = &self.label {
+            parts.push(label.clone());
+        }
+        write!(f, "[{}", parts.join("+"))?;
+        if parts.is_empty() {
+            write!(f, "{}", self.displacement)?;
+        } else if self.displacement > 0 {
+            write!(f, "+{}", self.displacement)?;
+        } else if self.displacement < 0 {
+            write!(f, "{}", self.displacement)?;
+        }
+        write!(f, "]")
+    }
+}
+/// a sized memory operand, shared by [`Source`] and [`Destination`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memory {
+    pub data_type: DataType,
+    pub address: Address,
+}
+impl Display for Memory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} PTR {}", self.data_type, self.address)
+    }
+}
 #[derive(Debug, Clone, PartialEq)]
 pub enum Destination {
     Register(Register),
-    Memory {
-        data_type: DataType,
-        at: usize,
-    },
-    MemoryRegister {
-        data_type: DataType,
-        register: Register,
-    },
-    MemoryOffset {
-        data_type: DataType,
-        register: Register,
-        offset: usize,
-        scale: usize,
-    },
+    Memory(Memory),
 }
 impl Display for Destination {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Destination::Register(register) => write!(f, "{register}"),
-            Destination::Memory { data_type, at } => write!(f, "{data_type} PTR [{at}]"),
-            Destination::MemoryRegister {
-                data_type,
-                register,
-            } => write!(f, "{data_type} PTR [{register}]"),
-            Destination::MemoryOffset {
-                data_type,
-                register,
-                offset,
-                scale,
-            } => write!(f, "{data_type} PTR [{register}+{offset}*{scale}]"),
+            Destination::Memory(memory) => write!(f, "{memory}"),
         }
     }
 }
 #[derive(Debug, Clone, PartialEq)]
 pub enum Source {
     Register(Register),
-    Memory {
-        data_type: DataType,
-        at: usize,
-    },
-    MemoryRegister {
-        data_type: DataType,
-        register: Register,
-    },
-    MemoryOffset {
-        data_type: DataType,
-        register: Register,
-        offset: usize,
-        scale: usize,
-    },
+    Memory(Memory),
     Int(i32),
+    /// only taken by a `mov` into a 64-bit register, the compiler loads 64-bit values into the
+    /// D and A register pair as two halves instead
+    Int64(i64),
     Name(String),
     Amount(usize),
 }
@@ -497,46 +806,67 @@ impl Display for Source {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Source::Register(register) => write!(f, "{register}"),
-            Source::Memory { data_type, at } => write!(f, "{data_type} PTR [{at}]"),
-            Source::MemoryRegister {
-                data_type,
-                register,
-            } => write!(f, "{data_type} PTR [{register}]"),
-            Source::MemoryOffset {
-                data_type,
-                register,
-                offset,
-                scale,
-            } => write!(f, "{data_type} PTR [{register}+{offset}*{scale}]"),
+            Source::Memory(memory) => write!(f, "{memory}"),
             Source::Name(name) => write!(f, "{name}"),
-            Source::Int(int) => write!(f, "${int}"),
+            Source::Int(int) => write!(f, "{int}"),
+            Source::Int64(int) => write!(f, "{int}"),
             Source::Amount(amount) => write!(f, "{amount}"),
         }
     }
 }
+impl Destination {
+    pub fn size(&self) -> RegisterSize {
+        match self {
+            Destination::Register(register) => register.size,
+            Destination::Memory(memory) => memory.data_type.into(),
+        }
+    }
+}
+impl Source {
+    /// the operand size, immediates and names adapt to the other operand
+    pub fn size(&self) -> Option<RegisterSize> {
+        match self {
+            Source::Register(register) => Some(register.size),
+            Source::Memory(memory) => Some(memory.data_type.into()),
+            Source::Int(_) | Source::Int64(_) | Source::Name(_) | Source::Amount(_) => None,
+        }
+    }
+}
+impl From<Register> for Destination {
+    fn from(value: Register) -> Self {
+        Self::Register(value)
+    }
+}
+impl From<Memory> for Destination {
+    fn from(value: Memory) -> Self {
+        Self::Memory(value)
+    }
+}
+impl From<Register> for Source {
+    fn from(value: Register) -> Self {
+        Self::Register(value)
+    }
+}
+impl From<Memory> for Source {
+    fn from(value: Memory) -> Self {
+        Self::Memory(value)
+    }
+}
 impl From<Destination> for Source {
     fn from(value: Destination) -> Self {
         match value {
             Destination::Register(register) => Self::Register(register),
-            Destination::Memory { data_type, at } => Self::Memory { data_type, at },
-            Destination::MemoryRegister {
-                data_type,
-                register,
-            } => Self::MemoryRegister {
-                data_type,
-                register,
-            },
-            Destination::MemoryOffset {
-                data_type,
-                register,
-                offset,
-                scale,
-            } => Self::MemoryOffset {
-                data_type,
-                register,
-                offset,
-                scale,
-            },
+            Destination::Memory(memory) => Self::Memory(memory),
+        }
+    }
+}
+impl TryFrom<Source> for Destination {
+    type Error = Source;
+    fn try_from(value: Source) -> Result<Self, Self::Error> {
+        match value {
+            Source::Register(register) => Ok(Self::Register(register)),
+            Source::Memory(memory) => Ok(Self::Memory(memory)),
+            src => Err(src),
         }
     }
 }
@@ -547,9 +877,20 @@ pub enum DataType {
     DoubleWord,
     QuadWord,
 }
-impl Into<RegisterSize> for DataType {
-    fn into(self) -> RegisterSize {
+impl DataType {
+    /// the directive declaring initialized data of this size
+    pub fn directive(&self) -> &'static str {
         match self {
+            DataType::Byte => "db",
+            DataType::Word => "dw",
+            DataType::DoubleWord => "dd",
+            DataType::QuadWord => "dq",
+        }
+    }
+}
+impl From<DataType> for RegisterSize {
+    fn from(value: DataType) -> Self {
+        match value {
             DataType::Byte => RegisterSize::S8,
             DataType::Word => RegisterSize::S16,
             DataType::DoubleWord => RegisterSize::S32,
@@ -557,6 +898,16 @@ impl Into<RegisterSize> for DataType {
         }
     }
 }
+impl From<RegisterSize> for DataType {
+    fn from(value: RegisterSize) -> Self {
+        match value {
+            RegisterSize::S8 => DataType::Byte,
+            RegisterSize::S16 => DataType::Word,
+            RegisterSize::S32 => DataType::DoubleWord,
+            RegisterSize::S64 => DataType::QuadWord,
+        }
+    }
+}
 impl Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -577,6 +928,18 @@ pub enum Instruction {
         dest: Destination,
         src: Source,
     },
+    Movzx {
+        dest: Register,
+        src: Source,
+    },
+    Movsx {
+        dest: Register,
+        src: Source,
+    },
+    Lea {
+        dest: Register,
+        addr: Address,
+    },
 
     Push {
         src: Source,
@@ -585,52 +948,161 @@ pub enum Instruction {
         dest: Destination,
     },
     Call {
-        func: String,
+        func: Symbol,
     },
+    CallIndirect(Source),
     Leave,
     Ret,
 
-    Label(String),
+    Label(Symbol),
     Jmp {
-        label: String,
+        label: Symbol,
     },
+    /// jumps to the address in `src`, which is read from one of the function's tables
+    JmpIndirect(Source),
     JOp {
         op: ComparisonOperator,
-        label: String,
+        label: Symbol,
     },
     Cmp {
         a: Source,
         b: Source,
     },
+    Set {
+        op: ComparisonOperator,
+        dest: Destination,
+    },
+    /// moves `src` into `dest` if the comparison holds
+    Cmov {
+        op: ComparisonOperator,
+        dest: Register,
+        src: Source,
+    },
 
     Add {
         dest: Destination,
         src: Source,
     },
+    Sub {
+        dest: Destination,
+        src: Source,
+    },
+    And {
+        dest: Destination,
+        src: Source,
+    },
+    /// arithmetic shift right by an immediate amount
+    Sar {
+        dest: Destination,
+        amount: u8,
+    },
+    /// shift left by an immediate amount
+    Shl {
+        dest: Destination,
+        amount: u8,
+    },
+    /// logical shift right by an immediate amount, shifting in zeros
+    Shr {
+        dest: Destination,
+        amount: u8,
+    },
+    /// swaps `dest` and `src`, atomically if `dest` is memory
+    Xchg {
+        dest: Destination,
+        src: Register,
+    },
+    /// adds `src` to `dest` and sets `src` to what `dest` was
+    Xadd {
+        lock: bool,
+        dest: Destination,
+        src: Register,
+    },
+    /// stores `src` in `dest` if `dest` equals the A register, else loads `dest` into it
+    Cmpxchg {
+        lock: bool,
+        dest: Destination,
+        src: Register,
+    },
     Mul {
         src: Source,
     },
     Div {
         src: Source,
     },
+    /// divides the D and A register pair by `src` like `Div`, as signed integers
+    Idiv {
+        src: Source,
+    },
+    /// sign-extends the A register into the D register, for an `Idiv`
+    Cdq,
+    /// copies as many bytes as the C register says from where the SI register points to where
+    /// the DI register points, advancing both and counting the C register down to zero
+    RepMovsb,
+    /// stores the low byte of the A register into as many bytes as the C register says from
+    /// where the DI register points, advancing it and counting the C register down to zero
+    RepStosb,
+    /// raises the software interrupt with the given vector, 0x80 making a Linux system call
+    Interrupt(u8),
+    /// pushes the float `src`, a dword or qword, onto the x87 stack
+    Fld {
+        src: Memory,
+    },
+    /// pops the top of the x87 stack into `dest`, rounding it to a dword or qword float
+    Fstp {
+        dest: Memory,
+    },
 }
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Instruction::NOp => write!(f, "\tnop"),
             Instruction::Mov { dest, src } => write!(f, "\tmov {dest}, {src}"),
+            Instruction::Movzx { dest, src } => write!(f, "\tmovzx {dest}, {src}"),
+            Instruction::Movsx { dest, src } => write!(f, "\tmovsx {dest}, {src}"),
+            Instruction::Lea { dest, addr } => write!(f, "\tlea {dest}, {addr}"),
             Instruction::Push { src } => write!(f, "\tpush {src}"),
             Instruction::Pop { dest } => write!(f, "\tpop {dest}"),
             Instruction::Call { func } => write!(f, "\tcall {func}"),
+            Instruction::CallIndirect(src) => write!(f, "\tcall {src}"),
             Instruction::Leave => write!(f, "\tleave"),
             Instruction::Ret => write!(f, "\tret"),
             Instruction::Label(label) => write!(f, ".{label}:"),
-            Instruction::Jmp { label } => write!(f, "\tjmp {label}"),
-            Instruction::JOp { op, label } => write!(f, "\tj{op} {label}"),
+            Instruction::Jmp { label } => write!(f, "\tjmp .{label}"),
+            Instruction::JmpIndirect(src) => write!(f, "\tjmp {src}"),
+            Instruction::JOp { op, label } => write!(f, "\tj{op} .{label}"),
             Instruction::Cmp { a, b } => write!(f, "\tcmp {a}, {b}"),
+            Instruction::Set { op, dest } => write!(f, "\tset{op} {dest}"),
+            Instruction::Cmov { op, dest, src } => write!(f, "\tcmov{op} {dest}, {src}"),
             Instruction::Add { dest, src } => write!(f, "\tadd {dest}, {src}"),
+            Instruction::Sub { dest, src } => write!(f, "\tsub {dest}, {src}"),
+            Instruction::And { dest, src } => write!(f, "\tand {dest}, {src}"),
+            Instruction::Sar { dest, amount } => write!(f, "\tsar {dest}, {amount}"),
+            Instruction::Shl { dest, amount } => write!(f, "\tshl {dest}, {amount}"),
+            Instruction::Shr { dest, amount } => write!(f, "\tshr {dest}, {amount}"),
+            Instruction::Xchg { dest, src } => write!(f, "\txchg {dest}, {src}"),
+            Instruction::Xadd { lock, dest, src } => {
+                write!(
+                    f,
+                    "\t{}xadd {dest}, {src}",
+                    if *lock { "lock " } else { "" }
+                )
+            }
+            Instruction::Cmpxchg { lock, dest, src } => {
+                write!(
+                    f,
+                    "\t{}cmpxchg {dest}, {src}",
+                    if *lock { "lock " } else { "" }
+                )
+            }
             Instruction::Mul { src } => write!(f, "\tmul {src}"),
             Instruction::Div { src } => write!(f, "\tdiv {src}"),
+            Instruction::Idiv { src } => write!(f, "\tidiv {src}"),
+            Instruction::Cdq => write!(f, "\tcdq"),
+            Instruction::RepMovsb => write!(f, "\trep movsb"),
+            Instruction::RepStosb => write!(f, "\trep stosb"),
+            Instruction::Interrupt(vector) => write!(f, "\tint {vector:#x}"),
+            Instruction::Fld { src } => write!(f, "\tfld {src}"),
+            Instruction::Fstp { dest } => write!(f, "\tfstp {dest}"),
         }
     }
 }
@@ -646,6 +1118,28 @@ pub enum ComparisonOperator {
     GreaterUnsigned,
     LessEqualUnsigned,
     GreaterEqualUnsigned,
+    /// the last arithmetic overflowed as signed integers
+    Overflow,
+    NotOverflow,
+}
+impl ComparisonOperator {
+    /// the condition holding exactly when this one doesn't
+    pub fn negate(self) -> Self {
+        match self {
+            ComparisonOperator::Equal => ComparisonOperator::NotEqual,
+            ComparisonOperator::NotEqual => ComparisonOperator::Equal,
+            ComparisonOperator::Less => ComparisonOperator::GreaterEqual,
+            ComparisonOperator::Greater => ComparisonOperator::LessEqual,
+            ComparisonOperator::LessEqual => ComparisonOperator::Greater,
+            ComparisonOperator::GreaterEqual => ComparisonOperator::Less,
+            ComparisonOperator::LessUnsigned => ComparisonOperator::GreaterEqualUnsigned,
+            ComparisonOperator::GreaterUnsigned => ComparisonOperator::LessEqualUnsigned,
+            ComparisonOperator::LessEqualUnsigned => ComparisonOperator::GreaterUnsigned,
+            ComparisonOperator::GreaterEqualUnsigned => ComparisonOperator::LessUnsigned,
+            ComparisonOperator::Overflow => ComparisonOperator::NotOverflow,
+            ComparisonOperator::NotOverflow => ComparisonOperator::Overflow,
+        }
+    }
 }
 impl Display for ComparisonOperator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -660,6 +1154,505 @@ impl Display for ComparisonOperator {
             ComparisonOperator::GreaterUnsigned => write!(f, "a"),
             ComparisonOperator::LessEqualUnsigned => write!(f, "be"),
             ComparisonOperator::GreaterEqualUnsigned => write!(f, "ae"),
+            ComparisonOperator::Overflow => write!(f, "o"),
+            ComparisonOperator::NotOverflow => write!(f, "no"),
+        }
+    }
+}
+
+/// a line of assembly which couldn't be parsed back into the IR
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidAsm {
+    pub line: usize,
+    pub text: String,
+}
+impl Display for InvalidAsm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: invalid assembly {:?}", self.line + 1, self.text)
+    }
+}
+impl std::error::Error for InvalidAsm {}
+impl InvalidAsm {
+    fn new(text: &str) -> Self {
+        Self {
+            line: 0,
+            text: text.to_string(),
+        }
+    }
+    fn at(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+}
+impl FromStr for ComparisonOperator {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "e" => Ok(Self::Equal),
+            "ne" => Ok(Self::NotEqual),
+            "l" => Ok(Self::Less),
+            "g" => Ok(Self::Greater),
+            "le" => Ok(Self::LessEqual),
+            "ge" => Ok(Self::GreaterEqual),
+            "b" => Ok(Self::LessUnsigned),
+            "a" => Ok(Self::GreaterUnsigned),
+            "be" => Ok(Self::LessEqualUnsigned),
+            "ae" => Ok(Self::GreaterEqualUnsigned),
+            "o" => Ok(Self::Overflow),
+            "no" => Ok(Self::NotOverflow),
+            _ => Err(InvalidAsm::new(s)),
+        }
+    }
+}
+impl FromStr for DataType {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BYTE" | "db" => Ok(Self::Byte),
+            "WORD" | "dw" => Ok(Self::Word),
+            "DWORD" | "dd" => Ok(Self::DoubleWord),
+            "QWORD" | "dq" => Ok(Self::QuadWord),
+            _ => Err(InvalidAsm::new(s)),
+        }
+    }
+}
+impl FromStr for Address {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidAsm::new(s);
+        let inner = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .ok_or_else(invalid)?;
+        let mut address = Self::default();
+        let mut terms = vec![];
+        let mut start = 0;
+        for (idx, c) in inner.char_indices() {
+            if (c == '+' || c == '-') && idx > 0 {
+                terms.push(&inner[start..idx]);
+                start = idx;
+            }
+        }
+        terms.push(&inner[start..]);
+        for term in terms {
+            let (negative, term) = match term.strip_prefix('-') {
+                Some(term) => (true, term),
+                None => (false, term.strip_prefix('+').unwrap_or(term)),
+            };
+            if let Ok(displacement) = term.parse::<i32>() {
+                address.displacement += if negative {
+                    -displacement
+                } else {
+                    displacement
+                };
+            } else if negative {
+                return Err(invalid());
+            } else if let Some((index, scale)) = term.split_once('*') {
+                let index = index.parse().map_err(|_| invalid())?;
+                let scale = scale.parse().map_err(|_| invalid())?;
+                address.index = Some((index, scale));
+            } else if let Ok(base) = term.parse() {
+                address.base = Some(base);
+            } else if !term.is_empty() {
+                address.label = Some(term.to_string());
+            }
+        }
+        Ok(address)
+    }
+}
+impl FromStr for Memory {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (data_type, address) = s.split_once(" PTR ").ok_or_else(|| InvalidAsm::new(s))?;
+        Ok(Self {
+            data_type: data_type.parse()?,
+            address: address.parse()?,
+        })
+    }
+}
+/// numbers parse as `Int`, only those beyond `i32` become an `Amount`
+impl FromStr for Source {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(" PTR ") {
+            return Ok(Self::Memory(s.parse()?));
+        }
+        if let Ok(register) = s.parse() {
+            return Ok(Self::Register(register));
+        }
+        if let Ok(int) = s.parse() {
+            return Ok(Self::Int(int));
+        }
+        if let Ok(int) = s.parse() {
+            return Ok(Self::Int64(int));
+        }
+        if let Ok(amount) = s.parse() {
+            return Ok(Self::Amount(amount));
+        }
+        if s.is_empty() || s.contains(char::is_whitespace) {
+            return Err(InvalidAsm::new(s));
+        }
+        Ok(Self::Name(s.to_string()))
+    }
+}
+impl FromStr for Destination {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s.parse::<Source>()?).map_err(|_| InvalidAsm::new(s))
+    }
+}
+impl FromStr for Instruction {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidAsm::new(s);
+        let s = s.trim();
+        if let Some(label) = s.strip_prefix('.').and_then(|s| s.strip_suffix(':')) {
+            return Ok(Self::Label(label.into()));
+        }
+        if let Some(instr) = s.strip_prefix("lock ") {
+            return match instr.parse()? {
+                Self::Xadd { dest, src, .. } => Ok(Self::Xadd {
+                    lock: true,
+                    dest,
+                    src,
+                }),
+                Self::Cmpxchg { dest, src, .. } => Ok(Self::Cmpxchg {
+                    lock: true,
+                    dest,
+                    src,
+                }),
+                _ => Err(invalid()),
+            };
+        }
+        let (mnemonic, operands) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let operands: Vec<&str> = match operands.trim() {
+            "" => vec![],
+            operands => operands.split(", ").collect(),
+        };
+        let instr = match (mnemonic, operands.as_slice()) {
+            ("nop", []) => Self::NOp,
+            ("mov", [dest, src]) => Self::Mov {
+                dest: dest.parse()?,
+                src: src.parse()?,
+            },
+            ("movzx", [dest, src]) => Self::Movzx {
+                dest: dest.parse().map_err(|_| invalid())?,
+                src: src.parse()?,
+            },
+            ("movsx", [dest, src]) => Self::Movsx {
+                dest: dest.parse().map_err(|_| invalid())?,
+                src: src.parse()?,
+            },
+            ("lea", [dest, addr]) => Self::Lea {
+                dest: dest.parse().map_err(|_| invalid())?,
+                addr: addr.parse()?,
+            },
+            ("push", [src]) => Self::Push { src: src.parse()? },
+            ("pop", [dest]) => Self::Pop {
+                dest: dest.parse()?,
+            },
+            ("call", [func]) => match func.parse()? {
+                Source::Name(func) => Self::Call { func: func.into() },
+                src => Self::CallIndirect(src),
+            },
+            ("leave", []) => Self::Leave,
+            ("ret", []) => Self::Ret,
+            ("jmp", [label]) => match label.strip_prefix('.') {
+                Some(label) => Self::Jmp {
+                    label: label.into(),
+                },
+                None => Self::JmpIndirect(label.parse()?),
+            },
+            ("cmp", [a, b]) => Self::Cmp {
+                a: a.parse()?,
+                b: b.parse()?,
+            },
+            ("add", [dest, src]) => Self::Add {
+                dest: dest.parse()?,
+                src: src.parse()?,
+            },
+            ("sub", [dest, src]) => Self::Sub {
+                dest: dest.parse()?,
+                src: src.parse()?,
+            },
+            ("and", [dest, src]) => Self::And {
+                dest: dest.parse()?,
+                src: src.parse()?,
+            },
+            ("sar", [dest, amount]) => Self::Sar {
+                dest: dest.parse()?,
+                amount: amount.parse().map_err(|_| invalid())?,
+            },
+            ("shl", [dest, amount]) => Self::Shl {
+                dest: dest.parse()?,
+                amount: amount.parse().map_err(|_| invalid())?,
+            },
+            ("shr", [dest, amount]) => Self::Shr {
+                dest: dest.parse()?,
+                amount: amount.parse().map_err(|_| invalid())?,
+            },
+            ("xchg", [dest, src]) => Self::Xchg {
+                dest: dest.parse()?,
+                src: src.parse().map_err(|_| invalid())?,
+            },
+            ("xadd", [dest, src]) => Self::Xadd {
+                lock: false,
+                dest: dest.parse()?,
+                src: src.parse().map_err(|_| invalid())?,
+            },
+            ("cmpxchg", [dest, src]) => Self::Cmpxchg {
+                lock: false,
+                dest: dest.parse()?,
+                src: src.parse().map_err(|_| invalid())?,
+            },
+            ("mul", [src]) => Self::Mul { src: src.parse()? },
+            ("div", [src]) => Self::Div { src: src.parse()? },
+            ("idiv", [src]) => Self::Idiv { src: src.parse()? },
+            ("cdq", []) => Self::Cdq,
+            ("fld", [src]) => Self::Fld { src: src.parse()? },
+            ("fstp", [dest]) => Self::Fstp {
+                dest: dest.parse()?,
+            },
+            ("rep", ["movsb"]) => Self::RepMovsb,
+            ("rep", ["stosb"]) => Self::RepStosb,
+            ("int", [vector]) => Self::Interrupt(
+                match vector.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => vector.parse(),
+                }
+                .map_err(|_| invalid())?,
+            ),
+            (mnemonic, [label]) if mnemonic.starts_with('j') => Self::JOp {
+                op: mnemonic[1..].parse()?,
+                label: label.strip_prefix('.').ok_or_else(invalid)?.into(),
+            },
+            (mnemonic, [dest]) if mnemonic.starts_with("set") => Self::Set {
+                op: mnemonic[3..].parse()?,
+                dest: dest.parse()?,
+            },
+            (mnemonic, [dest, src]) if mnemonic.starts_with("cmov") => Self::Cmov {
+                op: mnemonic[4..].parse()?,
+                dest: dest.parse().map_err(|_| invalid())?,
+                src: src.parse()?,
+            },
+            _ => return Err(invalid()),
+        };
+        Ok(instr)
+    }
+}
+impl Function {
+    /// parses the lines of a function written by `fmt_with_source`, adding the source files
+    /// named by its `%line` directives to `sources`
+    fn parse_lines<'a>(
+        lines: impl IntoIterator<Item = (usize, &'a str)>,
+        sources: &mut Vec<String>,
+    ) -> Result<Self, InvalidAsm> {
+        let mut lines = lines.into_iter();
+        let Some((ln, header)) = lines.next() else {
+            return Err(InvalidAsm::new(""));
+        };
+        let Some(name) = header.trim().strip_suffix(':') else {
+            return Err(InvalidAsm::new(header).at(ln));
+        };
+        let mut function = Self {
+            name: name.to_string(),
+            registers: 0,
+            return_type: Type::default(),
+            body: vec![],
+            strings: vec![],
+            tables: vec![],
+            floats: vec![],
+            bytes: vec![],
+            comments: vec![],
+            lines: vec![],
+            local_offsets: vec![],
+        };
+        let string_prefix = format!("{name}_c");
+        let local_prefix = format!("{name}_v");
+        for (ln, line) in lines {
+            let invalid = || InvalidAsm::new(line).at(ln);
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            if let Some(directive) = trimmed.strip_prefix("%line ") {
+                let (number, file) = directive.split_once(' ').ok_or_else(invalid)?;
+                let number = number.strip_suffix("+0").ok_or_else(invalid)?;
+                let number: usize = number.parse().map_err(|_| invalid())?;
+                let file = match sources.iter().position(|source| source == file) {
+                    Some(idx) => idx,
+                    None => {
+                        sources.push(file.to_string());
+                        sources.len() - 1
+                    }
+                };
+                function.lines.push((
+                    function.body.len(),
+                    Position {
+                        ln: number.saturating_sub(1),
+                        col: 0,
+                        file: FileId(file as u32),
+                    },
+                ));
+            } else if let Some(comment) = trimmed.strip_prefix("; ") {
+                function
+                    .comments
+                    .push((function.body.len(), comment.to_string()));
+            } else if trimmed.starts_with(&local_prefix) && trimmed.contains(" equ ") {
+                let (label, offset) = trimmed.split_once(" equ ").ok_or_else(invalid)?;
+                let offset = offset.parse().map_err(|_| invalid())?;
+                function.local_offsets.push((label.to_string(), offset));
+            } else if trimmed.starts_with(&string_prefix) && trimmed.contains(" db `") {
+                let (_, string) = trimmed.split_once(" db `").ok_or_else(invalid)?;
+                let string = string.strip_suffix("`, 0").ok_or_else(invalid)?;
+                function.strings.push(string.replace("\\`", "`"));
+            } else {
+                function
+                    .body
+                    .push(trimmed.parse().map_err(|err: InvalidAsm| err.at(ln))?);
+            }
+        }
+        Ok(function)
+    }
+}
+impl FromStr for Function {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_lines(s.lines().enumerate(), &mut vec![])
+    }
+}
+impl FromStr for Data {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidAsm::new(s);
+        let mut parts = s.trim().splitn(3, ' ');
+        let (Some(label), Some(directive), Some(values)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            label: label.to_string(),
+            data_type: directive.parse()?,
+            values: values
+                .split(", ")
+                .map(|value| value.parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+/// parses assembly written by `Program`'s `Display` back into the IR
+impl FromStr for Program {
+    type Err = InvalidAsm;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut program = Self::default();
+        let mut section = "";
+        let mut function: Vec<(usize, &str)> = vec![];
+        let finish = |program: &mut Self, function: &mut Vec<(usize, &str)>| {
+            if function.is_empty() {
+                return Ok(());
+            }
+            let parsed = Function::parse_lines(function.drain(..), &mut program.sources)?;
+            program.functions.push(parsed);
+            Ok::<_, InvalidAsm>(())
+        };
+        for (ln, line) in s.lines().enumerate() {
+            let invalid = || InvalidAsm::new(line).at(ln);
+            if let Some(name) = line.strip_prefix("section ") {
+                finish(&mut program, &mut function)?;
+                section = name.trim();
+                continue;
+            }
+            match section {
+                "" => {
+                    if let Some(name) = line.strip_prefix("extern ") {
+                        program.externs.push(name.trim().to_string());
+                    } else if let Some(library) = line.strip_prefix("; link ") {
+                        program.libraries.push(library.trim().to_string());
+                    } else if let Some(name) = line.strip_prefix("global ") {
+                        if name.trim() != "main" {
+                            program.exports.push(name.trim().to_string());
+                        }
+                    } else if !line.trim().is_empty() {
+                        return Err(invalid());
+                    }
+                }
+                ".text" => {
+                    let is_header = !line.starts_with(char::is_whitespace)
+                        && !line.starts_with(['.', '%', ';'])
+                        && line.ends_with(':');
+                    if is_header {
+                        finish(&mut program, &mut function)?;
+                    }
+                    function.push((ln, line));
+                }
+                ".data" => program
+                    .data
+                    .push(line.parse().map_err(|err: InvalidAsm| err.at(ln))?),
+                ".rodata" if line.contains(" db ") || line.ends_with(':') => {
+                    let (label, bytes) = match line.strip_suffix(':') {
+                        Some(label) => (label, None),
+                        None => line
+                            .split_once(" db ")
+                            .map(|(label, bytes)| (label, Some(bytes)))
+                            .ok_or_else(invalid)?,
+                    };
+                    let function = program
+                        .functions
+                        .iter_mut()
+                        .find(|function| {
+                            label == format!("{}_b{}", function.name, function.bytes.len())
+                        })
+                        .ok_or_else(invalid)?;
+                    let bytes = bytes
+                        .into_iter()
+                        .flat_map(|bytes| bytes.split(", "))
+                        .map(|byte| byte.trim().parse().map_err(|_| invalid()))
+                        .collect::<Result<_, _>>()?;
+                    function.bytes.push(bytes);
+                }
+                ".rodata" if line.contains(" 0x") => {
+                    let (label, directive, bits) = line
+                        .split_once(' ')
+                        .and_then(|(label, rest)| Some((label, rest.split_once(" 0x")?)))
+                        .map(|(label, (directive, bits))| (label, directive, bits))
+                        .ok_or_else(invalid)?;
+                    let function = program
+                        .functions
+                        .iter_mut()
+                        .find(|function| {
+                            label == format!("{}_f{}", function.name, function.floats.len())
+                        })
+                        .ok_or_else(invalid)?;
+                    let bits = u64::from_str_radix(bits.trim(), 16).map_err(|_| invalid())?;
+                    function.floats.push((directive.parse()?, bits));
+                }
+                ".rodata" => {
+                    let (label, labels) = line.split_once(" dd ").ok_or_else(invalid)?;
+                    let function = program
+                        .functions
+                        .iter_mut()
+                        .find(|function| {
+                            label == format!("{}_t{}", function.name, function.tables.len())
+                        })
+                        .ok_or_else(invalid)?;
+                    let prefix = format!("{}.", function.name);
+                    let table = labels
+                        .split(", ")
+                        .map(|label| label.trim().strip_prefix(&prefix).map(Symbol::from))
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?;
+                    function.tables.push(table);
+                }
+                ".bss" => {
+                    let (label, bytes) = line.split_once(" resb ").ok_or_else(invalid)?;
+                    let bytes = bytes.trim().parse().map_err(|_| invalid())?;
+                    program.bss.push((label.to_string(), bytes));
+                }
+                _ => return Err(invalid()),
+            }
         }
+        finish(&mut program, &mut function)?;
+        Ok(program)
     }
 }
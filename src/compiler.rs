@@ -1,40 +1,235 @@
 use std::{collections::HashMap, fmt::Display};
 
 use crate::{
+    args::uses_args,
+    cfg,
     code::{
-        Destination, Function, Instruction, Program, Register, RegisterName, RegisterSize, Source,
+        string_bytes, Address, ComparisonOperator, Data, DataType, Destination, Function,
+        Instruction, Memory, Program, Register, RegisterName, RegisterSize, Source,
     },
-    parser::{Located, SExpr},
-    typ::{IntType, Type},
+    const_eval::const_eval,
+    diagnostic::suggest,
+    infer,
+    intern::Symbol,
+    macros::{Expander, MAX_EXPANSION_DEPTH},
+    opt,
+    options::CompileOptions,
+    os::{SYS_CLOSE, SYS_OPEN, SYS_READ, SYS_WRITE},
+    parser::{FileId, Located, Position, QuoteKind, SExpr},
+    pass::PassRun,
+    prelude::prelude,
+    profile, stats,
+    timings::{StageStart, Timings},
+    typ::{FloatType, IntType, Type},
 };
 
-#[derive(Debug, Default)]
+/// size of the static heap used by the bump allocator runtime
+pub const BUMP_HEAP_SIZE: usize = 1 << 20;
+/// what a program exits with when a runtime check fails
+pub const PANIC_EXIT_CODE: i32 = 101;
+
+#[derive(Debug, Clone, Default)]
 pub struct Compiler {
     pub program: Program,
     pub frames: Vec<Frame>,
+    pub signatures: HashMap<Symbol, Signature>,
+    pub generics: HashMap<Symbol, Generic>,
+    /// the label every function defined in lerp is emitted under
+    pub symbols: HashMap<Symbol, String>,
+    /// functions which keep their name and are made visible to other objects, with where they
+    /// were exported
+    pub exports: HashMap<Symbol, Position>,
+    /// functions `declare_top_level` declared ahead of their definition, with where they are
+    /// defined
+    pub declarations: HashMap<Symbol, Position>,
+    /// lower `alloc` to the built-in bump allocator instead of `malloc`
+    pub bump_allocator: bool,
+    pub consts: HashMap<Symbol, i64>,
+    pub globals: HashMap<Symbol, Type>,
+    pub options: CompileOptions,
+    /// the optimization passes run by `compile_program`
+    pub pass_runs: Vec<PassRun>,
+    pub timings: Timings,
+    /// the warnings found so far, which don't stop the compilation
+    pub warnings: Vec<Located<CompileWarning>>,
+    /// the prelude functions which weren't compiled yet
+    pub prelude: HashMap<Symbol, Defn>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub names: Vec<Symbol>,
+    pub params: Vec<Type>,
+    pub ret: Type,
+    /// whether any amount of arguments can follow the parameters, like `printf`
+    pub variadic: bool,
+}
+/// a generic function definition, instantiated once per set of concrete type arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generic {
+    pub type_params: Vec<Symbol>,
+    pub params: Vec<(Symbol, Located<SExpr>)>,
+    pub ret: Located<SExpr>,
+    pub body: Vec<Located<SExpr>>,
 }
 #[derive(Debug, Clone, PartialEq)]
 pub struct Frame {
     pub function: Function,
     pub scopes: Vec<Scope>,
     pub registers: usize,
+    /// concrete types bound to the type parameters of a generic instantiation
+    pub types: HashMap<Symbol, Type>,
+    /// how many groups of labels were made, keeping the next ones unique
+    pub labels: usize,
+    /// offset from the base pointer of the pointer to the next variadic argument, in
+    /// variadic functions
+    pub varargs: Option<i32>,
+    /// bytes pushed below the base pointer by the instructions written so far
+    pub depth: i32,
+    /// the `loop`s around what is being compiled, the innermost last
+    pub loops: Vec<Loop>,
 }
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Scope {
-    pub locals: HashMap<String, u8>,
-    pub offset: u8,
+    pub locals: HashMap<Symbol, Local>,
+    /// the stack depth the scope starts at, which leaving it restores
+    pub depth: i32,
+    /// the expressions `defer`red in the scope, run in reverse order wherever it is left
+    pub deferred: Vec<Located<SExpr>>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Local {
+    pub typ: Type,
+    /// offset from the base pointer
+    pub offset: i32,
+    /// where the local is declared
+    pub pos: Position,
+    /// whether the local is read anywhere yet
+    pub used: bool,
+}
+/// a `loop` which `recur` jumps back to the head of
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub head: Symbol,
+    /// the stack depth at the head
+    pub depth: i32,
+    /// the index of the scope of the body, which every iteration leaves
+    pub scope: usize,
+    /// the names, offsets and types of the loop variables, in the order they are bound
+    pub vars: Vec<(Symbol, i32, Type)>,
+    /// the bodies of the iterations an unrolled loop continues with at a `recur` instead of
+    /// jumping back, the next one last
+    pub unrolled: Vec<Vec<Located<SExpr>>>,
+    /// the label after the loop, which `break` jumps to
+    pub end: Symbol,
+    /// the types of the values the `break`s so far leave the loop with, and where they are
+    pub breaks: Vec<Located<Type>>,
 }
 #[derive(Debug, Clone, PartialEq)]
 pub enum CompileError {
-    NotFound(String),
+    NotFound {
+        name: String,
+        /// a similarly named candidate, for typos
+        suggestion: Option<String>,
+    },
     ExpectedArgs(usize),
-    InvalidHead,
+    /// the head of a form isn't a name, holding a snippet of what was found instead
+    InvalidHead(String),
     InvalidType(Type),
-    InvalidTypeExpected { expected: Type, got: Type },
-    UnknownSize,
+    InvalidTypeExpected {
+        expected: Type,
+        got: Type,
+    },
+    UnknownSize(Type),
+    /// a pointer added to or subtracted from something else than an offset, or a pointer
+    /// subtracted from a pointer of another type
+    InvalidPointerArithmetic {
+        left: Type,
+        right: Type,
+    },
+    UnknownType(String),
+    InvalidForm(&'static str),
+    CannotInfer(String),
+    MacroDepth(String),
+    NotConstant,
+    Overflow,
+    DivisionByZero,
+    OutOfRange(i128),
+    MissingArg(String),
+    DuplicateArg(String),
+    /// a function defined twice, possibly in different files
+    Redefined(String),
+    /// an `export` of a function which was already emitted under its mangled name
+    LateExport(String),
+    /// a `case` arm for a value an earlier arm already matches
+    DuplicateCase(String),
+    /// a `recur` which isn't inside of a `loop` of the function
+    RecurOutsideLoop,
+    /// a `break` which isn't inside of a `loop` of the function
+    BreakOutsideLoop,
+    /// a builtin calling into the C library in a freestanding program
+    NeedsLibc(String),
+    /// an entry point which may take more stack than `--max-stack` allows
+    StackTooDeep {
+        entry: String,
+        bytes: usize,
+        limit: usize,
+    },
+    /// an entry point under `--max-stack` reaching a recursive function
+    UnboundedStack {
+        entry: String,
+        recursive: String,
+    },
+    /// a `static-assert` whose condition is false, with its message or the condition as written
+    StaticAssert(String),
+    /// a lint denied with `--deny`
+    DeniedLint(CompileWarning),
+    /// an `(allow ...)`, `--allow`, `--warn` or `--deny` of a lint which doesn't exist
+    UnknownLint(String),
+    /// a file `embed-file` couldn't read
+    CannotEmbed {
+        path: String,
+        reason: String,
+    },
+    Unsupported(&'static str),
+    /// the compiler was used in a way it doesn't support, like compiling outside of a function
+    Internal(&'static str),
+}
+/// how many bytes `instr` pushes onto the stack, negative if it releases them
+pub fn stack_effect(instr: &Instruction) -> i32 {
+    let is_stack_pointer = |dest: &Destination| {
+        matches!(
+            dest,
+            Destination::Register(Register {
+                name: RegisterName::SP,
+                ..
+            })
+        )
+    };
+    match instr {
+        Instruction::Push { src } => src.size().map_or(4, |size| size.bytes()) as i32,
+        Instruction::Pop { dest } => -(dest.size().bytes() as i32),
+        Instruction::Add {
+            dest,
+            src: Source::Amount(amount),
+        } if is_stack_pointer(dest) => -(*amount as i32),
+        Instruction::Add {
+            dest,
+            src: Source::Int(int),
+        } if is_stack_pointer(dest) => -int,
+        Instruction::Sub {
+            dest,
+            src: Source::Amount(amount),
+        } if is_stack_pointer(dest) => *amount as i32,
+        Instruction::Sub {
+            dest,
+            src: Source::Int(int),
+        } if is_stack_pointer(dest) => *int,
+        _ => 0,
+    }
 }
 impl Frame {
     pub fn write(&mut self, instr: Instruction) -> usize {
+        self.depth += stack_effect(&instr);
         let addr = self.function.body.len();
         self.function.body.push(instr);
         addr
@@ -44,12 +239,34 @@ impl Frame {
         self.function.strings.push(string);
         format!("{}_c{idx}", self.function.name)
     }
+    pub fn new_float(&mut self, data_type: DataType, bits: u64) -> String {
+        let idx = self.function.floats.len();
+        self.function.floats.push((data_type, bits));
+        format!("{}_f{idx}", self.function.name)
+    }
+    pub fn new_bytes(&mut self, bytes: Vec<u8>) -> String {
+        let idx = self.function.bytes.len();
+        self.function.bytes.push(bytes);
+        format!("{}_b{idx}", self.function.name)
+    }
+    /// a number no other group of labels in the function uses
+    pub fn new_labels(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+    pub fn new_table(&mut self, labels: Vec<Symbol>) -> String {
+        let idx = self.function.tables.len();
+        self.function.tables.push(labels);
+        format!("{}_t{idx}", self.function.name)
+    }
 }
 impl Compiler {
-    pub fn frame(&self) -> &Frame {
+    // everything writing into the current frame is reached through `compile`, which checks
+    // that there is one
+    pub(crate) fn frame(&self) -> &Frame {
         self.frames.last().expect("no frame on stack")
     }
-    pub fn frame_mut(&mut self) -> &mut Frame {
+    pub(crate) fn frame_mut(&mut self) -> &mut Frame {
         self.frames.last_mut().expect("no frame on stack")
     }
     pub fn push_frame(&mut self, name: String) {
@@ -60,9 +277,20 @@ impl Compiler {
                 return_type: Type::default(),
                 body: vec![],
                 strings: vec![],
+                tables: vec![],
+                floats: vec![],
+                bytes: vec![],
+                comments: vec![],
+                lines: vec![],
+                local_offsets: vec![],
             },
             scopes: vec![Scope::default()],
             registers: 0,
+            types: HashMap::new(),
+            labels: 0,
+            varargs: None,
+            depth: 0,
+            loops: vec![],
         });
         self.write(Instruction::Push {
             src: Source::Register(Register {
@@ -80,9 +308,14 @@ impl Compiler {
                 size: RegisterSize::S32,
             }),
         });
+        // the saved base pointer is above it
+        self.frame_mut().depth = 0;
     }
+    /// finishes the current function, doing nothing if there is none
     pub fn pop_frame(&mut self) {
-        let registers = self.frame().registers;
+        let Some(registers) = self.frames.last().map(|frame| frame.registers) else {
+            return;
+        };
         if registers > 0 {
             self.write(Instruction::Add {
                 dest: Destination::Register(Register {
@@ -94,12 +327,9 @@ impl Compiler {
         }
         self.write(Instruction::Leave);
         self.write(Instruction::Ret);
-        let Frame {
-            function,
-            scopes: _,
-            registers: _,
-        } = self.frames.pop().expect("no frame on stack");
-        self.program.functions.push(function);
+        if let Some(Frame { function, .. }) = self.frames.pop() {
+            self.program.functions.push(function);
+        }
     }
     pub fn write(&mut self, instr: Instruction) -> usize {
         self.frame_mut().write(instr)
@@ -107,24 +337,388 @@ impl Compiler {
     pub fn new_string(&mut self, string: String) -> String {
         self.frame_mut().new_string(string)
     }
+    pub fn new_labels(&mut self) -> usize {
+        self.frame_mut().new_labels()
+    }
+    pub fn new_table(&mut self, labels: Vec<Symbol>) -> String {
+        self.frame_mut().new_table(labels)
+    }
+    pub fn comment(&mut self, comment: String) {
+        let frame = self.frame_mut();
+        let addr = frame.function.body.len();
+        frame.function.comments.push((addr, comment));
+    }
+    pub fn line(&mut self, pos: Position) {
+        let frame = self.frame_mut();
+        if frame
+            .function
+            .lines
+            .last()
+            .map(|(_, last)| (last.file, last.ln))
+            == Some((pos.file, pos.ln))
+        {
+            return;
+        }
+        let addr = frame.function.body.len();
+        frame.function.lines.push((addr, pos));
+    }
+    /// declares `local` as `name` in the scope at `scope` of the current frame, also keeping
+    /// its offset with `--local-offsets`
+    pub fn declare_local(&mut self, scope: usize, name: Symbol, local: Local) {
+        let local_offsets = self.options.local_offsets;
+        let frame = self.frame_mut();
+        if local_offsets {
+            let label = format!(
+                "{}_v{}{}",
+                frame.function.name,
+                frame.function.local_offsets.len(),
+                mangle(name.as_str())
+            );
+            frame.function.local_offsets.push((label, local.offset));
+        }
+        frame.scopes[scope].locals.insert(name, local);
+    }
     pub fn new_extern(&mut self, name: String) {
         self.program.externs.push(name)
     }
+    /// declares the extern `name` if it isn't declared yet
+    pub fn use_extern(&mut self, name: &str) {
+        if !self
+            .program
+            .externs
+            .iter()
+            .any(|extern_name| extern_name == name)
+        {
+            self.new_extern(name.to_string());
+        }
+    }
+    /// widens the value of type `typ` held in the A register to at least 32 bits,
+    /// zero-extending unsigned and sign-extending signed integers
+    pub fn widen(&mut self, typ: &Type) -> Option<RegisterSize> {
+        let size = RegisterSize::typ(typ)?;
+        if size.bytes() >= RegisterSize::S32.bytes() {
+            return Some(size);
+        }
+        let dest = Register {
+            name: RegisterName::A,
+            size: RegisterSize::S32,
+        };
+        let src = Source::Register(Register {
+            name: RegisterName::A,
+            size,
+        });
+        match typ {
+            Type::Int(_) => self.write(Instruction::Movsx { dest, src }),
+            _ => self.write(Instruction::Movzx { dest, src }),
+        };
+        Some(RegisterSize::S32)
+    }
+    /// pushes the value of the A register widened to `size`, a 64-bit value from the D and A
+    /// register pair with its low half on top like it's laid out in memory, and returns the
+    /// bytes pushed
+    pub fn push_value(&mut self, size: RegisterSize) -> usize {
+        let half = |name| {
+            Source::Register(Register {
+                name,
+                size: RegisterSize::S32,
+            })
+        };
+        if size == RegisterSize::S64 {
+            self.write(Instruction::Push {
+                src: half(RegisterName::D),
+            });
+            self.write(Instruction::Push {
+                src: half(RegisterName::A),
+            });
+            return size.bytes();
+        }
+        self.write(Instruction::Push {
+            src: Source::Register(Register {
+                name: RegisterName::A,
+                size,
+            }),
+        });
+        size.bytes()
+    }
+    /// widens the float pushed last to a double, as C does for variadic arguments, returning
+    /// the bytes it grew by
+    fn promote_float(&mut self) -> usize {
+        let top = |data_type| Memory {
+            data_type,
+            address: Address {
+                base: Some(Register {
+                    name: RegisterName::SP,
+                    size: RegisterSize::S32,
+                }),
+                ..Default::default()
+            },
+        };
+        self.write(Instruction::Fld {
+            src: top(DataType::DoubleWord),
+        });
+        self.write(Instruction::Sub {
+            dest: Destination::Register(Register {
+                name: RegisterName::SP,
+                size: RegisterSize::S32,
+            }),
+            src: Source::Int(4),
+        });
+        self.write(Instruction::Fstp {
+            dest: top(DataType::QuadWord),
+        });
+        RegisterSize::S32.bytes()
+    }
+    /// loads a value of type `typ` from `src` into the A register, extending sub-word values,
+    /// and a 64-bit value from memory into the D and A register pair
+    pub fn load(&mut self, typ: &Type, src: Source) -> Option<RegisterSize> {
+        let size = RegisterSize::typ(typ)?;
+        let dest = Register {
+            name: RegisterName::A,
+            size: RegisterSize::S32,
+        };
+        if let (RegisterSize::S64, Source::Memory(Memory { address, .. })) = (size, &src) {
+            // the high half first, the address may be based on the A register
+            for (name, displacement) in [(RegisterName::D, 4), (RegisterName::A, 0)] {
+                self.write(Instruction::Mov {
+                    dest: Destination::Register(Register {
+                        name,
+                        size: RegisterSize::S32,
+                    }),
+                    src: Source::Memory(Memory {
+                        data_type: DataType::DoubleWord,
+                        address: Address {
+                            displacement: address.displacement + displacement,
+                            ..address.clone()
+                        },
+                    }),
+                });
+            }
+            return Some(size);
+        }
+        if size.bytes() >= RegisterSize::S32.bytes() {
+            self.write(Instruction::Mov {
+                dest: Destination::Register(Register {
+                    name: RegisterName::A,
+                    size,
+                }),
+                src,
+            });
+            return Some(size);
+        }
+        match typ {
+            Type::Int(_) => self.write(Instruction::Movsx { dest, src }),
+            _ => self.write(Instruction::Movzx { dest, src }),
+        };
+        Some(RegisterSize::S32)
+    }
     pub fn compile_program(
         &mut self,
         program: Vec<Located<SExpr>>,
     ) -> Result<Type, Located<CompileError>> {
+        let start = StageStart::now();
+        let program = cfg::prune(program, &self.options)?;
+        let program = Expander::default().expand_program(program)?;
+        self.timings.finish("expand", start);
+        // expressions are type checked while they are lowered
+        let start = StageStart::now();
+        if !self.options.no_prelude {
+            self.prelude = prelude();
+        }
         self.push_frame("main".to_string());
-        for sexpr in program {
-            self.compile(sexpr)?;
+        if uses_args(&program) {
+            self.save_args();
         }
+        self.declare_top_level(&program);
+        self.warn_unused_results(&program);
+        // the lints run once the constants they evaluate are defined
+        let linted = program.clone();
+        self.compile_top_level(program)?;
+        self.leave_scopes(0)?;
+        self.lint(&linted)?;
         self.pop_frame();
+        let mut exports: Vec<_> = self.exports.iter().collect();
+        exports.sort_by_key(|(_, pos)| (pos.file, pos.ln, pos.col));
+        if let Some((name, pos)) = exports
+            .into_iter()
+            .find(|(name, _)| !self.symbols.contains_key(name))
+        {
+            return Err(Located {
+                value: CompileError::NotFound {
+                    name: name.to_string(),
+                    suggestion: self.suggest_function(*name),
+                },
+                pos: *pos,
+            });
+        }
+        if self.options.no_recursion {
+            self.warn_recursion();
+        }
+        self.timings.finish("lower", start);
+        if self.options.check_only {
+            return Ok(Type::default());
+        }
+        let start = StageStart::now();
+        let mut passes = opt::passes(self.options.opt_level);
+        for name in &self.options.print_after {
+            passes.print_after(name.clone());
+        }
+        self.pass_runs = passes.run(&mut self.program).map_err(|_| Located {
+            value: CompileError::Internal("optimization passes can't be ordered"),
+            pos: Position {
+                ln: 0,
+                col: 0,
+                file: FileId::default(),
+            },
+        })?;
+        if let Some(profile) = &self.options.profile {
+            profile::apply(&mut self.program, profile);
+        }
+        if self.options.profile_generate {
+            if self.options.freestanding {
+                return Err(Located {
+                    value: CompileError::NeedsLibc("--profile-generate".to_string()),
+                    pos: Position {
+                        ln: 0,
+                        col: 0,
+                        file: FileId::default(),
+                    },
+                });
+            }
+            profile::instrument(&mut self.program);
+        }
+        self.timings.finish("optimize", start);
+        if let Some(limit) = self.options.max_stack {
+            self.check_stack(limit)?;
+        }
         Ok(Type::default())
     }
-    pub fn compile(
+    /// warns about the recursive functions of the program before the optimizations could turn
+    /// their calls into loops, at their definitions
+    fn warn_recursion(&mut self) {
+        let mut warnings: Vec<_> = stats::recursive(&self.program)
+            .into_iter()
+            .map(|label| {
+                let name = self
+                    .symbols
+                    .iter()
+                    .find(|(_, symbol)| **symbol == label)
+                    .map(|(name, _)| *name);
+                let pos = name
+                    .and_then(|name| self.declarations.get(&name))
+                    .copied()
+                    .unwrap_or(Position {
+                        ln: 0,
+                        col: 0,
+                        file: FileId::default(),
+                    });
+                let name = name.map_or(label, |name| name.to_string());
+                Located {
+                    value: CompileWarning::Recursion(name),
+                    pos,
+                }
+            })
+            .collect();
+        warnings.sort_by_key(|warning| (warning.pos.file, warning.pos.ln, warning.pos.col));
+        self.warnings.extend(warnings);
+    }
+    /// rejects the program if `main` or an exported function may take more than `limit` bytes
+    /// of stack, which recursion makes unknowable
+    fn check_stack(&self, limit: usize) -> Result<(), Located<CompileError>> {
+        for usage in stats::stack_usage(&self.program) {
+            let pos = self
+                .exports
+                .get(&Symbol::intern(&usage.entry))
+                .copied()
+                .unwrap_or(Position {
+                    ln: 0,
+                    col: 0,
+                    file: FileId::default(),
+                });
+            if let Some(recursive) = usage.recursive.first() {
+                return Err(Located {
+                    value: CompileError::UnboundedStack {
+                        entry: usage.entry,
+                        recursive: recursive.clone(),
+                    },
+                    pos,
+                });
+            }
+            if usage.bytes > limit {
+                return Err(Located {
+                    value: CompileError::StackTooDeep {
+                        entry: usage.entry,
+                        bytes: usage.bytes,
+                        limit,
+                    },
+                    pos,
+                });
+            }
+        }
+        Ok(())
+    }
+    /// compiles the top-level expressions into the current frame
+    fn compile_top_level(
         &mut self,
-        Located { value: sexpr, pos }: Located<SExpr>,
-    ) -> Result<Type, Located<CompileError>> {
+        program: Vec<Located<SExpr>>,
+    ) -> Result<(), Located<CompileError>> {
+        if self.options.jobs > 1 || self.options.cache.is_some() {
+            return self.compile_deferred(program);
+        }
+        for sexpr in program {
+            self.compile(sexpr)?;
+        }
+        Ok(())
+    }
+    /// the value of `sexpr` if it is arithmetic on constants and constant folding is enabled
+    fn fold(&self, sexpr: &Located<SExpr>) -> Option<i32> {
+        if !self.options.opt_level.folds_constants() {
+            return None;
+        }
+        let SExpr::Expr(sexprs) = &sexpr.value else {
+            return None;
+        };
+        if !matches!(sexprs.first(), Some(Located { value: SExpr::Word(head), .. }) if head == "+" || head == "-" || head == "/")
+        {
+            return None;
+        }
+        // a typed literal makes the value of the expression typed too
+        if !infer::is_untyped(sexpr) {
+            return None;
+        }
+        i32::try_from(const_eval(self, sexpr).ok()?).ok()
+    }
+    /// records the comment and source line of `sexpr` if they are enabled
+    pub fn annotate(&mut self, sexpr: &Located<SExpr>) {
+        if self.options.comments && matches!(sexpr.value, SExpr::Expr(_)) {
+            self.comment(format!(
+                "{sexpr} at {}:{}",
+                sexpr.pos.ln + 1,
+                sexpr.pos.col + 1
+            ));
+        }
+        if self.options.debug_info || self.options.asm_source {
+            self.line(sexpr.pos);
+        }
+    }
+    pub fn compile(&mut self, sexpr: Located<SExpr>) -> Result<Type, Located<CompileError>> {
+        if self.frames.is_empty() {
+            return Err(Located {
+                value: CompileError::Internal("no function to compile into"),
+                pos: sexpr.pos,
+            });
+        }
+        self.annotate(&sexpr);
+        if let Some(value) = self.fold(&sexpr) {
+            self.write(Instruction::Mov {
+                dest: Destination::Register(Register {
+                    name: RegisterName::A,
+                    size: RegisterSize::S32,
+                }),
+                src: Source::Int(value),
+            });
+            return Ok(Type::Int(IntType::S32));
+        }
+        let Located { value: sexpr, pos } = sexpr;
         match sexpr {
             SExpr::Expr(mut sexprs) => {
                 if sexprs.is_empty() {
@@ -134,79 +728,56 @@ impl Compiler {
                     value: head,
                     pos: head_pos,
                 } = sexprs.remove(0);
+                if let SExpr::Word(word) = &head {
+                    self.check_freestanding(word.as_str(), pos)?;
+                }
                 match head {
                     SExpr::Word(word) => match word.as_str() {
-                        "+" => {
-                            if sexprs.len() != 2 {
-                                return Err(Located {
-                                    value: CompileError::ExpectedArgs(2),
-                                    pos,
-                                });
-                            }
-                            let left = sexprs.remove(0);
-                            let left_pos = left.pos.clone();
-                            let right = sexprs.remove(0);
-                            let right_pos = right.pos.clone();
-
-                            let left_typ = self.compile(left)?;
-                            let Some(size) = RegisterSize::typ(&left_typ) else {
-                                return Err(Located {
-                                    value: CompileError::InvalidType(left_typ),
-                                    pos: left_pos,
-                                });
-                            };
-                            self.write(Instruction::Push {
-                                src: Source::Register(Register {
-                                    name: RegisterName::A,
-                                    size,
-                                }),
-                            });
-
-                            let right_typ = self.compile(right)?;
-                            if right_typ != left_typ {
-                                return Err(Located {
-                                    value: CompileError::InvalidTypeExpected {
-                                        expected: left_typ,
-                                        got: right_typ,
-                                    },
-                                    pos: right_pos,
-                                });
-                            }
-                            self.write(Instruction::Mov {
-                                dest: Destination::Register(Register {
-                                    name: RegisterName::B,
-                                    size,
-                                }),
-                                src: Source::Register(Register {
-                                    name: RegisterName::A,
-                                    size,
-                                }),
-                            });
-                            self.write(Instruction::Pop {
-                                dest: Destination::Register(Register {
-                                    name: RegisterName::A,
-                                    size,
-                                }),
-                            });
-                            self.write(Instruction::Add {
-                                dest: Destination::Register(Register {
-                                    name: RegisterName::A,
-                                    size,
-                                }),
-                                src: Source::Register(Register {
-                                    name: RegisterName::B,
-                                    size,
-                                }),
-                            });
-
-                            Ok(left_typ)
-                        }
+                        "+" => self.compile_additive(
+                            false,
+                            self.options.checked_arithmetic,
+                            sexprs,
+                            pos,
+                        ),
+                        "-" => self.compile_additive(
+                            true,
+                            self.options.checked_arithmetic,
+                            sexprs,
+                            pos,
+                        ),
+                        "add-checked" => self.compile_additive(false, true, sexprs, pos),
+                        "sub-checked" => self.compile_additive(true, true, sexprs, pos),
+                        "wrapping-add" => self.compile_additive(false, false, sexprs, pos),
+                        "wrapping-sub" => self.compile_additive(true, false, sexprs, pos),
+                        "wrapping-mul" => self.compile_wrapping_mul(sexprs, pos),
+                        "/" => self.compile_divide(sexprs, pos),
+                        "saturating-add" => self.compile_saturating(false, sexprs, pos),
+                        "saturating-sub" => self.compile_saturating(true, sexprs, pos),
+                        "atomic-load" => self.compile_atomic_load(sexprs, pos),
+                        "atomic-store" => self.compile_atomic_store(sexprs, pos),
+                        "atomic-add" => self.compile_atomic_add(sexprs, pos),
+                        "cas" => self.compile_cas(sexprs, pos),
+                        "mem-copy" => self.compile_mem_copy(sexprs, pos),
+                        "mem-set" => self.compile_mem_set(sexprs, pos),
+                        "defer" => self.compile_defer(sexprs, pos),
+                        "block" => self.compile_scoped(sexprs),
+                        "let" => self.compile_let(sexprs, pos),
+                        "loop" => self.compile_loop(sexprs, pos),
+                        "recur" => self.compile_recur(sexprs, pos),
+                        "break" => self.compile_break(sexprs, pos),
+                        "return" => self.compile_return(sexprs, pos),
+                        "spawn" => self.compile_spawn(sexprs, pos),
+                        "join" => self.compile_join(sexprs, pos),
+                        "lambda" => Err(Located {
+                            value: CompileError::Unsupported("lambdas outside of spawn"),
+                            pos,
+                        }),
                         "extern" => {
                             for Located { value: sexpr, pos } in sexprs.into_iter().rev() {
                                 match sexpr {
-                                    SExpr::Word(name) | SExpr::String(name) => {
-                                        self.new_extern(name);
-                                    }
+                                    SExpr::Word(name) => self.use_extern(name.as_str()),
+                                    SExpr::String(name) => self.use_extern(&name),
+                                    SExpr::Expr(sexprs) => self.typed_extern(sexprs, pos)?,
                                     sexpr => {
                                         return Err(Located {
                                             value: CompileError::InvalidType(
@@ -219,78 +790,153 @@ impl Compiler {
                             }
                             Ok(Type::default())
                         }
-                        _ => {
-                            let mut args = 0;
-                            for sexpr in sexprs.into_iter().rev() {
-                                let pos = sexpr.pos.clone();
-                                let typ = self.compile(sexpr)?;
-                                match typ {
-                                    Type::Array { typ, size } => {
-                                        let Some(size) = size else {
-                                            return Err(Located {
-                                                value: CompileError::UnknownSize,
-                                                pos,
-                                            });
-                                        };
-                                        let Some(single_size) = RegisterSize::typ(&typ) else {
-                                            return Err(Located {
-                                                value: CompileError::InvalidType(*typ),
-                                                pos,
-                                            });
-                                        };
-                                        args += single_size.bytes() * size;
-                                    }
-                                    typ => {
-                                        let Some(size) = RegisterSize::typ(&typ) else {
-                                            return Err(Located {
-                                                value: CompileError::InvalidType(typ),
-                                                pos,
-                                            });
-                                        };
-                                        args += size.bytes();
-                                        self.write(Instruction::Push {
-                                            src: Source::Register(Register {
-                                                name: RegisterName::A,
-                                                size,
-                                            }),
-                                        });
-                                    }
-                                }
-                            }
-                            self.write(Instruction::Call { func: word });
-                            self.write(Instruction::Add {
-                                dest: Destination::Register(Register {
-                                    name: RegisterName::SP,
-                                    size: RegisterSize::S32,
-                                }),
-                                src: Source::Amount(args),
-                            });
-                            Ok(Type::None)
-                        }
+                        "addr-of" => self.compile_addr_of(sexprs, pos),
+                        "call-ptr" => self.compile_call_ptr(sexprs, pos),
+                        "defn" => self.compile_defn(sexprs, pos),
+                        "alloc" => self.compile_alloc(sexprs, pos),
+                        "free" => self.compile_free(sexprs, pos),
+                        "str-len" => self.compile_str_len(sexprs, pos),
+                        "str-cat" => self.compile_str_cat(sexprs, pos),
+                        "str-eq" => self.compile_str_eq(sexprs, pos),
+                        "print" => self.compile_print(sexprs, false),
+                        "println" => self.compile_print(sexprs, true),
+                        "format" => self.compile_format(sexprs, pos),
+                        "const" => self.compile_const(sexprs, pos),
+                        "static-assert" => self.compile_static_assert(sexprs, pos),
+                        "allow" => self.compile_allow(sexprs, pos),
+                        "global" => self.compile_global(sexprs, pos),
+                        "link" => self.compile_link(sexprs, pos),
+                        "export" => self.compile_export(sexprs, pos),
+                        "sizeof" => self.compile_sizeof(sexprs, pos),
+                        "embed-file" => self.compile_embed_file(sexprs, pos),
+                        "va-arg" => self.compile_va_arg(sexprs, pos),
+                        "argc" => self.compile_argc(sexprs, pos),
+                        "argv" => self.compile_argv(sexprs, pos),
+                        "getenv" => self.compile_getenv(sexprs, pos),
+                        "exit" => self.compile_exit(sexprs, pos),
+                        "open" => self.compile_file_call(SYS_OPEN, sexprs, pos),
+                        "read" => self.compile_file_call(SYS_READ, sexprs, pos),
+                        "write" => self.compile_file_call(SYS_WRITE, sexprs, pos),
+                        "close" => self.compile_file_call(SYS_CLOSE, sexprs, pos),
+                        "case" => self.compile_case(sexprs, pos),
+                        "case-str" => self.compile_case_str(sexprs, pos),
+                        _ => self.compile_call(word, sexprs, pos),
                     },
-                    _ => Err(Located {
-                        value: CompileError::InvalidHead,
+                    head => Err(Located {
+                        value: CompileError::InvalidHead(snippet(&Located {
+                            value: head,
+                            pos: head_pos,
+                        })),
                         pos: head_pos,
                     }),
                 }
             }
-            SExpr::Word(_) => todo!(),
-            SExpr::Int(int) => {
-                self.write(Instruction::Mov {
-                    dest: Destination::Register(Register {
-                        name: RegisterName::A,
-                        size: RegisterSize::S32,
+            SExpr::Word(word) => {
+                if let Some(value) = self.consts.get(&word).copied() {
+                    let value = i32::try_from(value).map_err(|_| Located {
+                        value: CompileError::OutOfRange(value.into()),
+                        pos,
+                    })?;
+                    self.write(Instruction::Mov {
+                        dest: Destination::Register(Register {
+                            name: RegisterName::A,
+                            size: RegisterSize::S32,
+                        }),
+                        src: Source::Int(value),
+                    });
+                    return Ok(Type::Int(IntType::S32));
+                }
+                if self.local(word).is_none() {
+                    if let Some(typ) = self.globals.get(&word).cloned() {
+                        return self.compile_global_read(word, typ, pos);
+                    }
+                }
+                let Some(Local { typ, offset, .. }) = self.use_local(word) else {
+                    let frame = self.frame();
+                    let locals = frame.scopes.iter().flat_map(|scope| scope.locals.keys());
+                    let names = locals.chain(self.globals.keys()).chain(self.consts.keys());
+                    return Err(Located {
+                        value: CompileError::NotFound {
+                            name: word.to_string(),
+                            suggestion: suggest(word.as_str(), names.map(|name| name.as_str())),
+                        },
+                        pos,
+                    });
+                };
+                let Some(size) = RegisterSize::typ(&typ) else {
+                    return Err(Located {
+                        value: CompileError::InvalidType(typ),
+                        pos,
+                    });
+                };
+                self.load(
+                    &typ,
+                    Source::Memory(Memory {
+                        data_type: size.into(),
+                        address: Address::offset(
+                            Register {
+                                name: RegisterName::BP,
+                                size: RegisterSize::S32,
+                            },
+                            offset,
+                        ),
                     }),
-                    src: Source::Int(int),
-                });
-                Ok(Type::Int(IntType::S32))
+                );
+                Ok(typ)
+            }
+            SExpr::Int(int, Some(typ)) => self.compile_int(int, &typ, pos),
+            // without a type from its context a literal is an `i32`
+            SExpr::Int(int, None) => self.compile_int(int, &Type::Int(IntType::S32), pos),
+            SExpr::Float(float, Some(typ)) => self.compile_float(float, &typ, pos),
+            // without a type from its context a float literal is an `f64`
+            SExpr::Float(float, None) => {
+                self.compile_float(float, &Type::Float(FloatType::S64), pos)
             }
-            SExpr::Float(_) => todo!(),
+            SExpr::Keyword(_) => Err(Located {
+                value: CompileError::InvalidForm("keyword"),
+                pos,
+            }),
+            SExpr::Bracket(_) => Err(Located {
+                value: CompileError::InvalidForm("bracket"),
+                pos,
+            }),
+            SExpr::Brace(_) => Err(Located {
+                value: CompileError::InvalidForm("brace"),
+                pos,
+            }),
+            SExpr::Quoted(kind, _) => Err(Located {
+                value: CompileError::InvalidForm(match kind {
+                    QuoteKind::Quote => "quote",
+                    QuoteKind::Quasiquote => "quasiquote",
+                    QuoteKind::Unquote => "unquote",
+                }),
+                pos,
+            }),
             SExpr::String(string) => {
                 let size = string.len() + 1; // \0 at the end
                 let constant = self.new_string(string);
-                self.write(Instruction::Push {
-                    src: Source::Name(constant),
+                self.write(Instruction::Lea {
+                    dest: Register {
+                        name: RegisterName::A,
+                        size: RegisterSize::S32,
+                    },
+                    addr: Address::label(constant),
+                });
+                Ok(Type::Array {
+                    typ: Box::new(Type::UInt(IntType::S8)),
+                    size: Some(size),
+                })
+            }
+            SExpr::ByteString(string) => {
+                let bytes = string_bytes(&string);
+                let size = bytes.len();
+                let constant = self.frame_mut().new_bytes(bytes);
+                self.write(Instruction::Lea {
+                    dest: Register {
+                        name: RegisterName::A,
+                        size: RegisterSize::S32,
+                    },
+                    addr: Address::label(constant),
                 });
                 Ok(Type::Array {
                     typ: Box::new(Type::UInt(IntType::S8)),
@@ -299,35 +945,2041 @@ impl Compiler {
             }
         }
     }
-}
-impl Display for CompileError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CompileError::NotFound(word) => write!(f, "{word:?} not found"),
-            CompileError::ExpectedArgs(amount) => write!(f, "expected {amount} arguments"),
-            CompileError::InvalidHead => write!(f, "invalid head"),
-            CompileError::InvalidType(typ) => write!(f, "invalid type {typ}"),
-            CompileError::InvalidTypeExpected { expected, got } => {
-                write!(f, "expected {expected}, got {got}")
+    /// pushes the arguments right to left and returns the amount of bytes pushed
+    /// with the argument types in source order
+    pub fn push_args(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        params: &[Type],
+    ) -> Result<(usize, Vec<Located<Type>>), Located<CompileError>> {
+        let mut args = 0;
+        let mut types = vec![];
+        for (idx, sexpr) in sexprs.into_iter().enumerate().rev() {
+            let pos = sexpr.pos;
+            let typ = self.compile_expecting(sexpr, params.get(idx))?;
+            types.push(Located {
+                value: typ.clone(),
+                pos,
+            });
+            match typ {
+                Type::Array { .. } => {
+                    args += RegisterSize::S32.bytes();
+                    self.write(Instruction::Push {
+                        src: Source::Register(Register {
+                            name: RegisterName::A,
+                            size: RegisterSize::S32,
+                        }),
+                    });
+                }
+                typ => {
+                    let Some(size) = self.widen(&typ) else {
+                        return Err(Located {
+                            value: CompileError::InvalidType(typ),
+                            pos,
+                        });
+                    };
+                    args += self.push_value(size);
+                    if params.get(idx).is_none() && typ == Type::Float(FloatType::S32) {
+                        args += self.promote_float();
+                    }
+                }
             }
-            CompileError::UnknownSize => write!(f, "unknown size"),
         }
+        types.reverse();
+        Ok((args, types))
     }
-}
-impl Display for Located<CompileError> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}:{}: {}",
-            self.pos.ln + 1,
-            self.pos.col + 1,
-            self.value
-        )
+    /// `(addr-of name)`: the address of a function as a function pointer
+    pub fn compile_addr_of(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.len() != 1 {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        }
+        let name = sexprs.remove(0);
+        let pos = name.pos;
+        let SExpr::Word(name) = name.value else {
+            return Err(Located {
+                value: CompileError::InvalidHead(snippet(&name)),
+                pos,
+            });
+        };
+        let Some(typ) = self.function_type(name) else {
+            return Err(Located {
+                value: CompileError::NotFound {
+                    name: name.to_string(),
+                    suggestion: self.suggest_function(name),
+                },
+                pos,
+            });
+        };
+        self.write(Instruction::Lea {
+            dest: Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            },
+            addr: Address::label(self.symbol(name)),
+        });
+        Ok(typ)
     }
-}
-
-pub fn compile_program(program: Vec<Located<SExpr>>) -> Result<Program, Located<CompileError>> {
-    let mut compiler = Compiler::default();
-    compiler.compile_program(program)?;
-    Ok(compiler.program)
+    /// `(call-ptr p args...)`: calls the function pointer `p`
+    pub fn compile_call_ptr(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        }
+        let ptr = sexprs.remove(0);
+        let ptr_pos = ptr.pos;
+        let params = match self.type_hint(&ptr) {
+            Some(Type::Func { params, .. }) => params,
+            _ => Box::default(),
+        };
+        let (args, types) = self.push_args(sexprs, &params)?;
+        let ret = match self.compile(ptr)? {
+            Type::Func {
+                params,
+                variadic,
+                ret,
+            } => {
+                check_args(&params, variadic, &types, pos)?;
+                *ret
+            }
+            typ => {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ),
+                    pos: ptr_pos,
+                })
+            }
+        };
+        self.write(Instruction::CallIndirect(Source::Register(Register {
+            name: RegisterName::A,
+            size: RegisterSize::S32,
+        })));
+        self.write(Instruction::Add {
+            dest: Destination::Register(Register {
+                name: RegisterName::SP,
+                size: RegisterSize::S32,
+            }),
+            src: Source::Amount(args),
+        });
+        Ok(ret)
+    }
+    /// the type of the function `name`, an extern declared without a signature taking any
+    /// arguments and returning nothing like its calls do
+    pub fn function_type(&self, name: Symbol) -> Option<Type> {
+        if let Some(Signature {
+            params,
+            ret,
+            variadic,
+            ..
+        }) = self.signatures.get(&name)
+        {
+            return Some(Type::Func {
+                params: params.clone().into(),
+                variadic: *variadic,
+                ret: Box::new(ret.clone()),
+            });
+        }
+        self.program
+            .externs
+            .iter()
+            .any(|extern_name| name == extern_name.as_str())
+            .then(|| Type::Func {
+                params: Box::default(),
+                variadic: true,
+                ret: Box::new(Type::None),
+            })
+    }
+    /// the local `name` refers to, looking from the innermost scope outwards
+    pub fn local(&self, name: Symbol) -> Option<&Local> {
+        self.frame()
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.locals.get(&name))
+    }
+    /// the local `name` refers to like `local`, marking it as read
+    pub fn use_local(&mut self, name: Symbol) -> Option<Local> {
+        let local = self
+            .frame_mut()
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.locals.get_mut(&name))?;
+        local.used = true;
+        Some(local.clone())
+    }
+    /// parses a type expression, resolving the type parameters of the current instantiation
+    pub fn typ(
+        &self,
+        Located { value: sexpr, pos }: &Located<SExpr>,
+    ) -> Result<Type, Located<CompileError>> {
+        match sexpr {
+            SExpr::Word(word) => {
+                if let Some(typ) = self.frame().types.get(word) {
+                    return Ok(typ.clone());
+                }
+                word.as_str().parse().map_err(|_| Located {
+                    value: CompileError::UnknownType(word.to_string()),
+                    pos: *pos,
+                })
+            }
+            SExpr::Expr(sexprs) if matches!(sexprs.first(), Some(Located { value: SExpr::Word(head), .. }) if head == "fn") =>
+            {
+                let invalid = || Located {
+                    value: CompileError::InvalidForm("fn"),
+                    pos: *pos,
+                };
+                let [_, params, ret] = sexprs.as_slice() else {
+                    return Err(invalid());
+                };
+                let SExpr::Expr(params) = &params.value else {
+                    return Err(invalid());
+                };
+                let (params, variadic) = match params.split_last() {
+                    Some((
+                        Located {
+                            value: SExpr::Word(word),
+                            ..
+                        },
+                        params,
+                    )) if word == "..." => (params, true),
+                    _ => (params.as_slice(), false),
+                };
+                Ok(Type::Func {
+                    params: params
+                        .iter()
+                        .map(|param| self.typ(param))
+                        .collect::<Result<_, _>>()?,
+                    variadic,
+                    ret: Box::new(self.typ(ret)?),
+                })
+            }
+            SExpr::Expr(sexprs) if matches!(sexprs.first(), Some(Located { value: SExpr::Word(head), .. }) if head == "array") =>
+            {
+                let [_, typ, size] = sexprs.as_slice() else {
+                    return Err(Located {
+                        value: CompileError::InvalidForm("array"),
+                        pos: *pos,
+                    });
+                };
+                let typ = self.typ(typ)?;
+                let size_pos = size.pos;
+                let size = const_eval(self, size)?;
+                let size = usize::try_from(size).map_err(|_| Located {
+                    value: CompileError::OutOfRange(size.into()),
+                    pos: size_pos,
+                })?;
+                Ok(Type::Array {
+                    typ: Box::new(typ),
+                    size: Some(size),
+                })
+            }
+            sexpr => Err(Located {
+                value: CompileError::UnknownType(
+                    Located {
+                        value: sexpr.clone(),
+                        pos: *pos,
+                    }
+                    .to_string(),
+                ),
+                pos: *pos,
+            }),
+        }
+    }
+    /// `(defn name [((T)...)] ((param type)...) ret body...)`
+    pub fn compile_defn(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Defn {
+            name,
+            type_params,
+            params,
+            variadic,
+            ret,
+            body,
+        } = parse_defn(sexprs, pos)?;
+        self.check_redefinition(name, pos)?;
+        if !type_params.is_empty() && variadic {
+            return Err(Located {
+                value: CompileError::Unsupported("variadic generic functions"),
+                pos,
+            });
+        }
+        if !type_params.is_empty() && self.exports.contains_key(&name) {
+            return Err(Located {
+                value: CompileError::Unsupported("exported generic functions"),
+                pos,
+            });
+        }
+        if type_params.is_empty() {
+            self.compile_function(name, HashMap::new(), params, variadic, ret, body)?;
+        } else {
+            self.generics.insert(
+                name,
+                Generic {
+                    type_params,
+                    params,
+                    ret,
+                    body,
+                },
+            );
+        }
+        Ok(Type::None)
+    }
+    /// `(name ((param type)...) ret)` in an `extern`: a C function whose calls are type checked
+    pub fn typed_extern(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<(), Located<CompileError>> {
+        let invalid = Located {
+            value: CompileError::InvalidForm("extern"),
+            pos,
+        };
+        let defn = parse_defn(sexprs, pos).map_err(|_| invalid.clone())?;
+        if !defn.type_params.is_empty() || !defn.body.is_empty() {
+            return Err(invalid);
+        }
+        let mut signature = self.function_signature(&defn.params, &defn.ret)?;
+        signature.variadic = defn.variadic;
+        // headers may declare the same function more than once
+        if self.signatures.get(&defn.name) != Some(&signature) {
+            self.check_redefinition(defn.name, pos)?;
+        }
+        self.signatures.insert(defn.name, signature);
+        self.use_extern(defn.name.as_str());
+        Ok(())
+    }
+    /// errors if a function called `name` was already defined, other than by declaring the
+    /// definition at `pos` ahead of it
+    pub fn check_redefinition(
+        &self,
+        name: Symbol,
+        pos: Position,
+    ) -> Result<(), Located<CompileError>> {
+        if self.declarations.get(&name) == Some(&pos) {
+            return Ok(());
+        }
+        if self.signatures.contains_key(&name) || self.generics.contains_key(&name) {
+            return Err(Located {
+                value: CompileError::Redefined(name.to_string()),
+                pos,
+            });
+        }
+        Ok(())
+    }
+    /// resolves the parameter and return types of a function in the current frame
+    pub fn function_signature(
+        &self,
+        params: &[(Symbol, Located<SExpr>)],
+        ret: &Located<SExpr>,
+    ) -> Result<Signature, Located<CompileError>> {
+        let mut signature = Signature {
+            names: vec![],
+            params: vec![],
+            ret: self.typ(ret)?,
+            variadic: false,
+        };
+        for (param, typ) in params {
+            let typ_pos = typ.pos;
+            let typ = self.typ(typ)?;
+            if RegisterSize::typ(&typ).is_none() {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ),
+                    pos: typ_pos,
+                });
+            }
+            signature.names.push(*param);
+            signature.params.push(typ);
+        }
+        Ok(signature)
+    }
+    /// compiles a function definition into its own frame, binding `types` for its type parameters
+    pub fn compile_function(
+        &mut self,
+        name: Symbol,
+        types: HashMap<Symbol, Type>,
+        params: Vec<(Symbol, Located<SExpr>)>,
+        variadic: bool,
+        ret: Located<SExpr>,
+        body: Vec<Located<SExpr>>,
+    ) -> Result<(), Located<CompileError>> {
+        let symbol = self.define_symbol(name);
+        self.push_frame(symbol);
+        self.frame_mut().types = types;
+        let mut signature = self.function_signature(&params, &ret)?;
+        signature.variadic = variadic;
+        // skip the return address and the saved base pointer
+        let mut offset = 2 * RegisterSize::S32.bytes() as i32;
+        let positions = params.iter().map(|(_, typ)| typ.pos);
+        for ((param, typ), pos) in signature.names.iter().zip(&signature.params).zip(positions) {
+            self.declare_local(
+                0,
+                *param,
+                Local {
+                    typ: typ.clone(),
+                    offset,
+                    pos,
+                    used: false,
+                },
+            );
+            let size = RegisterSize::typ(typ).map_or(0, |size| size.bytes());
+            offset += size.max(RegisterSize::S32.bytes()) as i32;
+        }
+        if variadic {
+            // the variadic arguments follow the parameters, `va-arg` reads them through a
+            // pointer kept below the saved base pointer
+            self.write(Instruction::Lea {
+                dest: Register {
+                    name: RegisterName::A,
+                    size: RegisterSize::S32,
+                },
+                addr: Address {
+                    base: Some(Register {
+                        name: RegisterName::BP,
+                        size: RegisterSize::S32,
+                    }),
+                    ..Address::at(offset)
+                },
+            });
+            self.write(Instruction::Push {
+                src: Source::Register(Register {
+                    name: RegisterName::A,
+                    size: RegisterSize::S32,
+                }),
+            });
+            self.frame_mut().varargs = Some(-(RegisterSize::S32.bytes() as i32));
+            // locals go below the pointer
+            let frame = self.frame_mut();
+            frame.scopes[0].depth = frame.depth;
+        }
+        let ret_typ = signature.ret.clone();
+        self.signatures.insert(name, signature);
+        // `return` checks its value against it
+        self.frame_mut().function.return_type = ret_typ.clone();
+        let pos = body.last().map_or(ret.pos, |sexpr| sexpr.pos);
+        let typ = self.compile_body(body, Some(&ret_typ))?;
+        if typ != Type::Never {
+            self.leave_scopes(0)?;
+        }
+        // a body which never returns has the value of any type
+        if ret_typ != Type::None && typ != Type::Never && typ != ret_typ {
+            return Err(Located {
+                value: CompileError::InvalidTypeExpected {
+                    expected: ret_typ,
+                    got: typ,
+                },
+                pos,
+            });
+        }
+        self.pop_frame();
+        Ok(())
+    }
+    /// instantiates the generic function `name` for the argument types, returning the mangled name
+    pub fn instantiate(
+        &mut self,
+        name: Symbol,
+        args: &[Located<Type>],
+        pos: Position,
+    ) -> Result<Symbol, Located<CompileError>> {
+        let Generic {
+            type_params,
+            params,
+            ret,
+            body,
+        } = self.generics[&name].clone();
+        if args.len() != params.len() {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(params.len()),
+                pos,
+            });
+        }
+        let mut types: HashMap<Symbol, Type> = HashMap::new();
+        for ((_, typ), arg) in params.iter().zip(args) {
+            let SExpr::Word(word) = &typ.value else {
+                continue;
+            };
+            if !type_params.contains(word) {
+                continue;
+            }
+            match types.get(word) {
+                Some(bound) if bound != &arg.value => {
+                    return Err(Located {
+                        value: CompileError::InvalidTypeExpected {
+                            expected: bound.clone(),
+                            got: arg.value.clone(),
+                        },
+                        pos: arg.pos,
+                    })
+                }
+                Some(_) => {}
+                None => {
+                    types.insert(*word, arg.value.clone());
+                }
+            }
+        }
+        let mut instance = vec![];
+        for type_param in &type_params {
+            let Some(typ) = types.get(type_param) else {
+                return Err(Located {
+                    value: CompileError::CannotInfer(type_param.to_string()),
+                    pos,
+                });
+            };
+            instance.push(typ.to_string());
+        }
+        // parentheses can't be part of a name, so instances never collide with functions
+        let instance = Symbol::from(format!("{name}({})", instance.join(",")));
+        if !self.signatures.contains_key(&instance) {
+            self.compile_function(instance, types, params, false, ret, body)?;
+        }
+        Ok(instance)
+    }
+    /// `(alloc type count)`: allocates `count` values of `type` on the heap
+    pub fn compile_alloc(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([typ, count]) = <[Located<SExpr>; 2]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(2),
+                pos,
+            });
+        };
+        let typ_pos = typ.pos;
+        let typ = self.typ(&typ)?;
+        let Some(size) = typ.size() else {
+            return Err(Located {
+                value: CompileError::UnknownSize(typ),
+                pos: typ_pos,
+            });
+        };
+        let count_pos = count.pos;
+        let count_typ = self.compile(count)?;
+        if !matches!(count_typ, Type::Int(_) | Type::UInt(_)) {
+            return Err(Located {
+                value: CompileError::InvalidType(count_typ),
+                pos: count_pos,
+            });
+        }
+        let Some(count_size) = self.widen(&count_typ) else {
+            return Err(Located {
+                value: CompileError::InvalidType(count_typ),
+                pos: count_pos,
+            });
+        };
+        if size.is_power_of_two() {
+            self.shift_left(
+                Register {
+                    name: RegisterName::A,
+                    size: count_size,
+                },
+                size.trailing_zeros() as u8,
+            );
+        } else {
+            let b = Register {
+                name: RegisterName::B,
+                size: count_size,
+            };
+            self.write(Instruction::Mov {
+                dest: Destination::Register(b),
+                src: Source::Amount(size),
+            });
+            self.write(Instruction::Mul {
+                src: Source::Register(b),
+            });
+        }
+        self.write(Instruction::Push {
+            src: Source::Register(Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            }),
+        });
+        let func = self.alloc_function();
+        self.call(func, RegisterSize::S32.bytes());
+        Ok(Type::Pointer(Box::new(typ)))
+    }
+    /// `(free p)`: frees a pointer returned by `alloc`, a no-op with the bump allocator
+    pub fn compile_free(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([ptr]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        let ptr_pos = ptr.pos;
+        let typ = self.compile(ptr)?;
+        if !matches!(typ, Type::Pointer(_)) {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: ptr_pos,
+            });
+        }
+        if self.bump_allocator {
+            return Ok(Type::None);
+        }
+        self.use_extern("free");
+        self.write(Instruction::Push {
+            src: Source::Register(Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            }),
+        });
+        self.call("free", RegisterSize::S32.bytes());
+        Ok(Type::None)
+    }
+    /// emits the bump allocator runtime on first use and returns its name, it returns a null
+    /// pointer once the allocation wouldn't fit into the rest of the heap
+    pub fn bump_alloc_function(&mut self) -> Symbol {
+        let name = Symbol::intern("lerp_alloc");
+        if self.signatures.contains_key(&name) {
+            return name;
+        }
+        self.signatures.insert(
+            name,
+            Signature {
+                names: vec![Symbol::intern("size")],
+                params: vec![Type::UInt(IntType::Size)],
+                ret: Type::Pointer(Box::new(Type::UInt(IntType::S8))),
+                variadic: false,
+            },
+        );
+        // calls to it are checked against the signature alone
+        if self.options.check_only {
+            return name;
+        }
+        let heap = "lerp_heap".to_string();
+        let top = "lerp_heap_top".to_string();
+        self.program.bss.push((heap.clone(), BUMP_HEAP_SIZE));
+        self.program
+            .bss
+            .push((top.clone(), RegisterSize::S32.bytes()));
+        let a = Register {
+            name: RegisterName::A,
+            size: RegisterSize::S32,
+        };
+        let c = Register {
+            name: RegisterName::C,
+            size: RegisterSize::S32,
+        };
+        let top = Memory {
+            data_type: DataType::DoubleWord,
+            address: Address::label(top),
+        };
+        self.push_frame(name.to_string());
+        let labels = self.new_labels();
+        let (full, done) = (
+            Symbol::intern(&format!("full{labels}")),
+            Symbol::intern(&format!("done{labels}")),
+        );
+        self.write(Instruction::Mov {
+            dest: Destination::Register(a),
+            src: Source::Memory(top.clone()),
+        });
+        // round the requested size up to keep every allocation 4 byte aligned, a size larger
+        // than the heap is rejected first as rounding it up could wrap around
+        self.write(Instruction::Mov {
+            dest: Destination::Register(c),
+            src: Source::Memory(Memory {
+                data_type: DataType::DoubleWord,
+                address: Address::offset(
+                    Register {
+                        name: RegisterName::BP,
+                        size: RegisterSize::S32,
+                    },
+                    2 * RegisterSize::S32.bytes() as i32,
+                ),
+            }),
+        });
+        let exhausted = |compiler: &mut Self| {
+            compiler.write(Instruction::Cmp {
+                a: Source::Register(c),
+                b: Source::Amount(BUMP_HEAP_SIZE),
+            });
+            compiler.write(Instruction::JOp {
+                op: ComparisonOperator::GreaterUnsigned,
+                label: full,
+            });
+        };
+        exhausted(self);
+        self.write(Instruction::Add {
+            dest: Destination::Register(c),
+            src: Source::Amount(3),
+        });
+        self.write(Instruction::And {
+            dest: Destination::Register(c),
+            src: Source::Int(-4),
+        });
+        self.write(Instruction::Add {
+            dest: Destination::Register(c),
+            src: Source::Register(a),
+        });
+        // the top pointer is right after the heap, it mustn't be allocated over
+        exhausted(self);
+        self.write(Instruction::Mov {
+            dest: Destination::Memory(top),
+            src: Source::Register(c),
+        });
+        self.write(Instruction::Lea {
+            dest: a,
+            addr: Address {
+                base: Some(a),
+                label: Some(heap),
+                ..Default::default()
+            },
+        });
+        self.write(Instruction::Jmp { label: done });
+        self.write(Instruction::Label(full));
+        self.write(Instruction::Mov {
+            dest: Destination::Register(a),
+            src: Source::Amount(0),
+        });
+        self.write(Instruction::Label(done));
+        self.pop_frame();
+        name
+    }
+    /// emits the panic runtime on first use and returns its name, it prints the message it's
+    /// called with and exits with `PANIC_EXIT_CODE`
+    pub fn panic_function(&mut self) -> Symbol {
+        let name = Symbol::intern("lerp_panic");
+        if self.signatures.contains_key(&name) {
+            return name;
+        }
+        self.signatures.insert(
+            name,
+            Signature {
+                names: vec![Symbol::intern("message")],
+                params: vec![Type::Pointer(Box::new(Type::UInt(IntType::S8)))],
+                ret: Type::Never,
+                variadic: false,
+            },
+        );
+        if self.options.check_only {
+            return name;
+        }
+        self.use_extern("puts");
+        self.use_extern("exit");
+        self.push_frame(name.to_string());
+        self.write(Instruction::Push {
+            src: Source::Memory(Memory {
+                data_type: DataType::DoubleWord,
+                address: Address::offset(
+                    Register {
+                        name: RegisterName::BP,
+                        size: RegisterSize::S32,
+                    },
+                    2 * RegisterSize::S32.bytes() as i32,
+                ),
+            }),
+        });
+        self.call("puts", RegisterSize::S32.bytes());
+        self.write(Instruction::Push {
+            src: Source::Int(PANIC_EXIT_CODE),
+        });
+        self.call("exit", RegisterSize::S32.bytes());
+        self.pop_frame();
+        name
+    }
+    /// calls `func` and pops `args` bytes of arguments afterwards
+    pub fn call(&mut self, func: impl Into<Symbol>, args: usize) {
+        self.write(Instruction::Call { func: func.into() });
+        if args > 0 {
+            self.write(Instruction::Add {
+                dest: Destination::Register(Register {
+                    name: RegisterName::SP,
+                    size: RegisterSize::S32,
+                }),
+                src: Source::Amount(args),
+            });
+        }
+    }
+    /// calls the auto-declared extern `func` with exactly `arity` arguments
+    pub fn call_extern(
+        &mut self,
+        func: &str,
+        sexprs: Vec<Located<SExpr>>,
+        arity: usize,
+        pos: Position,
+    ) -> Result<Vec<Located<Type>>, Located<CompileError>> {
+        if sexprs.len() != arity {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(arity),
+                pos,
+            });
+        }
+        self.use_extern(func);
+        let (args, types) = self.push_args(sexprs, &[])?;
+        self.call(func, args);
+        Ok(types)
+    }
+    /// checks that every argument is a string (`u8[n]` or `*u8`)
+    pub fn expect_strings(&self, types: Vec<Located<Type>>) -> Result<(), Located<CompileError>> {
+        for Located { value: typ, pos } in types {
+            if !typ.is_string() {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ),
+                    pos,
+                });
+            }
+        }
+        Ok(())
+    }
+    /// `(str-len s)`: the length of a string without the terminating zero
+    pub fn compile_str_len(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let types = self.call_extern("strlen", sexprs, 1, pos)?;
+        self.expect_strings(types)?;
+        Ok(Type::UInt(IntType::Size))
+    }
+    /// `(str-eq a b)`: 1 if both strings are equal, 0 otherwise
+    pub fn compile_str_eq(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let types = self.call_extern("strcmp", sexprs, 2, pos)?;
+        self.expect_strings(types)?;
+        self.write(Instruction::Cmp {
+            a: Source::Register(Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            }),
+            b: Source::Int(0),
+        });
+        self.write(Instruction::Set {
+            op: ComparisonOperator::Equal,
+            dest: Destination::Register(Register {
+                name: RegisterName::A,
+                size: RegisterSize::S8,
+            }),
+        });
+        self.widen(&Type::UInt(IntType::S8));
+        Ok(Type::Int(IntType::S32))
+    }
+    /// `(str-cat a b)`: a newly allocated string holding `a` followed by `b`
+    pub fn compile_str_cat(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.len() != 2 {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(2),
+                pos,
+            });
+        }
+        let (args, types) = self.push_args(sexprs, &[])?;
+        self.expect_strings(types)?;
+        let func = self.str_cat_function();
+        self.call(func, args);
+        Ok(Type::Pointer(Box::new(Type::UInt(IntType::S8))))
+    }
+    /// emits the string concatenation runtime on first use and returns its name
+    pub fn str_cat_function(&mut self) -> Symbol {
+        let name = Symbol::intern("lerp_str_cat");
+        if self.signatures.contains_key(&name) {
+            return name;
+        }
+        let string = Type::Pointer(Box::new(Type::UInt(IntType::S8)));
+        self.signatures.insert(
+            name,
+            Signature {
+                names: vec![Symbol::intern("a"), Symbol::intern("b")],
+                params: vec![string.clone(), string.clone()],
+                ret: string,
+                variadic: false,
+            },
+        );
+        if self.options.check_only {
+            return name;
+        }
+        let alloc = self.alloc_function();
+        for func in ["strlen", "strcpy", "strcat"] {
+            self.use_extern(func);
+        }
+        let a = Register {
+            name: RegisterName::A,
+            size: RegisterSize::S32,
+        };
+        let param = |idx: i32| {
+            Source::Memory(Memory {
+                data_type: DataType::DoubleWord,
+                address: Address::offset(
+                    Register {
+                        name: RegisterName::BP,
+                        size: RegisterSize::S32,
+                    },
+                    (2 + idx) * RegisterSize::S32.bytes() as i32,
+                ),
+            })
+        };
+        self.push_frame(name.to_string());
+        // length of `a`, kept at [ebp-4]
+        self.write(Instruction::Push { src: param(0) });
+        self.call("strlen", 4);
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        self.write(Instruction::Push { src: param(1) });
+        self.call("strlen", 4);
+        self.write(Instruction::Add {
+            dest: Destination::Register(a),
+            src: Source::Memory(Memory {
+                data_type: DataType::DoubleWord,
+                address: Address::offset(
+                    Register {
+                        name: RegisterName::BP,
+                        size: RegisterSize::S32,
+                    },
+                    -(RegisterSize::S32.bytes() as i32),
+                ),
+            }),
+        });
+        self.write(Instruction::Add {
+            dest: Destination::Register(a),
+            src: Source::Amount(1),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        self.call(alloc, 4);
+        self.write(Instruction::Push { src: param(0) });
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        self.call("strcpy", 8);
+        self.write(Instruction::Push { src: param(1) });
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        self.call("strcat", 8);
+        self.pop_frame();
+        name
+    }
+    /// `(print args...)` / `(println args...)`: prints the space separated arguments with
+    /// `printf`, choosing the conversion from each argument's type
+    pub fn compile_print(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        newline: bool,
+    ) -> Result<Type, Located<CompileError>> {
+        let (args, types) = self.push_args(sexprs, &[])?;
+        let mut format = vec![];
+        for Located { value: typ, pos } in types {
+            let Some(spec) = typ.printf_spec() else {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ),
+                    pos,
+                });
+            };
+            format.push(spec);
+        }
+        let mut format = format.join(" ");
+        if newline {
+            format.push_str("\\n");
+        }
+        let format = self.new_string(format);
+        self.write(Instruction::Lea {
+            dest: Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            },
+            addr: Address::label(format),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            }),
+        });
+        self.use_extern("printf");
+        self.call("printf", args + RegisterSize::S32.bytes());
+        Ok(Type::None)
+    }
+    /// the allocation function `alloc` lowers to
+    pub fn alloc_function(&mut self) -> Symbol {
+        if self.bump_allocator {
+            self.bump_alloc_function()
+        } else {
+            self.use_extern("malloc");
+            Symbol::intern("malloc")
+        }
+    }
+    /// `(format "x={} y={}" x y)`: a newly allocated string with every `{}` replaced by
+    /// the next argument, lowered to `snprintf`
+    pub fn compile_format(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        }
+        let Located {
+            value: SExpr::String(template),
+            pos: template_pos,
+        } = sexprs.remove(0)
+        else {
+            return Err(Located {
+                value: CompileError::InvalidForm("format"),
+                pos,
+            });
+        };
+        let pieces = split_template(&template).ok_or(Located {
+            value: CompileError::InvalidForm("format"),
+            pos: template_pos,
+        })?;
+        if pieces.len() - 1 != sexprs.len() {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(pieces.len()),
+                pos,
+            });
+        }
+        let (args, types) = self.push_args(sexprs, &[])?;
+        let mut format = pieces[0].replace('%', "%%");
+        for (Located { value: typ, pos }, piece) in types.into_iter().zip(&pieces[1..]) {
+            let Some(spec) = typ.printf_spec() else {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ),
+                    pos,
+                });
+            };
+            format.push_str(spec);
+            format.push_str(&piece.replace('%', "%%"));
+        }
+        let format = self.new_string(format);
+        let a = Register {
+            name: RegisterName::A,
+            size: RegisterSize::S32,
+        };
+        let b = Register {
+            name: RegisterName::B,
+            size: RegisterSize::S32,
+        };
+        let c = Register {
+            name: RegisterName::C,
+            size: RegisterSize::S32,
+        };
+        self.use_extern("snprintf");
+        self.write(Instruction::Lea {
+            dest: a,
+            addr: Address::label(format),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        // measure the formatted length with `snprintf(NULL, 0, ...)`
+        self.write(Instruction::Push {
+            src: Source::Int(0),
+        });
+        self.write(Instruction::Push {
+            src: Source::Int(0),
+        });
+        self.call("snprintf", 2 * RegisterSize::S32.bytes());
+        self.write(Instruction::Add {
+            dest: Destination::Register(a),
+            src: Source::Amount(1),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        let alloc = self.alloc_function();
+        self.call(alloc, RegisterSize::S32.bytes());
+        self.write(Instruction::Pop {
+            dest: Destination::Register(c),
+        });
+        // the buffer survives the call in the callee-saved B register
+        self.write(Instruction::Mov {
+            dest: Destination::Register(b),
+            src: Source::Register(a),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(c),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(a),
+        });
+        self.call("snprintf", args + 3 * RegisterSize::S32.bytes());
+        self.write(Instruction::Mov {
+            dest: Destination::Register(a),
+            src: Source::Register(b),
+        });
+        Ok(Type::Pointer(Box::new(Type::UInt(IntType::S8))))
+    }
+    pub fn compile_global_read(
+        &mut self,
+        name: Symbol,
+        typ: Type,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if let Type::Array { .. } = typ {
+            self.write(Instruction::Lea {
+                dest: Register {
+                    name: RegisterName::A,
+                    size: RegisterSize::S32,
+                },
+                addr: Address::label(name.to_string()),
+            });
+            return Ok(typ);
+        }
+        let Some(size) = RegisterSize::typ(&typ) else {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos,
+            });
+        };
+        self.load(
+            &typ,
+            Source::Memory(Memory {
+                data_type: size.into(),
+                address: Address::label(name.to_string()),
+            }),
+        );
+        Ok(typ)
+    }
+    /// `(const NAME expr)`: a compile-time integer constant
+    pub fn compile_const(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok(
+            [Located {
+                value: SExpr::Word(name),
+                ..
+            }, value],
+        ) = <[Located<SExpr>; 2]>::try_from(sexprs)
+        else {
+            return Err(Located {
+                value: CompileError::InvalidForm("const"),
+                pos,
+            });
+        };
+        let value = const_eval(self, &value)?;
+        self.consts.insert(name, value);
+        Ok(Type::None)
+    }
+    /// `(static-assert condition ["message"])`: fails compilation with the message if the
+    /// constant `condition` is 0, emitting nothing otherwise
+    pub fn compile_static_assert(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let (condition, message) = match sexprs.as_slice() {
+            [condition] => (condition, None),
+            [condition, Located {
+                value: SExpr::String(message),
+                ..
+            }] => (condition, Some(message)),
+            _ => {
+                return Err(Located {
+                    value: CompileError::InvalidForm("static-assert"),
+                    pos,
+                })
+            }
+        };
+        if const_eval(self, condition)? == 0 {
+            return Err(Located {
+                value: CompileError::StaticAssert(
+                    message.cloned().unwrap_or_else(|| snippet(condition)),
+                ),
+                pos: condition.pos,
+            });
+        }
+        Ok(Type::None)
+    }
+    /// `(export name...)`: emits the functions under their own names and makes them visible to
+    /// other objects
+    pub fn compile_export(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::InvalidForm("export"),
+                pos,
+            });
+        }
+        for sexpr in sexprs {
+            let SExpr::Word(name) = sexpr.value else {
+                return Err(Located {
+                    value: CompileError::InvalidForm("export"),
+                    pos: sexpr.pos,
+                });
+            };
+            if self.generics.contains_key(&name) {
+                return Err(Located {
+                    value: CompileError::Unsupported("exported generic functions"),
+                    pos: sexpr.pos,
+                });
+            }
+            // declaring it ahead may have given it its own name already
+            if self
+                .symbols
+                .get(&name)
+                .is_some_and(|symbol| symbol != name.as_str())
+            {
+                return Err(Located {
+                    value: CompileError::LateExport(name.to_string()),
+                    pos: sexpr.pos,
+                });
+            }
+            self.exports.insert(name, sexpr.pos);
+        }
+        Ok(Type::None)
+    }
+    /// the label the function `name` is called by, its mangled name if it was defined in lerp
+    pub fn symbol(&self, name: Symbol) -> String {
+        self.symbols
+            .get(&name)
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+    /// decides the label of the function `name` when it is first declared
+    pub fn define_symbol(&mut self, name: Symbol) -> String {
+        if let Some(symbol) = self.symbols.get(&name) {
+            return symbol.clone();
+        }
+        let symbol = if self.exports.contains_key(&name) {
+            if !self
+                .program
+                .exports
+                .iter()
+                .any(|export| export == name.as_str())
+            {
+                self.program.exports.push(name.to_string());
+            }
+            name.to_string()
+        } else {
+            mangle(name.as_str())
+        };
+        self.symbols.insert(name, symbol.clone());
+        symbol
+    }
+    /// `(link "name"...)`: links the program against the libraries, like `-lname`
+    pub fn compile_link(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::InvalidForm("link"),
+                pos,
+            });
+        }
+        for sexpr in sexprs {
+            let SExpr::String(library) = sexpr.value else {
+                return Err(Located {
+                    value: CompileError::InvalidForm("link"),
+                    pos: sexpr.pos,
+                });
+            };
+            if !self.program.libraries.contains(&library) {
+                self.program.libraries.push(library);
+            }
+        }
+        Ok(Type::None)
+    }
+    /// `(global name type [init])`: a static variable, initialized in `.data` or zeroed in `.bss`
+    pub fn compile_global(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let mut sexprs = sexprs.into_iter();
+        let (
+            Some(Located {
+                value: SExpr::Word(name),
+                ..
+            }),
+            Some(typ),
+            init,
+            None,
+        ) = (sexprs.next(), sexprs.next(), sexprs.next(), sexprs.next())
+        else {
+            return Err(Located {
+                value: CompileError::InvalidForm("global"),
+                pos,
+            });
+        };
+        let typ_pos = typ.pos;
+        let typ = self.typ(&typ)?;
+        let Some(size) = typ.size() else {
+            return Err(Located {
+                value: CompileError::UnknownSize(typ),
+                pos: typ_pos,
+            });
+        };
+        let mut values = vec![];
+        if let Some(init) = init {
+            self.static_values(&typ, &init, &mut values)?;
+        }
+        // zeroed data takes no space in the object file
+        if values.iter().all(|value| *value == 0) {
+            self.program.bss.push((name.to_string(), size));
+        } else {
+            let mut element = &typ;
+            while let Type::Array { typ, .. } = element {
+                element = typ;
+            }
+            let Some(register_size) = RegisterSize::typ(element) else {
+                return Err(Located {
+                    value: CompileError::InvalidType(element.clone()),
+                    pos: typ_pos,
+                });
+            };
+            self.program.data.push(Data {
+                label: name.to_string(),
+                data_type: register_size.into(),
+                values,
+            });
+        }
+        self.globals.insert(name, typ);
+        Ok(Type::None)
+    }
+    /// appends the values of the constant initializer `init` of a `typ` global to `values`, arrays
+    /// are initialized from `[...]` with missing elements zeroed and `u8` arrays also from strings
+    fn static_values(
+        &self,
+        typ: &Type,
+        init: &Located<SExpr>,
+        values: &mut Vec<i64>,
+    ) -> Result<(), Located<CompileError>> {
+        let Type::Array {
+            typ: element,
+            size: Some(size),
+        } = typ
+        else {
+            values.push(const_eval(self, init)?);
+            return Ok(());
+        };
+        let invalid = Located {
+            value: CompileError::InvalidForm("array initializer"),
+            pos: init.pos,
+        };
+        let start = values.len();
+        match &init.value {
+            SExpr::Bracket(items) if items.len() <= *size => {
+                for item in items {
+                    self.static_values(element, item, values)?;
+                }
+            }
+            SExpr::String(string)
+                if **element == Type::UInt(IntType::S8) && string.len() <= *size =>
+            {
+                values.extend(string.bytes().map(i64::from));
+            }
+            SExpr::ByteString(string) if **element == Type::UInt(IntType::S8) => {
+                let bytes = string_bytes(string);
+                if bytes.len() > *size {
+                    return Err(invalid);
+                }
+                values.extend(bytes.into_iter().map(i64::from));
+            }
+            _ => return Err(invalid),
+        }
+        let mut elements = *size;
+        let mut scalar = element;
+        while let Type::Array { typ, size } = scalar.as_ref() {
+            elements *= size.unwrap_or(0);
+            scalar = typ;
+        }
+        values.resize(start + elements, 0);
+        Ok(())
+    }
+    /// `(va-arg type)`: the next variadic argument of the function, which has to be passed as
+    /// `type`
+    pub fn compile_va_arg(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let [typ] = sexprs.as_slice() else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        let Some(cursor) = self.frame().varargs else {
+            return Err(Located {
+                value: CompileError::InvalidForm("va-arg"),
+                pos,
+            });
+        };
+        let typ_pos = typ.pos;
+        let typ = self.typ(typ)?;
+        let Some(size) = RegisterSize::typ(&typ) else {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: typ_pos,
+            });
+        };
+        let ebp = Register {
+            name: RegisterName::BP,
+            size: RegisterSize::S32,
+        };
+        let ecx = Register {
+            name: RegisterName::C,
+            size: RegisterSize::S32,
+        };
+        let cursor = Memory {
+            data_type: RegisterSize::S32.into(),
+            address: Address {
+                base: Some(ebp),
+                ..Address::at(cursor)
+            },
+        };
+        self.write(Instruction::Mov {
+            dest: Destination::Register(ecx),
+            src: Source::Memory(cursor.clone()),
+        });
+        self.load(
+            &typ,
+            Source::Memory(Memory {
+                data_type: size.into(),
+                address: Address {
+                    base: Some(ecx),
+                    ..Default::default()
+                },
+            }),
+        );
+        // arguments smaller than a stack slot still take a whole one
+        self.write(Instruction::Add {
+            dest: Destination::Memory(cursor),
+            src: Source::Int(size.bytes().max(RegisterSize::S32.bytes()) as i32),
+        });
+        Ok(typ)
+    }
+    /// `(sizeof type)`: the size of a type in bytes
+    pub fn compile_sizeof(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let mut sexprs = sexprs;
+        sexprs.insert(
+            0,
+            Located {
+                value: SExpr::Word(Symbol::intern("sizeof")),
+                pos,
+            },
+        );
+        let size = const_eval(
+            self,
+            &Located {
+                value: SExpr::Expr(sexprs),
+                pos,
+            },
+        )?;
+        self.write(Instruction::Mov {
+            dest: Destination::Register(Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            }),
+            src: Source::Amount(size as usize),
+        });
+        Ok(Type::UInt(IntType::Size))
+    }
+    /// reorders `:name value` arguments into the parameter order of the function `name`
+    pub fn named_args(
+        &self,
+        name: Symbol,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Vec<Located<SExpr>>, Located<CompileError>> {
+        if !sexprs
+            .iter()
+            .any(|sexpr| matches!(sexpr.value, SExpr::Keyword(_)))
+        {
+            return Ok(sexprs);
+        }
+        let names = match (self.signatures.get(&name), self.generics.get(&name)) {
+            (Some(signature), _) => signature.names.clone(),
+            (None, Some(generic)) => generic.params.iter().map(|(name, _)| *name).collect(),
+            (None, None) => {
+                return Err(Located {
+                    value: CompileError::InvalidForm("keyword"),
+                    pos,
+                })
+            }
+        };
+        let mut args: Vec<Option<Located<SExpr>>> = vec![None; names.len()];
+        let mut positional = 0;
+        let mut sexprs = sexprs.into_iter();
+        while let Some(sexpr) = sexprs.next() {
+            let (idx, arg) = match sexpr.value {
+                SExpr::Keyword(keyword) => {
+                    let Some(idx) = names.iter().position(|name| name == &keyword) else {
+                        return Err(Located {
+                            value: CompileError::NotFound {
+                                name: keyword.to_string(),
+                                suggestion: suggest(
+                                    keyword.as_str(),
+                                    names.iter().map(|name| name.as_str()),
+                                ),
+                            },
+                            pos: sexpr.pos,
+                        });
+                    };
+                    let Some(arg) = sexprs.next() else {
+                        return Err(Located {
+                            value: CompileError::MissingArg(keyword.to_string()),
+                            pos: sexpr.pos,
+                        });
+                    };
+                    (idx, arg)
+                }
+                value => {
+                    positional += 1;
+                    (
+                        positional - 1,
+                        Located {
+                            value,
+                            pos: sexpr.pos,
+                        },
+                    )
+                }
+            };
+            let Some(slot) = args.get_mut(idx) else {
+                return Err(Located {
+                    value: CompileError::ExpectedArgs(names.len()),
+                    pos,
+                });
+            };
+            if slot.is_some() {
+                return Err(Located {
+                    value: CompileError::DuplicateArg(names[idx].to_string()),
+                    pos: arg.pos,
+                });
+            }
+            *slot = Some(arg);
+        }
+        args.into_iter()
+            .zip(names)
+            .map(|(arg, name)| {
+                arg.ok_or(Located {
+                    value: CompileError::MissingArg(name.to_string()),
+                    pos,
+                })
+            })
+            .collect()
+    }
+    /// calls a user defined function, a generic instantiation or an extern
+    /// the function, extern or form named most like `name`
+    fn suggest_function(&self, name: Symbol) -> Option<String> {
+        let functions = self.signatures.keys().chain(self.generics.keys());
+        let names = functions.map(|name| name.as_str());
+        let externs = self.program.externs.iter().map(String::as_str);
+        suggest(
+            name.as_str(),
+            FORMS.iter().copied().chain(names).chain(externs),
+        )
+    }
+    pub fn compile_call(
+        &mut self,
+        name: Symbol,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if !self.signatures.contains_key(&name)
+            && !self.generics.contains_key(&name)
+            && !self
+                .program
+                .externs
+                .iter()
+                .any(|func| func == name.as_str())
+            && !self.use_prelude(name, pos)?
+        {
+            return Err(Located {
+                value: CompileError::NotFound {
+                    name: name.to_string(),
+                    suggestion: self.suggest_function(name),
+                },
+                pos,
+            });
+        }
+        let sexprs = self.named_args(name, sexprs, pos)?;
+        // the arguments of generic functions decide their parameter types instead
+        let params = self
+            .signatures
+            .get(&name)
+            .map(|signature| signature.params.clone())
+            .unwrap_or_default();
+        let (args, types) = self.push_args(sexprs, &params)?;
+        let func = if self.generics.contains_key(&name) {
+            self.instantiate(name, &types, pos)?
+        } else {
+            name
+        };
+        let ret = match self.signatures.get(&func) {
+            Some(Signature {
+                params,
+                ret,
+                variadic,
+                ..
+            }) => {
+                check_args(params, *variadic, &types, pos)?;
+                ret.clone()
+            }
+            None => Type::None,
+        };
+        self.call(self.symbol(func), args);
+        Ok(ret)
+    }
+}
+/// checks the types of the arguments of a call, at `pos`, against the parameters
+fn check_args(
+    params: &[Type],
+    variadic: bool,
+    args: &[Located<Type>],
+    pos: Position,
+) -> Result<(), Located<CompileError>> {
+    if params.len() != args.len() && !(variadic && args.len() > params.len()) {
+        return Err(Located {
+            value: CompileError::ExpectedArgs(params.len()),
+            pos,
+        });
+    }
+    for (param, arg) in params.iter().zip(args) {
+        if !param.accepts(&arg.value) {
+            return Err(Located {
+                value: CompileError::InvalidTypeExpected {
+                    expected: param.clone(),
+                    got: arg.value.clone(),
+                },
+                pos: arg.pos,
+            });
+        }
+    }
+    Ok(())
+}
+impl CompileError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompileError::NotFound { .. } => "not-found",
+            CompileError::ExpectedArgs(_) => "expected-args",
+            CompileError::InvalidHead(_) => "invalid-head",
+            CompileError::InvalidType(_) => "invalid-type",
+            CompileError::InvalidTypeExpected { .. } => "type-mismatch",
+            CompileError::UnknownSize(_) => "unknown-size",
+            CompileError::InvalidPointerArithmetic { .. } => "invalid-pointer-arithmetic",
+            CompileError::UnknownType(_) => "unknown-type",
+            CompileError::InvalidForm(_) => "invalid-form",
+            CompileError::CannotInfer(_) => "cannot-infer",
+            CompileError::MacroDepth(_) => "macro-depth",
+            CompileError::NotConstant => "not-constant",
+            CompileError::Overflow => "overflow",
+            CompileError::DivisionByZero => "division-by-zero",
+            CompileError::OutOfRange(_) => "out-of-range",
+            CompileError::MissingArg(_) => "missing-argument",
+            CompileError::DuplicateArg(_) => "duplicate-argument",
+            CompileError::Redefined(_) => "redefined",
+            CompileError::LateExport(_) => "late-export",
+            CompileError::DuplicateCase(_) => "duplicate-case",
+            CompileError::RecurOutsideLoop => "recur-outside-loop",
+            CompileError::BreakOutsideLoop => "break-outside-loop",
+            CompileError::NeedsLibc(_) => "needs-libc",
+            CompileError::StaticAssert(_) => "static-assert",
+            CompileError::DeniedLint(warning) => warning.code(),
+            CompileError::UnknownLint(_) => "unknown-lint",
+            CompileError::CannotEmbed { .. } => "cannot-embed",
+            CompileError::StackTooDeep { .. } => "stack-too-deep",
+            CompileError::UnboundedStack { .. } => "unbounded-stack",
+            CompileError::Unsupported(_) => "unsupported",
+            CompileError::Internal(_) => "internal-error",
+        }
+    }
+}
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::NotFound { name, suggestion } => {
+                write!(f, "{name:?} not found")?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean `{suggestion}`?")?;
+                }
+                Ok(())
+            }
+            CompileError::ExpectedArgs(amount) => write!(f, "expected {amount} arguments"),
+            CompileError::InvalidHead(found) => {
+                write!(
+                    f,
+                    "expected a name at the head of the form, found `{found}`"
+                )
+            }
+            CompileError::InvalidType(typ) => write!(f, "invalid type {typ}"),
+            CompileError::InvalidTypeExpected { expected, got } => {
+                write!(f, "expected {expected}, got {got}")
+            }
+            CompileError::UnknownSize(typ) => write!(f, "the size of {typ} is unknown"),
+            CompileError::InvalidPointerArithmetic { left, right } => {
+                write!(f, "invalid pointer arithmetic on {left} and {right}")
+            }
+            CompileError::UnknownType(typ) => write!(f, "unknown type {typ}"),
+            CompileError::InvalidForm(form) => write!(f, "invalid {form} form"),
+            CompileError::CannotInfer(param) => {
+                write!(f, "cannot infer type parameter {param}")
+            }
+            CompileError::MacroDepth(name) => write!(
+                f,
+                "expansion of macro {name:?} exceeded the depth limit of {MAX_EXPANSION_DEPTH}"
+            ),
+            CompileError::NotConstant => write!(f, "expression is not constant"),
+            CompileError::Overflow => write!(f, "arithmetic overflow in constant expression"),
+            CompileError::DivisionByZero => write!(f, "division by zero in constant expression"),
+            CompileError::OutOfRange(value) => write!(f, "integer {value} out of range"),
+            CompileError::MissingArg(name) => write!(f, "missing argument {name:?}"),
+            CompileError::DuplicateArg(name) => write!(f, "argument {name:?} given twice"),
+            CompileError::Redefined(name) => write!(f, "{name:?} is defined more than once"),
+            CompileError::LateExport(name) => {
+                write!(f, "{name:?} has to be exported before it is defined")
+            }
+            CompileError::DuplicateCase(value) => write!(f, "case {value} is matched twice"),
+            CompileError::RecurOutsideLoop => write!(f, "recur outside of a loop"),
+            CompileError::BreakOutsideLoop => write!(f, "break outside of a loop"),
+            CompileError::StaticAssert(message) => write!(f, "static assertion failed: {message}"),
+            CompileError::DeniedLint(warning) => write!(f, "{warning}"),
+            CompileError::UnknownLint(name) => write!(f, "there is no lint called {name:?}"),
+            CompileError::CannotEmbed { path, reason } => {
+                write!(f, "couldn't embed {path:?}: {reason}")
+            }
+            CompileError::NeedsLibc(name) => {
+                write!(
+                    f,
+                    "{name:?} needs the C library, which freestanding programs lack"
+                )
+            }
+            CompileError::StackTooDeep {
+                entry,
+                bytes,
+                limit,
+            } => write!(
+                f,
+                "{entry:?} may take {bytes} bytes of stack, more than the {limit} allowed"
+            ),
+            CompileError::UnboundedStack { entry, recursive } => write!(
+                f,
+                "the stack {entry:?} takes has no bound, as {recursive:?} is recursive"
+            ),
+            CompileError::Unsupported(feature) => write!(f, "unsupported: {feature}"),
+            CompileError::Internal(reason) => write!(f, "internal compiler error: {reason}"),
+        }
+    }
+}
+impl std::error::Error for CompileError {}
+/// something suspicious in a program which still compiles
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileWarning {
+    /// an expression without effects whose value is dropped, see `unused::PURE_FORMS`
+    UnusedResult,
+    /// a `loop` variable which the loop never reads, however often `recur` rebinds it
+    UnreadLoopVariable(String),
+    /// a function calling itself, directly or through others, under `--no-recursion`
+    Recursion(String),
+    /// an expression following one which never returns, like `exit` or `recur`
+    Unreachable,
+    /// code one of the lints in `lint::LINTS` found
+    Lint { lint: &'static str, message: String },
+}
+impl CompileWarning {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CompileWarning::UnusedResult => "unused-result",
+            CompileWarning::UnreadLoopVariable(_) => "unread-loop-variable",
+            CompileWarning::Recursion(_) => "recursion",
+            CompileWarning::Unreachable => "unreachable",
+            CompileWarning::Lint { lint, .. } => lint,
+        }
+    }
+}
+impl Display for CompileWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileWarning::UnusedResult => write!(f, "the value of this expression is unused"),
+            CompileWarning::UnreadLoopVariable(name) => {
+                write!(f, "the loop variable {name:?} is never read")
+            }
+            CompileWarning::Recursion(name) => write!(f, "{name:?} is recursive"),
+            CompileWarning::Unreachable => write!(f, "this expression is never reached"),
+            CompileWarning::Lint { lint, message } => write!(f, "{message} [{lint}]"),
+        }
+    }
+}
+impl Display for Located<CompileWarning> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.pos.ln + 1,
+            self.pos.col + 1,
+            self.value
+        )
+    }
+}
+impl Display for Located<CompileError> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.pos.ln + 1,
+            self.pos.col + 1,
+            self.value
+        )
+    }
+}
+impl std::error::Error for Located<CompileError> {}
+
+pub fn compile_program(program: Vec<Located<SExpr>>) -> Result<Program, Located<CompileError>> {
+    let mut compiler = Compiler::default();
+    compiler.compile_program(program)?;
+    Ok(compiler.program)
+}
+
+/// the parts of a `defn` form
+#[derive(Debug, Clone, PartialEq)]
+pub struct Defn {
+    pub name: Symbol,
+    pub type_params: Vec<Symbol>,
+    pub params: Vec<(Symbol, Located<SExpr>)>,
+    /// the parameters end in `...`
+    pub variadic: bool,
+    pub ret: Located<SExpr>,
+    pub body: Vec<Located<SExpr>>,
+}
+/// splits the arguments of `(defn name [(type-params)] (params) ret body...)` into their parts
+pub fn parse_defn(
+    sexprs: Vec<Located<SExpr>>,
+    pos: Position,
+) -> Result<Defn, Located<CompileError>> {
+    let invalid = |pos| Located {
+        value: CompileError::InvalidForm("defn"),
+        pos,
+    };
+    let mut sexprs = sexprs.into_iter();
+    let Some(Located {
+        value: SExpr::Word(name),
+        ..
+    }) = sexprs.next()
+    else {
+        return Err(invalid(pos));
+    };
+    let Some(Located {
+        value: SExpr::Expr(mut params),
+        ..
+    }) = sexprs.next()
+    else {
+        return Err(invalid(pos));
+    };
+    let mut type_params = vec![];
+    let is_type_params = !params.is_empty()
+        && params
+            .iter()
+            .all(|param| matches!(&param.value, SExpr::Expr(sexprs) if sexprs.len() == 1));
+    if is_type_params {
+        for param in params {
+            let SExpr::Expr(param) = param.value else {
+                return Err(invalid(param.pos));
+            };
+            let Some(Located {
+                value: SExpr::Word(type_param),
+                ..
+            }) = param.into_iter().next()
+            else {
+                return Err(invalid(pos));
+            };
+            type_params.push(type_param);
+        }
+        let Some(Located {
+            value: SExpr::Expr(next),
+            ..
+        }) = sexprs.next()
+        else {
+            return Err(invalid(pos));
+        };
+        params = next;
+    }
+    let variadic =
+        matches!(params.last(), Some(Located { value: SExpr::Word(word), .. }) if word == "...");
+    if variadic {
+        params.pop();
+    }
+    let mut typed_params = vec![];
+    for Located { value: param, pos } in params {
+        let SExpr::Expr(param) = param else {
+            return Err(invalid(pos));
+        };
+        let Ok(
+            [Located {
+                value: SExpr::Word(param_name),
+                ..
+            }, typ],
+        ) = <[Located<SExpr>; 2]>::try_from(param)
+        else {
+            return Err(invalid(pos));
+        };
+        typed_params.push((param_name, typ));
+    }
+    let Some(ret) = sexprs.next() else {
+        return Err(invalid(pos));
+    };
+    Ok(Defn {
+        name,
+        type_params,
+        params: typed_params,
+        variadic,
+        ret,
+        body: sexprs.collect(),
+    })
+}
+
+/// the special forms handled by the compiler itself
+pub const FORMS: &[&str] = &[
+    "+",
+    "-",
+    "add-checked",
+    "sub-checked",
+    "wrapping-add",
+    "wrapping-sub",
+    "wrapping-mul",
+    "/",
+    "saturating-add",
+    "saturating-sub",
+    "atomic-load",
+    "atomic-store",
+    "atomic-add",
+    "cas",
+    "mem-copy",
+    "mem-set",
+    "defer",
+    "block",
+    "let",
+    "loop",
+    "recur",
+    "break",
+    "return",
+    "spawn",
+    "join",
+    "lambda",
+    "extern",
+    "addr-of",
+    "call-ptr",
+    "defn",
+    "alloc",
+    "free",
+    "str-len",
+    "str-cat",
+    "str-eq",
+    "print",
+    "println",
+    "format",
+    "const",
+    "static-assert",
+    "allow",
+    "global",
+    "sizeof",
+    "embed-file",
+    "link",
+    "export",
+    "case",
+    "case-str",
+    "va-arg",
+    "argc",
+    "argv",
+    "getenv",
+    "exit",
+    "open",
+    "read",
+    "write",
+    "close",
+];
+/// `sexpr` as written, shortened to fit into an error message
+fn snippet(sexpr: &Located<SExpr>) -> String {
+    const MAX: usize = 40;
+    let text = sexpr.to_string();
+    match text.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/// the label of the function `name`, made of characters every assembler accepts and distinct
+/// from the names of C functions
+///
+/// `_L` is followed by the name with `_` doubled and every other byte which isn't alphanumeric
+/// written as `_` and two hex digits, so `str-cat` becomes `_Lstr_2dcat`
+pub fn mangle(name: &str) -> String {
+    let mut mangled = String::from("_L");
+    for byte in name.bytes() {
+        match byte {
+            b'_' => mangled.push_str("__"),
+            byte if byte.is_ascii_alphanumeric() => mangled.push(byte as char),
+            byte => mangled.push_str(&format!("_{byte:02x}")),
+        }
+    }
+    mangled
+}
+/// the name `mangle` made `symbol` from
+pub fn demangle(symbol: &str) -> Option<String> {
+    let mut bytes = symbol.strip_prefix("_L")?.bytes();
+    let mut name = vec![];
+    while let Some(byte) = bytes.next() {
+        if byte != b'_' {
+            name.push(byte);
+            continue;
+        }
+        match bytes.next()? {
+            b'_' => name.push(b'_'),
+            high => {
+                let hex = [high, bytes.next()?];
+                name.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+        }
+    }
+    String::from_utf8(name).ok()
+}
+
+/// splits a `format` template at its `{}` placeholders, unescaping `{{` and `}}`
+pub fn split_template(template: &str) -> Option<Vec<String>> {
+    let mut pieces = vec![String::new()];
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => pieces.last_mut()?.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => pieces.last_mut()?.push('}'),
+            '{' if chars.next_if_eq(&'}').is_some() => pieces.push(String::new()),
+            '{' | '}' => return None,
+            c => pieces.last_mut()?.push(c),
+        }
+    }
+    Some(pieces)
 }
@@ -0,0 +1,112 @@
+use crate::{
+    compiler::{CompileError, Compiler},
+    parser::{Located, SExpr},
+};
+
+/// evaluates the pure integer subset of expressions at compile time: literals, constants,
+/// arithmetic, comparisons, `sizeof` and the length of an `embed-file`; comparisons evaluate
+/// to 1 or 0
+pub fn const_eval(
+    compiler: &Compiler,
+    Located { value: sexpr, pos }: &Located<SExpr>,
+) -> Result<i64, Located<CompileError>> {
+    let pos = *pos;
+    match sexpr {
+        SExpr::Int(int, _) => i64::try_from(*int).map_err(|_| Located {
+            value: CompileError::OutOfRange(*int),
+            pos,
+        }),
+        SExpr::Word(word) => compiler.consts.get(word).copied().ok_or(Located {
+            value: CompileError::NotConstant,
+            pos,
+        }),
+        SExpr::Expr(sexprs) => {
+            let Some((
+                Located {
+                    value: SExpr::Word(head),
+                    ..
+                },
+                args,
+            )) = sexprs.split_first()
+            else {
+                return Err(Located {
+                    value: CompileError::NotConstant,
+                    pos,
+                });
+            };
+            if head == "embed-file" {
+                return Ok(compiler.embedded(args, pos)?.len() as i64);
+            }
+            if head == "sizeof" {
+                let [typ] = args else {
+                    return Err(Located {
+                        value: CompileError::ExpectedArgs(1),
+                        pos,
+                    });
+                };
+                let typ = compiler.typ(typ)?;
+                return match typ.size() {
+                    Some(size) => Ok(size as i64),
+                    None => Err(Located {
+                        value: CompileError::UnknownSize(typ),
+                        pos,
+                    }),
+                };
+            }
+            let values = args
+                .iter()
+                .map(|arg| const_eval(compiler, arg))
+                .collect::<Result<Vec<i64>, _>>()?;
+            let overflow = Located {
+                value: CompileError::Overflow,
+                pos,
+            };
+            match (head.as_str(), values.as_slice()) {
+                ("-", [value]) => value.checked_neg().ok_or(overflow),
+                ("+", [first, rest @ ..]) => rest
+                    .iter()
+                    .try_fold(*first, |acc, value| acc.checked_add(*value))
+                    .ok_or(overflow),
+                ("-", [first, rest @ ..]) => rest
+                    .iter()
+                    .try_fold(*first, |acc, value| acc.checked_sub(*value))
+                    .ok_or(overflow),
+                ("*", [first, rest @ ..]) => rest
+                    .iter()
+                    .try_fold(*first, |acc, value| acc.checked_mul(*value))
+                    .ok_or(overflow),
+                ("/" | "%", [_, rest @ ..]) if rest.contains(&0) => Err(Located {
+                    value: CompileError::DivisionByZero,
+                    pos,
+                }),
+                ("/", [first, rest @ ..]) => rest
+                    .iter()
+                    .try_fold(*first, |acc, value| acc.checked_div(*value))
+                    .ok_or(overflow),
+                ("%", [left, right]) => left.checked_rem(*right).ok_or(overflow),
+                ("=", [left, right]) => Ok((left == right) as i64),
+                ("!=", [left, right]) => Ok((left != right) as i64),
+                ("<", [left, right]) => Ok((left < right) as i64),
+                (">", [left, right]) => Ok((left > right) as i64),
+                ("<=", [left, right]) => Ok((left <= right) as i64),
+                (">=", [left, right]) => Ok((left >= right) as i64),
+                ("%" | "=" | "!=" | "<" | ">" | "<=" | ">=", _) => Err(Located {
+                    value: CompileError::ExpectedArgs(2),
+                    pos,
+                }),
+                ("+" | "-" | "*" | "/", []) => Err(Located {
+                    value: CompileError::ExpectedArgs(1),
+                    pos,
+                }),
+                _ => Err(Located {
+                    value: CompileError::NotConstant,
+                    pos,
+                }),
+            }
+        }
+        _ => Err(Located {
+            value: CompileError::NotConstant,
+            pos,
+        }),
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{
+    compiler::{parse_defn, Compiler, Generic, Signature},
+    parser::{Located, Position, SExpr},
+};
+
+impl Compiler {
+    /// declares the functions, externs and globals of the top-level `program` before any of it
+    /// is compiled, so they can be used above their definition
+    ///
+    /// a declaration which doesn't resolve is skipped, compiling it reports the error in order
+    pub fn declare_top_level(&mut self, program: &[Located<SExpr>]) {
+        for sexpr in program {
+            let SExpr::Expr(sexprs) = &sexpr.value else {
+                continue;
+            };
+            let Some((
+                Located {
+                    value: SExpr::Word(head),
+                    ..
+                },
+                args,
+            )) = sexprs.split_first()
+            else {
+                continue;
+            };
+            match head.as_str() {
+                "defn" => self.declare_defn(args.to_vec(), sexpr.pos),
+                "extern" => self.declare_externs(args),
+                "global" => self.declare_global(args),
+                "export" => self.declare_exports(args),
+                _ => {}
+            }
+        }
+    }
+    fn declare_defn(&mut self, sexprs: Vec<Located<SExpr>>, pos: Position) {
+        let Ok(defn) = parse_defn(sexprs, pos) else {
+            return;
+        };
+        if self.check_redefinition(defn.name, pos).is_err() {
+            return;
+        }
+        if defn.type_params.is_empty() {
+            let Ok(signature) = self.function_signature(&defn.params, &defn.ret) else {
+                return;
+            };
+            self.signatures.insert(
+                defn.name,
+                Signature {
+                    variadic: defn.variadic,
+                    ..signature
+                },
+            );
+            // calls above the definition need its label
+            self.define_symbol(defn.name);
+        } else if !defn.variadic {
+            self.generics.insert(
+                defn.name,
+                Generic {
+                    type_params: defn.type_params,
+                    params: defn.params,
+                    ret: defn.ret,
+                    body: defn.body,
+                },
+            );
+        } else {
+            return;
+        }
+        self.declarations.insert(defn.name, pos);
+    }
+    fn declare_externs(&mut self, sexprs: &[Located<SExpr>]) {
+        // in the order compiling the `extern` declares them
+        for Located { value: sexpr, pos } in sexprs.iter().rev() {
+            match sexpr {
+                SExpr::Word(name) => self.use_extern(name.as_str()),
+                SExpr::String(name) => self.use_extern(name),
+                SExpr::Expr(sexprs) => {
+                    let _ = self.typed_extern(sexprs.clone(), *pos);
+                }
+                _ => {}
+            }
+        }
+    }
+    fn declare_global(&mut self, sexprs: &[Located<SExpr>]) {
+        let [Located {
+            value: SExpr::Word(name),
+            ..
+        }, typ, ..] = sexprs
+        else {
+            return;
+        };
+        if let Ok(typ) = self.typ(typ) {
+            self.globals.insert(*name, typ);
+        }
+    }
+    /// exports decide the labels of functions, so they are declared in order with them
+    fn declare_exports(&mut self, sexprs: &[Located<SExpr>]) {
+        for sexpr in sexprs {
+            if let SExpr::Word(name) = sexpr.value {
+                if !self.symbols.contains_key(&name) {
+                    self.exports.entry(name).or_insert(sexpr.pos);
+                }
+            }
+        }
+    }
+}
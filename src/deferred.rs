@@ -0,0 +1,270 @@
+use crate::{
+    cache::{Cache, StableHasher},
+    code::Program,
+    compiler::{parse_defn, CompileError, CompileWarning, Compiler, Defn, Signature},
+    parser::{Located, SExpr},
+};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+/// forms which change what later expressions see or add to the program outside of functions, a
+/// function body containing one of them is compiled in order with the rest of the program
+const DECLARATIONS: &[&str] = &["defn", "const", "global", "extern", "link", "export"];
+
+/// the output of a deferred function and the warnings compiling it gave
+type Output = (Program, Vec<Located<CompileWarning>>);
+
+/// a function whose body is compiled after the rest of the program
+struct Deferred {
+    /// the index of the compiler as it was where the function was defined, shared by the
+    /// functions with no other top-level expression between them as every signature is
+    /// declared before compiling
+    compiler: usize,
+    defn: Defn,
+    /// identifies the definition together with everything before it
+    key: u64,
+}
+/// how long the program's lists were when a function was deferred, where its output goes
+#[derive(Clone, Copy)]
+struct Slot {
+    functions: usize,
+    externs: usize,
+    data: usize,
+    bss: usize,
+}
+
+fn declares(sexpr: &Located<SExpr>) -> bool {
+    match &sexpr.value {
+        SExpr::Expr(sexprs) => {
+            matches!(sexprs.first(), Some(Located { value: SExpr::Word(head), .. }) if DECLARATIONS.contains(&head.as_str()))
+                || sexprs.iter().any(declares)
+        }
+        SExpr::Bracket(sexprs) | SExpr::Brace(sexprs) => sexprs.iter().any(declares),
+        SExpr::Quoted(_, sexpr) => declares(sexpr),
+        _ => false,
+    }
+}
+/// the parts of `sexpr` if it is a non-generic `defn` whose body can be compiled on its own
+fn independent_defn(sexpr: &Located<SExpr>) -> Option<Defn> {
+    let SExpr::Expr(sexprs) = &sexpr.value else {
+        return None;
+    };
+    let (head, args) = sexprs.split_first()?;
+    if !matches!(&head.value, SExpr::Word(word) if word == "defn") {
+        return None;
+    }
+    let defn = parse_defn(args.to_vec(), sexpr.pos).ok()?;
+    (defn.type_params.is_empty() && !defn.body.iter().any(declares)).then_some(defn)
+}
+
+/// keeps the first of the items with the same key
+fn dedup_by_key<T, K: Eq + std::hash::Hash>(items: &mut Vec<T>, key: impl Fn(&T) -> K) {
+    let mut seen = HashSet::new();
+    items.retain(|item| seen.insert(key(item)));
+}
+
+impl Compiler {
+    /// a copy of the compiler for compiling a function into a frame of its own, which leaves
+    /// out everything in the program but the externs and sources since lowering only reads
+    /// those
+    fn function_compiler(&mut self) -> Compiler {
+        let own = Program {
+            externs: self.program.externs.clone(),
+            sources: self.program.sources.clone(),
+            ..Default::default()
+        };
+        let program = std::mem::replace(&mut self.program, own);
+        let frames = std::mem::take(&mut self.frames);
+        let warnings = std::mem::take(&mut self.warnings);
+        let compiler = self.clone();
+        (self.program, self.frames, self.warnings) = (program, frames, warnings);
+        compiler
+    }
+    /// the text `sexpr` is hashed as, with positions if they end up in the output
+    fn cache_text(&self, sexpr: &Located<SExpr>) -> String {
+        let options = &self.options;
+        if options.comments || options.debug_info || options.asm_source {
+            format!("{sexpr:?}")
+        } else {
+            sexpr.to_string()
+        }
+    }
+    /// hashes what the code using the function `defn` depends on
+    fn write_signature(&self, hasher: &mut StableHasher, defn: &Defn) {
+        hasher.write(defn.name.as_str().as_bytes());
+        for (name, typ) in &defn.params {
+            hasher.write(format!("{name} {}", self.cache_text(typ)).as_bytes());
+        }
+        hasher.write(self.cache_text(&defn.ret).as_bytes());
+    }
+    /// compiles the top-level expressions into the current frame like `compile`, but compiles
+    /// the bodies of independent functions last: on a thread pool of `options.jobs` threads
+    /// with the `parallel` feature, and only if they aren't in `options.cache` yet
+    ///
+    /// the result is the same as compiling in order: a function's output is spliced in where it
+    /// was defined and everything compiled twice, like generic instances, is kept once
+    pub fn compile_deferred(
+        &mut self,
+        program: Vec<Located<SExpr>>,
+    ) -> Result<(), Located<CompileError>> {
+        let mut environment = StableHasher::default();
+        let options = &self.options;
+        environment.write(
+            format!(
+                "{:?} {:?} {:?} {:?} {} {} {} {} {} {} {} {} {} {} {}",
+                self.program.sources,
+                options.target,
+                options.syntax,
+                options.opt_level,
+                options.debug_info,
+                options.local_offsets,
+                options.check_only,
+                options.asm_source,
+                options.checked_arithmetic,
+                options.no_libc,
+                options.no_prelude,
+                options.freestanding,
+                options.unroll_factor,
+                options.comments,
+                self.bump_allocator
+            )
+            .as_bytes(),
+        );
+        // functions see what is declared after them too
+        for sexpr in &program {
+            match independent_defn(sexpr) {
+                Some(defn) => self.write_signature(&mut environment, &defn),
+                None if declares(sexpr) => environment.write(self.cache_text(sexpr).as_bytes()),
+                None => {}
+            }
+        }
+        let (mut deferred, mut slots, mut compilers) = (vec![], vec![], vec![]);
+        // whether the last of `compilers` is out of date
+        let mut changed = true;
+        let mut error = None;
+        for sexpr in program {
+            let Some(defn) = independent_defn(&sexpr) else {
+                changed = true;
+                environment.write(self.cache_text(&sexpr).as_bytes());
+                if let Err(err) = self.compile(sexpr) {
+                    error = Some(err);
+                    break;
+                }
+                continue;
+            };
+            let mut key = environment;
+            key.write(self.cache_text(&sexpr).as_bytes());
+            key.write(&self.embedded_files(&sexpr));
+            // later definitions only see the signature
+            self.write_signature(&mut environment, &defn);
+            self.annotate(&sexpr);
+            if let Err(err) = self.check_redefinition(defn.name, sexpr.pos) {
+                error = Some(err);
+                break;
+            }
+            // the signature is needed by everything after the definition, the body isn't
+            let signature = match self.function_signature(&defn.params, &defn.ret) {
+                Ok(signature) => Signature {
+                    variadic: defn.variadic,
+                    ..signature
+                },
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            };
+            self.signatures.insert(defn.name, signature);
+            self.define_symbol(defn.name);
+            slots.push(Slot {
+                functions: self.program.functions.len(),
+                externs: self.program.externs.len(),
+                data: self.program.data.len(),
+                bss: self.program.bss.len(),
+            });
+            if changed {
+                compilers.push(self.function_compiler());
+                changed = false;
+            }
+            deferred.push(Deferred {
+                compiler: compilers.len() - 1,
+                defn,
+                key: key.finish(),
+            });
+        }
+        let cache = self.options.cache.as_ref().map(Cache::new);
+        let compile = |Deferred {
+                           compiler,
+                           defn,
+                           key,
+                       }| {
+            if let Some(output) = cache.as_ref().and_then(|cache| cache.load(key)) {
+                return Ok((output, vec![]));
+            }
+            let mut compiler = compilers[compiler].clone();
+            let externs = compiler.program.externs.len();
+            compiler.compile_function(
+                defn.name,
+                HashMap::new(),
+                defn.params,
+                defn.variadic,
+                defn.ret,
+                defn.body,
+            )?;
+            let mut output = compiler.program;
+            output.externs.drain(..externs);
+            // functions with warnings aren't cached to report them every time
+            let warnings = compiler.warnings;
+            if let Some(cache) = cache.as_ref().filter(|_| warnings.is_empty()) {
+                cache.store(key, &output);
+            }
+            Ok((output, warnings))
+        };
+        #[cfg(feature = "parallel")]
+        let results: Vec<Result<Output, Located<CompileError>>> = {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(self.options.jobs.max(1))
+                .build()
+                .map_err(|_| Located {
+                    value: CompileError::Internal("couldn't start the compilation threads"),
+                    pos: crate::parser::Position {
+                        ln: 0,
+                        col: 0,
+                        file: Default::default(),
+                    },
+                })?;
+            pool.install(|| deferred.into_par_iter().map(compile).collect())
+        };
+        #[cfg(not(feature = "parallel"))]
+        let results: Vec<Result<Output, Located<CompileError>>> =
+            deferred.into_iter().map(compile).collect();
+        let mut outputs = vec![];
+        for result in results {
+            let (output, warnings) = result?;
+            outputs.push(output);
+            self.warnings.extend(warnings);
+        }
+        if let Some(err) = error {
+            return Err(err);
+        }
+        // splicing from the back keeps the positions of the earlier slots valid
+        for (slot, output) in slots.iter().zip(outputs).rev() {
+            let program = &mut self.program;
+            program
+                .functions
+                .splice(slot.functions..slot.functions, output.functions);
+            program
+                .externs
+                .splice(slot.externs..slot.externs, output.externs);
+            program.data.splice(slot.data..slot.data, output.data);
+            program.bss.splice(slot.bss..slot.bss, output.bss);
+        }
+        dedup_by_key(&mut self.program.functions, |function| {
+            function.name.clone()
+        });
+        dedup_by_key(&mut self.program.externs, |name| name.clone());
+        dedup_by_key(&mut self.program.data, |data| data.label.clone());
+        dedup_by_key(&mut self.program.bss, |(label, _)| label.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    compiler::{CompileError, CompileWarning},
+    parser::{Lexer, Located, ParseError, Position},
+};
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub pos: Position,
+    /// right after the last character of the expression at `pos`
+    pub end: Position,
+    pub code: &'static str,
+    pub message: String,
+    pub severity: Severity,
+}
+impl Diagnostic {
+    /// `source` is the text of `file`, which the end of the span is found in
+    pub fn parse_error(file: String, source: &str, err: &ParseError) -> Self {
+        Self {
+            file,
+            pos: err.pos,
+            end: span_end(source, err.pos),
+            code: err.kind.code(),
+            message: err.kind.to_string(),
+            severity: Severity::Error,
+        }
+    }
+    pub fn compile_error(file: String, source: &str, err: &Located<CompileError>) -> Self {
+        Self {
+            file,
+            pos: err.pos,
+            end: span_end(source, err.pos),
+            code: err.value.code(),
+            message: err.value.to_string(),
+            severity: Severity::Error,
+        }
+    }
+    pub fn compile_warning(file: String, source: &str, warning: &Located<CompileWarning>) -> Self {
+        Self {
+            file,
+            pos: warning.pos,
+            end: span_end(source, warning.pos),
+            code: warning.value.code(),
+            message: warning.value.to_string(),
+            severity: Severity::Warning,
+        }
+    }
+    /// the diagnostic as a single-line JSON object, lines and columns counting from 1 and the
+    /// end being exclusive
+    pub fn json(&self) -> String {
+        format!(
+            r#"{{"file":{},"span":{{"line":{},"column":{},"end_line":{},"end_column":{}}},"code":{},"message":{},"severity":{}}}"#,
+            json_string(&self.file),
+            self.pos.ln + 1,
+            self.pos.col + 1,
+            self.end.ln + 1,
+            self.end.col + 1,
+            json_string(self.code),
+            json_string(&self.message),
+            json_string(&self.severity.to_string()),
+        )
+    }
+}
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}:{}:{}: {}",
+            self.severity,
+            self.file,
+            self.pos.ln + 1,
+            self.pos.col + 1,
+            self.message
+        )
+    }
+}
+
+/// where the expression starting at `pos` in `source` ends, or the end of the character at
+/// `pos` if no expression can be read from there, like at a stray `)`
+fn span_end(source: &str, pos: Position) -> Position {
+    let line: usize = source
+        .split_inclusive('\n')
+        .take(pos.ln)
+        .map(str::len)
+        .sum();
+    let offset = source[line..]
+        .char_indices()
+        .nth(pos.col)
+        .map_or(source.len(), |(idx, _)| line + idx);
+    let mut lexer = Lexer::from(&source[offset..]);
+    (lexer.ln, lexer.col) = (pos.ln, pos.col);
+    match lexer.parse_next() {
+        Ok(Some(_)) => Position {
+            ln: lexer.ln,
+            col: lexer.col,
+            file: pos.file,
+        },
+        _ => Position {
+            col: pos.col + 1,
+            ..pos
+        },
+    }
+}
+
+pub fn json_string(string: &str) -> String {
+    let mut json = String::from('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// the candidate closest to `name` by edit distance, if it's close enough to be a likely typo
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let max = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+/// the Levenshtein distance between `a` and `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + (a != *b) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
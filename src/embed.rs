@@ -0,0 +1,108 @@
+use crate::{
+    code::{Address, Instruction, Register, RegisterName, RegisterSize},
+    compiler::{CompileError, Compiler},
+    parser::{Located, Position, SExpr},
+    typ::{IntType, Type},
+    visit::{walk, SExprVisitor},
+};
+use std::{fs, path::PathBuf};
+
+/// the path of the file `(embed-file "path")` reads, none if `sexpr` isn't one
+fn embedded_path(sexpr: &Located<SExpr>) -> Option<&str> {
+    let SExpr::Expr(sexprs) = &sexpr.value else {
+        return None;
+    };
+    match sexprs.as_slice() {
+        [Located {
+            value: SExpr::Word(head),
+            ..
+        }, Located {
+            value: SExpr::String(path),
+            ..
+        }] if head == "embed-file" => Some(path),
+        _ => None,
+    }
+}
+
+/// finds the files `embed-file`s read
+#[derive(Default)]
+struct EmbeddedFiles(Vec<(String, Position)>);
+impl SExprVisitor for EmbeddedFiles {
+    fn visit(&mut self, sexpr: &Located<SExpr>) {
+        if let Some(path) = embedded_path(sexpr) {
+            self.0.push((path.to_string(), sexpr.pos));
+        }
+        walk(self, sexpr)
+    }
+}
+
+impl Compiler {
+    /// where the file `path` embedded in the source file of `pos` is, relative paths are
+    /// relative to the directory of the source file if it is known
+    fn embed_path(&self, path: &str, pos: Position) -> PathBuf {
+        let source = self.program.sources.get(pos.file.0 as usize);
+        match source.and_then(|source| PathBuf::from(source).parent().map(PathBuf::from)) {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+    /// the contents of the file `(embed-file "path")` reads
+    pub fn embedded(
+        &self,
+        sexprs: &[Located<SExpr>],
+        pos: Position,
+    ) -> Result<Vec<u8>, Located<CompileError>> {
+        let [Located {
+            value: SExpr::String(path),
+            ..
+        }] = sexprs
+        else {
+            return Err(Located {
+                value: CompileError::InvalidForm("embed-file"),
+                pos,
+            });
+        };
+        fs::read(self.embed_path(path, pos)).map_err(|err| Located {
+            value: CompileError::CannotEmbed {
+                path: path.clone(),
+                reason: err.to_string(),
+            },
+            pos,
+        })
+    }
+    /// the contents of every file embedded in `sexpr`, which the code compiled from it
+    /// depends on besides its text
+    pub fn embedded_files(&self, sexpr: &Located<SExpr>) -> Vec<u8> {
+        let mut files = EmbeddedFiles::default();
+        files.visit(sexpr);
+        files
+            .0
+            .iter()
+            .flat_map(|(path, pos)| fs::read(self.embed_path(path, *pos)).unwrap_or_default())
+            .collect()
+    }
+    /// `(embed-file "path")`: the bytes of the file at `path`, read at compile time, as a
+    /// `u8[n]` constant in `.rodata`
+    ///
+    /// in constant expressions it is the length of the file
+    pub fn compile_embed_file(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let bytes = self.embedded(&sexprs, pos)?;
+        let size = bytes.len();
+        let constant = self.frame_mut().new_bytes(bytes);
+        self.write(Instruction::Lea {
+            dest: Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            },
+            addr: Address::label(constant),
+        });
+        Ok(Type::Array {
+            typ: Box::new(Type::UInt(IntType::S8)),
+            size: Some(size),
+        })
+    }
+}
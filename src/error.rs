@@ -0,0 +1,55 @@
+use crate::{compiler::CompileError, parser::Located, parser::ParseError};
+use std::{fmt::Display, io, path::PathBuf};
+
+/// any error from reading, parsing or compiling a lerp program
+#[derive(Debug)]
+pub enum LerpError {
+    Io {
+        path: PathBuf,
+        err: io::Error,
+    },
+    /// every error found while parsing, in source order
+    Parse(Vec<ParseError>),
+    Compile(Located<CompileError>),
+}
+impl Display for LerpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LerpError::Io { path, err } => write!(f, "couldn't read {}: {err}", path.display()),
+            LerpError::Parse(errors) => {
+                for (idx, err) in errors.iter().enumerate() {
+                    if idx > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "parse error {err}")?;
+                }
+                Ok(())
+            }
+            LerpError::Compile(err) => write!(f, "compile error {err}"),
+        }
+    }
+}
+impl std::error::Error for LerpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LerpError::Io { err, .. } => Some(err),
+            LerpError::Parse(errors) => errors.first().map(|err| err as _),
+            LerpError::Compile(err) => Some(err),
+        }
+    }
+}
+impl From<ParseError> for LerpError {
+    fn from(value: ParseError) -> Self {
+        Self::Parse(vec![value])
+    }
+}
+impl From<Vec<ParseError>> for LerpError {
+    fn from(value: Vec<ParseError>) -> Self {
+        Self::Parse(value)
+    }
+}
+impl From<Located<CompileError>> for LerpError {
+    fn from(value: Located<CompileError>) -> Self {
+        Self::Compile(value)
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    code::{
+        Address, DataType, Destination, Instruction, Memory, Register, RegisterName, RegisterSize,
+        Source,
+    },
+    compiler::{CompileError, Compiler, FORMS},
+    parser::{Located, Position, SExpr},
+    typ::{FloatType, Type},
+    visit::{walk, SExprVisitor},
+};
+
+/// the forms whose value has the type of their operands
+const ARITHMETIC: &[&str] = &[
+    "+",
+    "-",
+    "add-checked",
+    "sub-checked",
+    "wrapping-add",
+    "wrapping-sub",
+    "wrapping-mul",
+    "/",
+    "saturating-add",
+    "saturating-sub",
+];
+
+/// finds a literal with a type suffix
+#[derive(Default)]
+struct TypedInts(bool);
+impl SExprVisitor for TypedInts {
+    fn visit(&mut self, sexpr: &Located<SExpr>) {
+        if let SExpr::Int(_, Some(_)) | SExpr::Float(_, Some(_)) = sexpr.value {
+            self.0 = true;
+        }
+        walk(self, sexpr)
+    }
+}
+
+/// whether `sexpr` has no literal with a type suffix in it, so folding it to an `i32` keeps
+/// its type
+pub fn is_untyped(sexpr: &Located<SExpr>) -> bool {
+    let mut typed = TypedInts::default();
+    typed.visit(sexpr);
+    !typed.0
+}
+
+/// whether the integer `value` can be held by the integer type `typ`
+fn fits(value: i128, typ: &Type) -> bool {
+    let Some(bits) = typ.size().map(|size| size as u32 * 8) else {
+        return false;
+    };
+    match typ {
+        Type::Int(_) => (-(1 << (bits - 1))..1 << (bits - 1)).contains(&value),
+        Type::UInt(_) => (0..1 << bits).contains(&value),
+        _ => false,
+    }
+}
+
+impl Compiler {
+    /// whether `sexpr` is an integer without a type of its own, a literal without a suffix or a
+    /// constant, which takes the type its context expects
+    fn is_untyped_int(&self, sexpr: &Located<SExpr>) -> bool {
+        match &sexpr.value {
+            SExpr::Int(_, None) => true,
+            SExpr::Word(word) => self.consts.contains_key(word),
+            _ => false,
+        }
+    }
+    /// the type `sexpr` has if it can be told without compiling it, for the integers next to
+    /// it to take
+    pub fn type_hint(&self, sexpr: &Located<SExpr>) -> Option<Type> {
+        match &sexpr.value {
+            SExpr::Int(_, Some(typ)) | SExpr::Float(_, Some(typ)) => Some(typ.clone()),
+            SExpr::Word(word) if !self.consts.contains_key(word) => self
+                .local(*word)
+                .map(|local| local.typ.clone())
+                .or_else(|| self.globals.get(word).cloned()),
+            SExpr::Expr(sexprs) => {
+                let (
+                    Located {
+                        value: SExpr::Word(head),
+                        ..
+                    },
+                    args,
+                ) = sexprs.split_first()?
+                else {
+                    return None;
+                };
+                if ARITHMETIC.contains(&head.as_str()) {
+                    args.iter().find_map(|arg| self.type_hint(arg))
+                } else if FORMS.contains(&head.as_str()) {
+                    None
+                } else {
+                    self.signatures
+                        .get(head)
+                        .map(|signature| signature.ret.clone())
+                }
+            }
+            _ => None,
+        }
+    }
+    /// the type the untyped integer `sexpr` takes next to `other`, if `other` has one
+    pub fn operand_hint(&self, sexpr: &Located<SExpr>, other: &Located<SExpr>) -> Option<Type> {
+        if !self.is_untyped_int(sexpr) {
+            return None;
+        }
+        self.type_hint(other)
+    }
+    /// compiles `sexpr` where a value of type `expected` is wanted, an untyped integer taking
+    /// that type if it is an integer type and the integer fits into it, and an untyped float
+    /// if it is a float type
+    ///
+    /// the type of the value is still checked by the caller
+    pub fn compile_expecting(
+        &mut self,
+        sexpr: Located<SExpr>,
+        expected: Option<&Type>,
+    ) -> Result<Type, Located<CompileError>> {
+        if let (SExpr::Float(float, None), Some(typ @ Type::Float(_))) = (&sexpr.value, expected) {
+            self.annotate(&sexpr);
+            return self.compile_float(*float, typ, sexpr.pos);
+        }
+        let Some(typ @ (Type::Int(_) | Type::UInt(_))) = expected else {
+            return self.compile(sexpr);
+        };
+        let value = match &sexpr.value {
+            SExpr::Int(int, None) => *int,
+            SExpr::Word(word) => match self.consts.get(word) {
+                Some(value) => (*value).into(),
+                None => return self.compile(sexpr),
+            },
+            _ => return self.compile(sexpr),
+        };
+        self.annotate(&sexpr);
+        self.compile_int(value, typ, sexpr.pos)
+    }
+    /// loads the integer `value` of type `typ` into the A register, extended to 32 bits, a
+    /// 64-bit one into the D and A register pair
+    pub fn compile_int(
+        &mut self,
+        value: i128,
+        typ: &Type,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if !fits(value, typ) {
+            return Err(Located {
+                value: CompileError::OutOfRange(value),
+                pos,
+            });
+        }
+        if RegisterSize::typ(typ) == Some(RegisterSize::S64) {
+            self.write(Instruction::Mov {
+                dest: Destination::Register(Register {
+                    name: RegisterName::D,
+                    size: RegisterSize::S32,
+                }),
+                src: Source::Int((value >> 32) as i32),
+            });
+        }
+        self.write(Instruction::Mov {
+            dest: Destination::Register(Register {
+                name: RegisterName::A,
+                size: RegisterSize::S32,
+            }),
+            src: Source::Int(value as i32),
+        });
+        Ok(typ.clone())
+    }
+    /// loads the float `value` of type `typ` into the A register from a constant in `.rodata`,
+    /// as its bits
+    pub fn compile_float(
+        &mut self,
+        value: f64,
+        typ: &Type,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let (data_type, bits) = match typ {
+            Type::Float(FloatType::S32) => (DataType::DoubleWord, (value as f32).to_bits().into()),
+            Type::Float(FloatType::S64) => (DataType::QuadWord, value.to_bits()),
+            typ => {
+                return Err(Located {
+                    value: CompileError::InvalidType(typ.clone()),
+                    pos,
+                })
+            }
+        };
+        let label = self.frame_mut().new_float(data_type, bits);
+        self.load(
+            typ,
+            Source::Memory(Memory {
+                data_type,
+                address: Address::label(label),
+            }),
+        );
+        Ok(typ.clone())
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{LazyLock, RwLock},
+};
+
+/// an interned string, compared and hashed by its id
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+/// maps strings to `Symbol`s, every string is only stored once for the lifetime of the program
+#[derive(Debug, Default)]
+pub struct Interner {
+    pub strings: Vec<&'static str>,
+    pub ids: HashMap<&'static str, Symbol>,
+}
+impl Interner {
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.ids.get(string) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let string: &'static str = Box::leak(string.into());
+        self.strings.push(string);
+        self.ids.insert(string, symbol);
+        symbol
+    }
+    pub fn get(&self, symbol: Symbol) -> &'static str {
+        self.strings[symbol.0 as usize]
+    }
+}
+/// shared by all threads so symbols can be compared across them
+static INTERNER: LazyLock<RwLock<Interner>> = LazyLock::new(RwLock::default);
+impl Symbol {
+    pub fn intern(string: &str) -> Self {
+        if let Some(symbol) = INTERNER.read().unwrap().ids.get(string) {
+            return *symbol;
+        }
+        INTERNER.write().unwrap().intern(string)
+    }
+    pub fn as_str(&self) -> &'static str {
+        INTERNER.read().unwrap().get(*self)
+    }
+}
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Self::intern(value)
+    }
+}
+impl From<String> for Symbol {
+    fn from(value: String) -> Self {
+        Self::intern(&value)
+    }
+}
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Ok(Self::intern(&string))
+    }
+}
@@ -1,7 +1,94 @@
 #[cfg(test)]
 mod tests;
 
+pub mod analysis;
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod args;
+pub mod arith;
+pub mod atomic;
+pub mod bindgen;
+pub mod build;
+pub mod builder;
+pub mod bytecode;
+pub mod cache;
+pub mod case;
+pub mod cfg;
 pub mod code;
 pub mod compiler;
+pub mod const_eval;
+pub mod declare;
+pub mod deferred;
+pub mod diagnostic;
+pub mod embed;
+pub mod error;
+pub mod infer;
+pub mod intern;
+pub mod lint;
+pub mod macros;
+pub mod manifest;
+pub mod mem;
+pub mod opt;
+pub mod options;
+pub mod os;
 pub mod parser;
+pub mod pass;
+pub mod prelude;
+pub mod profile;
+pub mod qbe;
+pub mod scope;
+pub mod stats;
+pub mod testing;
+pub mod thread;
+pub mod timings;
 pub mod typ;
+pub mod unroll;
+pub mod unused;
+pub mod verify;
+pub mod visit;
+pub mod vm;
+pub mod watch;
+
+pub use error::LerpError;
+pub use options::CompileOptions;
+
+use code::Program;
+use compiler::{CompileWarning, Compiler};
+use parser::{parse_recovering, Located};
+use std::{fs, path::Path};
+
+/// a compiled program with the warnings found while compiling it
+#[derive(Debug)]
+pub struct Compiled {
+    pub program: Program,
+    pub warnings: Vec<Located<CompileWarning>>,
+}
+
+/// parses and compiles the program `code`
+pub fn compile_str(code: &str, options: CompileOptions) -> Result<Compiled, LerpError> {
+    let (program, errors) = parse_recovering(code);
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+    let mut compiler = Compiler {
+        options,
+        ..Default::default()
+    };
+    compiler.compile_program(program)?;
+    Ok(Compiled {
+        program: compiler.program,
+        warnings: compiler.warnings,
+    })
+}
+/// reads, parses and compiles the file at `path`
+pub fn compile_file(
+    path: impl AsRef<Path>,
+    options: CompileOptions,
+) -> Result<Compiled, LerpError> {
+    let path = path.as_ref();
+    let code = fs::read_to_string(path).map_err(|err| LerpError::Io {
+        path: path.to_path_buf(),
+        err,
+    })?;
+    compile_str(&code, options)
+}
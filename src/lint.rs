@@ -0,0 +1,442 @@
+use crate::{
+    compiler::{parse_defn, CompileError, CompileWarning, Compiler},
+    const_eval::const_eval,
+    intern::Symbol,
+    options::LintLevel,
+    parser::{Located, Position, SExpr},
+    typ::Type,
+    visit::{walk, SExprVisitor},
+};
+use std::collections::BTreeSet;
+
+/// a check for code which compiles but is likely a mistake
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lint {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+pub const UNUSED_EXTERN: Lint = Lint {
+    name: "unused-extern",
+    description: "an extern the program never refers to",
+};
+pub const SHADOWED_BINDING: Lint = Lint {
+    name: "shadowed-binding",
+    description: "a `let` or loop variable with the name of another one in scope",
+};
+pub const CONSTANT_CONDITION: Lint = Lint {
+    name: "constant-condition",
+    description: "a `case` on a constant, which always takes the same arm",
+};
+pub const EMPTY_BODY: Lint = Lint {
+    name: "empty-body",
+    description: "a function, block or loop without expressions in its body",
+};
+pub const INTEGER_TRUNCATION: Lint = Lint {
+    name: "integer-truncation",
+    description: "an integer only the low byte of which is used, like the one `mem-set` fills \
+                  memory with",
+};
+/// every lint, which `--allow`, `--warn`, `--deny` and `(allow name...)` refer to by name
+pub const LINTS: &[Lint] = &[
+    UNUSED_EXTERN,
+    SHADOWED_BINDING,
+    CONSTANT_CONDITION,
+    EMPTY_BODY,
+    INTEGER_TRUNCATION,
+];
+
+/// the lint called `name`
+pub fn find(name: &str) -> Option<Lint> {
+    LINTS.iter().find(|lint| lint.name == name).copied()
+}
+
+/// the head word of `sexpr` and its arguments if it is a form
+fn form(sexpr: &Located<SExpr>) -> Option<(&str, &[Located<SExpr>])> {
+    let SExpr::Expr(sexprs) = &sexpr.value else {
+        return None;
+    };
+    match sexprs.split_first()? {
+        (
+            Located {
+                value: SExpr::Word(head),
+                ..
+            },
+            args,
+        ) => Some((head.as_str(), args)),
+        _ => None,
+    }
+}
+/// the names an `(extern ...)` declares, either alone or with their signatures
+fn extern_names(args: &[Located<SExpr>]) -> impl Iterator<Item = Symbol> + '_ {
+    args.iter().filter_map(|arg| match &arg.value {
+        SExpr::Word(name) => Some(*name),
+        SExpr::Expr(signature) => match signature.first() {
+            Some(Located {
+                value: SExpr::Word(name),
+                ..
+            }) => Some(*name),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// the type of a parameter if it is a plain one, the others needing the compiler to resolve
+/// them
+fn param_type(typ: &Located<SExpr>) -> Option<Type> {
+    match &typ.value {
+        SExpr::Word(word) => word.as_str().parse().ok(),
+        _ => None,
+    }
+}
+
+/// a name bound in a body being linted
+struct Binding {
+    name: Symbol,
+    /// the type of the value if it can be told
+    typ: Option<Type>,
+    /// whether the name was read since it was bound
+    used: bool,
+}
+
+/// finds the words used outside of `extern` declarations
+#[derive(Default)]
+struct Words(BTreeSet<Symbol>);
+impl SExprVisitor for Words {
+    fn visit(&mut self, sexpr: &Located<SExpr>) {
+        match &sexpr.value {
+            SExpr::Word(word) => {
+                self.0.insert(*word);
+            }
+            _ if matches!(form(sexpr), Some(("extern", _))) => {}
+            _ => walk(self, sexpr),
+        }
+    }
+}
+
+/// runs the lints over a program, keeping track of the bindings in scope and the lints allowed
+/// in the bodies it is in
+struct Linter<'a> {
+    compiler: &'a Compiler,
+    /// the lints allowed by the `(allow ...)`s of every body being linted
+    allowed: Vec<BTreeSet<String>>,
+    /// the names bound in every body of the function being linted, innermost last
+    scopes: Vec<Vec<Binding>>,
+    /// the externs declared where `unused-extern` isn't allowed
+    externs: Vec<(Symbol, Position)>,
+    found: Vec<Located<CompileWarning>>,
+}
+impl Linter<'_> {
+    fn report(&mut self, lint: Lint, message: String, pos: Position) {
+        if self.allows(lint) {
+            return;
+        }
+        self.found.push(Located {
+            value: CompileWarning::Lint {
+                lint: lint.name,
+                message,
+            },
+            pos,
+        });
+    }
+    fn allows(&self, lint: Lint) -> bool {
+        self.allowed
+            .iter()
+            .any(|allowed| allowed.contains(lint.name))
+    }
+    /// lints the expressions of a body, which the `(allow ...)`s in it apply to
+    fn body(&mut self, body: &[Located<SExpr>]) {
+        let allowed = body
+            .iter()
+            .filter_map(form)
+            .filter(|(head, _)| *head == "allow")
+            .flat_map(|(_, names)| names)
+            .filter_map(|name| match &name.value {
+                SExpr::Word(name) => Some(name.to_string()),
+                _ => None,
+            })
+            .collect();
+        self.allowed.push(allowed);
+        self.scopes.push(vec![]);
+        for sexpr in body {
+            self.expr(sexpr);
+        }
+        self.scopes.pop();
+        self.allowed.pop();
+    }
+    /// lints the body of a function, which sees none of the bindings around it
+    fn function(&mut self, params: Vec<(Symbol, Option<Type>)>, body: &[Located<SExpr>]) {
+        let params = params
+            .into_iter()
+            .map(|(name, typ)| Binding {
+                name,
+                typ,
+                used: false,
+            })
+            .collect();
+        let outer = std::mem::replace(&mut self.scopes, vec![params]);
+        self.body(body);
+        self.scopes = outer;
+    }
+    /// the type of `sexpr`, the locals having been dropped by the compiler once it compiled them
+    fn type_hint(&self, sexpr: &Located<SExpr>) -> Option<Type> {
+        let bound = match sexpr.value {
+            SExpr::Word(word) => self
+                .scopes
+                .iter()
+                .flatten()
+                .rfind(|binding| binding.name == word),
+            _ => None,
+        };
+        match bound {
+            Some(binding) => binding.typ.clone(),
+            None => self.compiler.type_hint(sexpr),
+        }
+    }
+    fn bind(&mut self, name: Symbol, typ: Option<Type>, pos: Position) {
+        let shadowed = self
+            .scopes
+            .iter()
+            .flatten()
+            .rfind(|binding| binding.name == name);
+        if let Some(shadowed) = shadowed {
+            let message = match shadowed.used {
+                true => format!("{name:?} shadows another binding of the same name"),
+                false => {
+                    format!("{name:?} shadows another binding of the same name before it is used")
+                }
+            };
+            self.report(SHADOWED_BINDING, message, pos);
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Binding {
+                name,
+                typ,
+                used: false,
+            });
+        }
+    }
+    /// marks the binding `word` refers to as read
+    fn read(&mut self, word: Symbol) {
+        let bound = self
+            .scopes
+            .iter_mut()
+            .flatten()
+            .rfind(|binding| binding.name == word);
+        if let Some(binding) = bound {
+            binding.used = true;
+        }
+    }
+    fn empty(&mut self, body: &[Located<SExpr>], what: &str, pos: Position) {
+        if body.is_empty() {
+            self.report(EMPTY_BODY, format!("this {what} has an empty body"), pos);
+        }
+    }
+    fn expr(&mut self, sexpr: &Located<SExpr>) {
+        let Some((head, args)) = form(sexpr) else {
+            match &sexpr.value {
+                SExpr::Expr(sexprs) => sexprs.iter().for_each(|sexpr| self.expr(sexpr)),
+                SExpr::Word(word) => self.read(*word),
+                _ => {}
+            }
+            return;
+        };
+        let pos = sexpr.pos;
+        match (head, args) {
+            ("allow", _) => {}
+            ("extern", args) => {
+                if !self.allows(UNUSED_EXTERN) {
+                    self.externs
+                        .extend(extern_names(args).map(|name| (name, pos)));
+                }
+            }
+            ("defn", args) => {
+                if let Ok(defn) = parse_defn(args.to_vec(), pos) {
+                    self.empty(&defn.body, "function", pos);
+                    let params = defn
+                        .params
+                        .iter()
+                        .map(|(name, typ)| (*name, param_type(typ)))
+                        .collect();
+                    self.function(params, &defn.body);
+                }
+            }
+            ("lambda", [params, body @ ..]) => {
+                let params = match &params.value {
+                    SExpr::Expr(params) => params
+                        .iter()
+                        .filter_map(|param| match &param.value {
+                            SExpr::Expr(param) => match param.as_slice() {
+                                [Located {
+                                    value: SExpr::Word(name),
+                                    ..
+                                }, typ] => Some((*name, param_type(typ))),
+                                _ => None,
+                            },
+                            _ => None,
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                self.function(params, body);
+            }
+            ("let", [name, value]) => {
+                self.expr(value);
+                if let SExpr::Word(name) = name.value {
+                    let typ = self.type_hint(value);
+                    self.bind(name, typ, pos);
+                }
+            }
+            ("loop", [bindings, body @ ..]) => {
+                self.empty(body, "loop", pos);
+                let SExpr::Expr(bindings) = &bindings.value else {
+                    return;
+                };
+                let mut names = vec![];
+                for binding in bindings {
+                    if let SExpr::Expr(binding) = &binding.value {
+                        if let [Located {
+                            value: SExpr::Word(name),
+                            ..
+                        }, value] = binding.as_slice()
+                        {
+                            self.expr(value);
+                            names.push((*name, self.type_hint(value), binding[0].pos));
+                        }
+                    }
+                }
+                // the variables are bound in a scope of their own around the body
+                self.scopes.push(vec![]);
+                for (name, typ, pos) in names {
+                    self.bind(name, typ, pos);
+                }
+                self.body(body);
+                self.scopes.pop();
+            }
+            ("block", body) => {
+                self.empty(body, "block", pos);
+                self.body(body);
+            }
+            ("case", [scrutinee, arms @ ..]) => {
+                if const_eval(self.compiler, scrutinee).is_ok() {
+                    self.report(
+                        CONSTANT_CONDITION,
+                        "this `case` is on a constant and always takes the same arm".to_string(),
+                        scrutinee.pos,
+                    );
+                }
+                self.expr(scrutinee);
+                self.arms(arms);
+            }
+            ("case-str", [scrutinee, arms @ ..]) => {
+                self.expr(scrutinee);
+                self.arms(arms);
+            }
+            ("mem-set", [_, byte, _]) => {
+                self.truncation(byte);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            (_, args) => args.iter().for_each(|arg| self.expr(arg)),
+        }
+    }
+    fn arms(&mut self, arms: &[Located<SExpr>]) {
+        for arm in arms {
+            if let SExpr::Expr(arm) = &arm.value {
+                if let Some((_, body)) = arm.split_first() {
+                    self.body(body);
+                }
+            }
+        }
+    }
+    /// `byte` is an integer `mem-set` only uses the low byte of
+    fn truncation(&mut self, byte: &Located<SExpr>) {
+        let message = match const_eval(self.compiler, byte) {
+            Ok(value) if !(-128..=255).contains(&value) => {
+                format!("{value} doesn't fit into the byte `mem-set` fills memory with")
+            }
+            Ok(_) => return,
+            Err(_) => match self.type_hint(byte) {
+                Some(typ @ (Type::Int(_) | Type::UInt(_))) if typ.size() > Some(1) => {
+                    format!("`mem-set` fills memory with the low byte of this {typ}")
+                }
+                _ => return,
+            },
+        };
+        self.report(INTEGER_TRUNCATION, message, byte.pos);
+    }
+}
+
+impl Compiler {
+    /// `(allow name...)`: keeps the lints called `name` quiet in the body it is in, emitting
+    /// nothing
+    pub fn compile_allow(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::InvalidForm("allow"),
+                pos,
+            });
+        }
+        for sexpr in sexprs {
+            let SExpr::Word(name) = sexpr.value else {
+                return Err(Located {
+                    value: CompileError::InvalidForm("allow"),
+                    pos: sexpr.pos,
+                });
+            };
+            if find(name.as_str()).is_none() {
+                return Err(Located {
+                    value: CompileError::UnknownLint(name.to_string()),
+                    pos: sexpr.pos,
+                });
+            }
+        }
+        Ok(Type::None)
+    }
+    /// runs every lint over the compiled `program`, warning about what it finds unless the
+    /// lint is allowed and failing on the first lint denied by the options
+    pub fn lint(&mut self, program: &[Located<SExpr>]) -> Result<(), Located<CompileError>> {
+        let mut linter = Linter {
+            compiler: self,
+            allowed: vec![],
+            scopes: vec![],
+            externs: vec![],
+            found: vec![],
+        };
+        linter.body(program);
+        let mut words = Words::default();
+        words.visit_all(program);
+        for (name, pos) in std::mem::take(&mut linter.externs) {
+            if !words.0.contains(&name) {
+                linter.found.push(Located {
+                    value: CompileWarning::Lint {
+                        lint: UNUSED_EXTERN.name,
+                        message: format!("the extern {name:?} is never used"),
+                    },
+                    pos,
+                });
+            }
+        }
+        let mut found = linter.found;
+        found.sort_by_key(|warning| (warning.pos.file, warning.pos.ln, warning.pos.col));
+        for warning in found {
+            let CompileWarning::Lint { lint, .. } = &warning.value else {
+                continue;
+            };
+            match self.options.lints.get(*lint).copied().unwrap_or_default() {
+                LintLevel::Allow => {}
+                LintLevel::Warn => self.warnings.push(warning),
+                LintLevel::Deny => {
+                    return Err(Located {
+                        value: CompileError::DeniedLint(warning.value),
+                        pos: warning.pos,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    compiler::CompileError,
+    diagnostic::suggest,
+    intern::Symbol,
+    parser::{Located, Position, QuoteKind, SExpr},
+};
+use std::collections::HashMap;
+
+/// how deep macro expansions may nest before expansion is aborted
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// `(defmacro name (params...) `template)`, where `,param` in the quasiquoted template is
+/// replaced with the argument and words ending in `#` are renamed to a fresh symbol on
+/// every expansion
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub params: Vec<Symbol>,
+    pub template: Located<SExpr>,
+}
+#[derive(Debug, Clone, Default)]
+pub struct Expander {
+    pub macros: HashMap<Symbol, Macro>,
+    pub gensyms: usize,
+}
+impl Expander {
+    pub fn expand_program(
+        &mut self,
+        program: Vec<Located<SExpr>>,
+    ) -> Result<Vec<Located<SExpr>>, Located<CompileError>> {
+        let mut expanded = vec![];
+        for sexpr in program {
+            match sexpr.value {
+                SExpr::Expr(sexprs) if is_defmacro(&sexprs) => self.define(sexprs, sexpr.pos)?,
+                value => expanded.push(self.expand(
+                    Located {
+                        value,
+                        pos: sexpr.pos,
+                    },
+                    0,
+                )?),
+            }
+        }
+        Ok(expanded)
+    }
+    pub fn expand(
+        &mut self,
+        Located { value: sexpr, pos }: Located<SExpr>,
+        depth: usize,
+    ) -> Result<Located<SExpr>, Located<CompileError>> {
+        let sexprs = match sexpr {
+            SExpr::Expr(sexprs) => sexprs,
+            SExpr::Bracket(sexprs) => {
+                return Ok(Located {
+                    value: SExpr::Bracket(self.expand_all(sexprs, depth)?),
+                    pos,
+                })
+            }
+            SExpr::Brace(sexprs) => {
+                return Ok(Located {
+                    value: SExpr::Brace(self.expand_all(sexprs, depth)?),
+                    pos,
+                })
+            }
+            sexpr => return Ok(Located { value: sexpr, pos }),
+        };
+        let head = match sexprs.first() {
+            Some(Located {
+                value: SExpr::Word(word),
+                ..
+            }) => Some(*word),
+            _ => None,
+        };
+        match head {
+            Some(head) if head == "defmacro" => {
+                self.define(sexprs, pos)?;
+                Ok(Located {
+                    value: SExpr::Expr(vec![]),
+                    pos,
+                })
+            }
+            Some(name) if self.macros.contains_key(&name) => {
+                if depth >= MAX_EXPANSION_DEPTH {
+                    return Err(Located {
+                        value: CompileError::MacroDepth(name.to_string()),
+                        pos,
+                    });
+                }
+                let expanded = self.instantiate(name, sexprs, pos)?;
+                self.expand(expanded, depth + 1)
+            }
+            _ => Ok(Located {
+                value: SExpr::Expr(self.expand_all(sexprs, depth)?),
+                pos,
+            }),
+        }
+    }
+    pub fn expand_all(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        depth: usize,
+    ) -> Result<Vec<Located<SExpr>>, Located<CompileError>> {
+        sexprs
+            .into_iter()
+            .map(|sexpr| self.expand(sexpr, depth))
+            .collect()
+    }
+    pub fn define(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<(), Located<CompileError>> {
+        let invalid = Located {
+            value: CompileError::InvalidForm("defmacro"),
+            pos,
+        };
+        let Ok(
+            [_, Located {
+                value: SExpr::Word(name),
+                ..
+            }, Located {
+                value: SExpr::Expr(params),
+                ..
+            }, template],
+        ) = <[Located<SExpr>; 4]>::try_from(sexprs)
+        else {
+            return Err(invalid);
+        };
+        let SExpr::Quoted(QuoteKind::Quasiquote, template) = template.value else {
+            return Err(invalid);
+        };
+        let mut names = vec![];
+        for param in params {
+            let SExpr::Word(param) = param.value else {
+                return Err(invalid);
+            };
+            names.push(param);
+        }
+        self.macros.insert(
+            name,
+            Macro {
+                params: names,
+                template: *template,
+            },
+        );
+        Ok(())
+    }
+    pub fn instantiate(
+        &mut self,
+        name: Symbol,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Located<SExpr>, Located<CompileError>> {
+        let Macro { params, template } = self.macros[&name].clone();
+        sexprs.remove(0);
+        if sexprs.len() != params.len() {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(params.len()),
+                pos,
+            });
+        }
+        let args = params.into_iter().zip(sexprs).collect();
+        self.gensyms += 1;
+        let gensym = self.gensyms;
+        substitute(template, &args, gensym, pos)
+    }
+}
+
+/// fills in a template, placing every node it introduces at the expansion site `pos`
+fn substitute(
+    Located {
+        value: sexpr,
+        pos: _,
+    }: Located<SExpr>,
+    args: &HashMap<Symbol, Located<SExpr>>,
+    gensym: usize,
+    pos: Position,
+) -> Result<Located<SExpr>, Located<CompileError>> {
+    let value = match sexpr {
+        SExpr::Quoted(QuoteKind::Unquote, sexpr) => {
+            let SExpr::Word(param) = &sexpr.value else {
+                return Err(Located {
+                    value: CompileError::InvalidForm("unquote"),
+                    pos,
+                });
+            };
+            return args.get(param).cloned().ok_or_else(|| Located {
+                value: CompileError::NotFound {
+                    name: param.to_string(),
+                    suggestion: suggest(param.as_str(), args.keys().map(|arg| arg.as_str())),
+                },
+                pos,
+            });
+        }
+        SExpr::Expr(sexprs) => SExpr::Expr(substitute_all(sexprs, args, gensym, pos)?),
+        SExpr::Bracket(sexprs) => SExpr::Bracket(substitute_all(sexprs, args, gensym, pos)?),
+        SExpr::Brace(sexprs) => SExpr::Brace(substitute_all(sexprs, args, gensym, pos)?),
+        SExpr::Word(word) => match word.as_str().strip_suffix('#') {
+            Some(word) => SExpr::Word(format!("{word}__m{gensym}").into()),
+            None => SExpr::Word(word),
+        },
+        SExpr::Quoted(kind, sexpr) => {
+            SExpr::Quoted(kind, Box::new(substitute(*sexpr, args, gensym, pos)?))
+        }
+        sexpr => sexpr,
+    };
+    Ok(Located { value, pos })
+}
+
+fn substitute_all(
+    sexprs: Vec<Located<SExpr>>,
+    args: &HashMap<Symbol, Located<SExpr>>,
+    gensym: usize,
+    pos: Position,
+) -> Result<Vec<Located<SExpr>>, Located<CompileError>> {
+    sexprs
+        .into_iter()
+        .map(|sexpr| substitute(sexpr, args, gensym, pos))
+        .collect()
+}
+
+fn is_defmacro(sexprs: &[Located<SExpr>]) -> bool {
+    matches!(sexprs.first(), Some(Located { value: SExpr::Word(word), .. }) if word == "defmacro")
+}
@@ -1,38 +1,751 @@
 extern crate lerp_lib;
 
-use lerp_lib::{compiler::compile_program, parser::parse};
-use std::{env, fs, process};
+use lerp_lib::{
+    bindgen::bindgen,
+    build::assemble_and_link,
+    bytecode::Bytecode,
+    cache::CACHE_DIR,
+    code::FlatBinary,
+    compiler::Compiler,
+    diagnostic::Diagnostic,
+    lint,
+    manifest::{Manifest, BUILD_DIR, MANIFEST_FILE},
+    opt,
+    options::{CompileOptions, LintLevel, OptLevel},
+    parser::{parse_file, FileId, Lexer, Located, SExpr},
+    profile::Profile,
+    qbe::Qbe,
+    stats::{stack_usage, Stats},
+    testing::{self, DiffOutcome},
+    timings::{CountingAllocator, StageStart, Timings},
+    vm::Vm,
+    watch::Watcher,
+    Compiled,
+};
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
 
 fn main() {
-    let mut args = env::args().skip(1);
-    let Some(input_path) = args.next() else {
+    let mut options = CompileOptions::default();
+    let mut emit = String::from("asm");
+    let mut json_errors = false;
+    let mut bump_allocator = false;
+    let mut print_timings = false;
+    let mut print_stats = false;
+    // the address a flat binary is loaded at, `None` for an object to link
+    let mut org = None;
+    let mut paths = vec![];
+    let mut output_path = None;
+    let mut args = env::args().skip(1).peekable();
+    // `lerp check files...` only reports diagnostics
+    let check = args.next_if(|arg| arg == "check").is_some();
+    options.check_only = check;
+    if args.next_if(|arg| arg == "run").is_some() {
+        let Some(path) = args.next() else {
+            eprintln!("no bytecode file provided");
+            process::exit(1);
+        };
+        let args = [path.clone()].into_iter().chain(args).collect();
+        run_bytecode(&path, args);
+    }
+    if args.next_if(|arg| arg == "difftest").is_some() {
+        difftest(args.collect());
+    }
+    if args.next_if(|arg| arg == "watch").is_some() {
+        watch(args.collect());
+    }
+    if args.next_if(|arg| arg == "asmdiff").is_some() {
+        asmdiff(args.collect());
+    }
+    if args.next_if(|arg| arg == "build").is_some() {
+        build(args.next());
+    }
+    if args.next_if(|arg| arg == "bindgen").is_some() {
+        generate_bindings(args.collect());
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(path) => output_path = Some(path),
+                None => {
+                    eprintln!("expected an output file after -o");
+                    process::exit(1);
+                }
+            },
+            "--timings" => print_timings = true,
+            "--stats" => print_stats = true,
+            "--format" => match args.next().as_deref() {
+                Some("elf") => org = None,
+                Some("bin") => org = Some(org.unwrap_or(0)),
+                format => {
+                    eprintln!("expected `elf` or `bin` after --format, got {format:?}");
+                    process::exit(1);
+                }
+            },
+            "--org" => match args.next().as_deref().and_then(parse_address) {
+                Some(address) => org = Some(address),
+                None => {
+                    eprintln!("expected an address after --org");
+                    process::exit(1);
+                }
+            },
+            "--emit" => {
+                let Some(kind) = args.next() else {
+                    eprintln!("expected an output kind after --emit");
+                    process::exit(1);
+                };
+                emit = kind;
+            }
+            "--runtime" => match args.next().as_deref() {
+                Some("libc") => bump_allocator = false,
+                Some("bump") => bump_allocator = true,
+                runtime => {
+                    eprintln!("expected `libc` or `bump` after --runtime, got {runtime:?}");
+                    process::exit(1);
+                }
+            },
+            "--error-format" => match args.next().as_deref() {
+                Some("human") => json_errors = false,
+                Some("json") => json_errors = true,
+                format => {
+                    eprintln!("expected `human` or `json` after --error-format, got {format:?}");
+                    process::exit(1);
+                }
+            },
+            _ if compile_flag(&arg, &mut args, &mut options) => {}
+            _ => paths.push(arg),
+        }
+    }
+    if org.is_some() && (!options.freestanding || emit != "asm") {
+        eprintln!("--format bin only writes the assembly of freestanding programs");
+        process::exit(1);
+    }
+    // with `-o` every other argument is an input, otherwise it's `lerp input output`
+    let (input_paths, output_path) = match output_path {
+        Some(output_path) => (paths, output_path),
+        None if check => (paths, String::new()),
+        None => {
+            let mut paths = paths.into_iter();
+            let input_paths: Vec<String> = paths.next().into_iter().collect();
+            let output_path = match paths.next() {
+                Some(path) => path,
+                None => {
+                    eprintln!("no output file provided");
+                    process::exit(1);
+                }
+            };
+            (input_paths, output_path)
+        }
+    };
+    if input_paths.is_empty() {
         eprintln!("no input file provided");
         process::exit(1);
+    }
+    let mut codes = vec![];
+    for input_path in &input_paths {
+        let code = if input_path == "-" {
+            io::read_to_string(io::stdin())
+        } else {
+            fs::read_to_string(input_path)
+        };
+        let Ok(code) = code else {
+            eprintln!("couldn't open file {input_path:?}");
+            process::exit(1);
+        };
+        codes.push(code);
+    }
+    // diagnostics and debug info name the file the code came from, indexed by `FileId`
+    let input_paths: Vec<String> = input_paths
+        .into_iter()
+        .map(|path| {
+            if path == "-" {
+                String::from("<stdin>")
+            } else {
+                path
+            }
+        })
+        .collect();
+    let file_name = |file: FileId| input_paths[file.0 as usize].clone();
+    let input_path = input_paths.join(", ");
+    let mut timings = Timings::default();
+    if print_timings {
+        // parsing lexes as it goes, so lexing is measured on its own first
+        timings.measure("lex", || {
+            for code in &codes {
+                Lexer::from(code.as_str()).take_while(Result::is_ok).count();
+            }
+        });
+    }
+    let (program, errors) = timings.measure("parse", || {
+        let (mut program, mut errors) = (vec![], vec![]);
+        for (file, code) in codes.iter().enumerate() {
+            let (sexprs, errs) = parse_file(code, FileId(file as u32));
+            program.extend(sexprs);
+            errors.extend(errs);
+        }
+        (program, errors)
+    });
+    if !errors.is_empty() {
+        for err in errors {
+            let input_path = file_name(err.pos.file);
+            if json_errors {
+                eprintln!(
+                    "{}",
+                    Diagnostic::parse_error(input_path, &codes[err.pos.file.0 as usize], &err)
+                        .json()
+                );
+            } else {
+                eprintln!("Parse Error {input_path}:{err}");
+            }
+        }
+        process::exit(1);
+    }
+    match emit.as_str() {
+        "asm" | "bytecode" => {}
+        // the program is lowered to QBE once it is type checked
+        "qbe" => options.check_only = true,
+        "ast-json" => {
+            write_ast_json(&program, &output_path);
+            return;
+        }
+        kind => {
+            eprintln!("unknown output kind {kind:?}");
+            process::exit(1);
+        }
+    }
+    let mut compiler = Compiler {
+        bump_allocator,
+        options,
+        timings,
+        ..Default::default()
+    };
+    if compiler.options.debug_info {
+        compiler.program.sources = input_paths.clone();
+    }
+    let source = (emit == "qbe").then(|| program.clone());
+    compiler
+        .compile_program(program)
+        .map_err(|err| {
+            let input_path = file_name(err.pos.file);
+            if json_errors {
+                eprintln!(
+                    "{}",
+                    Diagnostic::compile_error(input_path, &codes[err.pos.file.0 as usize], &err)
+                        .json()
+                );
+            } else {
+                eprintln!("Compilation Error {input_path}:{err}");
+            }
+            process::exit(1);
+        })
+        .unwrap();
+    for warning in &compiler.warnings {
+        let input_path = file_name(warning.pos.file);
+        if json_errors {
+            eprintln!(
+                "{}",
+                Diagnostic::compile_warning(
+                    input_path,
+                    &codes[warning.pos.file.0 as usize],
+                    warning
+                )
+                .json()
+            );
+        } else {
+            eprintln!("Compilation Warning {input_path}:{warning}");
+        }
+    }
+    if check {
+        process::exit(0);
+    }
+    if let Some(program) = source {
+        let output = Qbe::emit_program(program, &compiler.options)
+            .map_err(|err| {
+                eprintln!("Compilation Error {}:{err}", file_name(err.pos.file));
+                process::exit(1);
+            })
+            .unwrap();
+        write_output(&output_path, output.as_bytes())
+            .map_err(|err| {
+                eprintln!("couldn't write QBE IL to {output_path:?}: {err}");
+                process::exit(1);
+            })
+            .unwrap();
+        return;
+    }
+    for run in &compiler.pass_runs {
+        if let Some(dump) = &run.dump {
+            eprintln!("; after {}\n{dump}", run.name);
+        }
+    }
+    let mut program = compiler.program;
+    if compiler.options.asm_source {
+        program.interleave_source(&codes);
+    }
+    if print_stats {
+        eprintln!("{}", Stats::of(&program));
+        for usage in stack_usage(&program) {
+            eprintln!("{usage}");
+        }
+    }
+    let mut timings = compiler.timings;
+    let start = StageStart::now();
+    let (output, kind) = if emit == "bytecode" {
+        let bytecode = Bytecode::lower(&program)
+            .map_err(|err| {
+                eprintln!("Bytecode Error {input_path}: {err}");
+                process::exit(1);
+            })
+            .unwrap();
+        (bytecode.to_bytes(), "bytecode")
+    } else if let Some(org) = org {
+        if let Some(name) = program.externs.first() {
+            eprintln!("flat binaries can't refer to the extern {name}");
+            process::exit(1);
+        }
+        let program = FlatBinary {
+            program: &program,
+            org,
+        };
+        (program.to_string().into_bytes(), "assembly")
+    } else {
+        (program.to_string().into_bytes(), "assembly")
+    };
+    write_output(&output_path, &output)
+        .map_err(|err| {
+            eprintln!("couldn't write {kind} to {output_path:?}: {err}");
+            process::exit(1);
+        })
+        .unwrap();
+    timings.finish("emit", start);
+    if print_timings {
+        eprintln!("{timings}");
+    }
+}
+
+/// sets the option a flag of the main command, or of `watch` and `asmdiff`, stands for, taking
+/// its value from `args`, `false` if `arg` isn't such a flag
+fn compile_flag(
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+    options: &mut CompileOptions,
+) -> bool {
+    match arg {
+        "--comments" => options.comments = true,
+        "-g" => options.debug_info = true,
+        "--local-offsets" => options.local_offsets = true,
+        "--asm-source" => options.asm_source = true,
+        "-O0" => options.opt_level = OptLevel::O0,
+        "-O1" => options.opt_level = OptLevel::O1,
+        "-O2" => options.opt_level = OptLevel::O2,
+        "--cache" => options.cache = Some(CACHE_DIR.into()),
+        "--checked-arithmetic" => options.checked_arithmetic = true,
+        "--no-libc" => options.no_libc = true,
+        "--no-prelude" => options.no_prelude = true,
+        "--freestanding" => options.freestanding = true,
+        "--no-recursion" => options.no_recursion = true,
+        flag @ ("--allow" | "--warn" | "--deny") => {
+            let level = match flag {
+                "--allow" => LintLevel::Allow,
+                "--warn" => LintLevel::Warn,
+                _ => LintLevel::Deny,
+            };
+            match args.next() {
+                Some(name) if lint::find(&name).is_some() => {
+                    options.lints.insert(name, level);
+                }
+                name => {
+                    eprintln!("expected the name of a lint after {flag}, got {name:?}");
+                    process::exit(1);
+                }
+            }
+        }
+        "--profile-generate" => options.profile_generate = true,
+        "--profile-use" => {
+            let Some(path) = args.next() else {
+                eprintln!("expected a profile after --profile-use");
+                process::exit(1);
+            };
+            let profile = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| Profile::parse(&text).map_err(|err| err.to_string()));
+            match profile {
+                Ok(profile) => options.profile = Some(profile),
+                Err(err) => {
+                    eprintln!("{path}: {err}");
+                    process::exit(1);
+                }
+            }
+        }
+        "--unroll-factor" => match args.next().and_then(|factor| factor.parse().ok()) {
+            Some(factor) => options.unroll_factor = factor,
+            None => {
+                eprintln!("expected a number of copies after --unroll-factor");
+                process::exit(1);
+            }
+        },
+        "--max-stack" => match args.next().and_then(|bytes| bytes.parse().ok()) {
+            Some(bytes) => options.max_stack = Some(bytes),
+            None => {
+                eprintln!("expected a number of bytes after --max-stack");
+                process::exit(1);
+            }
+        },
+        "-D" => match args.next() {
+            Some(feature) => {
+                options.features.insert(feature);
+            }
+            None => {
+                eprintln!("expected a feature after -D");
+                process::exit(1);
+            }
+        },
+        "-j" => match args.next().and_then(|jobs| jobs.parse().ok()) {
+            Some(jobs) if jobs > 1 && !cfg!(feature = "parallel") => {
+                eprintln!("-j requires lerp to be built with the `parallel` feature");
+                process::exit(1);
+            }
+            Some(jobs) => options.jobs = jobs,
+            None => {
+                eprintln!("expected a number of threads after -j");
+                process::exit(1);
+            }
+        },
+        "--print-after" => match args.next() {
+            Some(pass) if opt::PASSES.iter().any(|known| known.name == pass) => {
+                options.print_after.push(pass)
+            }
+            pass => {
+                eprintln!("expected the name of a pass after --print-after, got {pass:?}");
+                process::exit(1);
+            }
+        },
+        _ => return false,
+    }
+    true
+}
+/// runs every program in `paths`, or in the directories among them, through the VM and
+/// natively and reports the ones which behave differently
+fn difftest(paths: Vec<String>) -> ! {
+    let mut files = vec![];
+    for path in paths.iter().map(PathBuf::from) {
+        if path.is_dir() {
+            let Ok(entries) = fs::read_dir(&path) else {
+                eprintln!("couldn't read directory {path:?}");
+                process::exit(1);
+            };
+            let mut entries: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|ext| ext == "lerp" || ext == "lp")
+                })
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path);
+        }
+    }
+    let dir = env::temp_dir().join(format!("lerp-difftest-{}", process::id()));
+    let (mut same, mut different, mut failed) = (0, 0, 0);
+    for file in files {
+        let Ok(code) = fs::read_to_string(&file) else {
+            eprintln!("couldn't open file {file:?}");
+            process::exit(1);
+        };
+        match testing::difftest(&code, &dir) {
+            DiffOutcome::Same(_) => {
+                same += 1;
+                println!("ok   {}", file.display());
+            }
+            DiffOutcome::Different { vm, native } => {
+                different += 1;
+                println!("DIFF {}", file.display());
+                if vm.status != native.status {
+                    println!("  exit status: vm {}, native {}", vm.status, native.status);
+                }
+                if vm.stdout != native.stdout {
+                    let (vm, native) = (
+                        String::from_utf8_lossy(&vm.stdout),
+                        String::from_utf8_lossy(&native.stdout),
+                    );
+                    print!("{}", testing::diff(&vm, &native));
+                }
+            }
+            DiffOutcome::Failed(err) => {
+                failed += 1;
+                println!("FAIL {}: {err}", file.display());
+            }
+        }
+    }
+    fs::remove_dir_all(&dir).ok();
+    println!("{same} same, {different} different, {failed} failed");
+    process::exit((different + failed > 0) as i32)
+}
+/// `lerp asmdiff old.lp new.lp [options]`: prints how the code of every function changed
+/// between the two programs compiled with the options of the main command, exiting with 1 if
+/// any did
+fn asmdiff(args: Vec<String>) -> ! {
+    let mut args = args.into_iter();
+    let (mut options, mut paths) = (CompileOptions::default(), vec![]);
+    while let Some(arg) = args.next() {
+        if !compile_flag(&arg, &mut args, &mut options) {
+            paths.push(arg);
+        }
+    }
+    let [old, new] = paths.as_slice() else {
+        eprintln!("expected the old and the new file");
+        process::exit(1);
+    };
+    let [old, new] = [old, new].map(|path| {
+        lerp_lib::compile_file(path, options.clone())
+            .map_err(|err| {
+                eprintln!("{path}: {err}");
+                process::exit(1);
+            })
+            .unwrap()
+            .program
+    });
+    let diff = testing::asm_diff(&old, &new);
+    print!("{diff}");
+    process::exit(!diff.is_empty() as i32)
+}
+/// `lerp watch file.lp [-o out.asm] [--run] [options]`: recompiles with the options of the main
+/// command on every save, building and running the executable after a successful compile
+/// with `--run`
+fn watch(args: Vec<String>) -> ! {
+    let mut args = args.into_iter();
+    let (mut input_path, mut output_path, mut run) = (None, None, false);
+    let mut options = CompileOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output_path = args.next(),
+            "--run" => run = true,
+            _ if compile_flag(&arg, &mut args, &mut options) => {}
+            _ => input_path = Some(arg),
+        }
+    }
+    let Some(input_path) = input_path else {
+        eprintln!("no input file provided");
+        process::exit(1);
+    };
+    // running needs the assembly on disk, next to the executable built from it
+    let asm_path = match &output_path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let dir = env::temp_dir().join(format!("lerp-watch-{}", process::id()));
+            if run {
+                fs::create_dir_all(&dir)
+                    .map_err(|err| {
+                        eprintln!("couldn't create {dir:?}: {err}");
+                        process::exit(1);
+                    })
+                    .unwrap();
+            }
+            dir.join("out.asm")
+        }
+    };
+    // a relative path without a directory would be looked up in `PATH` when run
+    let binary = Path::new(".").join(asm_path.with_extension(""));
+    let rebuild = || -> Result<(), String> {
+        let Compiled { program, warnings } = lerp_lib::compile_file(&input_path, options.clone())
+            .map_err(|err| format!("{input_path}: {err}"))?;
+        for warning in warnings {
+            eprintln!("Compilation Warning {input_path}:{warning}");
+        }
+        if output_path.is_some() || run {
+            fs::write(&asm_path, program.to_string())
+                .map_err(|err| format!("couldn't write assembly to {asm_path:?}: {err}"))?;
+        }
+        match &output_path {
+            Some(path) => eprintln!("compiled {input_path} -> {path}"),
+            None => eprintln!("compiled {input_path}"),
+        }
+        if run {
+            assemble_and_link(&asm_path, &binary, &program.libraries)?;
+            let status = process::Command::new(&binary)
+                .status()
+                .map_err(|err| format!("couldn't run {binary:?}: {err}"))?;
+            eprintln!("exited with {status}");
+        }
+        Ok(())
+    };
+    let mut watcher = Watcher::new(&input_path)
+        .map_err(|err| {
+            eprintln!("couldn't watch {input_path:?}: {err}");
+            process::exit(1);
+        })
+        .unwrap();
+    loop {
+        if let Err(err) = rebuild() {
+            eprintln!("{err}");
+        }
+        if let Err(err) = watcher.wait() {
+            eprintln!("couldn't watch {input_path:?}: {err}");
+            process::exit(1);
+        }
+    }
+}
+/// `lerp build [path]`: compiles the project described by the manifest at `path`, or in the
+/// directory `path`, into an executable in the build directory next to it
+fn build(path: Option<String>) -> ! {
+    let path = path.map_or_else(|| PathBuf::from(MANIFEST_FILE), PathBuf::from);
+    let path = if path.is_dir() {
+        path.join(MANIFEST_FILE)
+    } else {
+        path
+    };
+    let Ok(text) = fs::read_to_string(&path) else {
+        eprintln!("couldn't open file {path:?}");
+        process::exit(1);
     };
-    let Some(output_path) = args.next() else {
-        eprintln!("no output file provided");
+    let manifest: Manifest = text
+        .parse()
+        .map_err(|err| {
+            eprintln!("Manifest Error {}:{err}", path.display());
+            process::exit(1);
+        })
+        .unwrap();
+    let root = path.parent().unwrap_or(Path::new("."));
+    let entry = root.join(&manifest.entry);
+    let Compiled { program, warnings } = lerp_lib::compile_file(&entry, manifest.options())
+        .map_err(|err| {
+            eprintln!("{}: {err}", entry.display());
+            process::exit(1);
+        })
+        .unwrap();
+    for warning in warnings {
+        eprintln!("Compilation Warning {}:{warning}", entry.display());
+    }
+    let dir = root.join(BUILD_DIR);
+    let (asm, binary) = (
+        dir.join(format!("{}.asm", manifest.name)),
+        dir.join(&manifest.name),
+    );
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&asm, program.to_string()))
+        .map_err(|err| {
+            eprintln!("couldn't write assembly to {asm:?}: {err}");
+            process::exit(1);
+        })
+        .unwrap();
+    let mut libraries = manifest.libraries;
+    for library in program.libraries {
+        if !libraries.contains(&library) {
+            libraries.push(library);
+        }
+    }
+    if let Err(err) = assemble_and_link(&asm, &binary, &libraries) {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+    eprintln!("built {}", binary.display());
+    process::exit(0)
+}
+/// `lerp bindgen header.h [-o out.lp]`: writes typed externs for the functions declared in a C
+/// header, to stdout without `-o`
+fn generate_bindings(args: Vec<String>) -> ! {
+    let mut args = args.into_iter();
+    let (mut header_path, mut output_path) = (None, String::from("-"));
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => match args.next() {
+                Some(path) => output_path = path,
+                None => {
+                    eprintln!("expected an output file after -o");
+                    process::exit(1);
+                }
+            },
+            _ => header_path = Some(arg),
+        }
+    }
+    let Some(header_path) = header_path else {
+        eprintln!("no header file provided");
         process::exit(1);
     };
-    let Ok(code) = fs::read_to_string(&input_path) else {
-        eprintln!("couldn't open file {input_path:?}");
+    let Ok(header) = fs::read_to_string(&header_path) else {
+        eprintln!("couldn't open file {header_path:?}");
         process::exit(1);
     };
-    let program = parse(&code)
+    let bindings = bindgen(&header);
+    for skipped in &bindings.skipped {
+        eprintln!(
+            "skipped `{}` in {header_path}: {}",
+            skipped.declaration, skipped.reason
+        );
+    }
+    write_output(&output_path, bindings.to_string().as_bytes())
         .map_err(|err| {
-            eprintln!("Parse Error {input_path}:{err}");
+            eprintln!("couldn't write bindings to {output_path:?}: {err}");
             process::exit(1);
         })
         .unwrap();
-    let program = compile_program(program)
+    process::exit(0)
+}
+/// writes `bytes` to the file at `path`, or to stdout if it is `-`
+fn write_output(path: &str, bytes: &[u8]) -> io::Result<()> {
+    if path == "-" {
+        io::stdout().lock().write_all(bytes)
+    } else {
+        fs::write(path, bytes)
+    }
+}
+/// runs the bytecode file at `path`, passing `args` to its `main`
+fn run_bytecode(path: &str, args: Vec<String>) -> ! {
+    let Ok(bytes) = fs::read(path) else {
+        eprintln!("couldn't open file {path:?}");
+        process::exit(1);
+    };
+    let bytecode = Bytecode::from_bytes(&bytes)
         .map_err(|err| {
-            eprintln!("Compilation Error {input_path}:{err}");
+            eprintln!("Bytecode Error {path}: {err}");
             process::exit(1);
         })
         .unwrap();
-    fs::write(&output_path, program.to_string())
+    let code = Vm::new(&bytecode, io::stdout().lock())
+        .with_args(args)
+        .with_env(env::vars().collect())
+        .run()
         .map_err(|err| {
-            eprintln!("couldn't write assembly to {output_path:?}: {err}");
+            eprintln!("Runtime Error {path}: {err}");
             process::exit(1);
         })
         .unwrap();
+    process::exit(code)
+}
+
+#[cfg(feature = "serde")]
+fn write_ast_json(program: &[Located<SExpr>], output_path: &str) {
+    let json = serde_json::to_string_pretty(program)
+        .map_err(|err| {
+            eprintln!("couldn't serialize the ast: {err}");
+            process::exit(1);
+        })
+        .unwrap();
+    write_output(output_path, json.as_bytes())
+        .map_err(|err| {
+            eprintln!("couldn't write ast to {output_path:?}: {err}");
+            process::exit(1);
+        })
+        .unwrap();
+}
+#[cfg(not(feature = "serde"))]
+fn write_ast_json(_: &[Located<SExpr>], _: &str) {
+    eprintln!("--emit ast-json requires lerp to be built with the `serde` feature");
+    process::exit(1);
+}
+/// a decimal or `0x` prefixed hexadecimal address
+fn parse_address(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
 }
@@ -0,0 +1,197 @@
+use crate::options::{CompileOptions, OptLevel, Target};
+use std::{fmt::Display, path::PathBuf, str::FromStr};
+
+/// the file `lerp build` reads the project from
+pub const MANIFEST_FILE: &str = "lerp.toml";
+/// the directory `lerp build` writes to, next to the manifest
+pub const BUILD_DIR: &str = "build";
+
+/// how to build a project, read from a `lerp.toml` like
+///
+/// ```toml
+/// [package]
+/// name = "game"
+/// entry = "src/main.lp"
+///
+/// [build]
+/// target = "x86"
+/// opt-level = 2
+/// libraries = ["m", "raylib"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// what the executable is called
+    pub name: String,
+    /// the file compilation starts at, relative to the manifest
+    pub entry: PathBuf,
+    pub target: Target,
+    pub opt_level: OptLevel,
+    /// libraries the executable is linked against with `-l`
+    pub libraries: Vec<String>,
+}
+impl Manifest {
+    pub fn options(&self) -> CompileOptions {
+        CompileOptions {
+            target: self.target,
+            opt_level: self.opt_level,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestError {
+    /// a line which isn't a `[table]` header or a `key = value` pair
+    Syntax(usize),
+    UnknownKey {
+        line: usize,
+        key: String,
+    },
+    InvalidValue {
+        line: usize,
+        key: String,
+    },
+    Missing(&'static str),
+}
+impl Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestError::Syntax(line) => write!(f, "{}: expected `key = value`", line + 1),
+            ManifestError::UnknownKey { line, key } => {
+                write!(f, "{}: unknown key {key:?}", line + 1)
+            }
+            ManifestError::InvalidValue { line, key } => {
+                write!(f, "{}: invalid value for {key:?}", line + 1)
+            }
+            ManifestError::Missing(key) => write!(f, "missing {key:?}"),
+        }
+    }
+}
+impl std::error::Error for ManifestError {}
+
+/// the values a manifest can hold, a small part of TOML
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Array(Vec<Value>),
+}
+impl Value {
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Some(string) = text.strip_prefix('"') {
+            let string = string.strip_suffix('"')?;
+            return (!string.contains('"')).then(|| Value::String(string.to_string()));
+        }
+        if let Some(items) = text.strip_prefix('[') {
+            let items = items.strip_suffix(']')?.trim();
+            let items = items.strip_suffix(',').unwrap_or(items);
+            if items.trim().is_empty() {
+                return Some(Value::Array(vec![]));
+            }
+            return items
+                .split(',')
+                .map(Value::parse)
+                .collect::<Option<_>>()
+                .map(Value::Array);
+        }
+        text.parse().ok().map(Value::Integer)
+    }
+    fn string(self) -> Option<String> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+/// the part of `line` before a comment
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (idx, char) in line.char_indices() {
+        match char {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+impl FromStr for Manifest {
+    type Err = ManifestError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (mut name, mut entry) = (None, None);
+        let mut target = Target::default();
+        let mut opt_level = OptLevel::default();
+        let mut libraries = vec![];
+        let mut table = String::new();
+        for (line, text) in text.lines().enumerate() {
+            let text = strip_comment(text).trim();
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(header) = text.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or(ManifestError::Syntax(line))?;
+                table = header.trim().to_string();
+                continue;
+            }
+            let (key, value) = text.split_once('=').ok_or(ManifestError::Syntax(line))?;
+            let key = key.trim();
+            let invalid = || ManifestError::InvalidValue {
+                line,
+                key: key.to_string(),
+            };
+            let value = Value::parse(value).ok_or_else(invalid)?;
+            match (table.as_str(), key) {
+                ("package", "name") => name = Some(value.string().ok_or_else(invalid)?),
+                ("package", "entry") => {
+                    entry = Some(PathBuf::from(value.string().ok_or_else(invalid)?))
+                }
+                ("build", "target") => {
+                    target = match value.string().as_deref() {
+                        Some("x86") => Target::X86,
+                        _ => return Err(invalid()),
+                    }
+                }
+                ("build", "opt-level") => {
+                    opt_level = match value {
+                        Value::Integer(0) => OptLevel::O0,
+                        Value::Integer(1) => OptLevel::O1,
+                        Value::Integer(2) => OptLevel::O2,
+                        _ => return Err(invalid()),
+                    }
+                }
+                ("build", "libraries") => {
+                    let Value::Array(items) = value else {
+                        return Err(invalid());
+                    };
+                    libraries = items
+                        .into_iter()
+                        .map(Value::string)
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?;
+                }
+                _ => {
+                    return Err(ManifestError::UnknownKey {
+                        line,
+                        key: if table.is_empty() {
+                            key.to_string()
+                        } else {
+                            format!("{table}.{key}")
+                        },
+                    })
+                }
+            }
+        }
+        Ok(Self {
+            name: name.ok_or(ManifestError::Missing("package.name"))?,
+            entry: entry.unwrap_or_else(|| PathBuf::from("main.lp")),
+            target,
+            opt_level,
+            libraries,
+        })
+    }
+}
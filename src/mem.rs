@@ -0,0 +1,220 @@
+use crate::{
+    code::{
+        Address, Destination, Instruction, Memory, Register, RegisterName, RegisterSize, Source,
+    },
+    compiler::{CompileError, Compiler},
+    const_eval::const_eval,
+    parser::{Located, Position, SExpr},
+    typ::Type,
+};
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const ECX: Register = Register {
+    name: RegisterName::C,
+    size: RegisterSize::S32,
+};
+const EDX: Register = Register {
+    name: RegisterName::D,
+    size: RegisterSize::S32,
+};
+const ESI: Register = Register {
+    name: RegisterName::SI,
+    size: RegisterSize::S32,
+};
+const EDI: Register = Register {
+    name: RegisterName::DI,
+    size: RegisterSize::S32,
+};
+
+/// the largest constant size `mem-copy` and `mem-set` unroll into `mov`s instead of a `rep`
+const UNROLL_LIMIT: i64 = 16;
+
+/// the offsets and sizes of the widest moves covering `count` bytes
+fn chunks(count: usize) -> Vec<(i32, RegisterSize)> {
+    let mut chunks = vec![];
+    let mut offset = 0;
+    for size in [RegisterSize::S32, RegisterSize::S16, RegisterSize::S8] {
+        while count - offset >= size.bytes() {
+            chunks.push((offset as i32, size));
+            offset += size.bytes();
+        }
+    }
+    chunks
+}
+fn at(base: Register, displacement: i32, size: RegisterSize) -> Memory {
+    Memory {
+        data_type: size.into(),
+        address: Address::offset(base, displacement),
+    }
+}
+
+impl Compiler {
+    /// compiles the pointer or array `sexpr` and pushes its address
+    fn push_pointer(&mut self, sexpr: Located<SExpr>) -> Result<(), Located<CompileError>> {
+        let pos = sexpr.pos;
+        let typ = self.compile(sexpr)?;
+        if !matches!(typ, Type::Pointer(_) | Type::Array { .. }) {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos,
+            });
+        }
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        Ok(())
+    }
+    /// the byte count `sexpr` if it is a constant small enough to unroll, otherwise compiles
+    /// it into the C register
+    fn byte_count(
+        &mut self,
+        sexpr: Located<SExpr>,
+    ) -> Result<Option<usize>, Located<CompileError>> {
+        let pos = sexpr.pos;
+        if let Ok(count) = const_eval(self, &sexpr) {
+            if count < 0 {
+                return Err(Located {
+                    value: CompileError::OutOfRange(count.into()),
+                    pos,
+                });
+            }
+            if count <= UNROLL_LIMIT {
+                return Ok(Some(count as usize));
+            }
+        }
+        let typ = self.compile(sexpr)?;
+        if !matches!(typ, Type::Int(_) | Type::UInt(_)) || self.widen(&typ) != Some(EAX.size) {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos,
+            });
+        }
+        self.write(Instruction::Mov {
+            dest: ECX.into(),
+            src: Source::Register(EAX),
+        });
+        Ok(None)
+    }
+
+    /// `(mem-copy dst src n)`: copies `n` bytes from `src` to `dst`, which must not overlap
+    pub fn compile_mem_copy(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([dst, src, count]) = <[Located<SExpr>; 3]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(3),
+                pos,
+            });
+        };
+        self.push_pointer(dst)?;
+        self.push_pointer(src)?;
+        let count = self.byte_count(count)?;
+        self.write(Instruction::Pop { dest: EAX.into() });
+        if let Some(count) = count {
+            self.write(Instruction::Pop { dest: ECX.into() });
+            for (offset, size) in chunks(count) {
+                let edx = Register { size, ..EDX };
+                self.write(Instruction::Mov {
+                    dest: edx.into(),
+                    src: Source::Memory(at(EAX, offset, size)),
+                });
+                self.write(Instruction::Mov {
+                    dest: Destination::Memory(at(ECX, offset, size)),
+                    src: Source::Register(edx),
+                });
+            }
+            return Ok(Type::None);
+        }
+        self.write(Instruction::Pop { dest: EDX.into() });
+        // the string registers belong to the caller
+        self.write(Instruction::Push {
+            src: Source::Register(ESI),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(EDI),
+        });
+        self.write(Instruction::Mov {
+            dest: ESI.into(),
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Mov {
+            dest: EDI.into(),
+            src: Source::Register(EDX),
+        });
+        self.write(Instruction::RepMovsb);
+        self.write(Instruction::Pop { dest: EDI.into() });
+        self.write(Instruction::Pop { dest: ESI.into() });
+        Ok(Type::None)
+    }
+    /// `(mem-set dst byte n)`: sets `n` bytes from `dst` on to the low byte of the integer
+    /// `byte`
+    pub fn compile_mem_set(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([dst, byte, count]) = <[Located<SExpr>; 3]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(3),
+                pos,
+            });
+        };
+        self.push_pointer(dst)?;
+        let byte_pos = byte.pos;
+        let typ = self.compile(byte)?;
+        if !matches!(typ, Type::Int(_) | Type::UInt(_)) {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: byte_pos,
+            });
+        }
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        let count = self.byte_count(count)?;
+        self.write(Instruction::Pop { dest: EAX.into() });
+        if let Some(count) = count {
+            if count > 1 {
+                // repeats the byte in every byte of the A register
+                self.write(Instruction::Movzx {
+                    dest: EAX,
+                    src: Source::Register(Register {
+                        size: RegisterSize::S8,
+                        ..EAX
+                    }),
+                });
+                self.write(Instruction::Mov {
+                    dest: ECX.into(),
+                    src: Source::Int(0x0101_0101),
+                });
+                self.write(Instruction::Mul {
+                    src: Source::Register(ECX),
+                });
+            }
+            self.write(Instruction::Pop { dest: ECX.into() });
+            for (offset, size) in chunks(count) {
+                self.write(Instruction::Mov {
+                    dest: Destination::Memory(at(ECX, offset, size)),
+                    src: Source::Register(Register { size, ..EAX }),
+                });
+            }
+            return Ok(Type::None);
+        }
+        self.write(Instruction::Pop { dest: EDX.into() });
+        self.write(Instruction::Push {
+            src: Source::Register(EDI),
+        });
+        self.write(Instruction::Mov {
+            dest: EDI.into(),
+            src: Source::Register(EDX),
+        });
+        self.write(Instruction::RepStosb);
+        self.write(Instruction::Pop { dest: EDI.into() });
+        Ok(Type::None)
+    }
+}
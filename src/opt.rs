@@ -0,0 +1,376 @@
+use crate::{
+    code::{Destination, Function, Instruction, Program, Register, RegisterName, Source},
+    intern::Symbol,
+    options::OptLevel,
+    pass::{Pass, PassManager},
+};
+use std::collections::HashSet;
+
+/// the most instructions a function body may have to be inlined
+pub const INLINE_LIMIT: usize = 16;
+
+/// every optimization pass, in the order they run in
+pub const PASSES: &[Pass] = &[
+    Pass {
+        name: "inline",
+        after: &[],
+        run: inline,
+    },
+    Pass {
+        name: "peephole",
+        after: &["inline"],
+        run: peephole_functions,
+    },
+    Pass {
+        name: "dce",
+        after: &["inline", "peephole"],
+        run: eliminate_dead_code,
+    },
+];
+/// the passes enabled at `opt_level`
+pub fn passes(opt_level: OptLevel) -> PassManager {
+    let mut passes = PassManager::default();
+    for pass in PASSES {
+        let enabled = match pass.name {
+            "inline" => opt_level.inlines(),
+            "peephole" => opt_level.peephole(),
+            "dce" => opt_level.eliminates_dead_code(),
+            _ => false,
+        };
+        if enabled {
+            passes.add(*pass);
+        }
+    }
+    passes
+}
+
+/// replaces `len` instructions of `function` at `start` with `replacement`, keeping comments and
+/// source lines on the instructions they were attached to
+pub fn splice(function: &mut Function, start: usize, len: usize, replacement: Vec<Instruction>) {
+    let added = replacement.len();
+    function.body.splice(start..start + len, replacement);
+    let shift = |idx: &mut usize| {
+        if *idx >= start + len {
+            *idx = *idx + added - len;
+        } else if *idx > start {
+            *idx = start;
+        }
+    };
+    for (idx, _) in &mut function.comments {
+        shift(idx);
+    }
+    for (idx, _) in &mut function.lines {
+        shift(idx);
+    }
+}
+
+fn is_register(register: &Register, name: RegisterName) -> bool {
+    register.name == name
+}
+fn uses_stack_pointer(src: &Source) -> bool {
+    match src {
+        Source::Register(register) => is_register(register, RegisterName::SP),
+        Source::Memory(memory) => {
+            memory
+                .address
+                .base
+                .is_some_and(|base| is_register(&base, RegisterName::SP))
+                || memory
+                    .address
+                    .index
+                    .is_some_and(|(index, _)| is_register(&index, RegisterName::SP))
+        }
+        Source::Int(_) | Source::Int64(_) | Source::Name(_) | Source::Amount(_) => false,
+    }
+}
+
+/// rewrites short instruction sequences into cheaper equivalents until none apply
+pub fn peephole(function: &mut Function) {
+    let mut idx = 0;
+    while idx < function.body.len() {
+        if let Some((len, replacement)) = peephole_at(&function.body[idx..]) {
+            splice(function, idx, len, replacement);
+            // the replacement may complete a pattern starting a few instructions earlier
+            idx = idx.saturating_sub(3);
+        } else {
+            idx += 1;
+        }
+    }
+}
+fn peephole_functions(program: &mut Program) {
+    for function in &mut program.functions {
+        peephole(function);
+    }
+}
+fn peephole_at(body: &[Instruction]) -> Option<(usize, Vec<Instruction>)> {
+    match body {
+        // mov r, r
+        [Instruction::Mov {
+            dest: Destination::Register(dest),
+            src: Source::Register(src),
+        }, ..]
+            if dest == src =>
+        {
+            Some((1, vec![]))
+        }
+        // add r, 0
+        [Instruction::Add {
+            dest: Destination::Register(_),
+            src: Source::Int(0) | Source::Amount(0),
+        }, ..] => Some((1, vec![])),
+        // push a / pop b
+        [Instruction::Push {
+            src: Source::Register(src),
+        }, Instruction::Pop {
+            dest: Destination::Register(dest),
+        }, ..]
+            if src.size == dest.size =>
+        {
+            if src == dest {
+                Some((2, vec![]))
+            } else {
+                Some((
+                    2,
+                    vec![Instruction::Mov {
+                        dest: Destination::Register(*dest),
+                        src: Source::Register(*src),
+                    }],
+                ))
+            }
+        }
+        // push eax / mov eax, x / mov ebx, eax / pop eax, saving eax around loading an operand
+        [Instruction::Push {
+            src: Source::Register(saved),
+        }, Instruction::Mov {
+            dest: Destination::Register(loaded),
+            src,
+        }, Instruction::Mov {
+            dest: Destination::Register(dest),
+            src: Source::Register(moved),
+        }, Instruction::Pop {
+            dest: Destination::Register(restored),
+        }, ..]
+            if saved == restored
+                && saved.name == loaded.name
+                && loaded == moved
+                && dest.name != saved.name
+                && !uses_stack_pointer(src) =>
+        {
+            Some((
+                4,
+                vec![Instruction::Mov {
+                    dest: Destination::Register(*dest),
+                    src: src.clone(),
+                }],
+            ))
+        }
+        // add esp, a / add esp, b
+        [Instruction::Add {
+            dest: Destination::Register(first),
+            src: Source::Amount(a),
+        }, Instruction::Add {
+            dest: Destination::Register(second),
+            src: Source::Amount(b),
+        }, ..]
+            if first == second && is_register(first, RegisterName::SP) =>
+        {
+            Some((
+                2,
+                vec![Instruction::Add {
+                    dest: Destination::Register(*first),
+                    src: Source::Amount(a + b),
+                }],
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// every symbol `instr` refers to
+fn references(instr: &Instruction) -> Vec<Symbol> {
+    let from_source = |src: &Source| match src {
+        Source::Name(name) => Some(Symbol::intern(name)),
+        Source::Memory(memory) => memory.address.label.as_deref().map(Symbol::intern),
+        _ => None,
+    };
+    let from_destination = |dest: &Destination| match dest {
+        Destination::Memory(memory) => memory.address.label.as_deref().map(Symbol::intern),
+        Destination::Register(_) => None,
+    };
+    let symbols = match instr {
+        Instruction::Call { func } => return vec![*func],
+        Instruction::Mov { dest, src }
+        | Instruction::Add { dest, src }
+        | Instruction::Sub { dest, src }
+        | Instruction::And { dest, src } => {
+            vec![from_destination(dest), from_source(src)]
+        }
+        Instruction::Sar { dest, .. }
+        | Instruction::Shl { dest, .. }
+        | Instruction::Shr { dest, .. }
+        | Instruction::Xchg { dest, .. }
+        | Instruction::Xadd { dest, .. }
+        | Instruction::Cmpxchg { dest, .. } => vec![from_destination(dest)],
+        Instruction::Movzx { src, .. }
+        | Instruction::Movsx { src, .. }
+        | Instruction::Push { src }
+        | Instruction::CallIndirect(src)
+        | Instruction::JmpIndirect(src)
+        | Instruction::Cmov { src, .. }
+        | Instruction::Mul { src }
+        | Instruction::Div { src }
+        | Instruction::Idiv { src } => vec![from_source(src)],
+        Instruction::Lea { addr, .. } => vec![addr.label.as_deref().map(Symbol::intern)],
+        Instruction::Fld { src: memory } | Instruction::Fstp { dest: memory } => {
+            vec![memory.address.label.as_deref().map(Symbol::intern)]
+        }
+        Instruction::Pop { dest } | Instruction::Set { dest, .. } => vec![from_destination(dest)],
+        Instruction::Cmp { a, b } => vec![from_source(a), from_source(b)],
+        Instruction::NOp
+        | Instruction::Leave
+        | Instruction::Ret
+        | Instruction::Cdq
+        | Instruction::RepMovsb
+        | Instruction::RepStosb
+        | Instruction::Interrupt(_)
+        | Instruction::Label(_)
+        | Instruction::Jmp { .. }
+        | Instruction::JOp { .. } => vec![],
+    };
+    symbols.into_iter().flatten().collect()
+}
+
+/// removes instructions after an unconditional jump or return that no label makes reachable,
+/// then functions and externs `main` never refers to
+pub fn eliminate_dead_code(program: &mut Program) {
+    for function in &mut program.functions {
+        let mut idx = 0;
+        while idx < function.body.len() {
+            if matches!(
+                function.body[idx],
+                Instruction::Jmp { .. } | Instruction::JmpIndirect(_) | Instruction::Ret
+            ) {
+                let dead = function.body[idx + 1..]
+                    .iter()
+                    .take_while(|instr| !matches!(instr, Instruction::Label(_)))
+                    .count();
+                splice(function, idx + 1, dead, vec![]);
+            }
+            idx += 1;
+        }
+    }
+    // exported functions may be called from outside the program
+    let mut queue: Vec<Symbol> = std::iter::once("main")
+        .chain(program.exports.iter().map(String::as_str))
+        .map(Symbol::intern)
+        .collect();
+    let mut live: HashSet<Symbol> = queue.iter().copied().collect();
+    while let Some(name) = queue.pop() {
+        let Some(function) = program
+            .functions
+            .iter()
+            .find(|function| Symbol::intern(&function.name) == name)
+        else {
+            continue;
+        };
+        for symbol in function.body.iter().flat_map(references) {
+            if live.insert(symbol) {
+                queue.push(symbol);
+            }
+        }
+    }
+    program
+        .functions
+        .retain(|function| live.contains(&Symbol::intern(&function.name)));
+    program
+        .externs
+        .retain(|name| live.contains(&Symbol::intern(name)));
+}
+
+/// the body of `function` between its prologue and epilogue if it is small and straight-line
+/// enough to be inlined
+fn inlinable(function: &Function) -> Option<&[Instruction]> {
+    if function.name == "main" || function.has_constants() {
+        return None;
+    }
+    let [Instruction::Push { .. }, Instruction::Mov { .. }, body @ .., Instruction::Leave, Instruction::Ret] =
+        function.body.as_slice()
+    else {
+        return None;
+    };
+    if body.len() > INLINE_LIMIT {
+        return None;
+    }
+    let straight = body.iter().all(|instr| {
+        !matches!(
+            instr,
+            Instruction::Call { .. }
+                | Instruction::CallIndirect(_)
+                | Instruction::Leave
+                | Instruction::Ret
+                | Instruction::Label(_)
+                | Instruction::Jmp { .. }
+                | Instruction::JmpIndirect(_)
+                | Instruction::JOp { .. }
+        )
+    });
+    straight.then_some(body)
+}
+
+/// replaces calls to small leaf functions with their bodies, keeping their stack frames so
+/// arguments are found where the body expects them
+pub fn inline(program: &mut Program) {
+    let bodies: Vec<(Symbol, Vec<Instruction>)> = program
+        .functions
+        .iter()
+        .filter_map(|function| {
+            Some((
+                Symbol::intern(&function.name),
+                inlinable(function)?.to_vec(),
+            ))
+        })
+        .collect();
+    let esp = Register {
+        name: RegisterName::SP,
+        size: crate::code::RegisterSize::S32,
+    };
+    let ebp = Register {
+        name: RegisterName::BP,
+        ..esp
+    };
+    for function in &mut program.functions {
+        let mut idx = 0;
+        while idx < function.body.len() {
+            let Instruction::Call { func } = &function.body[idx] else {
+                idx += 1;
+                continue;
+            };
+            let Some((_, body)) = bodies.iter().find(|(name, _)| name == func) else {
+                idx += 1;
+                continue;
+            };
+            // the slot the return address would be in, then the callee's frame
+            let mut inlined = vec![
+                Instruction::Push {
+                    src: Source::Register(ebp),
+                },
+                Instruction::Push {
+                    src: Source::Register(ebp),
+                },
+                Instruction::Mov {
+                    dest: Destination::Register(ebp),
+                    src: Source::Register(esp),
+                },
+            ];
+            inlined.extend(body.iter().cloned());
+            inlined.push(Instruction::Leave);
+            inlined.push(Instruction::Add {
+                dest: Destination::Register(esp),
+                src: Source::Amount(4),
+            });
+            let len = inlined.len();
+            splice(function, idx, 1, inlined);
+            idx += len;
+        }
+    }
+}
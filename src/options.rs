@@ -0,0 +1,127 @@
+use crate::profile::Profile;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+/// the machine the compiled program runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// 32-bit x86 with the cdecl calling convention
+    #[default]
+    X86,
+}
+impl Target {
+    /// whether `name` is the architecture or operating system of the target, which
+    /// `(cfg (target name) ...)` tests
+    pub fn matches(self, name: &str) -> bool {
+        match self {
+            Target::X86 => matches!(name, "x86" | "linux"),
+        }
+    }
+}
+/// how much the compiler optimizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+}
+impl OptLevel {
+    /// evaluate arithmetic on constants at compile time
+    pub fn folds_constants(self) -> bool {
+        self >= OptLevel::O1
+    }
+    pub fn peephole(self) -> bool {
+        self >= OptLevel::O1
+    }
+    /// drop unreachable instructions and unused functions and externs
+    pub fn eliminates_dead_code(self) -> bool {
+        self >= OptLevel::O2
+    }
+    /// replace calls to small leaf functions with their bodies
+    pub fn inlines(self) -> bool {
+        self >= OptLevel::O2
+    }
+    /// copy the bodies of counted loops, see `Compiler::unroll`
+    pub fn unrolls_loops(self) -> bool {
+        self >= OptLevel::O2
+    }
+}
+/// what a lint in `lint::LINTS` does when it finds something
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LintLevel {
+    Allow,
+    #[default]
+    Warn,
+    /// fail compiling
+    Deny,
+}
+/// the assembler dialect the program is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Nasm,
+}
+
+/// options for compiling a whole program
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CompileOptions {
+    pub target: Target,
+    pub opt_level: OptLevel,
+    /// annotate emitted instructions with the source expression they were generated from
+    pub comments: bool,
+    /// record the source line of every expression for `%line` debug directives, which nasm
+    /// turns into DWARF line information with `-g -F dwarf`
+    pub debug_info: bool,
+    /// define a symbol for every local set to its offset from the base pointer, see
+    /// `Function::local_offsets`
+    pub local_offsets: bool,
+    /// only type check the program for `lerp check`: lowering still emits the functions of the
+    /// program, as that's where types are checked, but leaves out the runtime functions they
+    /// call and the overflow checks, and none of the passes after it run
+    pub check_only: bool,
+    /// record the source line of every expression like `debug_info`, to be interleaved with
+    /// the assembly by `Program::interleave_source`
+    pub asm_source: bool,
+    pub syntax: Syntax,
+    /// passes to dump the program after
+    pub print_after: Vec<String>,
+    /// threads compiling function bodies, more than one needs the `parallel` feature
+    pub jobs: usize,
+    /// directory to reuse compiled functions from, see `cache::Cache`
+    pub cache: Option<PathBuf>,
+    /// trap on integer `+` and `-` overflowing instead of wrapping around
+    pub checked_arithmetic: bool,
+    /// make Linux system calls for file access instead of calling the C library
+    pub no_libc: bool,
+    /// leave the functions of `prelude::prelude` out of the program
+    pub no_prelude: bool,
+    /// compile for a system without the C library, rejecting the builtins which call into it
+    /// and making system calls directly like `no_libc`
+    pub freestanding: bool,
+    /// how many copies of its body `-O2` partially unrolls a counted loop into, 0 for
+    /// `unroll::DEFAULT_FACTOR` and 1 to leave such loops alone
+    pub unroll_factor: usize,
+    /// count how often every function is entered and write the counts out when `main` returns,
+    /// see `profile::instrument`
+    pub profile_generate: bool,
+    /// the profile of an instrumented run to lay the program out by, see `profile::apply`
+    pub profile: Option<Profile>,
+    /// the most bytes of stack `main` and the exported functions may take, rejecting programs
+    /// which could take more or recurse, see `stats::stack_usage`
+    pub max_stack: Option<usize>,
+    /// warn about every function which calls itself, directly or through others
+    pub no_recursion: bool,
+    /// the features `(cfg (feature "name") ...)` compiles the code of, from `-D name`
+    pub features: BTreeSet<String>,
+    /// the levels of the lints set with `--allow`, `--warn` and `--deny`, the others warn
+    pub lints: BTreeMap<String, LintLevel>,
+}
+impl CompileOptions {
+    /// whether system calls go through the C library
+    pub fn uses_libc(&self) -> bool {
+        !self.no_libc && !self.freestanding
+    }
+}
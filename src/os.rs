@@ -0,0 +1,190 @@
+use crate::{
+    code::{Instruction, Register, RegisterName, RegisterSize, Source},
+    compiler::{CompileError, Compiler},
+    parser::{Located, Position, SExpr},
+    typ::{IntType, Type},
+};
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+
+/// the numbers of the 32-bit Linux system calls
+pub const SYS_EXIT: u32 = 1;
+pub const SYS_READ: u32 = 3;
+pub const SYS_WRITE: u32 = 4;
+pub const SYS_OPEN: u32 = 5;
+pub const SYS_CLOSE: u32 = 6;
+/// the permissions `open` creates files with
+const CREATE_MODE: i32 = 0o644;
+/// the builtins which always call into the C library
+const LIBC_FORMS: &[&str] = &[
+    "print", "println", "format", "str-len", "str-cat", "str-eq", "case-str", "spawn", "join",
+    "getenv",
+];
+
+fn string_type() -> Type {
+    Type::Pointer(Box::new(Type::UInt(IntType::S8)))
+}
+
+impl Compiler {
+    /// fails if the builtin `name` calls into the C library and the program is freestanding
+    ///
+    /// `alloc` and `free` only need it without the bump allocator
+    pub fn check_freestanding(
+        &self,
+        name: &str,
+        pos: Position,
+    ) -> Result<(), Located<CompileError>> {
+        let needs_libc = LIBC_FORMS.contains(&name)
+            || (matches!(name, "alloc" | "free") && !self.bump_allocator);
+        if self.options.freestanding && needs_libc {
+            return Err(Located {
+                value: CompileError::NeedsLibc(name.to_string()),
+                pos,
+            });
+        }
+        Ok(())
+    }
+    /// compiles and pushes an argument of a C function, which has to be a string if `string` and
+    /// an integer of at most 32 bits otherwise
+    fn push_c_arg(
+        &mut self,
+        sexpr: Located<SExpr>,
+        string: bool,
+    ) -> Result<(), Located<CompileError>> {
+        self.push_c_arg_of(sexpr, |typ| match string {
+            true => string_type().accepts(typ),
+            false => matches!(typ, Type::Int(_) | Type::UInt(_)),
+        })
+    }
+    /// compiles and pushes an argument of a C function, whose type has to be `valid` and fit
+    /// into 32 bits
+    fn push_c_arg_of(
+        &mut self,
+        sexpr: Located<SExpr>,
+        valid: impl Fn(&Type) -> bool,
+    ) -> Result<(), Located<CompileError>> {
+        let pos = sexpr.pos;
+        let typ = self.compile(sexpr)?;
+        // arrays are passed by their address
+        let valid = valid(&typ)
+            && (matches!(typ, Type::Array { .. }) || self.widen(&typ) == Some(EAX.size));
+        if !valid {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos,
+            });
+        }
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        Ok(())
+    }
+
+    /// `(getenv name)`: the value of the environment variable `name`, or a null pointer if it
+    /// isn't set
+    pub fn compile_getenv(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([name]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        self.push_c_arg(name, true)?;
+        self.use_extern("getenv");
+        self.call("getenv", RegisterSize::S32.bytes());
+        Ok(string_type())
+    }
+    /// `(exit code)`: ends the program with the integer `code` as its exit code, without
+    /// flushing the C library's buffers without `options.uses_libc`
+    pub fn compile_exit(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([code]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        self.push_c_arg(code, false)?;
+        if !self.options.uses_libc() {
+            self.syscall(SYS_EXIT, 1);
+            return Ok(Type::Never);
+        }
+        self.use_extern("exit");
+        self.call("exit", RegisterSize::S32.bytes());
+        Ok(Type::Never)
+    }
+    /// `(open path flags)`, `(read fd buf n)`, `(write fd buf n)` and `(close fd)`: the file
+    /// system call `number` through the C library, or made directly without `options.uses_libc`
+    ///
+    /// `open` creates files readable by everyone and writable by the owner, `read` and `write`
+    /// return an `isz`, and all of them fail with a negative value
+    pub fn compile_file_call(
+        &mut self,
+        number: u32,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let (name, params, typ) = match number {
+            SYS_OPEN => ("open", 2, Type::Int(IntType::S32)),
+            SYS_READ => ("read", 3, Type::Int(IntType::Size)),
+            SYS_WRITE => ("write", 3, Type::Int(IntType::Size)),
+            _ => ("close", 1, Type::Int(IntType::S32)),
+        };
+        if sexprs.len() != params {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(params),
+                pos,
+            });
+        }
+        let mut args = params;
+        if number == SYS_OPEN {
+            self.write(Instruction::Push {
+                src: Source::Int(CREATE_MODE),
+            });
+            args += 1;
+        }
+        // pushed from the last like the arguments of a call
+        for idx in (0..params).rev() {
+            let sexpr = sexprs.remove(idx);
+            match (number, idx) {
+                (SYS_OPEN, 0) => self.push_c_arg(sexpr, true)?,
+                (SYS_READ | SYS_WRITE, 1) => self.push_c_arg_of(sexpr, |typ| {
+                    matches!(typ, Type::Pointer(_) | Type::Array { .. })
+                })?,
+                _ => self.push_c_arg(sexpr, false)?,
+            }
+        }
+        if self.options.uses_libc() {
+            self.use_extern(name);
+            self.call(name, args * RegisterSize::S32.bytes());
+            return Ok(typ);
+        }
+        self.syscall(number, args);
+        Ok(typ)
+    }
+    /// makes the Linux system call `number` with the `args` arguments pushed last, the first
+    /// one on top
+    fn syscall(&mut self, number: u32, args: usize) {
+        let registers = [RegisterName::B, RegisterName::C, RegisterName::D];
+        for name in &registers[..args] {
+            self.write(Instruction::Pop {
+                dest: Register { name: *name, ..EAX }.into(),
+            });
+        }
+        self.write(Instruction::Mov {
+            dest: EAX.into(),
+            src: Source::Int(number as i32),
+        });
+        self.write(Instruction::Interrupt(0x80));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::{intern::Symbol, typ::Type};
 use std::{
     fmt::{Debug, Display},
     iter::Peekable,
@@ -6,12 +7,63 @@ use std::{
 };
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SExpr {
     Expr(Vec<Located<Self>>),
-    Word(String),
-    Int(i32),
-    Float(f32),
+    /// `[...]`
+    Bracket(Vec<Located<Self>>),
+    /// `{...}`
+    Brace(Vec<Located<Self>>),
+    Word(Symbol),
+    /// an integer with the type it is suffixed with, like `255u8`, if any
+    Int(i128, Option<Type>),
+    /// a float with the type it is suffixed with, like `3.0f64`, if any
+    Float(f64, Option<Type>),
     String(String),
+    /// `b"..."`, bytes without a terminating zero
+    ByteString(String),
+    /// `:name`
+    Keyword(Symbol),
+    Quoted(QuoteKind, Box<Located<Self>>),
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QuoteKind {
+    /// `'expr`
+    Quote,
+    /// `` `expr ``
+    Quasiquote,
+    /// `,expr`
+    Unquote,
+}
+impl QuoteKind {
+    pub fn symbol(&self) -> char {
+        match self {
+            QuoteKind::Quote => '\'',
+            QuoteKind::Quasiquote => '`',
+            QuoteKind::Unquote => ',',
+        }
+    }
+}
+/// a float literal as written, `inf` and `nan` included
+struct FloatLiteral(f64);
+impl Display for FloatLiteral {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            float if float.is_nan() => write!(f, "nan"),
+            float => write!(f, "{float:?}"),
+        }
+    }
+}
+/// the type suffix of a number literal as written, nothing if it has none
+struct Suffix<'t>(&'t Option<Type>);
+impl Display for Suffix<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(typ) => write!(f, "{typ}"),
+            None => Ok(()),
+        }
+    }
 }
 impl Display for Located<SExpr> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -26,19 +78,49 @@ impl Display for Located<SExpr> {
                     .collect::<Vec<String>>()
                     .join(" ")
             ),
+            SExpr::Bracket(sexprs) => write!(
+                f,
+                "[{}]",
+                sexprs
+                    .iter()
+                    .map(|sexpr| sexpr.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
+            SExpr::Brace(sexprs) => write!(
+                f,
+                "{{{}}}",
+                sexprs
+                    .iter()
+                    .map(|sexpr| sexpr.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
             SExpr::Word(word) => write!(f, "{word}"),
-            SExpr::Int(int) => write!(f, "{int:?}"),
-            SExpr::Float(float) => write!(f, "{float:?}"),
+            SExpr::Int(int, typ) => write!(f, "{int:?}{}", Suffix(typ)),
+            SExpr::Float(float, typ) => {
+                write!(f, "{}{}", FloatLiteral(*float), Suffix(typ))
+            }
             SExpr::String(string) => write!(f, "{string:?}"),
+            SExpr::ByteString(string) => write!(f, "b{string:?}"),
+            SExpr::Keyword(keyword) => write!(f, ":{keyword}"),
+            SExpr::Quoted(kind, sexpr) => write!(f, "{}{sexpr}", kind.symbol()),
         }
     }
 }
+/// identifies one of the source files of a program, the first one is 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(pub u32);
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Position {
     pub ln: usize,
     pub col: usize,
+    pub file: FileId,
 }
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Located<T>
 where
     T: Debug + Clone,
@@ -57,22 +139,82 @@ pub enum ParseErrorKind {
     Unexpected(char),
     Unclosed(char),
     UnclosedString,
+    StringTooLong(usize),
     ParseFloatError(ParseFloatError),
     ParseIntError(ParseIntError),
+    /// a number suffixed with something but a type it can have
+    InvalidSuffix(String),
+    /// a `_` in a number which doesn't separate two digits
+    MisplacedSeparator,
 }
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}:{}: {}", self.pos.ln + 1, self.pos.col + 1, self.kind)
     }
 }
+impl std::error::Error for ParseError {}
+impl ParseErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseErrorKind::Unexpected(_) => "unexpected-char",
+            ParseErrorKind::Unclosed(_) => "unclosed-delimiter",
+            ParseErrorKind::UnclosedString => "unclosed-string",
+            ParseErrorKind::StringTooLong(_) => "string-too-long",
+            ParseErrorKind::ParseFloatError(_) => "invalid-float",
+            ParseErrorKind::ParseIntError(_) => "invalid-int",
+            ParseErrorKind::InvalidSuffix(_) => "invalid-suffix",
+            ParseErrorKind::MisplacedSeparator => "misplaced-separator",
+        }
+    }
+}
 impl Display for ParseErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ParseErrorKind::Unexpected(c) => write!(f, "unexpected {c:?}"),
             ParseErrorKind::Unclosed(c) => write!(f, "unclosed {c:?}"),
             ParseErrorKind::UnclosedString => write!(f, "unclosed string"),
+            ParseErrorKind::StringTooLong(max) => {
+                write!(f, "raw string spans more than {max} lines")
+            }
             ParseErrorKind::ParseFloatError(err) => write!(f, "error while parsing float: {err}"),
             ParseErrorKind::ParseIntError(err) => write!(f, "error while parsing int: {err}"),
+            ParseErrorKind::InvalidSuffix(suffix) => {
+                write!(f, "{suffix:?} is not a type the number can have")
+            }
+            ParseErrorKind::MisplacedSeparator => {
+                write!(f, "digit separators have to be between two digits")
+            }
+        }
+    }
+}
+impl std::error::Error for ParseErrorKind {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// `(`, `[` or `{`
+    Open(char),
+    /// `)`, `]` or `}`
+    Close(char),
+    /// `'`, `` ` `` or `,`
+    Quote(QuoteKind),
+    Word(Symbol),
+    Int(i128, Option<Type>),
+    Float(f64, Option<Type>),
+    String(String),
+    ByteString(String),
+    Keyword(Symbol),
+}
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Open(c) | Token::Close(c) => write!(f, "{c}"),
+            Token::Quote(kind) => write!(f, "{}", kind.symbol()),
+            Token::Word(word) => write!(f, "{word}"),
+            Token::Int(int, typ) => write!(f, "{int:?}{}", Suffix(typ)),
+            Token::Float(float, typ) => write!(f, "{}{}", FloatLiteral(*float), Suffix(typ)),
+            Token::String(string) => write!(f, "{string:?}"),
+            Token::ByteString(string) => write!(f, "b{string:?}"),
+            Token::Keyword(keyword) => write!(f, ":{keyword}"),
         }
     }
 }
@@ -82,6 +224,14 @@ pub struct Lexer<'s> {
     pub text: Peekable<Chars<'s>>,
     pub ln: usize,
     pub col: usize,
+    /// maximum number of lines a raw `"""..."""` string may span
+    pub max_string_lines: usize,
+    /// position right after the last string literal
+    pub string_end: Position,
+    /// the file every position is in
+    pub file: FileId,
+    /// whether iterating returned an error, after which it ends
+    failed: bool,
 }
 impl<'s> From<&'s str> for Lexer<'s> {
     fn from(value: &'s str) -> Self {
@@ -89,12 +239,32 @@ impl<'s> From<&'s str> for Lexer<'s> {
             text: value.chars().peekable(),
             ln: 0,
             col: 0,
+            max_string_lines: Self::MAX_STRING_LINES,
+            string_end: Position {
+                ln: 0,
+                col: 0,
+                file: FileId::default(),
+            },
+            file: FileId::default(),
+            failed: false,
         }
     }
 }
+impl<'s> Iterator for Lexer<'s> {
+    type Item = Result<Located<Token>, ParseError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let token = self.next_token().transpose();
+        self.failed = matches!(token, Some(Err(_)));
+        token
+    }
+}
 impl<'s> Lexer<'s> {
-    pub const SYMBOLS: &'static [char] = &['(', ')', '"'];
-    pub fn next(&mut self) -> Option<char> {
+    pub const SYMBOLS: &'static [char] = &['(', ')', '[', ']', '{', '}', '"', '\'', '`', ','];
+    pub const MAX_STRING_LINES: usize = 1024;
+    pub fn advance(&mut self) -> Option<char> {
         let c = self.text.next()?;
         if c == '\n' {
             self.ln += 1;
@@ -107,136 +277,273 @@ impl<'s> Lexer<'s> {
     pub fn peek(&mut self) -> Option<&char> {
         self.text.peek()
     }
+    /// checks if the upcoming text starts with `prefix` without consuming it
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        let mut text = self.text.clone();
+        prefix.chars().all(|c| text.next() == Some(c))
+    }
     pub fn pos(&mut self) -> Position {
         Position {
             ln: self.ln,
             col: self.col,
+            file: self.file,
         }
     }
-    pub fn parse_next(&mut self) -> Result<Option<Located<SExpr>>, ParseError> {
+    /// collects characters up to the next whitespace or symbol
+    fn word(&mut self, mut word: String) -> String {
+        while let Some(&c) = self.peek() {
+            if c.is_ascii_whitespace() || Self::SYMBOLS.contains(&c) {
+                break;
+            }
+            self.advance();
+            word.push(c);
+        }
+        word
+    }
+    /// collects the following digits of `radix` into `number`, dropping the `_`s separating
+    /// them
+    fn digits(&mut self, number: &mut String, radix: u32, pos: Position) -> Result<(), ParseError> {
+        let misplaced = Err(ParseError {
+            kind: ParseErrorKind::MisplacedSeparator,
+            pos,
+        });
+        let mut last = number.chars().last();
+        while let Some(&c) = self.peek() {
+            if c == '_' {
+                if !last.is_some_and(|last| last.is_digit(radix)) {
+                    return misplaced;
+                }
+            } else if c.is_digit(radix) {
+                number.push(c);
+            } else {
+                break;
+            }
+            self.advance();
+            last = Some(c);
+        }
+        match last {
+            Some('_') => misplaced,
+            _ => Ok(()),
+        }
+    }
+    /// collects a string up to its closing `"` on the same line, the opening one already read
+    fn string(&mut self, pos: Position) -> Result<String, ParseError> {
+        let mut string = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => break,
+                Some('\n') | None => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::UnclosedString,
+                        pos,
+                    })
+                }
+                Some(c) => string.push(c),
+            }
+        }
+        self.string_end = self.pos();
+        Ok(string)
+    }
+    /// checks if an exponent like `e9`, `e-3` or `E+2` follows
+    fn starts_exponent(&self) -> bool {
+        let mut text = self.text.clone();
+        if !matches!(text.next(), Some('e' | 'E')) {
+            return false;
+        }
+        match text.next() {
+            Some('+' | '-') => text.next(),
+            c => c,
+        }
+        .is_some_and(|c| c.is_ascii_digit())
+    }
+    pub fn next_token(&mut self) -> Result<Option<Located<Token>>, ParseError> {
         while let Some(c) = self.peek() {
             if !c.is_ascii_whitespace() {
                 break;
             }
-            self.next();
+            self.advance();
         }
         let pos = self.pos();
-        let Some(c) = self.next() else {
+        let Some(c) = self.advance() else {
             return Ok(None);
         };
-        match c {
-            '(' => {
-                let mut exprs = vec![];
-                while let Some(c) = self.peek() {
-                    if c == &')' {
-                        break;
-                    }
-                    let Some(sexpr) = self.parse_next()? else {
+        let token = match c {
+            '(' | '[' | '{' => Token::Open(c),
+            ')' | ']' | '}' => Token::Close(c),
+            '\'' => Token::Quote(QuoteKind::Quote),
+            '`' => Token::Quote(QuoteKind::Quasiquote),
+            ',' => Token::Quote(QuoteKind::Unquote),
+            '"' if self.starts_with("\"\"") => {
+                self.advance();
+                self.advance();
+                let mut string = String::new();
+                while !self.starts_with("\"\"\"") {
+                    let Some(c) = self.advance() else {
                         return Err(ParseError {
-                            kind: ParseErrorKind::Unclosed('('),
+                            kind: ParseErrorKind::UnclosedString,
                             pos,
                         });
                     };
-                    exprs.push(sexpr);
-                    while let Some(c) = self.peek() {
-                        if !c.is_ascii_whitespace() {
-                            break;
-                        }
-                        self.next();
-                    }
-                }
-                if self.next() != Some(')') {
-                    return Err(ParseError {
-                        kind: ParseErrorKind::Unclosed('('),
-                        pos,
-                    });
-                }
-                Ok(Some(Located {
-                    value: SExpr::Expr(exprs),
-                    pos,
-                }))
-            }
-            '"' => {
-                let mut string = String::new();
-                while let Some(c) = self.peek() {
-                    if c == &'"' {
-                        break;
+                    if self.ln - pos.ln >= self.max_string_lines {
+                        return Err(ParseError {
+                            kind: ParseErrorKind::StringTooLong(self.max_string_lines),
+                            pos,
+                        });
                     }
-                    let c = self.next().unwrap();
                     string.push(c);
                 }
-                let Some(c) = self.next() else {
-                    return Err(ParseError {
-                        kind: ParseErrorKind::UnclosedString,
-                        pos: self.pos(),
-                    });
-                };
-                if c != '"' {
-                    return Err(ParseError {
-                        kind: ParseErrorKind::UnclosedString,
-                        pos: self.pos(),
-                    });
-                }
-                Ok(Some(Located {
-                    value: SExpr::String(string),
-                    pos,
-                }))
+                self.advance();
+                self.advance();
+                self.advance();
+                self.string_end = self.pos();
+                Token::String(string)
+            }
+            '"' => Token::String(self.string(pos)?),
+            'b' if self.peek() == Some(&'"') => {
+                self.advance();
+                Token::ByteString(self.string(pos)?)
             }
             c if c.is_ascii_digit() => {
-                let mut number = String::from(c);
-                while let Some(c) = self.peek() {
-                    if !c.is_ascii_digit() {
-                        break;
-                    }
-                    let c = self.next().unwrap();
+                let radix = match (c, self.peek()) {
+                    ('0', Some('x')) => 16,
+                    ('0', Some('b')) => 2,
+                    _ => 10,
+                };
+                let mut number = String::new();
+                if radix == 10 {
                     number.push(c);
+                } else {
+                    self.advance();
                 }
-                if self.peek() == Some(&'.') {
-                    let c = self.next().unwrap();
-                    number.push(c);
-                    while let Some(c) = self.peek() {
-                        if !c.is_ascii_digit() {
-                            break;
+                self.digits(&mut number, radix, pos)?;
+                let mut float = radix == 10 && self.peek() == Some(&'.');
+                if float {
+                    self.advance();
+                    number.push('.');
+                    self.digits(&mut number, radix, pos)?;
+                }
+                if radix == 10 && self.starts_exponent() {
+                    float = true;
+                    number.extend(self.advance());
+                    if let Some(&sign @ ('+' | '-')) = self.peek() {
+                        self.advance();
+                        number.push(sign);
+                    }
+                    self.digits(&mut number, radix, pos)?;
+                }
+                let typ = match self.peek() {
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        let suffix = self.word(String::new());
+                        match suffix.parse() {
+                            Ok(typ @ (Type::Int(_) | Type::UInt(_))) if !float => Some(typ),
+                            Ok(typ @ Type::Float(_)) if radix == 10 => Some(typ),
+                            _ => {
+                                return Err(ParseError {
+                                    kind: ParseErrorKind::InvalidSuffix(suffix),
+                                    pos,
+                                })
+                            }
                         }
-                        let c = self.next().unwrap();
-                        number.push(c);
                     }
-                    Ok(Some(Located {
-                        value: SExpr::Float(number.parse().map_err(|err| ParseError {
-                            kind: ParseErrorKind::ParseFloatError(err),
-                            pos,
-                        })?),
+                    _ => None,
+                };
+                // an integer suffixed with a float type is a float
+                if float || matches!(typ, Some(Type::Float(_))) {
+                    let float = number.parse().map_err(|err| ParseError {
+                        kind: ParseErrorKind::ParseFloatError(err),
                         pos,
-                    }))
+                    })?;
+                    Token::Float(float, typ)
                 } else {
-                    Ok(Some(Located {
-                        value: SExpr::Int(number.parse().map_err(|err| ParseError {
-                            kind: ParseErrorKind::ParseIntError(err),
-                            pos,
-                        })?),
+                    let int = i128::from_str_radix(&number, radix).map_err(|err| ParseError {
+                        kind: ParseErrorKind::ParseIntError(err),
                         pos,
-                    }))
+                    })?;
+                    Token::Int(int, typ)
                 }
             }
-            c if !Self::SYMBOLS.contains(&c) => {
-                let mut word = String::from(c);
-                while let Some(c) = self.peek() {
-                    if c.is_ascii_whitespace() || Self::SYMBOLS.contains(c) {
-                        break;
-                    }
-                    let c = self.next().unwrap();
-                    word.push(c);
+            ':' => {
+                let keyword = self.word(String::new());
+                if keyword.is_empty() {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::Unexpected(':'),
+                        pos,
+                    });
                 }
-                Ok(Some(Located {
-                    value: SExpr::Word(word),
+                Token::Keyword(Symbol::from(keyword))
+            }
+            c => match self.word(String::from(c)).as_str() {
+                "inf" => Token::Float(f64::INFINITY, None),
+                "nan" => Token::Float(f64::NAN, None),
+                word => Token::Word(Symbol::intern(word)),
+            },
+        };
+        Ok(Some(Located { value: token, pos }))
+    }
+    pub fn parse_next(&mut self) -> Result<Option<Located<SExpr>>, ParseError> {
+        let Some(token) = self.next_token()? else {
+            return Ok(None);
+        };
+        self.parse_token(token).map(Some)
+    }
+    /// builds the expression starting with `token`
+    pub fn parse_token(&mut self, token: Located<Token>) -> Result<Located<SExpr>, ParseError> {
+        let Located { value: token, pos } = token;
+        let value = match token {
+            Token::Open('(') => SExpr::Expr(self.parse_group('(', ')', pos)?),
+            Token::Open('[') => SExpr::Bracket(self.parse_group('[', ']', pos)?),
+            Token::Open(c) => SExpr::Brace(self.parse_group(c, '}', pos)?),
+            Token::Close(c) => {
+                return Err(ParseError {
+                    kind: ParseErrorKind::Unexpected(c),
                     pos,
-                }))
+                })
+            }
+            Token::Quote(kind) => {
+                let Some(sexpr) = self.parse_next()? else {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::Unexpected(kind.symbol()),
+                        pos,
+                    });
+                };
+                SExpr::Quoted(kind, Box::new(sexpr))
+            }
+            Token::Word(word) => SExpr::Word(word),
+            Token::Int(int, typ) => SExpr::Int(int, typ),
+            Token::Float(float, typ) => SExpr::Float(float, typ),
+            Token::String(string) => SExpr::String(string),
+            Token::ByteString(string) => SExpr::ByteString(string),
+            Token::Keyword(keyword) => SExpr::Keyword(keyword),
+        };
+        Ok(Located { value, pos })
+    }
+    /// parses the expressions up to the `close` delimiter of a group opened at `pos`
+    pub fn parse_group(
+        &mut self,
+        open: char,
+        close: char,
+        pos: Position,
+    ) -> Result<Vec<Located<SExpr>>, ParseError> {
+        let mut exprs = vec![];
+        loop {
+            let Some(token) = self.next_token()? else {
+                return Err(ParseError {
+                    kind: ParseErrorKind::Unclosed(open),
+                    pos,
+                });
+            };
+            match token.value {
+                Token::Close(c) if c == close => break,
+                Token::Close(_) => {
+                    return Err(ParseError {
+                        kind: ParseErrorKind::Unclosed(open),
+                        pos,
+                    })
+                }
+                _ => exprs.push(self.parse_token(token)?),
             }
-            c => Err(ParseError {
-                kind: ParseErrorKind::Unexpected(c),
-                pos,
-            }),
         }
+        Ok(exprs)
     }
     pub fn parse(&mut self) -> Result<Vec<Located<SExpr>>, ParseError> {
         let mut exprs = vec![];
@@ -245,8 +552,42 @@ impl<'s> Lexer<'s> {
         }
         Ok(exprs)
     }
+    /// skips to the next `(` at the start of a line, the start of the next top-level expression
+    pub fn synchronize(&mut self) {
+        while self.peek().is_some() {
+            if self.col == 0 && self.peek() == Some(&'(') {
+                break;
+            }
+            self.advance();
+        }
+    }
+    /// parses the whole text, recovering from errors to collect every one of them
+    pub fn parse_recovering(&mut self) -> (Vec<Located<SExpr>>, Vec<ParseError>) {
+        let mut exprs = vec![];
+        let mut errors = vec![];
+        loop {
+            match self.parse_next() {
+                Ok(Some(expr)) => exprs.push(expr),
+                Ok(None) => break,
+                Err(err) => {
+                    errors.push(err);
+                    self.synchronize();
+                }
+            }
+        }
+        (exprs, errors)
+    }
 }
 
 pub fn parse(code: &str) -> Result<Vec<Located<SExpr>>, ParseError> {
     Lexer::from(code).parse()
 }
+pub fn parse_recovering(code: &str) -> (Vec<Located<SExpr>>, Vec<ParseError>) {
+    Lexer::from(code).parse_recovering()
+}
+/// like `parse_recovering`, placing every expression in `file`
+pub fn parse_file(code: &str, file: FileId) -> (Vec<Located<SExpr>>, Vec<ParseError>) {
+    let mut lexer = Lexer::from(code);
+    lexer.file = file;
+    lexer.parse_recovering()
+}
@@ -0,0 +1,109 @@
+use crate::code::Program;
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// a named transformation of a whole program
+#[derive(Debug, Clone, Copy)]
+pub struct Pass {
+    pub name: &'static str,
+    /// passes which have to run before this one if they are scheduled at all
+    pub after: &'static [&'static str],
+    pub run: fn(&mut Program),
+}
+/// what running a single pass did
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassRun {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    /// the program after the pass if it was asked to be printed
+    pub dump: Option<String>,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassError {
+    /// the passes' ordering constraints contradict each other
+    Cycle(Vec<&'static str>),
+    Duplicate(&'static str),
+}
+impl Display for PassError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PassError::Cycle(names) => {
+                write!(
+                    f,
+                    "the passes {} have to run before each other",
+                    names.join(", ")
+                )
+            }
+            PassError::Duplicate(name) => write!(f, "the pass {name} is scheduled twice"),
+        }
+    }
+}
+impl std::error::Error for PassError {}
+
+/// runs passes in the order they were added, moving a pass later if it has to run after another
+#[derive(Debug, Clone, Default)]
+pub struct PassManager {
+    passes: Vec<Pass>,
+    print_after: Vec<String>,
+}
+impl PassManager {
+    pub fn add(&mut self, pass: Pass) {
+        self.passes.push(pass);
+    }
+    /// dumps the program after every run of the pass `name`
+    pub fn print_after(&mut self, name: impl Into<String>) {
+        self.print_after.push(name.into());
+    }
+    /// the names of the scheduled passes in the order they run in
+    pub fn order(&self) -> Result<Vec<&'static str>, PassError> {
+        Ok(self.schedule()?.into_iter().map(|pass| pass.name).collect())
+    }
+    fn schedule(&self) -> Result<Vec<Pass>, PassError> {
+        for (idx, pass) in self.passes.iter().enumerate() {
+            if self.passes[..idx]
+                .iter()
+                .any(|other| other.name == pass.name)
+            {
+                return Err(PassError::Duplicate(pass.name));
+            }
+        }
+        let mut pending = self.passes.clone();
+        let mut order = vec![];
+        while !pending.is_empty() {
+            // the first pass whose predecessors have all been scheduled
+            let ready = pending.iter().position(|pass| {
+                pass.after
+                    .iter()
+                    .all(|before| pending.iter().all(|other| other.name != *before))
+            });
+            let Some(ready) = ready else {
+                return Err(PassError::Cycle(
+                    pending.iter().map(|pass| pass.name).collect(),
+                ));
+            };
+            order.push(pending.remove(ready));
+        }
+        Ok(order)
+    }
+    pub fn run(&self, program: &mut Program) -> Result<Vec<PassRun>, PassError> {
+        let mut runs = vec![];
+        for pass in self.schedule()? {
+            let start = Instant::now();
+            (pass.run)(program);
+            let elapsed = start.elapsed();
+            let dump = self
+                .print_after
+                .iter()
+                .any(|name| name == pass.name)
+                .then(|| program.to_string());
+            runs.push(PassRun {
+                name: pass.name,
+                elapsed,
+                dump,
+            });
+        }
+        Ok(runs)
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{
+    compiler::{parse_defn, CompileError, Compiler, Defn},
+    intern::Symbol,
+    parser::{parse_file, FileId, Located, Position, SExpr},
+};
+use std::collections::HashMap;
+
+/// functions every program can call without defining them, `print` and `println` being built
+/// into the compiler
+///
+/// `min`, `max` and `abs` of `i32`s, and `str-empty`, `str-dup` and `str-join` of strings
+const SOURCE: &str = include_str!("prelude.lp");
+/// the file positions in the prelude are in, which isn't one of the program's
+pub const PRELUDE_FILE: FileId = FileId(u32::MAX);
+
+/// the functions of the prelude by name
+pub fn prelude() -> HashMap<Symbol, Defn> {
+    let (sexprs, errors) = parse_file(SOURCE, PRELUDE_FILE);
+    debug_assert!(errors.is_empty(), "the prelude doesn't parse: {errors:?}");
+    let mut functions = HashMap::new();
+    for sexpr in sexprs {
+        let SExpr::Expr(mut sexprs) = sexpr.value else {
+            continue;
+        };
+        if !matches!(sexprs.first(), Some(Located { value: SExpr::Word(head), .. }) if head == "defn")
+        {
+            continue;
+        }
+        sexprs.remove(0);
+        if let Ok(defn) = parse_defn(sexprs, sexpr.pos) {
+            functions.insert(defn.name, defn);
+        }
+    }
+    functions
+}
+
+impl Compiler {
+    /// compiles the prelude function `name` for a call to it at `pos`, if the program doesn't
+    /// define a function of that name, and returns whether there is one
+    ///
+    /// only the functions a program calls end up in it, errors in them are reported at the call
+    pub fn use_prelude(
+        &mut self,
+        name: Symbol,
+        pos: Position,
+    ) -> Result<bool, Located<CompileError>> {
+        let Some(defn) = self.prelude.remove(&name) else {
+            return Ok(false);
+        };
+        self.compile_function(
+            defn.name,
+            HashMap::new(),
+            defn.params,
+            defn.variadic,
+            defn.ret,
+            defn.body,
+        )
+        .map_err(|err| Located { pos, ..err })?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,361 @@
+use crate::{
+    code::{
+        Address, ComparisonOperator, DataType, Destination, Function, Instruction, Memory, Program,
+        Register, RegisterName, RegisterSize, Source,
+    },
+    intern::Symbol,
+    opt::splice,
+    typ::Type,
+};
+use std::{cmp::Reverse, collections::BTreeMap, fmt::Display};
+
+/// the file programs built with `--profile-generate` write their profile to, stderr if it can't
+/// be created
+pub const PROFILE_FILE: &str = "lerp.profile";
+/// the function instrumented programs write their profile with when `main` returns
+pub const DUMP_FUNCTION: &str = "lerp_profile_dump";
+/// the bytes a line of the profile is formatted into, longer names are cut off
+const LINE_SIZE: usize = 256;
+/// `O_WRONLY | O_CREAT | O_TRUNC`
+const CREATE_FLAGS: i32 = 0o1101;
+const CREATE_MODE: i32 = 0o644;
+const STDERR: i32 = 2;
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const ECX: Register = Register {
+    name: RegisterName::C,
+    size: RegisterSize::S32,
+};
+const EBP: Register = Register {
+    name: RegisterName::BP,
+    size: RegisterSize::S32,
+};
+const ESP: Register = Register {
+    name: RegisterName::SP,
+    size: RegisterSize::S32,
+};
+
+/// how often every function of a program was entered in a run of it, read from the lines
+/// `count name` an instrumented program writes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub counts: BTreeMap<String, u64>,
+}
+/// a line of a profile which isn't `count name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileError {
+    pub line: usize,
+}
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: expected `count name`", self.line + 1)
+    }
+}
+impl std::error::Error for ProfileError {}
+
+impl Profile {
+    pub fn parse(text: &str) -> Result<Self, ProfileError> {
+        let mut counts = BTreeMap::new();
+        for (line, text) in text.lines().enumerate() {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let entry = text
+                .trim()
+                .split_once(' ')
+                .and_then(|(count, name)| Some((count.parse::<u64>().ok()?, name.trim())));
+            let Some((count, name)) = entry else {
+                return Err(ProfileError { line });
+            };
+            // a function listed twice was entered as often as both lines say
+            *counts.entry(name.to_string()).or_default() += count;
+        }
+        Ok(Self { counts })
+    }
+    /// how often the function `name` was entered, none if it isn't in the profile
+    pub fn count(&self, name: &str) -> Option<u64> {
+        self.counts.get(name).copied()
+    }
+    /// whether the function `name` is in the profile but was never entered
+    pub fn is_cold(&self, name: &str) -> bool {
+        self.count(name) == Some(0)
+    }
+}
+
+fn counter(idx: usize) -> String {
+    format!("lerp_profile_count{idx}")
+}
+fn dword(address: Address) -> Memory {
+    Memory {
+        data_type: DataType::DoubleWord,
+        address,
+    }
+}
+
+/// counts the calls of every function in `.bss` and writes the counts to `PROFILE_FILE` when
+/// `main` returns, programs leaving through `exit` or a panic write nothing
+pub fn instrument(program: &mut Program) {
+    let names: Vec<String> = program
+        .functions
+        .iter()
+        .map(|function| function.name.clone())
+        .collect();
+    for (idx, function) in program.functions.iter_mut().enumerate() {
+        program.bss.push((counter(idx), RegisterSize::S32.bytes()));
+        splice(
+            function,
+            0,
+            0,
+            vec![Instruction::Add {
+                dest: Destination::Memory(dword(Address::label(counter(idx)))),
+                src: Source::Int(1),
+            }],
+        );
+        if function.name != "main" {
+            continue;
+        }
+        // the exit code is kept across the call
+        let mut idx = 0;
+        while idx + 1 < function.body.len() {
+            if matches!(
+                function.body[idx..],
+                [Instruction::Leave, Instruction::Ret, ..]
+            ) {
+                let dump = vec![
+                    Instruction::Push {
+                        src: Source::Register(EAX),
+                    },
+                    Instruction::Call {
+                        func: Symbol::intern(DUMP_FUNCTION),
+                    },
+                    Instruction::Pop { dest: EAX.into() },
+                ];
+                let len = dump.len();
+                splice(function, idx, 0, dump);
+                idx += len;
+            }
+            idx += 1;
+        }
+    }
+    for name in ["open", "snprintf", "write", "close"] {
+        if !program
+            .externs
+            .iter()
+            .any(|extern_name| extern_name == name)
+        {
+            program.externs.push(name.to_string());
+        }
+    }
+    program.functions.push(dump_function(names));
+}
+
+/// the function writing a line `count name` for each of the functions `names`
+fn dump_function(names: Vec<String>) -> Function {
+    let string = |idx: usize| Source::Name(format!("{DUMP_FUNCTION}_c{idx}"));
+    let buffer = Address::offset(EBP, -(LINE_SIZE as i32));
+    let fd = Address::offset(EBP, -(LINE_SIZE as i32) - RegisterSize::S32.bytes() as i32);
+    let (opened, done) = (Symbol::intern("opened"), Symbol::intern("done"));
+    let call = |func: &str, args: usize| {
+        [
+            Instruction::Call {
+                func: Symbol::intern(func),
+            },
+            Instruction::Add {
+                dest: ESP.into(),
+                src: Source::Amount(args * RegisterSize::S32.bytes()),
+            },
+        ]
+    };
+    let mut body = vec![
+        Instruction::Push {
+            src: Source::Register(EBP),
+        },
+        Instruction::Mov {
+            dest: EBP.into(),
+            src: Source::Register(ESP),
+        },
+        Instruction::Sub {
+            dest: ESP.into(),
+            src: Source::Amount(LINE_SIZE),
+        },
+        Instruction::Push {
+            src: Source::Int(CREATE_MODE),
+        },
+        Instruction::Push {
+            src: Source::Int(CREATE_FLAGS),
+        },
+        Instruction::Push { src: string(0) },
+    ];
+    body.extend(call("open", 3));
+    body.extend([
+        Instruction::Cmp {
+            a: Source::Register(EAX),
+            b: Source::Int(0),
+        },
+        Instruction::JOp {
+            op: ComparisonOperator::GreaterEqual,
+            label: opened,
+        },
+        Instruction::Mov {
+            dest: EAX.into(),
+            src: Source::Int(STDERR),
+        },
+        Instruction::Label(opened),
+        Instruction::Push {
+            src: Source::Register(EAX),
+        },
+    ]);
+    for idx in 0..names.len() {
+        body.extend([
+            Instruction::Push {
+                src: string(idx + 2),
+            },
+            Instruction::Push {
+                src: Source::Memory(dword(Address::label(counter(idx)))),
+            },
+            Instruction::Push { src: string(1) },
+            Instruction::Push {
+                src: Source::Amount(LINE_SIZE),
+            },
+            Instruction::Lea {
+                dest: EAX,
+                addr: buffer.clone(),
+            },
+            Instruction::Push {
+                src: Source::Register(EAX),
+            },
+        ]);
+        body.extend(call("snprintf", 5));
+        // `snprintf` returns the length the line would have had
+        body.extend([
+            Instruction::Mov {
+                dest: ECX.into(),
+                src: Source::Amount(LINE_SIZE - 1),
+            },
+            Instruction::Cmp {
+                a: Source::Register(EAX),
+                b: Source::Register(ECX),
+            },
+            Instruction::Cmov {
+                op: ComparisonOperator::GreaterUnsigned,
+                dest: EAX,
+                src: Source::Register(ECX),
+            },
+            Instruction::Push {
+                src: Source::Register(EAX),
+            },
+            Instruction::Lea {
+                dest: EAX,
+                addr: buffer.clone(),
+            },
+            Instruction::Push {
+                src: Source::Register(EAX),
+            },
+            Instruction::Push {
+                src: Source::Memory(dword(fd.clone())),
+            },
+        ]);
+        body.extend(call("write", 3));
+    }
+    body.extend([
+        Instruction::Pop { dest: EAX.into() },
+        Instruction::Cmp {
+            a: Source::Register(EAX),
+            b: Source::Int(STDERR),
+        },
+        Instruction::JOp {
+            op: ComparisonOperator::Equal,
+            label: done,
+        },
+        Instruction::Push {
+            src: Source::Register(EAX),
+        },
+    ]);
+    body.extend(call("close", 1));
+    body.extend([
+        Instruction::Label(done),
+        Instruction::Leave,
+        Instruction::Ret,
+    ]);
+    Function {
+        name: DUMP_FUNCTION.to_string(),
+        registers: 0,
+        return_type: Type::None,
+        body,
+        strings: [PROFILE_FILE.to_string(), "%u %s\n".to_string()]
+            .into_iter()
+            .chain(names)
+            .collect(),
+        tables: vec![],
+        floats: vec![],
+        bytes: vec![],
+        comments: vec![],
+        lines: vec![],
+        local_offsets: vec![],
+    }
+}
+
+/// lays `program` out for the run `profile` was recorded in: the functions entered most often
+/// first, so the hot ones share cache lines and pages, and the code calling functions which
+/// were never entered at the end of its function, so the hot paths fall through
+pub fn apply(program: &mut Program, profile: &Profile) {
+    program
+        .functions
+        .sort_by_key(|function| Reverse(profile.count(&function.name).unwrap_or(0)));
+    for function in &mut program.functions {
+        move_cold_code(function, profile);
+    }
+}
+
+/// moves the instructions a conditional jump skips to the end of `function` if they call a
+/// cold function, jumping to them with the opposite condition instead
+fn move_cold_code(function: &mut Function, profile: &Profile) {
+    let mut idx = 0;
+    while idx < function.body.len() {
+        let Instruction::JOp { op, label } = function.body[idx] else {
+            idx += 1;
+            continue;
+        };
+        let skipped = function.body[idx + 1..]
+            .iter()
+            .take_while(|instr| {
+                !matches!(
+                    instr,
+                    Instruction::Label(_)
+                        | Instruction::Jmp { .. }
+                        | Instruction::JOp { .. }
+                        | Instruction::JmpIndirect(_)
+                        | Instruction::Ret
+                )
+            })
+            .count();
+        let end = idx + 1 + skipped;
+        let cold = function.body.get(end) == Some(&Instruction::Label(label))
+            && function.body[idx + 1..end].iter().any(|instr| {
+                matches!(instr, Instruction::Call { func } if profile.is_cold(func.as_str()))
+            });
+        if !cold {
+            idx += 1;
+            continue;
+        }
+        let target = Symbol::intern(&format!("{label}_cold"));
+        let mut moved = vec![Instruction::Label(target)];
+        moved.extend(function.body[idx + 1..end].iter().cloned());
+        moved.push(Instruction::Jmp { label });
+        splice(
+            function,
+            idx,
+            end - idx,
+            vec![Instruction::JOp {
+                op: op.negate(),
+                label: target,
+            }],
+        );
+        let len = function.body.len();
+        splice(function, len, 0, moved);
+        idx += 1;
+    }
+}
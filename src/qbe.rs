@@ -0,0 +1,597 @@
+use crate::{
+    cfg,
+    code::string_bytes,
+    compiler::{mangle, parse_defn, CompileError, Defn},
+    intern::Symbol,
+    macros::Expander,
+    options::CompileOptions,
+    parser::{Located, Position, SExpr},
+    typ::{IntType, Type},
+};
+use std::{collections::HashMap, fmt::Write};
+
+/// a function a call can go to, an untyped extern taking any arguments and returning nothing
+#[derive(Debug, Clone)]
+struct Callee {
+    symbol: String,
+    params: Vec<Type>,
+    ret: Type,
+    typed: bool,
+}
+
+/// a QBE operand holding a value of type `typ`, with none for a value of type `none` or `!`
+#[derive(Debug, Clone)]
+struct Value {
+    operand: Option<String>,
+    typ: Type,
+}
+impl Value {
+    fn none(typ: Type) -> Self {
+        Self { operand: None, typ }
+    }
+}
+
+/// the QBE base type of a value of type `typ`, every target of QBE being 64-bit so pointers
+/// and sizes take a long
+fn base_type(typ: &Type) -> Option<&'static str> {
+    match typ {
+        Type::Int(IntType::S32) | Type::UInt(IntType::S32) => Some("w"),
+        Type::Int(IntType::S64 | IntType::Size)
+        | Type::UInt(IntType::S64 | IntType::Size)
+        | Type::Pointer(_) => Some("l"),
+        _ => None,
+    }
+}
+
+/// the `printf` conversion printing a value of type `typ` on a 64-bit target
+fn printf_spec(typ: &Type) -> Option<&'static str> {
+    match typ {
+        Type::Int(IntType::Size) => Some("%zd"),
+        Type::UInt(IntType::Size) => Some("%zu"),
+        typ => typ.printf_spec(),
+    }
+}
+
+/// the error for what QBE output doesn't support, at `pos`
+fn unsupported(what: &str, pos: Position) -> Located<CompileError> {
+    Located {
+        value: CompileError::Unsupported(Symbol::intern(&format!("{what} in QBE output")).as_str()),
+        pos,
+    }
+}
+
+/// the head and arguments of the form `sexpr`
+fn form(sexpr: &Located<SExpr>) -> Option<(Symbol, &[Located<SExpr>])> {
+    let SExpr::Expr(sexprs) = &sexpr.value else {
+        return None;
+    };
+    match sexprs.split_first()? {
+        (
+            Located {
+                value: SExpr::Word(head),
+                ..
+            },
+            args,
+        ) => Some((*head, args)),
+        _ => None,
+    }
+}
+
+/// lowers a program to QBE intermediate language, for `--emit qbe`
+///
+/// only the integer, pointer and string values of 32 and 64 bits are supported, in functions,
+/// calls, `let`, `block`, `return`, `exit`, `+`, `-`, `wrapping-mul`, `/` and printing, which
+/// the program has to be type checked for already, every other form is rejected
+///
+/// untyped externs are called like `printf`, variadic after their first argument
+#[derive(Debug, Default)]
+pub struct Qbe {
+    callees: HashMap<Symbol, Callee>,
+    consts: HashMap<Symbol, i128>,
+    /// whether `+` and `-` trap on overflowing, which QBE can't tell
+    checked: bool,
+    data: String,
+    strings: usize,
+    functions: String,
+    /// the instructions of the function being lowered
+    body: String,
+    temporaries: usize,
+    labels: usize,
+    scopes: Vec<HashMap<Symbol, Value>>,
+    ret: Type,
+}
+impl Qbe {
+    /// lowers `program`, type checked by `Compiler::compile_program` with `options` before
+    pub fn emit_program(
+        program: Vec<Located<SExpr>>,
+        options: &CompileOptions,
+    ) -> Result<String, Located<CompileError>> {
+        let program = cfg::prune(program, options)?;
+        let program = Expander::default().expand_program(program)?;
+        let mut qbe = Self {
+            checked: options.checked_arithmetic,
+            ..Default::default()
+        };
+        // functions can be called before they are defined
+        let mut defns = vec![];
+        let mut main = vec![];
+        for sexpr in program {
+            let pos = sexpr.pos;
+            match form(&sexpr) {
+                Some((head, args)) if head == "extern" => qbe.declare_externs(args)?,
+                Some((head, args)) if head == "defn" => {
+                    let defn = parse_defn(args.to_vec(), pos)?;
+                    qbe.declare_defn(&defn, pos)?;
+                    defns.push(defn);
+                }
+                Some((head, [name, value])) if head == "const" => {
+                    let (SExpr::Word(name), SExpr::Int(value, None)) = (&name.value, &value.value)
+                    else {
+                        return Err(unsupported("constants other than integer literals", pos));
+                    };
+                    qbe.consts.insert(*name, *value);
+                }
+                _ => main.push(sexpr),
+            }
+        }
+        qbe.begin(&[], Type::Int(IntType::S32));
+        qbe.statements(main, None)?;
+        qbe.body.push_str("\tret 0\n");
+        qbe.finish("export function w $main()");
+        for defn in defns {
+            qbe.function(defn)?;
+        }
+        Ok(format!("{}{}", qbe.data, qbe.functions))
+    }
+    /// the type a parameter or result is declared with
+    fn typ(&self, sexpr: &Located<SExpr>) -> Result<Type, Located<CompileError>> {
+        let typ = match &sexpr.value {
+            SExpr::Word(word) => word.as_str().parse::<Type>().ok(),
+            _ => None,
+        };
+        match typ {
+            Some(typ) if typ == Type::None || base_type(&typ).is_some() => Ok(typ),
+            Some(typ) => Err(unsupported(&format!("{typ} values"), sexpr.pos)),
+            None => Err(unsupported("this type", sexpr.pos)),
+        }
+    }
+    fn declare_externs(&mut self, sexprs: &[Located<SExpr>]) -> Result<(), Located<CompileError>> {
+        for sexpr in sexprs {
+            let (name, callee) = match &sexpr.value {
+                SExpr::Word(name) => (*name, None),
+                SExpr::String(name) => (Symbol::intern(name), None),
+                SExpr::Expr(sexprs) => {
+                    let Defn {
+                        name,
+                        params,
+                        variadic,
+                        ret,
+                        ..
+                    } = parse_defn(sexprs.clone(), sexpr.pos)?;
+                    if variadic {
+                        return Err(unsupported("typed variadic externs", sexpr.pos));
+                    }
+                    let params = params
+                        .iter()
+                        .map(|(_, typ)| self.typ(typ))
+                        .collect::<Result<_, _>>()?;
+                    (name, Some((params, self.typ(&ret)?)))
+                }
+                _ => return Err(unsupported("this extern", sexpr.pos)),
+            };
+            let typed = callee.is_some();
+            let (params, ret) = callee.unwrap_or_default();
+            self.callees.insert(
+                name,
+                Callee {
+                    symbol: name.to_string(),
+                    params,
+                    ret,
+                    typed,
+                },
+            );
+        }
+        Ok(())
+    }
+    fn declare_defn(&mut self, defn: &Defn, pos: Position) -> Result<(), Located<CompileError>> {
+        if !defn.type_params.is_empty() || defn.variadic {
+            return Err(unsupported("generic and variadic functions", pos));
+        }
+        let params = defn
+            .params
+            .iter()
+            .map(|(_, typ)| self.typ(typ))
+            .collect::<Result<_, _>>()?;
+        let callee = Callee {
+            symbol: mangle(defn.name.as_str()),
+            params,
+            ret: self.typ(&defn.ret)?,
+            typed: true,
+        };
+        self.callees.insert(defn.name, callee);
+        Ok(())
+    }
+    /// starts lowering a function returning `ret`, binding the parameters to `%a0`, `%a1`...
+    /// and returning them with their base types
+    fn begin(&mut self, params: &[(Symbol, Type)], ret: Type) -> Vec<String> {
+        self.body.clear();
+        self.temporaries = 0;
+        self.labels = 0;
+        self.ret = ret;
+        let mut scope = HashMap::new();
+        let mut declared = vec![];
+        for (idx, (name, typ)) in params.iter().enumerate() {
+            let operand = format!("%a{idx}");
+            let base = base_type(typ).expect("parameter types are checked when declared");
+            declared.push(format!("{base} {operand}"));
+            scope.insert(
+                *name,
+                Value {
+                    operand: Some(operand),
+                    typ: typ.clone(),
+                },
+            );
+        }
+        self.scopes = vec![scope];
+        declared
+    }
+    fn finish(&mut self, header: &str) {
+        writeln!(self.functions, "{header} {{\n@start\n{}}}", self.body).unwrap();
+    }
+    fn function(&mut self, defn: Defn) -> Result<(), Located<CompileError>> {
+        let callee = self.callees[&defn.name].clone();
+        let params: Vec<_> = defn
+            .params
+            .iter()
+            .map(|(name, _)| *name)
+            .zip(callee.params)
+            .collect();
+        let declared = self.begin(&params, callee.ret.clone());
+        let value = self.statements(defn.body, Some(&callee.ret))?;
+        match (base_type(&callee.ret), value.operand) {
+            (None, _) => self.body.push_str("\tret\n"),
+            (Some(_), Some(operand)) => writeln!(self.body, "\tret {operand}").unwrap(),
+            // the body ends in a `return`, after which nothing is reached
+            (Some(_), None) => self.body.push_str("\tret 0\n"),
+        }
+        let ret = base_type(&callee.ret).map_or(String::new(), |base| format!("{base} "));
+        let header = format!("function {ret}${}({})", callee.symbol, declared.join(", "));
+        self.finish(&header);
+        Ok(())
+    }
+    fn temporary(&mut self) -> String {
+        self.temporaries += 1;
+        format!("%t{}", self.temporaries - 1)
+    }
+    /// writes `op` with `args` into a new temporary of type `typ`
+    fn instruction(&mut self, typ: Type, op: &str, args: &[&str]) -> Value {
+        let temporary = self.temporary();
+        let base = base_type(&typ).expect("only values with a base type are computed");
+        writeln!(self.body, "\t{temporary} ={base} {op} {}", args.join(", ")).unwrap();
+        Value {
+            operand: Some(temporary),
+            typ,
+        }
+    }
+    /// a zero-terminated string in the data section
+    fn string(&mut self, string: &str) -> String {
+        let name = format!("$lerp_str{}", self.strings);
+        self.strings += 1;
+        let mut items = vec![];
+        let mut printable = String::new();
+        for byte in string_bytes(string).into_iter().chain([0]) {
+            if byte.is_ascii_graphic() && byte != b'"' && byte != b'\\' || byte == b' ' {
+                printable.push(byte as char);
+                continue;
+            }
+            if !printable.is_empty() {
+                items.push(format!("b \"{printable}\""));
+                printable.clear();
+            }
+            items.push(format!("b {byte}"));
+        }
+        writeln!(self.data, "data {name} = {{ {} }}", items.join(", ")).unwrap();
+        name
+    }
+    /// the type `sexpr` has if it can be told without lowering it, like `Compiler::type_hint`
+    fn type_hint(&self, sexpr: &Located<SExpr>) -> Option<Type> {
+        match &sexpr.value {
+            SExpr::Int(_, Some(typ)) => Some(typ.clone()),
+            SExpr::String(_) => Some(Type::Pointer(Box::new(Type::UInt(IntType::S8)))),
+            SExpr::Word(word) => self.local(*word).map(|value| value.typ.clone()),
+            SExpr::Expr(_) => {
+                let (head, args) = form(sexpr)?;
+                match head.as_str() {
+                    "+" | "-" | "wrapping-add" | "wrapping-sub" | "wrapping-mul" | "/" => {
+                        args.iter().find_map(|arg| self.type_hint(arg))
+                    }
+                    _ => self.callees.get(&head).map(|callee| callee.ret.clone()),
+                }
+            }
+            _ => None,
+        }
+    }
+    fn local(&self, name: Symbol) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name))
+    }
+    /// lowers `sexprs` one after the other, the last one where a value of type `expected` is
+    /// wanted, and returns the value of the last one
+    fn statements(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        expected: Option<&Type>,
+    ) -> Result<Value, Located<CompileError>> {
+        let mut value = Value::none(Type::None);
+        let last = sexprs.len().saturating_sub(1);
+        for (idx, sexpr) in sexprs.into_iter().enumerate() {
+            value = self.expression(sexpr, expected.filter(|_| idx == last))?;
+        }
+        Ok(value)
+    }
+    /// lowers `sexpr`, an untyped integer taking the type `expected` if it is an integer type
+    fn expression(
+        &mut self,
+        sexpr: Located<SExpr>,
+        expected: Option<&Type>,
+    ) -> Result<Value, Located<CompileError>> {
+        let pos = sexpr.pos;
+        let (value, typ) = match sexpr.value {
+            SExpr::Int(value, typ) => (value, typ),
+            SExpr::Word(word) if self.local(word).is_none() && self.consts.contains_key(&word) => {
+                (self.consts[&word], None)
+            }
+            SExpr::Word(word) => {
+                return self
+                    .local(word)
+                    .cloned()
+                    .ok_or_else(|| unsupported(&format!("`{word}`"), pos))
+            }
+            SExpr::String(string) => {
+                return Ok(Value {
+                    operand: Some(self.string(&string)),
+                    typ: Type::Pointer(Box::new(Type::UInt(IntType::S8))),
+                })
+            }
+            SExpr::Expr(mut sexprs) if !sexprs.is_empty() => {
+                let Located {
+                    value: SExpr::Word(head),
+                    ..
+                } = sexprs.remove(0)
+                else {
+                    return Err(unsupported("calls of expressions", pos));
+                };
+                return self.form(head, sexprs, pos, expected);
+            }
+            _ => return Err(unsupported("this expression", pos)),
+        };
+        let expected = expected.filter(|typ| matches!(typ, Type::Int(_) | Type::UInt(_)));
+        let typ = typ
+            .or_else(|| expected.cloned())
+            .unwrap_or(Type::Int(IntType::S32));
+        let operand = match base_type(&typ) {
+            Some("w") => (value as i32).to_string(),
+            Some(_) => (value as i64).to_string(),
+            None => return Err(unsupported(&format!("{typ} values"), pos)),
+        };
+        Ok(Value {
+            operand: Some(operand),
+            typ,
+        })
+    }
+    fn form(
+        &mut self,
+        head: Symbol,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+        expected: Option<&Type>,
+    ) -> Result<Value, Located<CompileError>> {
+        match head.as_str() {
+            "+" | "-" if self.checked => Err(unsupported("checked arithmetic", pos)),
+            "+" | "wrapping-add" => self.additive(false, sexprs, pos),
+            "-" | "wrapping-sub" => self.additive(true, sexprs, pos),
+            "wrapping-mul" => {
+                let (left, right) = self.operands(sexprs, pos)?;
+                Ok(self.binary("mul", left, right))
+            }
+            "/" => {
+                let (left, right) = self.operands(sexprs, pos)?;
+                let op = match left.typ {
+                    Type::UInt(_) => "udiv",
+                    _ => "div",
+                };
+                Ok(self.binary(op, left, right))
+            }
+            "let" => {
+                let Ok([name, value]) = <[Located<SExpr>; 2]>::try_from(sexprs) else {
+                    return Err(Located {
+                        value: CompileError::ExpectedArgs(2),
+                        pos,
+                    });
+                };
+                let SExpr::Word(name) = name.value else {
+                    return Err(Located {
+                        value: CompileError::InvalidForm("let"),
+                        pos: name.pos,
+                    });
+                };
+                let value = self.expression(value, None)?;
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(name, value);
+                }
+                Ok(Value::none(Type::None))
+            }
+            "block" => {
+                self.scopes.push(HashMap::new());
+                let value = self.statements(sexprs, expected)?;
+                self.scopes.pop();
+                Ok(value)
+            }
+            "return" => {
+                let ret = self.ret.clone();
+                match sexprs.into_iter().next() {
+                    Some(sexpr) => {
+                        let value = self.expression(sexpr, Some(&ret))?;
+                        let operand = value.operand.unwrap_or_default();
+                        writeln!(self.body, "\tret {operand}").unwrap();
+                    }
+                    None => self.body.push_str("\tret\n"),
+                }
+                // whatever follows is never reached, but needs a block of its own
+                writeln!(self.body, "@dead{}", self.labels).unwrap();
+                self.labels += 1;
+                Ok(Value::none(Type::Never))
+            }
+            "exit" => {
+                let Ok([code]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+                    return Err(Located {
+                        value: CompileError::ExpectedArgs(1),
+                        pos,
+                    });
+                };
+                let code = self.expression(code, Some(&Type::Int(IntType::S32)))?;
+                self.call("exit", &[code], Type::None, Some(1));
+                Ok(Value::none(Type::Never))
+            }
+            "print" | "println" => {
+                let mut format = vec![];
+                let mut args = vec![];
+                for sexpr in sexprs {
+                    let arg_pos = sexpr.pos;
+                    let value = self.expression(sexpr, None)?;
+                    let Some(spec) = printf_spec(&value.typ) else {
+                        return Err(Located {
+                            value: CompileError::InvalidType(value.typ),
+                            pos: arg_pos,
+                        });
+                    };
+                    format.push(spec);
+                    args.push(value);
+                }
+                let mut format = format.join(" ");
+                if head == "println" {
+                    format.push('\n');
+                }
+                let format = Value {
+                    operand: Some(self.string(&format)),
+                    typ: Type::Pointer(Box::new(Type::UInt(IntType::S8))),
+                };
+                args.insert(0, format);
+                self.call("printf", &args, Type::None, Some(1));
+                Ok(Value::none(Type::None))
+            }
+            _ => {
+                let Some(callee) = self.callees.get(&head).cloned() else {
+                    return Err(unsupported(&format!("`{head}`"), pos));
+                };
+                let mut args = vec![];
+                for (idx, sexpr) in sexprs.into_iter().enumerate() {
+                    let value = self.expression(sexpr, callee.params.get(idx))?;
+                    args.push(value);
+                }
+                let variadic = (!callee.typed).then_some(1);
+                Ok(self.call(&callee.symbol, &args, callee.ret, variadic))
+            }
+        }
+    }
+    /// calls `symbol` with `args`, the ones from the index `variadic` on passed as variadic
+    /// arguments
+    fn call(&mut self, symbol: &str, args: &[Value], ret: Type, variadic: Option<usize>) -> Value {
+        let mut passed = vec![];
+        for (idx, arg) in args.iter().enumerate() {
+            if variadic == Some(idx) {
+                passed.push("...".to_string());
+            }
+            let base = base_type(&arg.typ).expect("only values with a base type are passed");
+            passed.push(format!(
+                "{base} {}",
+                arg.operand.as_deref().unwrap_or_default()
+            ));
+        }
+        let call = format!("call ${symbol}({})", passed.join(", "));
+        match base_type(&ret) {
+            Some(base) => {
+                let temporary = self.temporary();
+                writeln!(self.body, "\t{temporary} ={base} {call}").unwrap();
+                Value {
+                    operand: Some(temporary),
+                    typ: ret,
+                }
+            }
+            None => {
+                writeln!(self.body, "\t{call}").unwrap();
+                Value::none(ret)
+            }
+        }
+    }
+    /// lowers the two operands of an arithmetic form, an untyped integer taking the type of
+    /// the other one like `Compiler::operand_hint` says
+    fn operands(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<(Value, Value), Located<CompileError>> {
+        let Ok([left, right]) = <[Located<SExpr>; 2]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(2),
+                pos,
+            });
+        };
+        let hint = match left.value {
+            SExpr::Int(_, None) => self.type_hint(&right),
+            SExpr::Word(word) if self.local(word).is_none() => self.type_hint(&right),
+            _ => None,
+        };
+        let left = self.expression(left, hint.as_ref())?;
+        let right = self.expression(right, Some(&left.typ))?;
+        Ok((left, right))
+    }
+    /// `op` of two values of the same type
+    fn binary(&mut self, op: &str, left: Value, right: Value) -> Value {
+        let operands = [left.operand, right.operand].map(Option::unwrap_or_default);
+        self.instruction(left.typ, op, &[&operands[0], &operands[1]])
+    }
+    /// `(+ a b)` and `(- a b)` of integers, or of a pointer and an integer counting elements of
+    /// the pointee
+    fn additive(
+        &mut self,
+        subtract: bool,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Value, Located<CompileError>> {
+        let op = if subtract { "sub" } else { "add" };
+        let (left, right) = self.operands(sexprs, pos)?;
+        let Type::Pointer(pointee) = &left.typ else {
+            return Ok(self.binary(op, left, right));
+        };
+        let Some(size) = pointee.size().filter(|size| *size > 0) else {
+            return Err(Located {
+                value: CompileError::UnknownSize(*pointee.clone()),
+                pos,
+            });
+        };
+        let right = match right.typ {
+            Type::Pointer(_) => return Err(unsupported("differences of pointers", pos)),
+            Type::Int(IntType::S32) => {
+                let operand = right.operand.unwrap_or_default();
+                self.instruction(Type::Int(IntType::Size), "extsw", &[&operand])
+            }
+            Type::UInt(IntType::S32) => {
+                let operand = right.operand.unwrap_or_default();
+                self.instruction(Type::UInt(IntType::Size), "extuw", &[&operand])
+            }
+            _ => right,
+        };
+        let offset = match size {
+            1 => right,
+            _ => self.binary(
+                "mul",
+                right,
+                Value {
+                    operand: Some(size.to_string()),
+                    typ: left.typ.clone(),
+                },
+            ),
+        };
+        Ok(self.binary(op, left, offset))
+    }
+}
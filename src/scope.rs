@@ -0,0 +1,536 @@
+use crate::{
+    code::{
+        Address, DataType, Destination, Instruction, Memory, Register, RegisterName, RegisterSize,
+        Source,
+    },
+    compiler::{CompileError, CompileWarning, Compiler, Local, Loop, Scope},
+    intern::Symbol,
+    parser::{Located, Position, SExpr},
+    typ::Type,
+    unroll::Unrolled,
+};
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const EDX: Register = Register {
+    name: RegisterName::D,
+    size: RegisterSize::S32,
+};
+const EBP: Register = Register {
+    name: RegisterName::BP,
+    size: RegisterSize::S32,
+};
+const ESP: Register = Register {
+    name: RegisterName::SP,
+    size: RegisterSize::S32,
+};
+
+impl Compiler {
+    /// compiles `body` in a new scope and returns the type of its last expression, releasing
+    /// the stack its locals took when it ends
+    ///
+    /// this is `(block body...)`, whose locals are only visible in it
+    pub fn compile_scoped(
+        &mut self,
+        body: Vec<Located<SExpr>>,
+    ) -> Result<Type, Located<CompileError>> {
+        self.push_scope();
+        let typ = self.compile_body(body, None)?;
+        self.pop_scope(&typ)?;
+        Ok(typ)
+    }
+    /// compiles `body` in the current scope and returns the type of its last expression, or
+    /// never if one of them never returns, warning about the first expression after that
+    ///
+    /// the last expression is compiled `expecting` a type like an argument
+    pub fn compile_body(
+        &mut self,
+        body: Vec<Located<SExpr>>,
+        expecting: Option<&Type>,
+    ) -> Result<Type, Located<CompileError>> {
+        let mut typ = Type::None;
+        let mut reached = true;
+        let last = body.len().saturating_sub(1);
+        for (idx, sexpr) in body.into_iter().enumerate() {
+            if reached && typ == Type::Never {
+                reached = false;
+                self.warnings.push(Located {
+                    value: CompileWarning::Unreachable,
+                    pos: sexpr.pos,
+                });
+            }
+            typ = match idx == last {
+                true => self.compile_expecting(sexpr, expecting)?,
+                false => self.compile(sexpr)?,
+            };
+        }
+        Ok(if reached { typ } else { Type::Never })
+    }
+    fn push_scope(&mut self) {
+        let depth = self.frame().depth;
+        self.frame_mut().scopes.push(Scope {
+            depth,
+            ..Default::default()
+        });
+    }
+    /// ends the innermost scope after an expression of type `typ`, leaving it unless that
+    /// never returns
+    fn pop_scope(&mut self, typ: &Type) -> Result<(), Located<CompileError>> {
+        if *typ != Type::Never {
+            self.leave_scopes(self.frame().scopes.len() - 1)?;
+        }
+        let scope = self
+            .frame_mut()
+            .scopes
+            .pop()
+            .expect("every scope ended was pushed");
+        let locals = self.frame().depth - scope.depth;
+        if locals > 0 {
+            self.write(Instruction::Add {
+                dest: ESP.into(),
+                src: Source::Amount(locals as usize),
+            });
+        }
+        Ok(())
+    }
+    /// `(let name value)`: declares the local `name` holding `value` in the enclosing scope
+    pub fn compile_let(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([name, value]) = <[Located<SExpr>; 2]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(2),
+                pos,
+            });
+        };
+        let name_pos = name.pos;
+        let SExpr::Word(name) = name.value else {
+            return Err(Located {
+                value: CompileError::InvalidForm("let"),
+                pos: name_pos,
+            });
+        };
+        let value_pos = value.pos;
+        let typ = self.compile(value)?;
+        let Some(scope) = self.frame().scopes.last() else {
+            return Err(Located {
+                value: CompileError::Internal("no scope to declare in"),
+                pos,
+            });
+        };
+        // locals are pushed right below the ones before them, the last one being the deepest
+        let top = scope
+            .locals
+            .values()
+            .map(|local| -local.offset)
+            .max()
+            .unwrap_or(scope.depth)
+            .max(scope.depth);
+        if self.frame().depth != top {
+            return Err(Located {
+                value: CompileError::Unsupported("lets inside of other expressions"),
+                pos,
+            });
+        }
+        // a local takes a whole push like an argument, a 64-bit one two
+        let Some(size) = self.widen(&typ) else {
+            return Err(Located {
+                value: CompileError::InvalidType(typ),
+                pos: value_pos,
+            });
+        };
+        self.push_value(size);
+        let offset = -self.frame().depth;
+        let scope = self.frame().scopes.len() - 1;
+        self.declare_local(
+            scope,
+            name,
+            Local {
+                typ,
+                offset,
+                pos: name_pos,
+                used: false,
+            },
+        );
+        Ok(Type::None)
+    }
+    /// `(loop ((name value)...) body...)`: binds the names like `let` and returns the value of
+    /// the body, which `recur` starts over with other values
+    pub fn compile_loop(
+        &mut self,
+        mut sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.is_empty() {
+            return Err(Located {
+                value: CompileError::InvalidForm("loop"),
+                pos,
+            });
+        }
+        let bindings = sexprs.remove(0);
+        let SExpr::Expr(bindings) = bindings.value else {
+            return Err(Located {
+                value: CompileError::InvalidForm("loop"),
+                pos: bindings.pos,
+            });
+        };
+        self.push_scope();
+        let mut vars = vec![];
+        let mut values = vec![];
+        for binding in bindings {
+            let binding_pos = binding.pos;
+            let name = match &binding.value {
+                SExpr::Expr(binding) => match binding.first() {
+                    Some(Located {
+                        value: SExpr::Word(name),
+                        ..
+                    }) => Some(*name),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let (Some(name), SExpr::Expr(binding)) = (name, binding.value) else {
+                return Err(Located {
+                    value: CompileError::InvalidForm("loop"),
+                    pos: binding_pos,
+                });
+            };
+            values.extend(binding.get(1).cloned());
+            self.compile_let(binding, binding_pos)?;
+            let local = self.local(name).expect("the variable was just bound");
+            // `recur` stores every variable as a single push
+            if RegisterSize::typ(&local.typ) == Some(RegisterSize::S64) {
+                return Err(Located {
+                    value: CompileError::Unsupported("64-bit loop variables"),
+                    pos: binding_pos,
+                });
+            }
+            vars.push((name, local.offset, local.typ.clone()));
+        }
+
+        let names: Vec<Symbol> = vars.iter().map(|(name, _, _)| *name).collect();
+        let (body, unrolled) = match self.unroll(&names, &values, &sexprs) {
+            Some(Unrolled { full, mut bodies }) => {
+                let first = bodies.remove(0);
+                bodies.reverse();
+                (first, Some((full, bodies)))
+            }
+            None => (sexprs, None),
+        };
+        let idx = self.new_labels();
+        let head = Symbol::intern(&format!("loop{idx}"));
+        let end = Symbol::intern(&format!("loop{idx}_end"));
+        // a fully unrolled loop never jumps back
+        if !matches!(unrolled, Some((true, _))) {
+            self.write(Instruction::Label(head));
+        }
+        let target = Loop {
+            head,
+            depth: self.frame().depth,
+            scope: self.frame().scopes.len(),
+            vars,
+            unrolled: unrolled.map(|(_, bodies)| bodies).unwrap_or_default(),
+            end,
+            breaks: vec![],
+        };
+        self.frame_mut().loops.push(target);
+        let mut typ = self.compile_scoped(body)?;
+        let breaks = self
+            .frame_mut()
+            .loops
+            .pop()
+            .map(|target| target.breaks)
+            .unwrap_or_default();
+        // the value of the loop is the one it ends with or any `break` leaves it with
+        for Located { value, pos } in &breaks {
+            if *value == Type::Never || *value == typ {
+                continue;
+            }
+            if typ != Type::Never {
+                return Err(Located {
+                    value: CompileError::InvalidTypeExpected {
+                        expected: typ,
+                        got: value.clone(),
+                    },
+                    pos: *pos,
+                });
+            }
+            typ = value.clone();
+        }
+        if !breaks.is_empty() {
+            self.write(Instruction::Label(end));
+        }
+        // every value bound to a variable the loop never reads is stored for nothing
+        let scope = self
+            .frame()
+            .scopes
+            .last()
+            .expect("the scope of the loop is left last");
+        let mut unread: Vec<_> = scope
+            .locals
+            .iter()
+            .filter(|(_, local)| !local.used)
+            .map(|(name, local)| (local.pos, name.to_string()))
+            .collect();
+        unread.sort_by_key(|(pos, _)| (pos.ln, pos.col));
+        for (pos, name) in unread {
+            self.warnings.push(Located {
+                value: CompileWarning::UnreadLoopVariable(name),
+                pos,
+            });
+        }
+        self.pop_scope(&typ)?;
+        Ok(typ)
+    }
+    /// `(recur value...)`: starts the innermost `loop` over with its variables bound to the
+    /// values, leaving the scopes of its body
+    ///
+    /// the stack is released down to the head of the loop, so it doesn't grow by iterating,
+    /// unless the loop is unrolled and the next iteration is compiled right here instead
+    pub fn compile_recur(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Some(target) = self.frame().loops.last().cloned() else {
+            return Err(Located {
+                value: CompileError::RecurOutsideLoop,
+                pos,
+            });
+        };
+        if sexprs.len() != target.vars.len() {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(target.vars.len()),
+                pos,
+            });
+        }
+        let depth = self.frame().depth;
+        let next = self
+            .frame_mut()
+            .loops
+            .last_mut()
+            .and_then(|target| target.unrolled.pop());
+        if next.is_some() {
+            self.push_scope();
+        }
+        let mut offsets = vec![];
+        // every value is computed before any variable changes
+        for (sexpr, (_, _, expected)) in sexprs.into_iter().zip(&target.vars) {
+            let value_pos = sexpr.pos;
+            let typ = self.compile_expecting(sexpr, Some(expected))?;
+            if typ != *expected {
+                return Err(Located {
+                    value: CompileError::InvalidTypeExpected {
+                        expected: expected.clone(),
+                        got: typ,
+                    },
+                    pos: value_pos,
+                });
+            }
+            self.widen(&typ);
+            self.write(Instruction::Push {
+                src: Source::Register(EAX),
+            });
+            offsets.push(-self.frame().depth);
+        }
+        if let Some(body) = next {
+            return self.compile_iteration(&target, offsets, body, pos);
+        }
+        self.leave_scopes(target.scope)?;
+        for (_, offset, _) in target.vars.iter().rev() {
+            self.write(Instruction::Pop { dest: EAX.into() });
+            self.write(Instruction::Mov {
+                dest: Destination::Memory(Memory {
+                    data_type: DataType::DoubleWord,
+                    address: Address::offset(EBP, *offset),
+                }),
+                src: Source::Register(EAX),
+            });
+        }
+        let locals = self.frame().depth - target.depth;
+        if locals > 0 {
+            self.write(Instruction::Add {
+                dest: ESP.into(),
+                src: Source::Amount(locals as usize),
+            });
+        }
+        self.write(Instruction::Jmp { label: target.head });
+        // what follows is never reached, but compiled with the stack it would have
+        self.frame_mut().depth = depth;
+        Ok(Type::Never)
+    }
+    /// `(break value)`: leaves the innermost `loop` with `value` as its value, or with none for
+    /// `(break)`, leaving the scopes of its body
+    pub fn compile_break(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.len() > 1 {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        }
+        let Some(target) = self.frame().loops.last().cloned() else {
+            return Err(Located {
+                value: CompileError::BreakOutsideLoop,
+                pos,
+            });
+        };
+        let depth = self.frame().depth;
+        let mut typ = Type::None;
+        for sexpr in sexprs {
+            typ = self.compile(sexpr)?;
+        }
+        if typ != Type::Never {
+            self.leave_scopes(target.scope)?;
+            let locals = self.frame().depth - target.depth;
+            if locals > 0 {
+                self.write(Instruction::Add {
+                    dest: ESP.into(),
+                    src: Source::Amount(locals as usize),
+                });
+            }
+            self.write(Instruction::Jmp { label: target.end });
+        }
+        self.frame_mut().depth = depth;
+        if let Some(target) = self.frame_mut().loops.last_mut() {
+            target.breaks.push(Located { value: typ, pos });
+        }
+        Ok(Type::Never)
+    }
+    /// `(return value)`: leaves the function with `value`, or with none for `(return)`,
+    /// running everything deferred in it first
+    pub fn compile_return(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        if sexprs.len() > 1 {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        }
+        let expected = self.frame().function.return_type.clone();
+        let mut typ = Type::None;
+        let mut value_pos = pos;
+        for sexpr in sexprs {
+            value_pos = sexpr.pos;
+            typ = self.compile_expecting(sexpr, Some(&expected))?;
+        }
+        if typ == Type::Never {
+            return Ok(typ);
+        }
+        // like the last expression of a body, the value of a function returning none is dropped
+        if expected != Type::None && typ != expected {
+            return Err(Located {
+                value: CompileError::InvalidTypeExpected { expected, got: typ },
+                pos: value_pos,
+            });
+        }
+        self.leave_scopes(0)?;
+        self.write(Instruction::Leave);
+        self.write(Instruction::Ret);
+        Ok(Type::Never)
+    }
+    /// compiles the `body` of the next iteration of the unrolled loop `target`, its variables
+    /// being the values pushed to `offsets` in the innermost scope
+    fn compile_iteration(
+        &mut self,
+        target: &Loop,
+        offsets: Vec<i32>,
+        body: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let scope = self.frame().scopes.len() - 1;
+        for ((name, _, typ), offset) in target.vars.iter().zip(offsets) {
+            self.declare_local(
+                scope,
+                *name,
+                Local {
+                    typ: typ.clone(),
+                    offset,
+                    pos,
+                    used: false,
+                },
+            );
+        }
+        let typ = self.compile_scoped(body)?;
+        // a variable read in any iteration is read by the loop
+        let read: Vec<Symbol> = self
+            .frame()
+            .scopes
+            .last()
+            .expect("the scope of the iteration is left last")
+            .locals
+            .iter()
+            .filter(|(_, local)| local.used)
+            .map(|(name, _)| *name)
+            .collect();
+        for name in read {
+            if let Some(local) = self.frame_mut().scopes[target.scope - 1]
+                .locals
+                .get_mut(&name)
+            {
+                local.used = true;
+            }
+        }
+        self.pop_scope(&typ)?;
+        Ok(typ)
+    }
+    /// `(defer expr)`: runs `expr` whenever the enclosing scope is left, after everything
+    /// deferred later in it
+    pub fn compile_defer(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([sexpr]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        let Some(scope) = self.frame_mut().scopes.last_mut() else {
+            return Err(Located {
+                value: CompileError::Internal("no scope to defer in"),
+                pos,
+            });
+        };
+        scope.deferred.push(sexpr);
+        Ok(Type::None)
+    }
+    /// compiles what is deferred in the scopes from the innermost one out to the one at
+    /// `depth`, for an exit leaving them, keeping the value in the A register
+    ///
+    /// every exit gets its own copy of the deferred code
+    pub fn leave_scopes(&mut self, depth: usize) -> Result<(), Located<CompileError>> {
+        let deferred: Vec<Located<SExpr>> = self.frame().scopes[depth..]
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.deferred.iter().rev().cloned())
+            .collect();
+        if deferred.is_empty() {
+            return Ok(());
+        }
+        // the value may be a 64-bit one in the D and A registers
+        self.write(Instruction::Push {
+            src: Source::Register(EDX),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        for sexpr in deferred {
+            self.compile(sexpr)?;
+        }
+        self.write(Instruction::Pop { dest: EAX.into() });
+        self.write(Instruction::Pop { dest: EDX.into() });
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{
+    code::{Destination, Function, Instruction, Program, Register, RegisterName},
+    compiler::stack_effect,
+    intern::Symbol,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+};
+
+/// what the code of a single function costs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub name: String,
+    /// the instructions in its body, labels not counted
+    pub instructions: usize,
+    /// the most bytes it pushes below its return address, its saved frame pointer included
+    pub frame: usize,
+    pub calls: usize,
+    /// the values it saves on the stack and pops back into a register later
+    pub spills: usize,
+}
+impl FunctionStats {
+    pub fn of(function: &Function) -> Self {
+        let mut stats = Self {
+            name: function.name.clone(),
+            instructions: 0,
+            frame: 0,
+            calls: 0,
+            spills: 0,
+        };
+        let depths = depths(function);
+        for (instr, depth) in function.body.iter().zip(depths) {
+            if matches!(instr, Instruction::Label(_)) {
+                continue;
+            }
+            stats.instructions += 1;
+            let depth = depth + stack_effect(instr);
+            stats.frame = stats.frame.max(depth.max(0) as usize);
+            match instr {
+                Instruction::Call { .. } | Instruction::CallIndirect(_) => stats.calls += 1,
+                Instruction::Pop {
+                    dest: Destination::Register(Register { name, .. }),
+                } if *name != RegisterName::BP => stats.spills += 1,
+                _ => {}
+            }
+        }
+        stats
+    }
+}
+
+/// the bytes `function` has pushed below its return address before every instruction
+///
+/// the stack is as deep at a label as at the jumps to it, which the code before it falling
+/// through agrees with
+fn depths(function: &Function) -> Vec<i32> {
+    let mut depths = Vec::with_capacity(function.body.len());
+    let mut labels: HashMap<Symbol, i32> = HashMap::new();
+    let mut depth = 0;
+    let mut frame = 0;
+    for instr in &function.body {
+        if let Instruction::Label(label) = instr {
+            depth = *labels.entry(*label).or_insert(depth);
+        }
+        depths.push(depth);
+        depth += stack_effect(instr);
+        match instr {
+            Instruction::Mov {
+                dest:
+                    Destination::Register(Register {
+                        name: RegisterName::BP,
+                        ..
+                    }),
+                ..
+            } => frame = depth,
+            // the frame pointer was pushed right before it was set up
+            Instruction::Leave => depth = frame - 4,
+            Instruction::Jmp { label } | Instruction::JOp { label, .. } => {
+                labels.entry(*label).or_insert(depth);
+            }
+            Instruction::JmpIndirect(_) => {
+                for label in function.tables.iter().flatten() {
+                    labels.entry(*label).or_insert(depth);
+                }
+            }
+            _ => {}
+        }
+    }
+    depths
+}
+
+/// the most stack an entry point of a program takes, the calls it makes included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackUsage {
+    /// `main` or an exported function
+    pub entry: String,
+    /// the most bytes pushed below its return address, with a return address for every call
+    pub bytes: usize,
+    /// the functions it reaches which call themselves, directly or through others, whose
+    /// recursion `bytes` counts a single level of
+    pub recursive: Vec<String>,
+}
+impl Display for StackUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} takes at most {} bytes of stack",
+            self.entry, self.bytes
+        )?;
+        if !self.recursive.is_empty() {
+            write!(
+                f,
+                ", recursion through {} not counted",
+                self.recursive.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// the calls a function makes
+#[derive(Clone)]
+struct Calls<'a> {
+    frame: usize,
+    /// the callee of every call, none if it is indirect, and the depth of the stack at it
+    sites: Vec<(Option<&'a str>, usize)>,
+}
+
+/// the functions of a program with the calls they make, which stack usage is computed over
+struct CallGraph<'a> {
+    calls: HashMap<&'a str, Calls<'a>>,
+    usage: HashMap<&'a str, usize>,
+    recursive: BTreeSet<&'a str>,
+    /// the functions whose usage is being computed, each one calling the next
+    path: Vec<&'a str>,
+}
+impl<'a> CallGraph<'a> {
+    fn new(program: &'a Program) -> Self {
+        let calls = program
+            .functions
+            .iter()
+            .map(|function| {
+                let sites = function
+                    .body
+                    .iter()
+                    .zip(depths(function))
+                    .filter_map(|(instr, depth)| {
+                        let depth = depth.max(0) as usize;
+                        match instr {
+                            Instruction::Call { func } => Some((Some(func.as_str()), depth)),
+                            Instruction::CallIndirect(_) => Some((None, depth)),
+                            _ => None,
+                        }
+                    })
+                    .collect();
+                let frame = FunctionStats::of(function).frame;
+                (function.name.as_str(), Calls { frame, sites })
+            })
+            .collect();
+        Self {
+            calls,
+            usage: HashMap::new(),
+            recursive: BTreeSet::new(),
+            path: vec![],
+        }
+    }
+    /// the most bytes a call to `name` pushes below its return address, none for externs and
+    /// for calls back into a function on the path, which end a recursion
+    fn usage(&mut self, name: &'a str) -> usize {
+        if let Some(usage) = self.usage.get(name) {
+            return *usage;
+        }
+        let Some(Calls { frame, sites }) = self.calls.get(name).cloned() else {
+            return 0;
+        };
+        if let Some(idx) = self.path.iter().position(|caller| *caller == name) {
+            self.recursive.extend(&self.path[idx..]);
+            return 0;
+        }
+        self.path.push(name);
+        let mut usage = frame;
+        for (callee, depth) in sites {
+            let callee = callee.map_or(0, |callee| self.usage(callee));
+            usage = usage.max(depth + RETURN_ADDRESS + callee);
+        }
+        self.path.pop();
+        self.usage.insert(name, usage);
+        usage
+    }
+    /// the functions `entry` calls, directly or through others, itself included
+    fn reachable(&self, entry: &'a str) -> BTreeSet<&'a str> {
+        let mut reached = BTreeSet::from([entry]);
+        let mut queue = vec![entry];
+        while let Some(name) = queue.pop() {
+            let Some(calls) = self.calls.get(name) else {
+                continue;
+            };
+            for callee in calls.sites.iter().filter_map(|(callee, _)| *callee) {
+                if reached.insert(callee) {
+                    queue.push(callee);
+                }
+            }
+        }
+        reached
+    }
+}
+
+/// the bytes a call pushes for its return address
+const RETURN_ADDRESS: usize = 4;
+
+/// the worst case stack usage of `main` and every exported function of `program`
+///
+/// calls through a pointer count only their return address, and calls into the C library
+/// nothing at all
+pub fn stack_usage(program: &Program) -> Vec<StackUsage> {
+    let mut graph = CallGraph::new(program);
+    let entries: Vec<&str> = std::iter::once("main")
+        .chain(program.exports.iter().map(String::as_str))
+        .filter(|name| graph.calls.contains_key(name))
+        .collect();
+    let usages: Vec<usize> = entries.iter().map(|entry| graph.usage(entry)).collect();
+    entries
+        .into_iter()
+        .zip(usages)
+        .map(|(entry, bytes)| StackUsage {
+            entry: entry.to_string(),
+            bytes,
+            recursive: graph
+                .reachable(entry)
+                .intersection(&graph.recursive)
+                .map(|name| name.to_string())
+                .collect(),
+        })
+        .collect()
+}
+
+/// the functions of `program` which call themselves, directly or through others
+pub fn recursive(program: &Program) -> BTreeSet<String> {
+    let mut graph = CallGraph::new(program);
+    for function in &program.functions {
+        graph.usage(&function.name);
+    }
+    graph
+        .recursive
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// the cost of every function of a program, printed by `--stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub functions: Vec<FunctionStats>,
+}
+impl Stats {
+    pub fn of(program: &Program) -> Self {
+        Self {
+            functions: program.functions.iter().map(FunctionStats::of).collect(),
+        }
+    }
+}
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .functions
+            .iter()
+            .map(|function| function.name.len())
+            .chain(["function".len()])
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            f,
+            "{:<width$} {:>8} {:>6} {:>6} {:>6}",
+            "function", "instrs", "frame", "calls", "spills"
+        )?;
+        for function in &self.functions {
+            writeln!(
+                f,
+                "{:<width$} {:>8} {:>6} {:>6} {:>6}",
+                function.name,
+                function.instructions,
+                function.frame,
+                function.calls,
+                function.spills
+            )?;
+        }
+        // the deepest frame is what a single function needs at most
+        write!(
+            f,
+            "{:<width$} {:>8} {:>6} {:>6} {:>6}",
+            "total",
+            self.functions
+                .iter()
+                .map(|function| function.instructions)
+                .sum::<usize>(),
+            self.functions
+                .iter()
+                .map(|function| function.frame)
+                .max()
+                .unwrap_or_default(),
+            self.functions
+                .iter()
+                .map(|function| function.calls)
+                .sum::<usize>(),
+            self.functions
+                .iter()
+                .map(|function| function.spills)
+                .sum::<usize>()
+        )
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{
+    build::assemble_and_link,
+    bytecode::Bytecode,
+    code::{Function, Instruction, Program},
+    compiler::Compiler,
+    intern::Symbol,
+    parser::parse_recovering,
+    vm::Vm,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// compiles `code` with the default options into a program or the errors, one per line
+pub fn compile_program(code: &str) -> Result<Program, String> {
+    let (program, errors) = parse_recovering(code);
+    if !errors.is_empty() {
+        let errors: Vec<String> = errors.iter().map(|err| format!("error {err}")).collect();
+        return Err(errors.join("\n"));
+    }
+    let mut compiler = Compiler::default();
+    compiler
+        .compile_program(program)
+        .map_err(|err| format!("error {err}"))?;
+    Ok(compiler.program)
+}
+/// compiles `code` with the default options, returning the assembly with trailing whitespace
+/// removed or the errors, one per line
+pub fn compile(code: &str) -> Result<String, String> {
+    compile_program(code).map(|program| normalize(&program.to_string()))
+}
+/// the snapshot of `code`, being its assembly or its errors
+pub fn snapshot(code: &str) -> String {
+    compile(code).unwrap_or_else(|errors| errors + "\n")
+}
+fn normalize(text: &str) -> String {
+    let mut normalized = String::new();
+    for line in text.lines() {
+        normalized.push_str(line.trim_end());
+        normalized.push('\n');
+    }
+    normalized
+}
+
+/// a golden file which doesn't match the current output
+#[derive(Debug, Clone, PartialEq)]
+pub struct GoldenMismatch {
+    pub case: PathBuf,
+    /// `None` if the golden file doesn't exist yet
+    pub expected: Option<String>,
+    pub actual: String,
+}
+impl GoldenMismatch {
+    /// a line diff from the expected to the actual output
+    pub fn diff(&self) -> String {
+        diff(self.expected.as_deref().unwrap_or_default(), &self.actual)
+    }
+}
+
+/// compares the snapshot of every `*.lp` file in `dir` against the `*.asm` file next to it,
+/// overwriting the golden files instead if `bless` is set
+pub fn run_golden(dir: &Path, bless: bool) -> io::Result<Vec<GoldenMismatch>> {
+    let mut cases = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "lp") {
+            cases.push(path);
+        }
+    }
+    cases.sort();
+    let mut mismatches = vec![];
+    for case in cases {
+        let actual = snapshot(&fs::read_to_string(&case)?);
+        let golden = case.with_extension("asm");
+        let expected = fs::read_to_string(&golden)
+            .ok()
+            .map(|text| normalize(&text));
+        if expected.as_ref() == Some(&actual) {
+            continue;
+        }
+        if bless {
+            fs::write(&golden, &actual)?;
+            continue;
+        }
+        mismatches.push(GoldenMismatch {
+            case,
+            expected,
+            actual,
+        });
+    }
+    Ok(mismatches)
+}
+
+/// a line diff of `old` and `new`, prefixing removed lines with `-` and added lines with `+`
+pub fn diff(old: &str, new: &str) -> String {
+    let (old, new): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    // lengths of the longest common subsequences of every pair of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff += &format!(" {}\n", old[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            diff += &format!("+{}\n", new[j]);
+            j += 1;
+        } else {
+            diff += &format!("-{}\n", old[i]);
+            i += 1;
+        }
+    }
+    diff
+}
+
+/// `function` with its labels renamed to `L0`, `L1`, ... in the order they are defined, so
+/// code which only numbers its loops and cases differently compares equal
+pub fn normalize_labels(function: &Function) -> Function {
+    let mut names = HashMap::new();
+    for instr in &function.body {
+        if let Instruction::Label(label) = instr {
+            let name = Symbol::intern(&format!("L{}", names.len()));
+            names.entry(*label).or_insert(name);
+        }
+    }
+    let rename = |label: &mut Symbol| {
+        if let Some(name) = names.get(label) {
+            *label = *name;
+        }
+    };
+    let mut function = function.clone();
+    for instr in &mut function.body {
+        match instr {
+            Instruction::Label(label)
+            | Instruction::Jmp { label }
+            | Instruction::JOp { label, .. } => rename(label),
+            _ => {}
+        }
+    }
+    function.tables.iter_mut().flatten().for_each(rename);
+    function
+}
+/// a line diff of the code of every function which differs between `old` and `new`, each
+/// headed by its name, with labels normalized by `normalize_labels`
+pub fn asm_diff(old: &Program, new: &Program) -> String {
+    let text = |program: &Program, name: &str| {
+        program
+            .functions
+            .iter()
+            .find(|function| function.name == name)
+            .map(|function| normalize(&normalize_labels(function).to_string()))
+    };
+    let mut names: Vec<&str> = old
+        .functions
+        .iter()
+        .map(|function| function.name.as_str())
+        .collect();
+    for function in &new.functions {
+        if !names.contains(&function.name.as_str()) {
+            names.push(&function.name);
+        }
+    }
+    let mut diffs = String::new();
+    for name in names {
+        let (before, after) = (text(old, name), text(new, name));
+        if before == after {
+            continue;
+        }
+        let header = match (&before, &after) {
+            (None, _) => format!("--- {name} (added)"),
+            (_, None) => format!("--- {name} (removed)"),
+            _ => format!("--- {name}"),
+        };
+        diffs += &header;
+        diffs.push('\n');
+        diffs += &diff(
+            before.as_deref().unwrap_or_default(),
+            after.as_deref().unwrap_or_default(),
+        );
+    }
+    diffs
+}
+
+/// what running a program printed and returned
+#[derive(Debug, Clone, PartialEq)]
+pub struct Execution {
+    pub stdout: Vec<u8>,
+    /// the exit status, truncated to a byte like the operating system does
+    pub status: u8,
+}
+/// the outcome of running a program on both backends
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffOutcome {
+    Same(Execution),
+    Different {
+        vm: Execution,
+        native: Execution,
+    },
+    /// compiling or running on one of the backends failed
+    Failed(String),
+}
+
+/// runs `program` in the bytecode VM
+pub fn run_vm(program: &Program) -> Result<Execution, String> {
+    let bytecode = Bytecode::lower(program).map_err(|err| format!("bytecode: {err}"))?;
+    let mut stdout = vec![];
+    let code = Vm::new(&bytecode, &mut stdout)
+        .run()
+        .map_err(|err| format!("vm: {err}"))?;
+    Ok(Execution {
+        stdout,
+        status: code as u8,
+    })
+}
+/// assembles `program` with nasm, links it with `gcc -m32` in `dir` and runs it
+pub fn run_native(program: &Program, dir: &Path) -> Result<Execution, String> {
+    fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    let (asm, binary) = (dir.join("a.asm"), dir.join("a.out"));
+    fs::write(&asm, program.to_string()).map_err(|err| err.to_string())?;
+    assemble_and_link(&asm, &binary, &program.libraries)?;
+    let output = Command::new(&binary)
+        .output()
+        .map_err(|err| format!("{}: {err}", binary.display()))?;
+    let Some(code) = output.status.code() else {
+        return Err(format!("native: {}", output.status));
+    };
+    Ok(Execution {
+        stdout: output.stdout,
+        status: code as u8,
+    })
+}
+/// compiles `code` and compares running it in the VM against running it natively, using `dir`
+/// for the build files
+pub fn difftest(code: &str, dir: &Path) -> DiffOutcome {
+    let (program, errors) = parse_recovering(code);
+    if let Some(err) = errors.first() {
+        return DiffOutcome::Failed(format!("parse error {err}"));
+    }
+    let mut compiler = Compiler::default();
+    if let Err(err) = compiler.compile_program(program) {
+        return DiffOutcome::Failed(format!("compilation error {err}"));
+    }
+    let results = run_vm(&compiler.program).and_then(|vm| {
+        let native = run_native(&compiler.program, dir)?;
+        Ok((vm, native))
+    });
+    match results {
+        Ok((vm, native)) if vm == native => DiffOutcome::Same(vm),
+        Ok((vm, native)) => DiffOutcome::Different { vm, native },
+        Err(err) => DiffOutcome::Failed(err),
+    }
+}
@@ -0,0 +1,3136 @@
+use crate::testing::{compile_program, run_golden};
+use std::{env, fs, path::Path, process::Command};
+
+#[test]
+fn verify() {
+    use crate::{
+        builder::{eax, ebp, esp, imm, ProgramBuilder},
+        code::ComparisonOperator,
+        compiler::Compiler,
+        parser::parse,
+        verify::VerifyErrorKind,
+    };
+    let kinds = |builder: ProgramBuilder| {
+        let program = builder.build().unwrap();
+        program
+            .verify()
+            .into_iter()
+            .map(|err| (err.index, err.kind))
+            .collect::<Vec<_>>()
+    };
+    let main = || {
+        ProgramBuilder::function("main")
+            .push(ebp())
+            .mov(ebp(), esp())
+    };
+    assert_eq!(kinds(main().leave().ret()), vec![]);
+    assert_eq!(
+        kinds(main().jmp("main.missing").leave().ret()),
+        vec![(2, VerifyErrorKind::UnknownLabel("main.missing".into()))]
+    );
+    assert_eq!(
+        kinds(
+            main()
+                .label("main.again")
+                .j(ComparisonOperator::Equal, "main.again")
+                .label("main.again")
+                .leave()
+                .ret()
+        ),
+        vec![(4, VerifyErrorKind::DuplicateLabel("main.again".into()))]
+    );
+    // a push without a pop, and a pop below the return address
+    assert_eq!(
+        kinds(main().pop(ebp()).push(eax()).push(imm(1)).pop(eax()).ret()),
+        vec![(6, VerifyErrorKind::StackImbalance(4))]
+    );
+    assert_eq!(
+        kinds(main().pop(ebp()).pop(eax()).ret()),
+        vec![(4, VerifyErrorKind::StackImbalance(-4))]
+    );
+    assert_eq!(
+        kinds(main().call("missing").leave().ret()),
+        vec![(2, VerifyErrorKind::UnknownFunction("missing".into()))]
+    );
+    // the golden cases which compile hold up
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    for entry in fs::read_dir(dir).unwrap() {
+        let case = entry.unwrap().path();
+        if case.extension().is_some_and(|ext| ext == "lp") {
+            let code = fs::read_to_string(&case).unwrap();
+            let mut compiler = Compiler::default();
+            if parse(&code).is_ok_and(|ast| compiler.compile_program(ast).is_ok()) {
+                assert_eq!(compiler.program.verify(), vec![], "{}", case.display());
+            }
+        }
+    }
+}
+
+mod vm {
+    use crate::{
+        bytecode::Bytecode,
+        code::{FlatBinary, Instruction, Program},
+        compiler::{CompileError, CompileWarning, Compiler, PANIC_EXIT_CODE},
+        options::{CompileOptions, LintLevel, OptLevel},
+        parser::parse,
+        profile::{Profile, ProfileError},
+        typ::{IntType, Type},
+        vm::Vm,
+    };
+    use std::{env, fs};
+
+    fn run(code: &str, bump_allocator: bool) -> String {
+        run_with(
+            code,
+            Compiler {
+                bump_allocator,
+                ..Default::default()
+            },
+        )
+    }
+    fn run_with(code: &str, mut compiler: Compiler) -> String {
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        let bytecode = Bytecode::lower(&compiler.program).unwrap();
+        let bytecode = Bytecode::from_bytes(&bytecode.to_bytes()).unwrap();
+        let mut output = vec![];
+        Vm::new(&bytecode, &mut output).run().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn run_programs() {
+        let code = r#"
+            (extern printf)
+            (defn id ((T)) ((x T)) T x)
+            (defn add ((a i32) (b i32)) i32 (+ a b))
+            (global counter i32 41)
+            (printf "%d %s|%-3s|%03d\n" (id (add 1 2)) "a" "b" 7)
+            (println (+ counter 1) (str-len "abc") (format "x={}" 5))
+        "#;
+        assert_eq!(run(code, false), "3 a|b  |007\n42 3 x=5\n");
+        assert_eq!(run(code, true), "3 a|b  |007\n42 3 x=5\n");
+    }
+
+    #[test]
+    fn wide_integers() {
+        let code = r#"
+            (extern printf)
+            (const NEG (- 3))
+            (global big i64 (- 5000000000))
+            (defn id ((n i64)) i64 n)
+            (defn max () u64 18446744073709551615u64)
+            (defn deferred () i64 (defer (printf "|")) 6000000000)
+            (defn arith () none
+                (let a (id 4294967295i64))
+                (let b (+ a 1i64))
+                (printf "%lld %lld " b (- b 2i64))
+                (printf "%lld %lld " (wrapping-mul a 3i64) (wrapping-mul big 3i64))
+                (printf "%llu\n" (- 0u64 1u64)))
+            (printf "%lld %llu %lld " (id 5000000000) (max) (id NEG))
+            (println big (deferred))
+            (arith)
+        "#;
+        assert_eq!(
+            run(code, false),
+            "5000000000 18446744073709551615 -3 |-5000000000 6000000000\n\
+             4294967296 4294967294 12884901885 -15000000000 18446744073709551615\n"
+        );
+        let err = |code, compiler: Compiler| {
+            let mut compiler = compiler;
+            compiler
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        let checked = Compiler {
+            options: CompileOptions {
+                checked_arithmetic: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            err("(defn f ((n i64)) i64 (+ n 1))", checked),
+            CompileError::Unsupported("checked 64-bit arithmetic")
+        );
+        assert_eq!(
+            err(
+                "(defn f () none (loop ((n 1i64)) (recur n)))",
+                Compiler::default()
+            ),
+            CompileError::Unsupported("64-bit loop variables")
+        );
+        assert_eq!(
+            CompileError::Unsupported("float operands").to_string(),
+            "unsupported: float operands"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let code = r#"
+            (extern printf)
+            (defn show ((x f32) (y f64)) none (printf "%.2f %.2f\n" x y))
+            (println 1.5f32 2.5)
+            (show 0.25f32 0.75)
+        "#;
+        assert_eq!(run(code, false), "1.500000 2.500000\n0.25 0.75\n");
+    }
+
+    #[test]
+    fn optimization_levels() {
+        let code = r#"
+            (extern printf puts)
+            (const N 40)
+            (defn add ((a i32) (b i32)) i32 (+ a b))
+            (defn unused () i32 (+ 1 1))
+            (printf "%d %d\n" (add N 2) (+ N (+ 1 1)))
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler), "42 42\n", "{opt_level:?}");
+        }
+        let mut compiler = Compiler {
+            options: CompileOptions {
+                opt_level: OptLevel::O2,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        let program = compiler.program;
+        assert_eq!(program.functions.len(), 1);
+        assert_eq!(program.externs, ["printf"]);
+        assert!(!program.functions[0]
+            .body
+            .iter()
+            .any(|instr| matches!(instr, Instruction::Call { func } if func == "add")));
+    }
+
+    #[test]
+    fn case_dispatch() {
+        let code = r#"
+            (defn dense ((x i32)) i32
+              (case x (3 30) (4 40) (5 50) (7 70) (else 0)))
+            (defn sparse ((x i32)) i32
+              (case x (1 10) (200 20) (else 0)))
+            (println (dense 2) (dense 3) (dense 5) (dense 6) (dense 7) (dense 8))
+            (println (sparse 1) (sparse 200) (sparse 2))
+            (case (dense 4) (40 (println "forty")))
+            (defn command ((name *u8)) i32
+              (case-str name ("add" 1) ("and" 2) ("" 3) ("quit" 4) (else 0)))
+            (println (command "add") (command "and") (command "") (command "quit") (command "ad"))
+            (case-str "x" ("y" (println "y")))
+        "#;
+        let expected = "0 30 50 0 70 0\n10 20 0\nforty\n1 2 3 4 0\n";
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler.clone()), expected, "{opt_level:?}");
+            compiler.compile_program(parse(code).unwrap()).unwrap();
+            let tables = &compiler.program.functions[0].tables;
+            assert_eq!(tables.len(), 1);
+            assert_eq!(tables[0].len(), 5);
+            // the tables survive being written out and read back, like the cache does
+            let reparsed: Program = compiler.program.to_string().parse().unwrap();
+            assert_eq!(reparsed.functions[0].tables, *tables);
+        }
+        let err = Compiler::default()
+            .compile_program(parse("(case 1 (2 0) (2 1))").unwrap())
+            .unwrap_err();
+        assert_eq!(err.value, CompileError::DuplicateCase("2".to_string()));
+        let err = Compiler::default()
+            .compile_program(parse(r#"(case-str "a" ("b" 0) ("b" 1))"#).unwrap())
+            .unwrap_err();
+        assert_eq!(err.value, CompileError::DuplicateCase("\"b\"".to_string()));
+    }
+
+    #[test]
+    fn variadic_functions() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn sum ((count i32) ...) i32
+              (case count
+                (1 (va-arg i32))
+                (3 (+ (va-arg i32) (+ (va-arg i32) (va-arg i32))))
+                (else 0)))
+            (defn show ((label *u8) ...) none
+              (printf "%s %s\n" label (va-arg *u8)))
+            (printf "%d %d %d\n" (sum 1 5) (sum 3 1 2 3) (sum 0))
+            (show "a" "b")
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler), "5 6 0\na b\n", "{opt_level:?}");
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(defn f ((a i32) ...) i32 a)\n(f)"),
+            CompileError::ExpectedArgs(1)
+        );
+        assert_eq!(
+            err("(defn f ((a i32)) i32 (va-arg i32))"),
+            CompileError::InvalidForm("va-arg")
+        );
+    }
+
+    #[test]
+    fn keyword_arguments() {
+        use crate::parser::{Lexer, ParseErrorKind, Token};
+        let tokens: Vec<_> = Lexer::from("(:x :std-out)")
+            .map(|token| token.unwrap().value)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Open('('),
+                Token::Keyword("x".into()),
+                Token::Keyword("std-out".into()),
+                Token::Close(')'),
+            ]
+        );
+        assert_eq!(
+            parse("(f : x)").unwrap_err().kind,
+            ParseErrorKind::Unexpected(':')
+        );
+        assert_eq!(parse("(f :a 1)").unwrap()[0].to_string(), "(f :a 1)");
+
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn sub ((a i32) (b i32)) i32 (- a b))
+            (defn pick ((T)) ((first T) (second T)) T second)
+            (printf "%d %d %d %d\n" (sub :b 1 :a 10) (sub 10 :b 3) (sub 5 1) (pick :second 7 :first 8))
+        "#;
+        assert_eq!(run(code, false), "9 7 4 7\n");
+        let err = |code: &str| {
+            Compiler::default()
+                .compile_program(
+                    parse(&format!("(defn sub ((a i32) (b i32)) i32 (- a b))\n{code}")).unwrap(),
+                )
+                .unwrap_err()
+                .value
+        };
+        assert!(matches!(
+            err("(sub :a 1 :bb 2)"),
+            CompileError::NotFound { name, suggestion: Some(suggestion) } if name == "bb" && suggestion == "b"
+        ));
+        assert_eq!(err("(sub :a 1)"), CompileError::MissingArg("b".into()));
+        assert_eq!(err("(sub 1 :b)"), CompileError::MissingArg("b".into()));
+        assert_eq!(err("(sub 1 :a 2)"), CompileError::DuplicateArg("a".into()));
+        assert_eq!(
+            err("(sub 1 2 :a 3)"),
+            CompileError::DuplicateArg("a".into())
+        );
+        assert_eq!(err("(exit :a)"), CompileError::InvalidForm("keyword"));
+        assert_eq!(
+            err("(extern abs)\n(abs :x 1)"),
+            CompileError::InvalidForm("keyword")
+        );
+    }
+
+    #[test]
+    fn pointer_arithmetic() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn tail ((s *u8) (n i32)) *u8 (+ s n))
+            (defn back ((s *u8) (n i32)) *u8 (- (+ 3 s) n))
+            (defn count ((p *i32) (n i32)) isz (- (+ p n) p))
+            (defn behind ((T)) ((p T) (n i32)) isz (- p (+ p n)))
+            (printf "%s %s\n" (tail "hello" 2) (back "hello" 1))
+            (printf "%d %d\n" (count (alloc i32 8) 5) (behind (alloc (array i32 3) 4) 7))
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler), "llo llo\n5 -7\n", "{opt_level:?}");
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(defn f ((p *i32) (q *u8)) isz (- p q))"),
+            CompileError::InvalidPointerArithmetic {
+                left: Type::Pointer(Box::new(Type::Int(IntType::S32))),
+                right: Type::Pointer(Box::new(Type::UInt(IntType::S8))),
+            }
+        );
+        assert_eq!(
+            err("(defn f ((p *i32) (n i64)) *i32 (+ p n))"),
+            CompileError::InvalidPointerArithmetic {
+                left: Type::Pointer(Box::new(Type::Int(IntType::S32))),
+                right: Type::Int(IntType::S64),
+            }
+        );
+        assert!(matches!(
+            err("(defn f ((p *i32) (n i32)) *i32 (- n p))"),
+            CompileError::InvalidPointerArithmetic { .. }
+        ));
+        assert!(matches!(
+            err("(defn f ((p *none) (n i32)) *none (+ p n))"),
+            CompileError::UnknownSize(Type::None)
+        ));
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let run = |code: &str, checked_arithmetic: bool| {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    checked_arithmetic,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            compiler.compile_program(parse(code).unwrap()).unwrap();
+            let bytecode = Bytecode::lower(&compiler.program).unwrap();
+            let mut output = vec![];
+            let status = Vm::new(&bytecode, &mut output).run().unwrap();
+            (String::from_utf8(output).unwrap(), status)
+        };
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn inc ((a i32)) i32 (+ a 1))
+            (defn dec ((a i32)) i32 (- a 1))
+            (printf "%d %d\n" (inc 2147483646) (dec (- 0 2147483647)))
+            (printf "%d\n" (inc 2147483647))
+        "#;
+        assert_eq!(run(code, false).0, "2147483647 -2147483648\n-2147483648\n");
+        assert_eq!(
+            run(code, true),
+            (
+                "2147483647 -2147483648\narithmetic overflow at 3:37\n".to_string(),
+                PANIC_EXIT_CODE
+            )
+        );
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (printf "%u\n" (sub-checked (sizeof i64) (sizeof i32)))
+            (defn dec-checked () i32 (sub-checked (- 0 2147483647) 2))
+            (printf "%d\n" (dec-checked))
+        "#;
+        assert_eq!(
+            run(code, false),
+            (
+                "4\narithmetic overflow at 4:38\n".to_string(),
+                PANIC_EXIT_CODE
+            )
+        );
+    }
+
+    #[test]
+    fn wrapping_and_saturating() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn add8 ((n i32) ...) i8 (saturating-add (va-arg i8) (va-arg i8)))
+            (defn sub8 ((n i32) ...) i8 (saturating-sub (va-arg i8) (va-arg i8)))
+            (defn subu16 ((n i32) ...) u16 (saturating-sub (va-arg u16) (va-arg u16)))
+            (defn mul8 ((n i32) ...) i8 (wrapping-mul (va-arg i8) (va-arg i8)))
+            (printf "%d %d %d %d %d\n" (add8 2 100 100) (add8 2 (- 0 100) (- 0 100))
+              (sub8 2 (- 0 100) 100) (subu16 2 1 2) (mul8 2 16 16))
+            (printf "%d %d %d\n" (saturating-add 2147483600 100) (saturating-sub (- 0 2147483600) 100)
+              (saturating-add 5 (- 0 7)))
+            (printf "%u %u %u\n" (saturating-add (wrapping-sub (sizeof i8) (sizeof i32)) (sizeof i64))
+              (saturating-sub (sizeof i32) (sizeof i64)) (wrapping-add (sizeof i8) (sizeof i8)))
+            (printf "%d %d\n" (wrapping-mul 65536 65536) (wrapping-mul 8 (- 0 3)))
+        "#;
+        let output = "127 -128 -128 0 0\n2147483647 -2147483648 -2\n4294967295 0 2\n0 -24\n";
+        for checked_arithmetic in [false, true] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    checked_arithmetic,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler), output, "{checked_arithmetic}");
+        }
+    }
+
+    #[test]
+    fn atomics() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn bump ((p *i32)) i32 (atomic-add p 5))
+            (defn run ((p *i32)) i32
+              (atomic-store p 10)
+              (printf "%d " (bump p))
+              (printf "%d " (cas p 15 20))
+              (printf "%d " (cas p 15 30))
+              (atomic-load p))
+            (printf "%d\n" (run (alloc i32 1)))
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler), "10 15 20 20\n", "{opt_level:?}");
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(atomic-load (alloc i64 1))"),
+            CompileError::InvalidType(Type::Pointer(Box::new(Type::Int(IntType::S64))))
+        );
+        assert_eq!(
+            err("(atomic-store (alloc i32 1) (alloc i32 1))"),
+            CompileError::InvalidTypeExpected {
+                expected: Type::Int(IntType::S32),
+                got: Type::Pointer(Box::new(Type::Int(IntType::S32))),
+            }
+        );
+        assert_eq!(err("(cas (alloc i32 1) 1)"), CompileError::ExpectedArgs(3));
+    }
+
+    #[test]
+    fn function_pointers() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32) puts)
+            (defn twice ((n i32)) i32 (+ n n))
+            (defn apply ((f (fn (i32) i32)) (x i32)) i32 (call-ptr f x))
+            (let print (addr-of printf))
+            (call-ptr print "%d %d\n" (apply (addr-of twice) 21) 7)
+            (call-ptr (addr-of puts) "any arguments")
+        "#;
+        assert_eq!(run(code, false), "42 7\nany arguments\n");
+        let i32 = Type::Int(IntType::S32);
+        assert_eq!(
+            "(fn (i32 (fn (*u8 ...) none)) i32)".parse::<Type>(),
+            Ok(Type::Func {
+                params: Box::new([
+                    i32.clone(),
+                    Type::Func {
+                        params: Box::new([Type::Pointer(Box::new(Type::UInt(IntType::S8)))]),
+                        variadic: true,
+                        ret: Box::new(Type::None),
+                    },
+                ]),
+                variadic: false,
+                ret: Box::new(i32.clone()),
+            })
+        );
+        assert!("(fn i32 i32)".parse::<Type>().is_err());
+        let err = |code: &str| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        let twice = "(defn twice ((n i32)) i32 (+ n n))";
+        assert_eq!(
+            err(&format!("{twice} (call-ptr (addr-of twice) 1 2)")),
+            CompileError::ExpectedArgs(1)
+        );
+        assert_eq!(
+            err(&format!("{twice} (call-ptr (addr-of twice) \"a\")")),
+            CompileError::InvalidTypeExpected {
+                expected: i32,
+                got: Type::Array {
+                    typ: Box::new(Type::UInt(IntType::S8)),
+                    size: Some(2),
+                },
+            }
+        );
+        assert_eq!(
+            err("(defn f ((g (fn (i32)))) none)"),
+            CompileError::InvalidForm("fn")
+        );
+    }
+
+    #[test]
+    fn mem_copy_and_set() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn show ((dst *u8) (n i32)) i32
+              (mem-set dst 0 32)
+              (mem-set dst 46 20)
+              (mem-copy dst "hello, world" n)
+              (mem-copy dst "HELLO" 5)
+              (mem-set (+ dst 7) 42 3)
+              (printf "%s\n" dst))
+            (show (alloc u8 32) 12)
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(
+                run_with(code, compiler),
+                "HELLO, ***ld........\n",
+                "{opt_level:?}"
+            );
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(mem-set (alloc u8 1) 0 (- 0 1))"),
+            CompileError::OutOfRange(-1)
+        );
+        assert_eq!(
+            err("(mem-copy 1 (alloc u8 1) 1)"),
+            CompileError::InvalidType(Type::Int(IntType::S32))
+        );
+    }
+
+    #[test]
+    fn defer() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn work ((n i32)) i32
+              (defer (printf "first deferred %d\n" n))
+              (defer (printf "second deferred\n"))
+              (printf "body\n")
+              n)
+            (defer (printf "main done\n"))
+            (printf "%d\n" (work 7))
+        "#;
+        assert_eq!(
+            run(code, false),
+            "body\nsecond deferred\nfirst deferred 7\n7\nmain done\n"
+        );
+        let err = Compiler::default()
+            .compile_program(parse("(defer)").unwrap())
+            .unwrap_err()
+            .value;
+        assert_eq!(err, CompileError::ExpectedArgs(1));
+    }
+
+    #[test]
+    fn blocks_and_lets() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn sum ((a i32) ...) i32
+              (let b (va-arg i32))
+              (let c (block
+                (let b (+ b 1))
+                (defer (printf "inner %d\n" b))
+                (+ a b)))
+              (block (let small (va-arg i8)) (printf "small %d\n" small))
+              c)
+            (defn pick ((n i32)) i32
+              (case n
+                (1 (let x 10) (+ x n))
+                (else (let y 20) (let z 3) (+ y z))))
+            (let total (sum 1 2 (- 0 5)))
+            (printf "%d %d %d\n" total (pick 1) (pick 2))
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(
+                run_with(code, compiler),
+                "inner 3\nsmall -5\n4 11 23\n",
+                "{opt_level:?}"
+            );
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(+ 1 (let x 2))"),
+            CompileError::Unsupported("lets inside of other expressions")
+        );
+        assert_eq!(
+            err("(block (let x 1)) x"),
+            CompileError::NotFound {
+                name: "x".to_string(),
+                suggestion: None,
+            }
+        );
+    }
+
+    #[test]
+    fn command_line_args() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn show ((i i32)) i32 (printf "%s\n" (argv i)))
+            (printf "%d\n" (argc))
+            (show 1)
+            (show 2)
+        "#;
+        let mut compiler = Compiler::default();
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        let bytecode = Bytecode::lower(&compiler.program).unwrap();
+        let mut output = vec![];
+        let args = ["prog", "a", "bc"].map(String::from).to_vec();
+        Vm::new(&bytecode, &mut output)
+            .with_args(args)
+            .run()
+            .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "3\na\nbc\n");
+    }
+
+    #[test]
+    fn environment_and_exit() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn quit ((code i32)) ! (exit code))
+            (printf "%s %d\n" (getenv "HOME") (getenv "MISSING"))
+            (quit 3)
+            (printf "unreachable\n")
+        "#;
+        let mut compiler = Compiler::default();
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        let bytecode = Bytecode::lower(&compiler.program).unwrap();
+        let mut output = vec![];
+        let env = vec![("HOME".to_string(), "/home/lerp".to_string())];
+        let code = Vm::new(&bytecode, &mut output).with_env(env).run().unwrap();
+        assert_eq!(code, 3);
+        assert_eq!(String::from_utf8(output).unwrap(), "/home/lerp 0\n");
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(getenv 1)"),
+            CompileError::InvalidType(Type::Int(IntType::S32))
+        );
+    }
+
+    #[test]
+    fn byte_strings() {
+        let code = r#"
+            (global blob (array u8 4) b"\x01\x02")
+            (write 1 b"ab\x00c\n" 5)
+            (write 1 b"" 0)
+        "#;
+        let mut compiler = Compiler::default();
+        assert_eq!(run_with(code, compiler.clone()), "ab\0c\n");
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        let main = &compiler.program.functions[0];
+        assert_eq!(main.bytes, [vec![b'a', b'b', 0, b'c', b'\n'], vec![]]);
+        assert_eq!(compiler.program.data[0].values, [1, 2, 0, 0]);
+        // the bytes survive being written out and read back, like the cache does
+        let reparsed: Program = compiler.program.to_string().parse().unwrap();
+        assert_eq!(reparsed.functions[0].bytes, main.bytes);
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(defn f ((x i32)) i32 x)\n(f b\"abc\")"),
+            CompileError::InvalidTypeExpected {
+                expected: Type::Int(IntType::S32),
+                got: Type::Array {
+                    typ: Box::new(Type::UInt(IntType::S8)),
+                    size: Some(3),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn embed_file() {
+        let dir = env::temp_dir().join(format!("lerp-embed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("blob.bin"), b"hi\0\n").unwrap();
+        let code = r#"
+            (const LEN (embed-file "blob.bin"))
+            (write 1 (embed-file "blob.bin") LEN)
+            (exit LEN)
+        "#;
+        // the path is relative to the source file
+        let mut compiler = Compiler::default();
+        compiler.program.sources = vec![dir.join("main.lp").display().to_string()];
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        assert_eq!(compiler.program.functions[0].bytes, [b"hi\0\n"]);
+        let bytecode = Bytecode::lower(&compiler.program).unwrap();
+        let mut output = vec![];
+        assert_eq!(Vm::new(&bytecode, &mut output).run().unwrap(), 4);
+        assert_eq!(output, b"hi\0\n");
+        let err = Compiler::default()
+            .compile_program(parse(r#"(embed-file "missing.bin")"#).unwrap())
+            .unwrap_err();
+        assert!(matches!(
+            err.value,
+            CompileError::CannotEmbed { path, .. } if path == "missing.bin"
+        ));
+        // a cached function is compiled again once a file it embeds changes
+        let options = CompileOptions {
+            cache: Some(dir.join("cache")),
+            ..Default::default()
+        };
+        let code = format!(
+            "(defn blob () (array u8 4) (embed-file {:?}))\n(blob)",
+            dir.join("blob.bin").display()
+        );
+        let compile = || {
+            crate::compile_str(&code, options.clone())
+                .unwrap()
+                .program
+                .functions
+        };
+        assert_eq!(compile()[0].bytes, [b"hi\0\n"]);
+        fs::write(dir.join("blob.bin"), b"ok\n\0").unwrap();
+        assert_eq!(compile()[0].bytes, [b"ok\n\0"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cfg_forms() {
+        let code = r#"
+            (cfg (target linux) (defn os () i32 1))
+            (cfg (not (target linux)) (defn os () i32 2) (undefined))
+            (cfg (feature "debug") (println "debug"))
+            (println (os) (cfg (any (feature "debug") (target windows)) 10) 20)
+        "#;
+        assert_eq!(run_with(code, Compiler::default()), "1 20\n");
+        let compiler = Compiler {
+            options: CompileOptions {
+                features: ["debug".to_string()].into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(run_with(code, compiler), "debug\n1 10 20\n");
+        let err = Compiler::default()
+            .compile_program(parse("(cfg (os linux) 1)").unwrap())
+            .unwrap_err();
+        assert_eq!(err.value, CompileError::InvalidForm("cfg"));
+    }
+
+    #[test]
+    fn file_calls() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (write 1 "hello\n" 6)
+            (printf "%d %d %d %d\n"
+              (open "missing.txt" 0) (read 0 "abcd" 4) (close 1) (write 7 "x" 1))
+            (exit 4)
+        "#;
+        for (no_libc, errors) in [(false, "-1 0 0 -1"), (true, "-2 0 0 -9")] {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    no_libc,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            compiler.compile_program(parse(code).unwrap()).unwrap();
+            let asm = compiler.program.to_string();
+            assert_eq!(asm.contains("int 0x80"), no_libc);
+            assert_eq!(asm.contains("extern open"), !no_libc);
+            let bytecode = Bytecode::lower(&compiler.program).unwrap();
+            let mut output = vec![];
+            let code = Vm::new(&bytecode, &mut output).run().unwrap();
+            assert_eq!(code, 4);
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                format!("hello\n{errors}\n")
+            );
+        }
+    }
+
+    #[test]
+    fn prelude() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (printf "%d %d %d %d %d\n" (min 3 (- 0 4)) (max 3 (- 0 4)) (abs (- 0 7)) (abs 7) (min 2 2))
+            (let joined (str-join "ab" ", " "cd"))
+            (printf "%s %d %d %s\n" joined (str-empty "") (str-empty joined) (str-dup "x"))
+        "#;
+        for jobs in [1, 2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    jobs,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler), "-4 3 7 7 2\nab, cd 1 0 x\n");
+        }
+        // only what is called is compiled in, and definitions of the program come first
+        let mut compiler = Compiler::default();
+        compiler
+            .compile_program(parse("(defn min ((a i32) (b i32)) i32 b)\n(abs (min 1 2))").unwrap())
+            .unwrap();
+        let asm = compiler.program.to_string();
+        assert!(asm.contains("abs:") && !asm.contains("max:"));
+        assert_eq!(
+            run("(extern (printf ((fmt *u8) ...) i32))\n(defn min ((a i32) (b i32)) i32 b)\n(printf \"%d\\n\" (min 1 2))", false),
+            "2\n"
+        );
+        let mut compiler = Compiler {
+            options: CompileOptions {
+                no_prelude: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = compiler
+            .compile_program(parse("(min 1 2)").unwrap())
+            .unwrap_err();
+        assert!(matches!(err.value, CompileError::NotFound { .. }));
+    }
+
+    #[test]
+    fn freestanding() {
+        let freestanding = |code: &str, bump_allocator| {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    freestanding: true,
+                    ..Default::default()
+                },
+                bump_allocator,
+                ..Default::default()
+            };
+            compiler
+                .compile_program(parse(code).unwrap())
+                .map(|_| compiler.program)
+        };
+        let program = freestanding(
+            "(let p (alloc i32 4))\n(write 1 \"hi\\n\" 3)\n(exit (abs (- 0 3)))",
+            true,
+        )
+        .unwrap();
+        assert!(program.externs.is_empty(), "{:?}", program.externs);
+        let bytecode = Bytecode::lower(&program).unwrap();
+        let mut output = vec![];
+        assert_eq!(Vm::new(&bytecode, &mut output).run().unwrap(), 3);
+        assert_eq!(output, b"hi\n");
+        // a flat binary starts with `main` and has nothing for a linker
+        let flat = FlatBinary {
+            program: &program,
+            org: 0x7c00,
+        }
+        .to_string();
+        assert!(
+            flat.starts_with("bits 32\norg 0x7c00\nsection .text\nmain:\n"),
+            "{flat}"
+        );
+        assert!(
+            !flat.contains("global") && !flat.contains("extern"),
+            "{flat}"
+        );
+        assert!(flat.contains("main_c0"), "{flat}");
+
+        for (code, name) in [
+            ("(println 1)", "println"),
+            ("(alloc i32 4)", "alloc"),
+            ("(str-dup \"x\")", "str-cat"),
+            ("(case-str \"x\" (else 0))", "case-str"),
+        ] {
+            let err = freestanding(code, false).unwrap_err();
+            assert_eq!(
+                err.value,
+                CompileError::NeedsLibc(name.to_string()),
+                "{code}"
+            );
+            assert_eq!(err.pos.file, Default::default(), "{code}");
+        }
+        let mut compiler = Compiler {
+            options: CompileOptions {
+                freestanding: true,
+                checked_arithmetic: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let err = compiler
+            .compile_program(parse("(+ (argc) (argc))").unwrap())
+            .unwrap_err();
+        assert_eq!(err.value, CompileError::NeedsLibc("+".to_string()));
+    }
+
+    #[test]
+    fn forward_declarations() {
+        let code = r#"
+            (printf "%d %d\n" (twice 4) (bump))
+            (defn twice ((n i32)) i32 (plus (id n) n))
+            (defn bump () i32 (+ counter 1))
+            (defn plus ((a i32) (b i32)) i32 (+ a b))
+            (defn id ((T)) ((x T)) T x)
+            (global counter i32 41)
+            (extern (printf ((fmt *u8) ...) i32))
+        "#;
+        for jobs in [1, 2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    jobs,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(run_with(code, compiler), "8 42\n", "{jobs} jobs");
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+        };
+        let redefined = err("(f)\n(defn f () i32 1)\n(defn f () i32 2)");
+        assert_eq!(redefined.value, CompileError::Redefined("f".to_string()));
+        assert_eq!(redefined.pos.ln, 2);
+    }
+
+    #[test]
+    fn loop_and_recur() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn fact ((n i32)) i32
+              (loop ((i n) (acc 1))
+                (case i (0 acc) (else (recur (- i 1) (wrapping-mul acc i))))))
+            (defn steps ((n i32)) i32
+              (loop ((i n) (steps 0))
+                (let next (- i 1))
+                (case i (0 steps) (else (recur next (+ steps 1))))))
+            (defn grid ((n i32)) i32
+              (loop ((i n) (total 0))
+                (case i
+                  (0 total)
+                  (else
+                    (let row (loop ((j i) (sum 0))
+                      (case j (0 sum) (else (recur (- j 1) (+ sum 1))))))
+                    (recur (- i 1) (+ total row))))))
+            (loop ((i 2))
+              (defer (printf "left %d\n" i))
+              (case i (0 i) (else (recur (- i 1)))))
+            (printf "%d %d %d\n" (fact 5) (steps 100000) (grid 4))
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(
+                run_with(code, compiler),
+                "left 2\nleft 1\nleft 0\n120 100000 10\n",
+                "{opt_level:?}"
+            );
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(err("(recur 1)"), CompileError::RecurOutsideLoop);
+        assert_eq!(err("(loop ((i 0)) (recur))"), CompileError::ExpectedArgs(1));
+        assert_eq!(err("(loop (i 0) i)"), CompileError::InvalidForm("loop"));
+    }
+
+    #[test]
+    fn integer_literal_inference() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (const LIMIT 7)
+            (defn inc ((x u16)) u16 (+ x 1))
+            (defn rest ((x u32)) u32 (- 100 x))
+            (defn byte () u8 255)
+            (defn limit ((x u32)) u32 (+ x LIMIT))
+            (defn count () u8
+              (loop ((i 0u8))
+                (case i
+                  (3 i)
+                  (else (recur (+ i 1))))))
+            (printf "%d %d %d %d %d %d\n" (inc 41) (rest 58) (byte) (limit 1) (count) (+ 250u8 5u8))
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            assert_eq!(
+                run_with(code, compiler),
+                "42 42 255 8 3 255\n",
+                "{opt_level:?}"
+            );
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(
+            err("(defn f ((x u8)) u8 x)\n(f 256)"),
+            CompileError::OutOfRange(256)
+        );
+        assert_eq!(err("(+ 128i8 0)"), CompileError::OutOfRange(128));
+        assert_eq!(
+            err("(+ 1u8 2u16)"),
+            CompileError::InvalidTypeExpected {
+                expected: Type::UInt(IntType::S8),
+                got: Type::UInt(IntType::S16),
+            }
+        );
+    }
+
+    #[test]
+    fn never_returning_expressions() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn clamp ((n i32)) i32
+              (case n (0 (return 7)))
+              (+ n 1))
+            (defn find () i32
+              (loop ((i 0))
+                (case i
+                  (5 (break (+ i 100)))
+                  (else (recur (+ i 1))))))
+            (defn left () i32
+              (loop ((i 2))
+                (defer (printf "left %d\n" i))
+                (break i)))
+            (defn fail () i32 (exit 3))
+            (defn forever () i32 (loop () (recur)))
+            (defn early () i32
+              (return 1)
+              (printf "never\n")
+              2)
+            (printf "%d %d %d %d %d\n" (clamp 0) (clamp 3) (find) (left) (early))
+        "#;
+        for opt_level in [OptLevel::O0, OptLevel::O2] {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            compiler.compile_program(parse(code).unwrap()).unwrap();
+            let warnings: Vec<_> = compiler
+                .warnings
+                .iter()
+                .map(|warning| (warning.value.clone(), warning.pos.ln))
+                .collect();
+            assert_eq!(warnings, [(CompileWarning::Unreachable, 18)]);
+            let bytecode = Bytecode::lower(&compiler.program).unwrap();
+            let mut output = vec![];
+            Vm::new(&bytecode, &mut output).run().unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "left 2\n7 4 105 2 1\n",
+                "{opt_level:?}"
+            );
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(err("(break 1)"), CompileError::BreakOutsideLoop);
+        assert_eq!(
+            err("(defn f () i32 (return (str-len \"a\")))"),
+            CompileError::InvalidTypeExpected {
+                expected: Type::Int(IntType::S32),
+                got: Type::UInt(IntType::Size),
+            }
+        );
+        assert_eq!(
+            err("(loop ((i 0)) (case i (0 (break 1)) (else (break (str-len \"a\")))))"),
+            CompileError::InvalidTypeExpected {
+                expected: Type::Int(IntType::S32),
+                got: Type::UInt(IntType::Size),
+            }
+        );
+    }
+
+    #[test]
+    fn division_by_constants() {
+        let signed = [
+            1,
+            2,
+            3,
+            5,
+            7,
+            10,
+            16,
+            641,
+            1 << 30,
+            i32::MAX,
+            -1,
+            -3,
+            -8,
+            -7,
+            i32::MIN,
+        ];
+        let unsigned = [1u32, 2, 3, 7, 10, 64, 641, 1 << 31, u32::MAX - 1, u32::MAX];
+        let dividends = [
+            0,
+            1,
+            -1,
+            7,
+            -7,
+            100,
+            -100,
+            12345,
+            -12345,
+            i32::MAX,
+            i32::MIN,
+        ];
+        // every literal is an `i32`, the unsigned values are their bits copied
+        let mut code = String::from(
+            r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn bits ((n i32)) u32
+              (let p (alloc i32 1))
+              (atomic-store p n)
+              (let u (alloc u32 1))
+              (mem-copy u p 4)
+              (atomic-load u))
+            (defn divide ((n i32) (d i32)) i32 (/ n d))
+            (defn divide-short ((n i16) (d i16)) i16 (/ n d))
+            "#,
+        );
+        fn literal(value: i64) -> String {
+            match value {
+                0..=0x7FFF_FFFF => value.to_string(),
+                0x8000_0000.. => format!("(+ {} {})", i32::MAX, literal(value - i32::MAX as i64)),
+                _ => format!("(- (- 0 {}) 1)", -value - 1),
+            }
+        }
+        let mut expected = String::new();
+        for (idx, divisor) in signed.iter().enumerate() {
+            code.push_str(&format!(
+                "(defn s{idx} ((n i32)) i32 (/ n {}))\n",
+                literal(*divisor as i64)
+            ));
+            for dividend in dividends {
+                let Some(quotient) = dividend.checked_div(*divisor) else {
+                    continue;
+                };
+                code.push_str(&format!(
+                    "(printf \"%d %d\\n\" (s{idx} {0}) (divide {0} {1}))\n",
+                    literal(dividend as i64),
+                    literal(*divisor as i64)
+                ));
+                expected.push_str(&format!("{quotient} {quotient}\n"));
+            }
+        }
+        for (idx, divisor) in unsigned.iter().enumerate() {
+            code.push_str(&format!(
+                "(defn u{idx} ((n u32)) u32 (/ n {}))\n",
+                literal(*divisor as i64)
+            ));
+            for dividend in dividends {
+                code.push_str(&format!(
+                    "(printf \"%u\\n\" (u{idx} (bits {})))\n",
+                    literal(dividend as i64)
+                ));
+                expected.push_str(&format!("{}\n", dividend as u32 / divisor));
+            }
+        }
+        code.push_str("(printf \"%d\\n\" (divide-short (- 0 300i16) 7i16))\n");
+        expected.push_str("-42\n");
+        assert_eq!(run(&code, false), expected);
+
+        let program =
+            crate::compiler::compile_program(parse("(defn f ((n i32)) i32 (/ n 7))").unwrap())
+                .unwrap();
+        assert!(!program.to_string().contains("div"));
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(err("(/ (argc) 0)"), CompileError::DivisionByZero);
+        let program =
+            crate::compiler::compile_program(parse("(defn f ((n i32)) i32 (/ 7 n))").unwrap())
+                .unwrap();
+        assert!(program.to_string().contains("\tcdq\n\tidiv ebx"));
+    }
+
+    #[test]
+    fn shadowing() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn f ((n i32)) i32
+              (let n 5)
+              (let m n)
+              (block (let m (+ m 1)) (printf "inner %d\n" m))
+              m)
+            (let x 1)
+            (block (let x 2) (printf "%d %d\n" x (f 0)))
+        "#;
+        // the `shadowed-binding` lint warns about each of them once, telling which bindings
+        // weren't used before
+        let mut compiler = Compiler::default();
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        let warnings: Vec<_> = compiler
+            .warnings
+            .iter()
+            .map(|warning| (warning.value.to_string(), warning.pos.ln))
+            .collect();
+        let shadows = |name: &str, unused| {
+            let before = if unused { " before it is used" } else { "" };
+            format!("{name:?} shadows another binding of the same name{before} [shadowed-binding]")
+        };
+        assert_eq!(
+            warnings,
+            [
+                (shadows("n", true), 3),
+                (shadows("m", false), 5),
+                (shadows("x", true), 8),
+            ]
+        );
+        let bytecode = Bytecode::lower(&compiler.program).unwrap();
+        let mut output = vec![];
+        Vm::new(&bytecode, &mut output).run().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "inner 6\n2 5\n");
+        let warnings = |level| {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    lints: [("shadowed-binding".to_string(), level)].into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            compiler
+                .compile_program(parse("(let x 3) (let x 4) x").unwrap())
+                .unwrap();
+            compiler.warnings.len()
+        };
+        assert_eq!(warnings(LintLevel::Warn), 1);
+        // allowing the lint silences every shadowing
+        assert_eq!(warnings(LintLevel::Allow), 0);
+    }
+
+    #[test]
+    fn unused_results_and_unread_loop_variables() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn f ((n i32)) i32
+              (+ n 1)
+              (block (printf "%d\n" n) (str-len "abc"))
+              (loop ((i 0) (unread 7))
+                (case i
+                  (3 n)
+                  (else (recur (+ i 1) 0)))))
+            (defer (sizeof i32))
+            (f 2)
+            (f 3)
+        "#;
+        let mut compiler = Compiler::default();
+        compiler.compile_program(parse(code).unwrap()).unwrap();
+        let warnings: Vec<_> = compiler
+            .warnings
+            .iter()
+            .map(|warning| (warning.value.clone(), warning.pos.ln))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (CompileWarning::UnusedResult, 3),
+                (CompileWarning::UnusedResult, 4),
+                (CompileWarning::UnusedResult, 9),
+                (CompileWarning::UnreadLoopVariable("unread".to_string()), 5),
+            ]
+        );
+        assert_eq!(compiler.warnings[3].value.code(), "unread-loop-variable");
+        let bytecode = Bytecode::lower(&compiler.program).unwrap();
+        let mut output = vec![];
+        assert_eq!(Vm::new(&bytecode, &mut output).run().unwrap(), 3);
+        assert_eq!(String::from_utf8(output).unwrap(), "2\n3\n");
+    }
+
+    #[test]
+    fn recursion() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn sum ((n i32)) i32
+              (case n
+                (0 0)
+                (else (+ n (sum (- n 1))))))
+            (defn even ((n i32)) i32
+              (case n
+                (0 1)
+                (else (odd (- n 1)))))
+            (defn odd ((n i32)) i32
+              (case n
+                (0 0)
+                (else (even (- n 1)))))
+            (defn twice ((n i32)) i32 (+ n n))
+            (printf "%d %d %d %d\n" (sum 10) (even 10) (odd 7) (twice 3))
+        "#;
+        let compile = |no_recursion| {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    no_recursion,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            compiler.compile_program(parse(code).unwrap()).unwrap();
+            compiler
+        };
+        assert!(compile(false).warnings.is_empty());
+        // functions calling each other are declared before either is compiled
+        let warnings: Vec<_> = compile(true)
+            .warnings
+            .iter()
+            .map(|warning| (warning.value.clone(), warning.pos.ln))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (CompileWarning::Recursion("sum".to_string()), 2),
+                (CompileWarning::Recursion("even".to_string()), 6),
+                (CompileWarning::Recursion("odd".to_string()), 10),
+            ]
+        );
+        assert_eq!(run_with(code, compile(true)), "55 1 1 6\n");
+    }
+
+    #[test]
+    fn loop_unrolling() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn sum ((n i32)) i32
+              (loop ((i n) (acc 0))
+                (case i
+                  (0 acc)
+                  (else (recur (- i 1) (+ acc i))))))
+            (defn powers () i32
+              (loop ((i 3) (acc 1))
+                (let twice (wrapping-mul acc 2))
+                (case i
+                  (0 acc)
+                  (else (recur (- i 1) twice)))))
+            (defn last () i32
+              (loop ((i 0) (acc 5) (unread 1))
+                (case i
+                  (2 acc)
+                  (else (recur (+ i 1) (+ i 10) 0)))))
+            (printf "%d %d %d %d %d\n" (sum 0) (sum 1) (sum 7) (powers) (last))
+        "#;
+        let compile = |opt_level, unroll_factor| {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    opt_level,
+                    unroll_factor,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            compiler.compile_program(parse(code).unwrap()).unwrap();
+            compiler
+        };
+        let count = |compiler: &Compiler, name: &str, pred: fn(&Instruction) -> bool| {
+            let function = compiler
+                .program
+                .functions
+                .iter()
+                .find(|function| function.name.ends_with(name))
+                .unwrap();
+            function.body.iter().filter(|instr| pred(instr)).count()
+        };
+        let compares = |instr: &Instruction| matches!(instr, Instruction::Cmp { .. });
+        let jumps_back = |instr: &Instruction| matches!(instr, Instruction::Jmp { label } if label.as_str().starts_with("loop"));
+        for (opt_level, unroll_factor, copies) in [
+            (OptLevel::O0, 3, 1),
+            (OptLevel::O2, 1, 1),
+            (OptLevel::O2, 3, 3),
+            (OptLevel::O2, 0, crate::unroll::DEFAULT_FACTOR),
+        ] {
+            let compiler = compile(opt_level, unroll_factor);
+            assert_eq!(count(&compiler, "sum", compares), copies, "{opt_level:?}");
+            assert_eq!(count(&compiler, "sum", jumps_back), 1);
+            // constant trip counts leave neither the tests nor the jump back
+            let unrolled = opt_level == OptLevel::O2;
+            assert_eq!(count(&compiler, "powers", compares) == 0, unrolled);
+            assert_eq!(count(&compiler, "last", jumps_back) == 0, unrolled);
+            let warnings: Vec<_> = compiler
+                .warnings
+                .iter()
+                .map(|warning| warning.value.clone())
+                .collect();
+            assert_eq!(
+                warnings,
+                [CompileWarning::UnreadLoopVariable("unread".to_string())]
+            );
+            assert_eq!(
+                run_with(code, compile(opt_level, unroll_factor)),
+                "0 1 28 8 11\n"
+            );
+        }
+    }
+
+    #[test]
+    fn profile_guided_layout() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn twice ((n i32)) i32 (add-checked n n))
+            (defn never ((n i32)) i32 (- n 1))
+            (printf "%d\n" (add-checked (twice 2) (twice 3)))
+        "#;
+        let compile = |profile_generate, profile| {
+            let mut compiler = Compiler {
+                options: CompileOptions {
+                    profile_generate,
+                    profile,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            compiler.compile_program(parse(code).unwrap()).unwrap();
+            compiler.program
+        };
+        let run_program = |program: &Program| {
+            let bytecode = Bytecode::lower(program).unwrap();
+            let mut output = vec![];
+            Vm::new(&bytecode, &mut output).run().unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        // the profile can't be created in the vm and goes to stderr
+        let output = run_program(&compile(true, None));
+        let (printed, profile) = output.split_once('\n').unwrap();
+        assert_eq!(printed, "10");
+        let profile = Profile::parse(profile).unwrap();
+        assert_eq!(profile.count("_Ltwice"), Some(2));
+        assert_eq!(profile.count("main"), Some(1));
+        assert!(profile.is_cold("_Lnever") && profile.is_cold("lerp_panic"));
+
+        let program = compile(false, Some(profile));
+        let names: Vec<_> = program
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect();
+        assert_eq!(names[..2], ["_Ltwice", "main"]);
+        // the overflow checks jump away to the panics after the return
+        for function in &program.functions[..2] {
+            let ret = function
+                .body
+                .iter()
+                .position(|instr| *instr == Instruction::Ret)
+                .unwrap();
+            assert!(function.body[..ret]
+                .iter()
+                .all(|instr| !matches!(instr, Instruction::Call { func } if func == "lerp_panic")));
+            assert!(function.body[ret..]
+                .iter()
+                .any(|instr| matches!(instr, Instruction::Call { func } if func == "lerp_panic")));
+        }
+        assert_eq!(run_program(&program), "10\n");
+        assert_eq!(Profile::parse("3 f\nx g"), Err(ProfileError { line: 1 }));
+    }
+
+    #[test]
+    fn spawn_and_join() {
+        let code = r#"
+            (extern (printf ((fmt *u8) ...) i32))
+            (defn start ((p *i32) (n i32)) usz
+              (spawn (lambda () (printf "thread %d\n" n) (atomic-add p n))))
+            (defn run ((p *i32)) i32
+              (join (start p 3))
+              (join (start p 4))
+              (join (spawn (lambda () (printf "no captures\n"))))
+              (atomic-load p))
+            (printf "%d\n" (run (alloc i32 1)))
+        "#;
+        for bump_allocator in [false, true] {
+            let compiler = Compiler {
+                bump_allocator,
+                ..Default::default()
+            };
+            assert_eq!(
+                run_with(code, compiler),
+                "thread 3\nthread 4\nno captures\n7\n",
+                "{bump_allocator}"
+            );
+        }
+        let err = |code| {
+            Compiler::default()
+                .compile_program(parse(code).unwrap())
+                .unwrap_err()
+                .value
+        };
+        assert_eq!(err("(spawn 1)"), CompileError::InvalidForm("spawn"));
+        assert_eq!(
+            err("(spawn (lambda (x) x))"),
+            CompileError::Unsupported("lambda parameters")
+        );
+        assert_eq!(
+            err("(join 1)"),
+            CompileError::InvalidTypeExpected {
+                expected: Type::UInt(IntType::Size),
+                got: Type::Int(IntType::S32),
+            }
+        );
+    }
+
+    #[test]
+    fn bump_allocator_exhaustion() {
+        // an allocation past the end of the 1 MiB heap, or too large to be rounded up, fails
+        // with null and leaves the heap to the ones which still fit
+        let code = r#"
+            (extern printf)
+            (let a (alloc u8 600000))
+            (let b (alloc u8 600000))
+            (let c (alloc i32 1073741823))
+            (let d (alloc u8 400000))
+            (printf "%d %d %d\n" b c (- d a))
+        "#;
+        assert_eq!(run(code, true), "0 0 600000\n");
+    }
+
+    #[test]
+    fn rejects_malformed_bytecode() {
+        assert!(Bytecode::from_bytes(b"ELF").is_err());
+        let mut bytes = Bytecode::default().to_bytes();
+        bytes.push(0);
+        assert!(Bytecode::from_bytes(&bytes).is_err());
+    }
+}
+
+/// set `LERP_BLESS=1` to update the golden files after an intended change
+#[test]
+fn golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mismatches = run_golden(&dir, env::var_os("LERP_BLESS").is_some()).unwrap();
+    for mismatch in &mismatches {
+        eprintln!("{}:\n{}", mismatch.case.display(), mismatch.diff());
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{} golden files differ",
+        mismatches.len()
+    );
+}
+
+/// assembles every function of the golden cases which compile as 32-bit code with GNU as when
+/// it's installed, one at a time as NASM scopes `.label`s to their function, the data being left
+/// out as its directives are NASM's
+#[test]
+fn golden_cases_assemble() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let out = env::temp_dir().join(format!("lerp-golden-{}", std::process::id()));
+    fs::create_dir_all(&out).unwrap();
+    let mut cases: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lp"))
+        .collect();
+    cases.sort();
+    let (source, object) = (out.join("a.s"), out.join("a.o"));
+    for case in cases {
+        let Ok(program) = compile_program(&fs::read_to_string(&case).unwrap()) else {
+            continue;
+        };
+        for function in &program.functions {
+            let mut asm = format!(".intel_syntax noprefix\n{}:\n", function.name);
+            for instr in &function.body {
+                asm.push_str(&format!("{instr}\n"));
+            }
+            fs::write(&source, asm).unwrap();
+            let assembled = Command::new("as")
+                .arg("--32")
+                .arg("-o")
+                .arg(&object)
+                .arg(&source)
+                .output();
+            let Ok(assembled) = assembled else {
+                eprintln!("skipping, GNU as isn't available");
+                fs::remove_dir_all(&out).ok();
+                return;
+            };
+            assert!(
+                assembled.status.success(),
+                "{} {}:\n{}",
+                case.display(),
+                function.name,
+                String::from_utf8_lossy(&assembled.stderr)
+            );
+        }
+    }
+    fs::remove_dir_all(&out).ok();
+}
+
+#[test]
+fn lexer_tokens() {
+    use crate::{
+        intern::Symbol,
+        parser::{Lexer, ParseErrorKind, QuoteKind, Token},
+        typ::{IntType, Type},
+    };
+    let tokens = |code| {
+        Lexer::from(code)
+            .map(|token| token.map(|token| (token.value, token.pos.ln, token.pos.col)))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        tokens("(add 1u8\n  [:key \"s\"] 'x)"),
+        [
+            Ok((Token::Open('('), 0, 0)),
+            Ok((Token::Word(Symbol::intern("add")), 0, 1)),
+            Ok((Token::Int(1, Some(Type::UInt(IntType::S8))), 0, 5)),
+            Ok((Token::Open('['), 1, 2)),
+            Ok((Token::Keyword(Symbol::intern("key")), 1, 3)),
+            Ok((Token::String("s".into()), 1, 8)),
+            Ok((Token::Close(']'), 1, 11)),
+            Ok((Token::Quote(QuoteKind::Quote), 1, 13)),
+            Ok((Token::Word(Symbol::intern("x")), 1, 14)),
+            Ok((Token::Close(')'), 1, 15)),
+        ]
+    );
+    // nothing is lexed after an error, though more tokens follow it
+    let lexed = tokens("(a 1_ b) 2x");
+    assert_eq!(lexed.len(), 3);
+    assert!(matches!(
+        &lexed[2],
+        Err(err) if err.kind == ParseErrorKind::MisplacedSeparator
+    ));
+    let mut lexer = Lexer::from("2x 3");
+    assert!(lexer.next().unwrap().is_err());
+    assert!(lexer.next().is_none());
+    assert!(lexer.next().is_none());
+}
+
+#[test]
+fn visitor_and_folder() {
+    use crate::{
+        parser::{parse, Located, SExpr},
+        visit::{fold_children, walk, SExprFolder, SExprVisitor},
+    };
+    /// every node in the order it is visited
+    struct Nodes(Vec<String>);
+    impl SExprVisitor for Nodes {
+        fn visit(&mut self, sexpr: &Located<SExpr>) {
+            self.0.push(sexpr.to_string());
+            walk(self, sexpr)
+        }
+    }
+    let program = parse("(f [x {:k 1}] 'x `(g ,x))\n\"s\"").unwrap();
+    let mut nodes = Nodes(vec![]);
+    nodes.visit_all(&program);
+    assert_eq!(
+        nodes.0,
+        [
+            "(f [x {:k 1}] 'x `(g ,x))",
+            "f",
+            "[x {:k 1}]",
+            "x",
+            "{:k 1}",
+            ":k",
+            "1",
+            "'x",
+            "x",
+            "`(g ,x)",
+            "(g ,x)",
+            "g",
+            ",x",
+            "x",
+            "\"s\"",
+        ]
+    );
+
+    /// renames `x` to `y` and doubles integers
+    struct Rewrite;
+    impl SExprFolder for Rewrite {
+        fn fold(&mut self, sexpr: Located<SExpr>) -> Located<SExpr> {
+            let value = match sexpr.value {
+                SExpr::Word(word) if word == "x" => SExpr::Word("y".into()),
+                SExpr::Int(int, typ) => SExpr::Int(int * 2, typ),
+                _ => return fold_children(self, sexpr),
+            };
+            Located {
+                value,
+                pos: sexpr.pos,
+            }
+        }
+    }
+    let folded = Rewrite.fold_all(program.clone());
+    let text: Vec<_> = folded.iter().map(ToString::to_string).collect();
+    assert_eq!(text, ["(f [y {:k 2}] 'y `(g ,y))", "\"s\""]);
+    // positions are kept
+    struct Positions(Vec<(usize, usize)>);
+    impl SExprVisitor for Positions {
+        fn visit(&mut self, sexpr: &Located<SExpr>) {
+            self.0.push((sexpr.pos.ln, sexpr.pos.col));
+            walk(self, sexpr)
+        }
+    }
+    let positions = |sexprs: &[Located<SExpr>]| {
+        let mut positions = Positions(vec![]);
+        positions.visit_all(sexprs);
+        positions.0
+    };
+    assert_eq!(positions(&program), positions(&folded));
+}
+
+#[test]
+fn quoting() {
+    use crate::{
+        compiler::{CompileError, Compiler},
+        macros::Expander,
+        parser::{parse, ParseErrorKind, QuoteKind, SExpr},
+    };
+    let word = |word: &str| SExpr::Word(word.into());
+    let program = parse("'(a b) ''x").unwrap();
+    let SExpr::Quoted(QuoteKind::Quote, list) = &program[0].value else {
+        panic!("{:?}", program[0]);
+    };
+    let SExpr::Expr(items) = &list.value else {
+        panic!("{list:?}");
+    };
+    assert_eq!(
+        items
+            .iter()
+            .map(|item| item.value.clone())
+            .collect::<Vec<_>>(),
+        [word("a"), word("b")]
+    );
+    let SExpr::Quoted(QuoteKind::Quote, inner) = &program[1].value else {
+        panic!("{:?}", program[1]);
+    };
+    assert!(matches!(&inner.value, SExpr::Quoted(QuoteKind::Quote, x) if x.value == word("x")));
+    // nested quotes print as they are written
+    for code in ["`(a `(b ,(c ,d)))", "'(1 [2 {3}])", "`(f ',x)"] {
+        assert_eq!(parse(code).unwrap()[0].to_string(), code);
+    }
+    assert_eq!(
+        parse("(a ')").unwrap_err().kind,
+        ParseErrorKind::Unexpected(')')
+    );
+
+    // arguments are substituted inside nested quotes of a template too
+    let expanded = Expander::default()
+        .expand_program(parse("(defmacro m (x) `(f '(,x 1) `(g ,x)))\n(m 7)").unwrap())
+        .unwrap();
+    assert_eq!(expanded[0].to_string(), "(f '(7 1) `(g 7))");
+
+    // quoted data has no value outside of macro templates yet
+    let compile = |code| {
+        Compiler::default()
+            .compile_program(parse(code).unwrap())
+            .map_err(|err| err.value)
+    };
+    assert!(compile("(defmacro twice (x) `(+ ,x ,x))\n(exit (twice 2))").is_ok());
+    for (code, form) in [
+        ("(exit '(1 2))", "quote"),
+        ("(exit `(1 ,x))", "quasiquote"),
+        ("(exit ,x)", "unquote"),
+        ("(defmacro m (x) `(exit '(,x)))\n(m 1)", "quote"),
+    ] {
+        assert_eq!(
+            compile(code),
+            Err(CompileError::InvalidForm(form)),
+            "{code}"
+        );
+    }
+}
+
+#[test]
+fn brackets_and_braces() {
+    use crate::{
+        compiler::{CompileError, Compiler},
+        parser::{parse, parse_recovering, ParseErrorKind, SExpr},
+    };
+    let program = parse("[a (b) {c :d}]").unwrap();
+    let SExpr::Bracket(items) = &program[0].value else {
+        panic!("{:?}", program[0]);
+    };
+    assert_eq!(items[0].value, SExpr::Word("a".into()));
+    assert!(matches!(&items[1].value, SExpr::Expr(b) if b.len() == 1));
+    assert!(matches!(&items[2].value, SExpr::Brace(c) if c.len() == 2));
+    assert_eq!(program[0].to_string(), "[a (b) {c :d}]");
+    // a group closed by another delimiter is reported where it was opened
+    let error = |code| {
+        let err = parse(code).unwrap_err();
+        (err.kind, err.pos.ln, err.pos.col)
+    };
+    assert_eq!(error("(f [a b)"), (ParseErrorKind::Unclosed('['), 0, 3));
+    assert_eq!(error("(f [a b]"), (ParseErrorKind::Unclosed('('), 0, 0));
+    assert_eq!(error("{a (b}"), (ParseErrorKind::Unclosed('('), 0, 3));
+    assert_eq!(error("[a\n b"), (ParseErrorKind::Unclosed('['), 0, 0));
+    assert_eq!(error("a]"), (ParseErrorKind::Unexpected(']'), 0, 1));
+    assert_eq!(
+        parse("(f [a b)").unwrap_err().to_string(),
+        "1:4: unclosed '['"
+    );
+    // recovery carries on with the next top-level expression
+    let (program, errors) = parse_recovering("(f [a)\n(g {b})");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(program[0].to_string(), "(g {b})");
+
+    let compile = |code| {
+        let mut compiler = Compiler::default();
+        compiler
+            .compile_program(parse(code).unwrap())
+            .map(|_| compiler.program)
+            .map_err(|err| err.value)
+    };
+    assert!(matches!(
+        compile("(exit [1])"),
+        Err(CompileError::InvalidForm("bracket"))
+    ));
+    assert!(matches!(
+        compile("(exit {1})"),
+        Err(CompileError::InvalidForm("brace"))
+    ));
+    // brackets initialize arrays, the missing elements being zero
+    let program = compile("(global grid (array (array u8 3) 2) [[1 2] [3]])\n(exit 0)").unwrap();
+    let grid = program
+        .data
+        .iter()
+        .find(|data| data.label.contains("grid"))
+        .unwrap();
+    assert_eq!(grid.values, [1, 2, 0, 3, 0, 0]);
+}
+
+#[test]
+fn const_evaluation() {
+    use crate::{
+        compiler::{CompileError, Compiler},
+        const_eval::const_eval,
+        parser::parse,
+        typ::Type,
+    };
+    let mut compiler = Compiler::default();
+    // types are resolved in the current frame
+    compiler.push_frame("main".into());
+    compiler.consts.insert("N".into(), 8);
+    let eval =
+        |code: &str| const_eval(&compiler, &parse(code).unwrap()[0]).map_err(|err| err.value);
+    for (code, value) in [
+        ("7", 7),
+        ("N", 8),
+        ("(+ 1 2 3)", 6),
+        ("(- 5)", -5),
+        ("(- 10 N 1)", 1),
+        ("(* 3 N)", 24),
+        ("(/ 100 N 2)", 6),
+        ("(/ (- 7) 2)", -3),
+        ("(% 17 5)", 2),
+        ("(% (- 17) 5)", -2),
+        ("(< 1 2)", 1),
+        ("(>= 1 2)", 0),
+        ("(= N 8)", 1),
+        ("(!= N 8)", 0),
+        ("(sizeof i64)", 8),
+        ("(sizeof (array i16 N))", 16),
+        ("9223372036854775807", i64::MAX),
+    ] {
+        assert_eq!(eval(code), Ok(value), "{code}");
+    }
+    let min = "(- (- 9223372036854775807) 1)";
+    for (code, err) in [
+        (
+            "(* 9223372036854775807 2)".to_string(),
+            CompileError::Overflow,
+        ),
+        (
+            "(+ 9223372036854775807 1)".to_string(),
+            CompileError::Overflow,
+        ),
+        (format!("(- {min} 1)"), CompileError::Overflow),
+        (format!("(- {min})"), CompileError::Overflow),
+        (format!("(/ {min} (- 1))"), CompileError::Overflow),
+        (format!("(% {min} (- 1))"), CompileError::Overflow),
+        (
+            "9223372036854775808".to_string(),
+            CompileError::OutOfRange(1 << 63),
+        ),
+        ("(/ 1 0)".to_string(), CompileError::DivisionByZero),
+        ("(/ 8 2 (- N 8))".to_string(), CompileError::DivisionByZero),
+        ("(% 1 (- N N))".to_string(), CompileError::DivisionByZero),
+        ("x".to_string(), CompileError::NotConstant),
+        ("(f 1)".to_string(), CompileError::NotConstant),
+        ("\"s\"".to_string(), CompileError::NotConstant),
+        ("(< 1)".to_string(), CompileError::ExpectedArgs(2)),
+        ("(+)".to_string(), CompileError::ExpectedArgs(1)),
+        ("(sizeof)".to_string(), CompileError::ExpectedArgs(1)),
+        (
+            "(sizeof none)".to_string(),
+            CompileError::UnknownSize(Type::None),
+        ),
+    ] {
+        assert_eq!(eval(&code), Err(err), "{code}");
+    }
+    // the error points at the operation which failed
+    let err = const_eval(
+        &compiler,
+        &parse("(+ 1\n  (* N 9223372036854775807))").unwrap()[0],
+    );
+    assert_eq!(err.map_err(|err| (err.pos.ln, err.pos.col)), Err((1, 2)));
+}
+
+#[test]
+fn typed_literals() {
+    use crate::{
+        parser::{parse, ParseErrorKind, SExpr},
+        typ::{FloatType, IntType, Type},
+    };
+    let values = |code| {
+        parse(code)
+            .unwrap()
+            .into_iter()
+            .map(|sexpr| sexpr.value)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        values("10i64 255u8 7 3.0f64 2f32 1.5"),
+        [
+            SExpr::Int(10, Some(Type::Int(IntType::S64))),
+            SExpr::Int(255, Some(Type::UInt(IntType::S8))),
+            SExpr::Int(7, None),
+            SExpr::Float(3.0, Some(Type::Float(FloatType::S64))),
+            SExpr::Float(2.0, Some(Type::Float(FloatType::S32))),
+            SExpr::Float(1.5, None),
+        ]
+    );
+    assert_eq!(
+        parse("(+ 255u8 3.0f64)").unwrap()[0].to_string(),
+        "(+ 255u8 3.0f64)"
+    );
+    for (code, suffix) in [("1u7", "u7"), ("3.0u8", "u8"), ("2x", "x")] {
+        assert_eq!(
+            parse(code).unwrap_err().kind,
+            ParseErrorKind::InvalidSuffix(suffix.to_string()),
+            "{code}"
+        );
+    }
+}
+
+#[test]
+fn digit_separators() {
+    use crate::{
+        parser::{parse, ParseErrorKind, SExpr},
+        typ::{IntType, Type},
+    };
+    let values = |code| {
+        parse(code)
+            .unwrap()
+            .into_iter()
+            .map(|sexpr| sexpr.value)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        values("1_000_000 0xFF_FF 0b1010_1010u8 1_000.000_1 1e1_0"),
+        [
+            SExpr::Int(1_000_000, None),
+            SExpr::Int(0xFFFF, None),
+            SExpr::Int(0b1010_1010, Some(Type::UInt(IntType::S8))),
+            SExpr::Float(1_000.000_1, None),
+            SExpr::Float(1e10, None),
+        ]
+    );
+    for code in ["1__0", "1_", "0x_FF", "1._5", "1_000_u32"] {
+        assert_eq!(
+            parse(code).unwrap_err().kind,
+            ParseErrorKind::MisplacedSeparator,
+            "{code}"
+        );
+    }
+}
+
+#[test]
+fn float_literals() {
+    use crate::{
+        code::{DataType, Program},
+        compiler::compile_program,
+        parser::{parse, ParseErrorKind, SExpr},
+        typ::{FloatType, Type},
+    };
+    let values = |code| {
+        parse(code)
+            .unwrap()
+            .into_iter()
+            .map(|sexpr| sexpr.value)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        values("1e9 2.5e-3 1E+2f32 inf"),
+        [
+            SExpr::Float(1e9, None),
+            SExpr::Float(2.5e-3, None),
+            SExpr::Float(100.0, Some(Type::Float(FloatType::S32))),
+            SExpr::Float(f64::INFINITY, None),
+        ]
+    );
+    assert!(matches!(values("nan")[..], [SExpr::Float(nan, None)] if nan.is_nan()));
+    assert_eq!(parse("(f nan inf)").unwrap()[0].to_string(), "(f nan inf)");
+    assert_eq!(
+        parse("2e").unwrap_err().kind,
+        ParseErrorKind::InvalidSuffix("e".to_string())
+    );
+    let program = compile_program(
+        parse("(defn a () f32 1.5)\n(defn b () f64 2.5e-3)\n(defn c () f64 inf)\n(a) (b) (c)")
+            .unwrap(),
+    )
+    .unwrap();
+    let floats = |program: &Program| {
+        program
+            .functions
+            .iter()
+            .flat_map(|function| function.floats.clone())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        floats(&program),
+        [
+            (DataType::DoubleWord, 0x3FC0_0000),
+            (DataType::QuadWord, 2.5e-3f64.to_bits()),
+            (DataType::QuadWord, 0x7FF0_0000_0000_0000),
+        ]
+    );
+    assert!(program.to_string().contains("a_f0 dd 0x3fc00000"));
+    // the constants survive being written out and read back, like the cache does
+    let reparsed: Program = program.to_string().parse().unwrap();
+    assert_eq!(floats(&reparsed), floats(&program));
+}
+
+#[test]
+fn wide_integer_literals() {
+    use crate::{
+        code::{Destination, Instruction, Source},
+        compiler::{compile_program, CompileError},
+        parser::{parse, SExpr},
+        typ::{IntType, Type},
+    };
+    assert_eq!(
+        parse("18446744073709551615u64").unwrap()[0].value,
+        SExpr::Int(u64::MAX.into(), Some(Type::UInt(IntType::S64)))
+    );
+    let program = compile_program(
+        parse("(defn big () u64 4294967296u64)\n(defn max () u64 18446744073709551615u64)\n(big)\n(max)")
+            .unwrap(),
+    )
+    .unwrap();
+    let loads: Vec<(String, Source)> = program
+        .functions
+        .iter()
+        .filter(|function| function.name != "main")
+        .flat_map(|function| &function.body)
+        .filter_map(|instr| match instr {
+            Instruction::Mov {
+                dest: Destination::Register(register),
+                src: src @ Source::Int(_),
+            } => Some((register.to_string(), src.clone())),
+            _ => None,
+        })
+        .collect();
+    // 64-bit values are returned in `edx:eax` like cdecl does
+    let half = |register: &str, value| (register.to_string(), Source::Int(value));
+    assert_eq!(
+        loads,
+        [
+            half("edx", 1),
+            half("eax", 0),
+            half("edx", -1),
+            half("eax", -1)
+        ]
+    );
+    let err = |code| compile_program(parse(code).unwrap()).unwrap_err().value;
+    assert_eq!(
+        err("(defn f () u32 4294967296)"),
+        CompileError::OutOfRange(1 << 32)
+    );
+    assert_eq!(err("(+ 2147483648 0)"), CompileError::OutOfRange(1 << 31));
+    assert_eq!(
+        err("(const BIG 9223372036854775808)"),
+        CompileError::OutOfRange(1 << 63)
+    );
+}
+
+#[test]
+fn static_assertions() {
+    use crate::{
+        compiler::{compile_program, CompileError},
+        parser::parse,
+    };
+    let code = "(const SIZE 8)\n(static-assert (<= (sizeof i64) SIZE) \"too big\")\n(defn f () i32 (static-assert 1) 0)";
+    assert!(compile_program(parse(code).unwrap()).is_ok());
+    let err = |code| compile_program(parse(code).unwrap()).unwrap_err();
+    let failed = err("(static-assert (<= (sizeof (array i32 32)) 64) \"header too big\")");
+    assert_eq!(
+        failed.value,
+        CompileError::StaticAssert("header too big".to_string())
+    );
+    assert_eq!((failed.pos.ln, failed.pos.col), (0, 15));
+    assert_eq!(
+        err("(static-assert (= 1 2))").value,
+        CompileError::StaticAssert("(= 1 2)".to_string())
+    );
+    assert_eq!(
+        err("(let x 1)\n(static-assert x)").value,
+        CompileError::NotConstant
+    );
+}
+
+#[test]
+fn asm_source_comments() {
+    use crate::{compiler::Compiler, options::CompileOptions, parser::parse};
+    let code = "(defn double ((x i32)) i32\n  (+ x x))\n(exit (double 3))\n";
+    let mut compiler = Compiler {
+        options: CompileOptions {
+            asm_source: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    compiler.compile_program(parse(code).unwrap()).unwrap();
+    let mut program = compiler.program;
+    program.interleave_source(&[code.to_string()]);
+    let text = program.to_string();
+    let above = |comment: &str, instr: &str| {
+        let at = text.find(comment).unwrap_or_else(|| panic!("{text}"));
+        assert!(text[at..].lines().nth(1) == Some(instr), "{text}");
+    };
+    above("; 2: (+ x x))", "\tmov eax, DWORD PTR [ebp+8]");
+    above("; 3: (exit (double 3))", "\tmov eax, 3");
+    // the `defn` line generates nothing in `main`
+    assert!(!text.contains("; 1:"), "{text}");
+    assert!(!text.contains("%line"), "{text}");
+}
+
+#[test]
+fn local_offsets() {
+    use crate::{code::Program, compiler::Compiler, options::CompileOptions, parser::parse};
+    let code = "(defn add ((a i32) (b i32)) i32\n  (let sum (+ a b))\n  sum)\n(let x-y (add 1 2))\n(block (let x-y 5) x-y)\n";
+    let mut compiler = Compiler {
+        options: CompileOptions {
+            debug_info: true,
+            local_offsets: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    compiler.program.sources = vec!["a.lerp".to_string()];
+    compiler.compile_program(parse(code).unwrap()).unwrap();
+    let text = compiler.program.to_string();
+    assert!(text.contains("%line 2+0 a.lerp"), "{text}");
+    // parameters are above the saved base pointer and the return address, lets below it, and
+    // a shadowing binding gets a label of its own
+    for local in [
+        "_Ladd_v0_La equ 8",
+        "_Ladd_v1_Lb equ 12",
+        "_Ladd_v2_Lsum equ -4",
+        "main_v0_Lx_2dy equ -4",
+        "main_v1_Lx_2dy equ -8",
+    ] {
+        assert!(text.lines().any(|line| line == local), "{local}\n{text}");
+    }
+    let reparsed: Program = text.parse().unwrap();
+    for (function, reparsed) in compiler.program.functions.iter().zip(&reparsed.functions) {
+        assert_eq!(function.local_offsets, reparsed.local_offsets);
+    }
+    // `-g` alone only maps lines
+    let mut compiler = Compiler {
+        options: CompileOptions {
+            debug_info: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    compiler.program.sources = vec!["a.lerp".to_string()];
+    compiler.compile_program(parse(code).unwrap()).unwrap();
+    let text = compiler.program.to_string();
+    assert!(
+        text.contains("%line 2+0 a.lerp") && !text.contains(" equ "),
+        "{text}"
+    );
+}
+
+#[test]
+fn check_only_stops_after_type_checking() {
+    use crate::{
+        code::Instruction,
+        compiler::{CompileError, CompileWarning, Compiler},
+        options::{CompileOptions, OptLevel},
+        parser::parse,
+    };
+    let compiler = || Compiler {
+        options: CompileOptions {
+            check_only: true,
+            opt_level: OptLevel::O2,
+            max_stack: Some(0),
+            no_recursion: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let check = |code| {
+        let mut compiler = compiler();
+        compiler
+            .compile_program(parse(code).unwrap())
+            .map(|_| compiler.pass_runs.len())
+            .map_err(|err| err.value)
+    };
+    // neither the passes nor the stack limit run
+    assert_eq!(check("(defn f () i32 1)\n(exit (f))"), Ok(0));
+    // the runtime functions and overflow checks are left out, the recursion is still found
+    let mut checked = Compiler {
+        bump_allocator: true,
+        options: CompileOptions {
+            checked_arithmetic: true,
+            ..compiler().options
+        },
+        ..compiler()
+    };
+    checked
+        .compile_program(
+            parse("(defn f () i32 (f))\n(str-cat \"a\" \"b\")\n(exit (+ (f) 1))").unwrap(),
+        )
+        .unwrap();
+    let names: Vec<_> = checked
+        .program
+        .functions
+        .iter()
+        .map(|function| function.name.as_str())
+        .collect();
+    assert_eq!(names, ["_Lf", "main"]);
+    assert!(checked.program.functions.iter().all(|function| !function
+        .body
+        .iter()
+        .any(|instr| matches!(instr, Instruction::JOp { .. }))));
+    assert!(checked
+        .warnings
+        .iter()
+        .any(|warning| warning.value == CompileWarning::Recursion("f".into())));
+    assert!(matches!(
+        check("(exit (+ 1 \"a\"))"),
+        Err(CompileError::InvalidTypeExpected { .. })
+    ));
+}
+
+#[test]
+fn qbe_output() {
+    use crate::{compiler::CompileError, options::CompileOptions, parser::parse, qbe::Qbe};
+    let emit = |code| Qbe::emit_program(parse(code).unwrap(), &CompileOptions::default());
+    let code = r#"
+        (extern printf)
+        (const N 40)
+        (defn add ((a i64) (b i64)) i64 (+ a b))
+        (defn at ((s *u8) (i i32)) *u8 (+ s i))
+        (println (add 5000000000i64 1i64) (/ N 3u32))
+        (printf "%s\n" (at "ab" 1))
+    "#;
+    assert_eq!(
+        emit(code).unwrap(),
+        "data $lerp_str0 = { b \"%lld %u\", b 10, b 0 }\n\
+         data $lerp_str1 = { b \"%s\", b 10, b 0 }\n\
+         data $lerp_str2 = { b \"ab\", b 0 }\n\
+         export function w $main() {\n\
+         @start\n\
+         \t%t0 =l call $_Ladd(l 5000000000, l 1)\n\
+         \t%t1 =w udiv 40, 3\n\
+         \tcall $printf(l $lerp_str0, ..., l %t0, w %t1)\n\
+         \t%t2 =l call $_Lat(l $lerp_str2, w 1)\n\
+         \tcall $printf(l $lerp_str1, ..., l %t2)\n\
+         \tret 0\n\
+         }\n\
+         function l $_Ladd(l %a0, l %a1) {\n\
+         @start\n\
+         \t%t0 =l add %a0, %a1\n\
+         \tret %t0\n\
+         }\n\
+         function l $_Lat(l %a0, w %a1) {\n\
+         @start\n\
+         \t%t0 =l extsw %a1\n\
+         \t%t1 =l add %a0, %t0\n\
+         \tret %t1\n\
+         }\n"
+    );
+    assert_eq!(
+        emit("(loop ((i 0)) (break))").unwrap_err().value,
+        CompileError::Unsupported("`loop` in QBE output")
+    );
+}
+
+#[test]
+fn lints() {
+    use crate::{
+        compiler::{CompileError, CompileWarning, Compiler},
+        options::{CompileOptions, LintLevel},
+        parser::parse,
+    };
+    let code = r#"
+        (extern printf puts)
+        (const BIG 300)
+        (defn fill ((p *u8) (n i32) (c i32)) i32
+          (let n 1)
+          (mem-set p BIG 4)
+          (mem-set p c 4)
+          (mem-set p 7 4)
+          (case BIG (300 n) (else 0)))
+        (defn nothing () none)
+        (defn quiet ((n i32)) i32
+          (allow shadowed-binding empty-body)
+          (let n 2)
+          (block)
+          n)
+        (printf "%d\n" (+ (fill "abcd" 0 0) (quiet 0)))
+    "#;
+    let lint = |lints: &[(&str, LintLevel)]| {
+        let mut compiler = Compiler {
+            options: CompileOptions {
+                lints: lints
+                    .iter()
+                    .map(|(name, level)| (name.to_string(), *level))
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        compiler
+            .compile_program(parse(code).unwrap())
+            .map(|_| {
+                compiler
+                    .warnings
+                    .iter()
+                    .filter_map(|warning| match &warning.value {
+                        CompileWarning::Lint { lint, .. } => Some((*lint, warning.pos.ln)),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .map_err(|err| (err.value.code(), err.pos.ln))
+    };
+    assert_eq!(
+        lint(&[]),
+        Ok(vec![
+            ("unused-extern", 1),
+            ("shadowed-binding", 4),
+            ("integer-truncation", 5),
+            ("integer-truncation", 6),
+            ("constant-condition", 8),
+            ("empty-body", 9),
+        ])
+    );
+    assert_eq!(
+        lint(&[
+            ("unused-extern", LintLevel::Allow),
+            ("integer-truncation", LintLevel::Allow),
+            ("constant-condition", LintLevel::Deny),
+        ]),
+        Err(("constant-condition", 8))
+    );
+    let err = |code| {
+        let mut compiler = Compiler::default();
+        compiler
+            .compile_program(parse(code).unwrap())
+            .unwrap_err()
+            .value
+    };
+    assert_eq!(
+        err("(allow unused-variable)"),
+        CompileError::UnknownLint("unused-variable".to_string())
+    );
+}
+
+#[test]
+fn asm_diff_between_programs() {
+    use crate::{
+        code::Function,
+        testing::{asm_diff, compile_program, normalize_labels},
+    };
+    let function = |text: &str| text.parse::<Function>().unwrap();
+    // only the numbers of the labels differ
+    let a = function("f:\n.loop3:\n\tjmp .loop3_end\n\tjmp .loop3\n.loop3_end:\n\tret\n");
+    let b = function("f:\n.loop0:\n\tjmp .loop0_end\n\tjmp .loop0\n.loop0_end:\n\tret\n");
+    assert_ne!(a, b);
+    assert_eq!(normalize_labels(&a), normalize_labels(&b));
+    assert!(normalize_labels(&a).to_string().contains("\tjmp .L1\n"));
+
+    let old = compile_program("(defn f () i32 1)\n(defn g () i32 2)\n(exit (+ (f) (g)))").unwrap();
+    let new = compile_program("(defn f () i32 3)\n(defn h () i32 2)\n(exit (+ (f) (h)))").unwrap();
+    assert_eq!(asm_diff(&old, &old), "");
+    let diff = asm_diff(&old, &new);
+    assert!(diff.contains("--- _Lf\n"), "{diff}");
+    assert!(diff.contains("-\tmov eax, 1\n") && diff.contains("+\tmov eax, 3\n"));
+    assert!(diff.contains("--- _Lg (removed)\n-_Lg:\n"), "{diff}");
+    assert!(diff.contains("--- _Lh (added)\n+_Lh:\n"), "{diff}");
+}
+
+#[test]
+fn compile_errors_instead_of_panics() {
+    use crate::{compiler::CompileError, parser::parse};
+    let mut compiler = crate::compiler::Compiler::default();
+    let sexpr = parse("(+ 1 2)").unwrap().remove(0);
+    assert_eq!(
+        compiler.compile(sexpr).unwrap_err().value,
+        CompileError::Internal("no function to compile into")
+    );
+    let err = compiler.compile_program(parse("(+ 1.5 2.5)").unwrap());
+    assert_eq!(
+        err.unwrap_err().value,
+        CompileError::Unsupported("float operands")
+    );
+}
+
+#[test]
+fn compile_file_errors() {
+    use crate::{compile_file, compile_str, CompileOptions, LerpError};
+    let options = CompileOptions::default();
+    assert!(compile_str("(+ 1 2)", options.clone()).is_ok());
+    // the warnings come back with the program, for `watch` to report
+    let compiled = compile_str("(let x 3) (let x 4) x", options.clone()).unwrap();
+    assert_eq!(compiled.warnings.len(), 1);
+    assert_eq!(compiled.warnings[0].value.code(), "shadowed-binding");
+    let commented = CompileOptions {
+        comments: true,
+        ..Default::default()
+    };
+    let text = compile_str("(+ 1 2)", commented)
+        .unwrap()
+        .program
+        .to_string();
+    assert!(text.contains("\t; (+ 1 2) at 1:1\n"), "{text}");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    assert!(compile_file(dir.join("printf.lp"), options.clone()).is_ok());
+    assert!(matches!(
+        compile_file(dir.join("missing.lp"), options.clone()),
+        Err(LerpError::Io { .. })
+    ));
+    assert!(matches!(
+        compile_file(dir.join("parse_errors.lp"), options.clone()),
+        Err(LerpError::Parse(errors)) if errors.len() == 2
+    ));
+    let err: Box<dyn std::error::Error> = compile_file(dir.join("typo.lp"), options)
+        .unwrap_err()
+        .into();
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn pass_ordering() {
+    use crate::pass::{Pass, PassError, PassManager};
+    let pass = |name, after| Pass {
+        name,
+        after,
+        run: |_| {},
+    };
+    let mut passes = PassManager::default();
+    passes.add(pass("c", &["b"]));
+    passes.add(pass("a", &["missing"]));
+    passes.add(pass("b", &[]));
+    assert_eq!(passes.order(), Ok(vec!["a", "b", "c"]));
+    passes.add(pass("d", &["e"]));
+    passes.add(pass("e", &["d"]));
+    assert_eq!(passes.order(), Err(PassError::Cycle(vec!["d", "e"])));
+    let mut program = crate::code::Program::default();
+    passes = crate::opt::passes(crate::options::OptLevel::O2);
+    passes.print_after("dce");
+    let runs = passes.run(&mut program).unwrap();
+    let names: Vec<_> = runs.iter().map(|run| run.name).collect();
+    assert_eq!(names, ["inline", "peephole", "dce"]);
+    assert!(runs[2].dump.is_some() && runs[1].dump.is_none());
+}
+
+#[test]
+fn liveness_of_registers() {
+    use crate::{
+        analysis::liveness,
+        code::{Function, RegisterName},
+    };
+    use std::collections::BTreeSet;
+    let function: Function = "f:
+        push ebp
+        mov ebp, esp
+        mov eax, DWORD PTR [ebp+8]
+        mov ecx, 3
+        cmp eax, 0
+        je .zero
+        add eax, ecx
+        jmp .done
+    .zero:
+        mov eax, ecx
+    .done:
+        leave
+        ret"
+    .parse()
+    .unwrap();
+    let info = liveness(&function);
+    let ranges: Vec<_> = info
+        .cfg
+        .blocks
+        .iter()
+        .map(|block| (block.start, block.end, block.successors.clone()))
+        .collect();
+    assert_eq!(
+        ranges,
+        [
+            (0, 6, vec![2, 1]),
+            (6, 8, vec![3]),
+            (8, 10, vec![3]),
+            (10, 13, vec![])
+        ]
+    );
+    assert_eq!(info.cfg.blocks[3].predecessors, [1, 2]);
+    let set = |names: &[RegisterName]| names.iter().copied().collect::<BTreeSet<_>>();
+    use RegisterName::{A, B, BP, C, DI, SI, SP};
+    assert_eq!(info.live_out[0], set(&[A, C, B, BP, SI, DI]));
+    assert_eq!(info.live_in[2], set(&[C, B, BP, SI, DI]));
+    // the stack pointer is only live until the frame is set up, as `leave` resets it
+    assert_eq!(info.live_in[0], set(&[B, SP, BP, SI, DI]));
+    let last_uses: Vec<_> = info
+        .last_uses
+        .iter()
+        .filter(|(_, registers)| registers.contains(&C))
+        .map(|(idx, registers)| (*idx, registers.clone()))
+        .collect();
+    assert_eq!(last_uses, [(6, set(&[A, C])), (9, set(&[C]))]);
+    assert_eq!(
+        info.live_after(&function.body, 3),
+        set(&[A, C, B, BP, SI, DI])
+    );
+}
+
+#[test]
+fn dominators_and_loops() {
+    use crate::{
+        analysis::{natural_loops, Cfg, DomTree},
+        code::Function,
+    };
+    use std::collections::BTreeSet;
+    let function: Function = "f:
+        push ebp
+        mov ecx, 3
+    .outer:
+        mov edx, 2
+    .inner:
+        sub edx, 1
+        cmp edx, 0
+        jne .inner
+        sub ecx, 1
+        cmp ecx, 0
+        jne .outer
+        pop ebp
+        ret
+    .dead:
+        jmp .inner"
+        .parse()
+        .unwrap();
+    let cfg = Cfg::build(&function);
+    let dom = DomTree::compute(&cfg);
+    let idoms: Vec<_> = (0..cfg.blocks.len()).map(|block| dom.idom(block)).collect();
+    assert_eq!(idoms, [None, Some(0), Some(1), Some(2), Some(3), None]);
+    assert!(!dom.reachable(5));
+    assert!(dom.dominates(1, 4) && dom.dominates(2, 2));
+    assert!(!dom.dominates(3, 2) && !dom.dominates(5, 2));
+    assert_eq!(dom.children(1), [2]);
+    let loops = natural_loops(&cfg, &dom);
+    let found: Vec<_> = loops
+        .iter()
+        .map(|found| (found.header, found.latches.clone(), found.blocks.clone()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (1, vec![3], BTreeSet::from([1, 2, 3])),
+            (2, vec![2], BTreeSet::from([2]))
+        ]
+    );
+    assert!(loops[0].contains(&loops[1]));
+    assert_eq!(loops[0].exits(&cfg), BTreeSet::from([4]));
+    assert_eq!(loops[1].exits(&cfg), BTreeSet::from([3]));
+}
+
+#[test]
+fn function_stats() {
+    use crate::{
+        code::Function,
+        stats::{FunctionStats, Stats},
+    };
+    let function: Function = "f:
+        push ebp
+        mov ebp, esp
+        push DWORD PTR [ebp+8]
+        cmp eax, 0
+        je .skip
+        push eax
+        push eax
+        call g
+        add esp, 4
+        pop eax
+    .skip:
+        call h
+        pop eax
+        leave
+        ret"
+    .parse()
+    .unwrap();
+    let stats = FunctionStats::of(&function);
+    assert_eq!(
+        stats,
+        FunctionStats {
+            name: "f".to_string(),
+            instructions: 14,
+            frame: 16,
+            calls: 2,
+            spills: 2,
+        }
+    );
+    let table = Stats {
+        functions: vec![stats],
+    }
+    .to_string();
+    assert_eq!(table.lines().count(), 3);
+    assert!(table.ends_with("total          14     16      2      2"));
+}
+
+#[test]
+fn stack_usage_per_entry() {
+    use crate::{
+        code::Program,
+        compile_str,
+        compiler::CompileError,
+        options::CompileOptions,
+        stats::{stack_usage, StackUsage},
+        LerpError,
+    };
+    let program: Program = "extern printf
+global main
+global h
+section .text
+main:
+    push ebp
+    mov ebp, esp
+    push 1
+    call f
+    leave
+    ret
+f:
+    push ebp
+    mov ebp, esp
+    sub esp, 8
+    call g
+    leave
+    ret
+g:
+    push ebp
+    mov ebp, esp
+    call printf
+    leave
+    ret
+h:
+    push ebp
+    mov ebp, esp
+    call h
+    leave
+    ret
+"
+    .parse()
+    .unwrap();
+    // every call takes its return address and the stack of the callee below what is pushed
+    assert_eq!(
+        stack_usage(&program),
+        [
+            StackUsage {
+                entry: "main".to_string(),
+                bytes: 36,
+                recursive: vec![],
+            },
+            StackUsage {
+                entry: "h".to_string(),
+                bytes: 8,
+                recursive: vec!["h".to_string()],
+            },
+        ]
+    );
+
+    let err = |code, max_stack| {
+        let options = CompileOptions {
+            max_stack: Some(max_stack),
+            ..Default::default()
+        };
+        match compile_str(code, options) {
+            Err(LerpError::Compile(err)) => Some(err.value),
+            Ok(_) => None,
+            result => panic!("{result:?}"),
+        }
+    };
+    let code = "(defn f ((n i32)) i32 (+ n 1))\n(f 1)";
+    assert_eq!(err(code, 1024), None);
+    assert!(matches!(
+        err(code, 8),
+        Some(CompileError::StackTooDeep { entry, limit: 8, .. }) if entry == "main"
+    ));
+    let code = "(export f)\n(defn f ((n i32)) i32 (case n (0 0) (else (f (- n 1)))))";
+    assert_eq!(
+        err(code, 1024),
+        Some(CompileError::UnboundedStack {
+            entry: "f".to_string(),
+            recursive: "f".to_string(),
+        })
+    );
+}
+
+#[test]
+fn compile_records_timings() {
+    let mut compiler = crate::compiler::Compiler::default();
+    compiler
+        .compile_program(crate::parser::parse("(+ 1 2)").unwrap())
+        .unwrap();
+    let stages: Vec<_> = compiler
+        .timings
+        .stages
+        .iter()
+        .map(|stage| stage.name)
+        .collect();
+    assert_eq!(stages, ["expand", "lower", "optimize"]);
+    assert!(compiler.timings.to_string().starts_with("stage"));
+}
+
+#[cfg(feature = "arena")]
+#[test]
+fn arena_matches_parser() {
+    use crate::{arena, parser};
+    let same = |code: &str| {
+        let ast = arena::parse(code).map(|ast| ast.to_sexprs(&ast.roots));
+        assert_eq!(ast, parser::parse(code), "{code}");
+    };
+    same("(defn f ((a i32)) i32 [a {:key 1.5f32 \"s\" b\"x\"}] 'x `(y ,z) 7u8)\n(f 1)");
+    same("");
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    for entry in fs::read_dir(dir).unwrap() {
+        let case = entry.unwrap().path();
+        if case.extension().is_some_and(|ext| ext == "lp") {
+            same(&fs::read_to_string(&case).unwrap());
+        }
+    }
+    // errors are reported at the same position
+    for code in ["(a [b)", "(a", "a)", "(a '"] {
+        same(code);
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_compilation_matches_sequential() {
+    use crate::{compile_str, options::CompileOptions};
+    let mut code = String::from(
+        "(extern printf)\n(defn id ((T)) ((x T)) T x)\n(const K 3)\n(global g i32 1)\n",
+    );
+    for idx in 0..32 {
+        code += &format!(
+            "(defn f{idx} ((a i32)) i32 (+ (id a) (+ K {idx})))\n\
+             (defn s{idx} () *u8 (str-cat \"a\" (format \"{{}}\" {idx})))\n\
+             (printf \"%d %s\\n\" (f{idx} g) (s{idx}))\n"
+        );
+    }
+    let compile = |code: &str, jobs| {
+        let options = CompileOptions {
+            jobs,
+            ..Default::default()
+        };
+        compile_str(code, options).map(|compiled| compiled.program.to_string())
+    };
+    let sequential = compile(&code, 1).unwrap();
+    assert_eq!(compile(&code, 4).unwrap(), sequential);
+    // the first error in the program is reported, whichever thread finds it
+    let code = code.replace(
+        "(defn f3 ((a i32)) i32 (+ (id a)",
+        "(defn f3 ((a i32)) i32 (+ (idd a)",
+    ) + "(undefined)";
+    let err = compile(&code, 4).unwrap_err().to_string();
+    assert_eq!(err, compile(&code, 1).unwrap_err().to_string());
+    assert!(err.contains("\"idd\" not found"), "{err}");
+}
+
+#[test]
+fn cache_reuses_unchanged_functions() {
+    use crate::{compile_str, options::CompileOptions};
+    let dir = env::temp_dir().join(format!("lerp-cache-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let options = CompileOptions {
+        cache: Some(dir.clone()),
+        ..Default::default()
+    };
+    let compile = |code: &str| {
+        compile_str(code, options.clone())
+            .unwrap()
+            .program
+            .to_string()
+    };
+    let code = "(defn a () i32 1)\n(defn b () i32 2)\n(println (a) (b))\n";
+    let fresh = compile(code);
+    assert_eq!(
+        fresh,
+        compile_str(code, CompileOptions::default())
+            .unwrap()
+            .program
+            .to_string()
+    );
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+    // tamper with the cached functions to see whether they are reused
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        let text = fs::read_to_string(&path).unwrap();
+        let text = text.replace("mov eax, 1", "mov eax, 11");
+        fs::write(&path, text.replace("mov eax, 2", "mov eax, 22")).unwrap();
+    }
+    let tampered = compile(code);
+    assert!(tampered.contains("mov eax, 11\n") && tampered.contains("mov eax, 22\n"));
+    let changed = compile(&code.replace("i32 2", "i32 3"));
+    assert!(changed.contains("mov eax, 11\n") && changed.contains("mov eax, 3\n"));
+    // changing a signature changes what every later function is compiled against
+    let changed = compile(
+        &code
+            .replace("(defn a ()", "(defn a ((x i32))")
+            .replace("(a)", "(a 0)"),
+    );
+    assert!(changed.contains("mov eax, 1\n") && changed.contains("mov eax, 2\n"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn watcher_notices_changes() {
+    use crate::watch::Watcher;
+    let dir = env::temp_dir().join(format!("lerp-watch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("a.lp");
+    fs::write(&path, "(println 1)").unwrap();
+    let mut watcher = Watcher::new(&path).unwrap();
+    let writer = std::thread::spawn({
+        let path = path.clone();
+        move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            fs::write(dir.join("b.lp"), "unrelated").unwrap();
+            fs::write(&path, "(println 2)").unwrap();
+        }
+    });
+    watcher.wait().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "(println 2)");
+    writer.join().unwrap();
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn duplicate_definitions_across_files() {
+    use crate::{
+        compiler::{CompileError, Compiler},
+        parser::{parse_file, FileId},
+    };
+    let files = [
+        "(defn f () i32 1)\n",
+        "(println (f))\n",
+        "\n(defn f () i32 2)",
+    ];
+    let mut program = vec![];
+    for (file, code) in files.iter().enumerate() {
+        let (sexprs, errors) = parse_file(code, FileId(file as u32));
+        assert!(errors.is_empty());
+        program.extend(sexprs);
+    }
+    assert!(Compiler::default()
+        .compile_program(program[..2].to_vec())
+        .is_ok());
+    let err = Compiler::default().compile_program(program).unwrap_err();
+    assert_eq!(err.value, CompileError::Redefined("f".into()));
+    assert_eq!((err.pos.file, err.pos.ln), (FileId(2), 1));
+}
+
+#[test]
+fn parse_manifest() {
+    use crate::{
+        manifest::{Manifest, ManifestError},
+        options::OptLevel,
+    };
+    let manifest: Manifest = "[package]\nname = \"game\" # comment\nentry = \"src/main.lp\"\n\n\
+                              [build]\ntarget = \"x86\"\nopt-level = 2\nlibraries = [\"m\", \"raylib\"]\n"
+        .parse()
+        .unwrap();
+    assert_eq!(manifest.name, "game");
+    assert_eq!(manifest.entry, Path::new("src/main.lp"));
+    assert_eq!(manifest.options().opt_level, OptLevel::O2);
+    assert_eq!(manifest.libraries, ["m", "raylib"]);
+    let parse = |text: &str| text.parse::<Manifest>().unwrap_err();
+    assert_eq!(parse("[package]\n"), ManifestError::Missing("package.name"));
+    assert_eq!(
+        parse("[build]\nopt = 2"),
+        ManifestError::UnknownKey {
+            line: 1,
+            key: "build.opt".into()
+        }
+    );
+    assert_eq!(
+        parse("[build]\nopt-level = 3"),
+        ManifestError::InvalidValue {
+            line: 1,
+            key: "opt-level".into()
+        }
+    );
+    assert_eq!(parse("name"), ManifestError::Syntax(0));
+}
+
+#[test]
+fn bindgen_typed_externs() {
+    use crate::{bindgen::bindgen, compile_str, CompileOptions};
+    let header = r#"
+        #ifndef FOO_H
+        #define FOO_H \
+            1
+        #ifdef __cplusplus
+        extern "C" {
+        #endif
+        /* a comment with a prototype: int commented(void); */
+        typedef struct foo Foo;
+        struct point { int x, y; };
+        extern int abs(int __x) __attribute__((__const__));
+        const char *foo_name(const Foo *foo, unsigned long len); // trailing comment
+        void foo_free(Foo *);
+        int printf(const char *format, ...);
+        static inline int twice(int x) { return 2 * x; }
+        double sqrt(double x);
+        struct point origin(void);
+        #ifdef __cplusplus
+        }
+        #endif
+        #endif
+    "#;
+    let bindings = bindgen(header);
+    assert_eq!(
+        bindings.to_string(),
+        "(extern (abs ((a0 i32)) i32))\n\
+         (extern (foo_name ((foo *u8) (len u32)) *u8))\n\
+         (extern (foo_free ((a0 *u8)) none))\n\
+         (extern (printf ((format *u8) ...) i32))\n"
+    );
+    let skipped: Vec<_> = bindings
+        .skipped
+        .iter()
+        .map(|skipped| skipped.declaration.as_str())
+        .collect();
+    assert_eq!(
+        skipped,
+        ["double sqrt ( double x )", "struct point origin ( void )"]
+    );
+    let code = bindings.to_string() + "(foo_free (alloc u8 4))\n(printf \"%d\\n\" (abs 5))";
+    assert!(compile_str(&code, CompileOptions::default()).is_ok());
+    let err = compile_str(
+        &(bindings.to_string() + "(abs \"5\")"),
+        CompileOptions::default(),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("expected i32, got u8[2]"), "{err}");
+}
+
+#[test]
+fn mangling_and_exports() {
+    use crate::{
+        compile_str,
+        compiler::{demangle, mangle, CompileError},
+        options::{CompileOptions, OptLevel},
+        LerpError,
+    };
+    for name in ["f", "str-cat", "a_2d", "a-d", "id(u8[2])", "λ"] {
+        assert_eq!(demangle(&mangle(name)).as_deref(), Some(name));
+    }
+    assert_ne!(mangle("a_2d"), mangle("a-d"));
+    let options = CompileOptions {
+        opt_level: OptLevel::O2,
+        ..Default::default()
+    };
+    // exported functions survive dead code elimination under their own name
+    let program = compile_str(
+        "(export api)\n(defn api () i32 1)\n(defn unused () i32 2)\n(println 0)",
+        options.clone(),
+    )
+    .unwrap()
+    .program;
+    let names: Vec<_> = program.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["api", "main"]);
+    assert!(program.to_string().contains("global main\nglobal api\n"));
+    let err = |code| match compile_str(code, options.clone()) {
+        Err(LerpError::Compile(err)) => err.value,
+        result => panic!("{result:?}"),
+    };
+    assert_eq!(
+        err("(defn f () i32 1)\n(export f)"),
+        CompileError::LateExport("f".into())
+    );
+    assert!(matches!(err("(export g)"), CompileError::NotFound { .. }));
+}
+
+#[test]
+fn json_diagnostics() {
+    use crate::{compiler::Compiler, diagnostic::Diagnostic, parser::parse};
+    let json = |code: &str| {
+        let mut compiler = Compiler::default();
+        let diagnostic = match parse(code) {
+            Err(err) => Diagnostic::parse_error("a.lerp".into(), code, &err),
+            Ok(ast) => match compiler.compile_program(ast) {
+                Err(err) => Diagnostic::compile_error("a.lerp".into(), code, &err),
+                Ok(_) => Diagnostic::compile_warning("a.lerp".into(), code, &compiler.warnings[0]),
+            },
+        };
+        diagnostic.json()
+    };
+    // a stray character spans just itself
+    assert_eq!(
+        json("(+ 1 2))"),
+        r#"{"file":"a.lerp","span":{"line":1,"column":8,"end_line":1,"end_column":9},"code":"unexpected-char","message":"unexpected ')'","severity":"error"}"#
+    );
+    // the span ends right after the closing parenthesis of the expression, even lines later
+    assert_eq!(
+        json("(let x 1)\n(+ x\n   (foo 1\n  2))"),
+        r#"{"file":"a.lerp","span":{"line":3,"column":4,"end_line":4,"end_column":5},"code":"not-found","message":"\"foo\" not found","severity":"error"}"#
+    );
+    assert_eq!(
+        json("(let x 1) (let x 2) x"),
+        r#"{"file":"a.lerp","span":{"line":1,"column":11,"end_line":1,"end_column":20},"code":"shadowed-binding","message":"\"x\" shadows another binding of the same name before it is used [shadowed-binding]","severity":"warning"}"#
+    );
+    // columns count characters, not bytes
+    assert_eq!(
+        json("(+ 1 \"é\")"),
+        r#"{"file":"a.lerp","span":{"line":1,"column":6,"end_line":1,"end_column":9},"code":"type-mismatch","message":"expected i32, got u8[3]","severity":"error"}"#
+    );
+}
@@ -0,0 +1,330 @@
+use crate::{
+    code::{
+        Address, ComparisonOperator, DataType, Destination, Instruction, Memory, Register,
+        RegisterName, RegisterSize, Source,
+    },
+    compiler::{CompileError, Compiler, Local},
+    intern::Symbol,
+    parser::{Located, Position, SExpr},
+    typ::{IntType, Type},
+    visit::{walk, SExprVisitor},
+};
+
+const EAX: Register = Register {
+    name: RegisterName::A,
+    size: RegisterSize::S32,
+};
+const ECX: Register = Register {
+    name: RegisterName::C,
+    size: RegisterSize::S32,
+};
+const EDX: Register = Register {
+    name: RegisterName::D,
+    size: RegisterSize::S32,
+};
+const EBP: Register = Register {
+    name: RegisterName::BP,
+    size: RegisterSize::S32,
+};
+const ESP: Register = Register {
+    name: RegisterName::SP,
+    size: RegisterSize::S32,
+};
+
+/// the type of the handles `spawn` returns, a `pthread_t`
+pub const THREAD_TYPE: Type = Type::UInt(IntType::Size);
+
+/// collects the words of an expression in the order they first appear
+#[derive(Default)]
+struct Words(Vec<Symbol>);
+impl SExprVisitor for Words {
+    fn visit(&mut self, sexpr: &Located<SExpr>) {
+        if let SExpr::Word(word) = &sexpr.value {
+            if !self.0.contains(word) {
+                self.0.push(*word);
+            }
+        }
+        walk(self, sexpr)
+    }
+}
+
+fn dword(address: Address) -> Memory {
+    Memory {
+        data_type: DataType::DoubleWord,
+        address,
+    }
+}
+/// the bytes a local of type `typ` takes in an environment, whole 32-bit words like arguments
+fn slot_size(typ: &Type) -> usize {
+    let size = RegisterSize::typ(typ).map_or(0, |size| size.bytes());
+    size.next_multiple_of(RegisterSize::S32.bytes())
+}
+
+impl Compiler {
+    /// `(spawn (lambda () body...))`: runs the body on a new thread and returns its handle
+    ///
+    /// the lambda is compiled into a thunk taking a pointer to a copy of the locals it uses,
+    /// which is allocated with `alloc` and freed by the thunk once it copied them
+    pub fn compile_spawn(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([lambda]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        let lambda_pos = lambda.pos;
+        let SExpr::Expr(lambda) = lambda.value else {
+            return Err(Located {
+                value: CompileError::InvalidForm("spawn"),
+                pos: lambda_pos,
+            });
+        };
+        let body = parse_lambda(lambda, lambda_pos)?;
+
+        // everything the body names which is a local here is captured by value
+        let mut words = Words::default();
+        words.visit_all(&body);
+        let captures: Vec<(Symbol, Local)> = words
+            .0
+            .into_iter()
+            .filter_map(|word| Some((word, self.use_local(word)?)))
+            .collect();
+        let size: usize = captures
+            .iter()
+            .map(|(_, local)| slot_size(&local.typ))
+            .sum();
+
+        let idx = self.new_labels();
+        let Some(enclosing) = self.frames.last() else {
+            return Err(Located {
+                value: CompileError::Internal("no function to compile into"),
+                pos,
+            });
+        };
+        let types = enclosing.types.clone();
+        let thunk = format!("{}_l{idx}", enclosing.function.name);
+        self.compile_thunk(thunk.clone(), types, &captures, size, body)?;
+
+        if captures.is_empty() {
+            self.write(Instruction::Mov {
+                dest: EAX.into(),
+                src: Source::Int(0),
+            });
+        } else {
+            self.write(Instruction::Push {
+                src: Source::Amount(size),
+            });
+            let alloc = self.alloc_function();
+            self.call(alloc, RegisterSize::S32.bytes());
+            let mut offset = 0;
+            for (_, local) in &captures {
+                let slot = slot_size(&local.typ);
+                for word in (0..slot).step_by(RegisterSize::S32.bytes()) {
+                    self.write(Instruction::Mov {
+                        dest: EDX.into(),
+                        src: Source::Memory(dword(Address::offset(
+                            EBP,
+                            local.offset + word as i32,
+                        ))),
+                    });
+                    self.write(Instruction::Mov {
+                        dest: Destination::Memory(dword(Address::offset(
+                            EAX,
+                            (offset + word) as i32,
+                        ))),
+                        src: Source::Register(EDX),
+                    });
+                }
+                offset += slot;
+            }
+        }
+        // pthread_create(&handle, NULL, thunk, environment), with the handle on the stack
+        self.write(Instruction::Push {
+            src: Source::Int(0),
+        });
+        self.write(Instruction::Mov {
+            dest: ECX.into(),
+            src: Source::Register(ESP),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Lea {
+            dest: EAX,
+            addr: Address::label(thunk),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        self.write(Instruction::Push {
+            src: Source::Int(0),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(ECX),
+        });
+        self.use_extern("pthread_create");
+        self.call("pthread_create", 4 * RegisterSize::S32.bytes());
+
+        let label = Symbol::intern(&format!("spawned{}", self.new_labels()));
+        self.write(Instruction::Cmp {
+            a: Source::Register(EAX),
+            b: Source::Int(0),
+        });
+        self.write(Instruction::JOp {
+            op: ComparisonOperator::Equal,
+            label,
+        });
+        let message = self.new_string(format!(
+            "couldn't spawn a thread at {}:{}",
+            pos.ln + 1,
+            pos.col + 1
+        ));
+        self.write(Instruction::Push {
+            src: Source::Name(message),
+        });
+        let panic = self.panic_function();
+        self.call(panic, RegisterSize::S32.bytes());
+        self.write(Instruction::Label(label));
+        self.write(Instruction::Pop { dest: EAX.into() });
+        Ok(THREAD_TYPE)
+    }
+    /// compiles the function a spawned thread starts in, which copies the `captures` out of
+    /// the environment of `size` bytes it's passed into locals of its own
+    fn compile_thunk(
+        &mut self,
+        name: String,
+        types: std::collections::HashMap<Symbol, Type>,
+        captures: &[(Symbol, Local)],
+        size: usize,
+        body: Vec<Located<SExpr>>,
+    ) -> Result<(), Located<CompileError>> {
+        self.push_frame(name);
+        let frame = self.frames.last_mut().expect("a frame was just pushed");
+        frame.types = types;
+        frame.function.return_type = Type::Pointer(Box::new(Type::UInt(IntType::S8)));
+        if size > 0 {
+            let mut offset = 0;
+            for (name, local) in captures {
+                self.declare_local(
+                    0,
+                    *name,
+                    Local {
+                        offset: offset as i32 - size as i32,
+                        ..local.clone()
+                    },
+                );
+                offset += slot_size(&local.typ);
+            }
+            self.write(Instruction::Sub {
+                dest: ESP.into(),
+                src: Source::Amount(size),
+            });
+            self.write(Instruction::Mov {
+                dest: ECX.into(),
+                src: Source::Memory(dword(Address::offset(
+                    EBP,
+                    2 * RegisterSize::S32.bytes() as i32,
+                ))),
+            });
+            for word in (0..size).step_by(RegisterSize::S32.bytes()) {
+                self.write(Instruction::Mov {
+                    dest: EDX.into(),
+                    src: Source::Memory(dword(Address::offset(ECX, word as i32))),
+                });
+                self.write(Instruction::Mov {
+                    dest: Destination::Memory(dword(Address::offset(
+                        EBP,
+                        word as i32 - size as i32,
+                    ))),
+                    src: Source::Register(EDX),
+                });
+            }
+            if !self.bump_allocator {
+                self.use_extern("free");
+                self.write(Instruction::Push {
+                    src: Source::Register(ECX),
+                });
+                self.call("free", RegisterSize::S32.bytes());
+            }
+        }
+        self.compile_body(body, None)?;
+        self.leave_scopes(0)?;
+        // the thread's result, which `join` ignores
+        self.write(Instruction::Mov {
+            dest: EAX.into(),
+            src: Source::Int(0),
+        });
+        self.pop_frame();
+        Ok(())
+    }
+    /// `(join t)`: waits for the thread `t` returned by `spawn` to finish
+    pub fn compile_join(
+        &mut self,
+        sexprs: Vec<Located<SExpr>>,
+        pos: Position,
+    ) -> Result<Type, Located<CompileError>> {
+        let Ok([thread]) = <[Located<SExpr>; 1]>::try_from(sexprs) else {
+            return Err(Located {
+                value: CompileError::ExpectedArgs(1),
+                pos,
+            });
+        };
+        let thread_pos = thread.pos;
+        let typ = self.compile(thread)?;
+        if typ != THREAD_TYPE {
+            return Err(Located {
+                value: CompileError::InvalidTypeExpected {
+                    expected: THREAD_TYPE,
+                    got: typ,
+                },
+                pos: thread_pos,
+            });
+        }
+        self.write(Instruction::Push {
+            src: Source::Int(0),
+        });
+        self.write(Instruction::Push {
+            src: Source::Register(EAX),
+        });
+        self.use_extern("pthread_join");
+        self.call("pthread_join", 2 * RegisterSize::S32.bytes());
+        Ok(Type::None)
+    }
+}
+
+/// the body of the `(lambda () body...)` made of `sexprs`
+fn parse_lambda(
+    mut sexprs: Vec<Located<SExpr>>,
+    pos: Position,
+) -> Result<Vec<Located<SExpr>>, Located<CompileError>> {
+    if !matches!(sexprs.first(), Some(Located { value: SExpr::Word(head), .. }) if head == "lambda")
+    {
+        return Err(Located {
+            value: CompileError::InvalidForm("spawn"),
+            pos,
+        });
+    }
+    sexprs.remove(0);
+    if sexprs.is_empty() {
+        return Err(Located {
+            value: CompileError::ExpectedArgs(1),
+            pos,
+        });
+    }
+    let params = sexprs.remove(0);
+    match params.value {
+        SExpr::Expr(params) if params.is_empty() => Ok(sexprs),
+        SExpr::Expr(_) => Err(Located {
+            value: CompileError::Unsupported("lambda parameters"),
+            pos: params.pos,
+        }),
+        _ => Err(Located {
+            value: CompileError::InvalidForm("lambda"),
+            pos: params.pos,
+        }),
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// the system allocator, counting allocations when installed as the `#[global_allocator]`
+pub struct CountingAllocator;
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+/// the number of allocations so far, always 0 if `CountingAllocator` isn't installed
+pub fn allocations() -> usize {
+    ALLOCATIONS.load(Ordering::Relaxed)
+}
+
+/// where a stage started, see `Timings::finish`
+#[derive(Debug, Clone, Copy)]
+pub struct StageStart {
+    instant: Instant,
+    allocations: usize,
+}
+impl StageStart {
+    pub fn now() -> Self {
+        Self {
+            instant: Instant::now(),
+            allocations: allocations(),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    pub name: &'static str,
+    pub elapsed: Duration,
+    pub allocations: usize,
+}
+/// how long every stage of a compilation took and how much it allocated
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    pub stages: Vec<Stage>,
+}
+impl Timings {
+    /// records the stage `name` as ending now
+    pub fn finish(&mut self, name: &'static str, start: StageStart) {
+        self.stages.push(Stage {
+            name,
+            elapsed: start.instant.elapsed(),
+            allocations: allocations() - start.allocations,
+        });
+    }
+    pub fn measure<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = StageStart::now();
+        let value = f();
+        self.finish(name, start);
+        value
+    }
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|stage| stage.elapsed).sum()
+    }
+}
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:<10} {:>12} {:>8}", "stage", "time", "allocs")?;
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "{:<10} {:>12} {:>8}",
+                stage.name,
+                format!("{:.3?}", stage.elapsed),
+                stage.allocations
+            )?;
+        }
+        write!(
+            f,
+            "{:<10} {:>12} {:>8}",
+            "total",
+            format!("{:.3?}", self.total()),
+            self.stages
+                .iter()
+                .map(|stage| stage.allocations)
+                .sum::<usize>()
+        )
+    }
+}
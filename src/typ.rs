@@ -1,6 +1,7 @@
 use std::{fmt::Display, str::FromStr};
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     #[default]
     None,
@@ -12,12 +13,116 @@ pub enum Type {
         typ: Box<Self>,
         size: Option<usize>,
     },
+    /// `(fn (params...) ret)`, with `...` after the parameters if it is variadic
+    Func {
+        params: Box<[Self]>,
+        /// whether any amount of arguments can follow the parameters, like `printf`
+        variadic: bool,
+        ret: Box<Self>,
+    },
+    Pointer(Box<Self>),
+}
+impl Type {
+    /// the `printf` conversion printing a value of this type
+    pub fn printf_spec(&self) -> Option<&'static str> {
+        match self {
+            Type::Int(IntType::S64) => Some("%lld"),
+            Type::Int(_) => Some("%d"),
+            Type::UInt(IntType::S64) => Some("%llu"),
+            Type::UInt(_) => Some("%u"),
+            Type::Float(_) => Some("%f"),
+            typ if typ.is_string() => Some("%s"),
+            Type::Pointer(_) | Type::Func { .. } => Some("%p"),
+            _ => None,
+        }
+    }
+    /// whether a value of type `other` can be passed where this type is expected, arrays are
+    /// passed by their address so they are accepted as pointers to their elements
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Pointer(typ), Type::Array { typ: elements, .. }) => typ == elements,
+            _ => self == other,
+        }
+    }
+    /// zero-terminated strings are `u8[n]` arrays and `*u8` pointers
+    pub fn is_string(&self) -> bool {
+        match self {
+            Type::Array { typ, size: _ } | Type::Pointer(typ) => {
+                typ.as_ref() == &Type::UInt(IntType::S8)
+            }
+            _ => false,
+        }
+    }
+    /// the size of a value of this type in bytes
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            Type::None | Type::Never => None,
+            Type::UInt(typ) | Type::Int(typ) => Some(match typ {
+                IntType::Size => 4,
+                IntType::S8 => 1,
+                IntType::S16 => 2,
+                IntType::S32 => 4,
+                IntType::S64 => 8,
+            }),
+            Type::Float(FloatType::S32) => Some(4),
+            Type::Float(FloatType::S64) => Some(8),
+            Type::Array { typ, size } => Some(typ.size()? * (*size)?),
+            Type::Func { .. } | Type::Pointer(_) => Some(4),
+        }
+    }
 }
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct InvalidType;
+impl Display for InvalidType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid type")
+    }
+}
+impl std::error::Error for InvalidType {}
+/// the items of the parenthesized list `s`, which may be lists themselves
+fn list_items(s: &str) -> Option<Vec<&str>> {
+    let inner = s.strip_prefix('(')?.strip_suffix(')')?;
+    let (mut items, mut depth, mut start) = (vec![], 0usize, None);
+    for (idx, c) in inner.char_indices() {
+        match c {
+            c if c.is_whitespace() && depth == 0 => {
+                items.extend(start.take().map(|start| &inner[start..idx]))
+            }
+            _ => {
+                start.get_or_insert(idx);
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.checked_sub(1)?,
+                    _ => {}
+                }
+            }
+        }
+    }
+    items.extend(start.map(|start| &inner[start..]));
+    (depth == 0).then_some(items)
+}
 impl FromStr for Type {
     type Err = InvalidType;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('(') {
+            let items = list_items(s).ok_or(InvalidType)?;
+            let ["fn", params, ret] = items.as_slice() else {
+                return Err(InvalidType);
+            };
+            let mut params = list_items(params).ok_or(InvalidType)?;
+            let variadic = params.last() == Some(&"...");
+            if variadic {
+                params.pop();
+            }
+            return Ok(Self::Func {
+                params: params
+                    .into_iter()
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?,
+                variadic,
+                ret: Box::new(ret.parse()?),
+            });
+        }
         match s {
             "none" => Ok(Self::None),
             "!" => Ok(Self::Never),
@@ -33,7 +138,10 @@ impl FromStr for Type {
             "i64" => Ok(Self::Int(IntType::S64)),
             "f32" => Ok(Self::Float(FloatType::S32)),
             "f64" => Ok(Self::Float(FloatType::S64)),
-            _ => Err(InvalidType),
+            _ => match s.strip_prefix('*') {
+                Some(typ) => Ok(Self::Pointer(Box::new(typ.parse()?))),
+                None => Err(InvalidType),
+            },
         }
     }
 }
@@ -54,10 +162,26 @@ impl Display for Type {
                     "".to_string()
                 }
             ),
+            Type::Func {
+                params,
+                variadic,
+                ret,
+            } => write!(
+                f,
+                "fn({}) -> {ret}",
+                params
+                    .iter()
+                    .map(|typ| typ.to_string())
+                    .chain(variadic.then(|| "...".to_string()))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            Type::Pointer(typ) => write!(f, "*{typ}"),
         }
     }
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum IntType {
     #[default]
@@ -79,6 +203,7 @@ impl Display for IntType {
     }
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum FloatType {
     #[default]
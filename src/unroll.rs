@@ -0,0 +1,248 @@
+use crate::{
+    compiler::Compiler,
+    const_eval::const_eval,
+    intern::Symbol,
+    parser::{Located, SExpr},
+    visit::{fold_children, walk, SExprFolder, SExprVisitor},
+};
+
+/// the most iterations a loop with a constant trip count is fully unrolled for
+pub const FULL_UNROLL_LIMIT: usize = 8;
+/// how many copies of its body a counted loop is partially unrolled into by default
+pub const DEFAULT_FACTOR: usize = 4;
+/// the most expressions the copies of an unrolled loop body may add up to
+pub const UNROLL_BUDGET: usize = 256;
+
+/// how `compile_loop` compiles a loop it unrolls
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unrolled {
+    /// whether the last body never jumps back to the head of the loop
+    pub full: bool,
+    /// the body of every iteration, each one continuing with the next at its `recur`
+    pub bodies: Vec<Vec<Located<SExpr>>>,
+}
+
+/// the head word of `sexpr` and its arguments if it is a form
+fn form(sexpr: &Located<SExpr>) -> Option<(&str, &[Located<SExpr>])> {
+    match &sexpr.value {
+        SExpr::Expr(sexprs) => match sexprs.split_first() {
+            Some((
+                Located {
+                    value: SExpr::Word(head),
+                    ..
+                },
+                args,
+            )) => Some((head.as_str(), args)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// what decides whether a loop body can be copied
+#[derive(Default)]
+struct Body {
+    /// the expressions in it
+    size: usize,
+    /// the `recur`s continuing this loop rather than one nested in it
+    recurs: usize,
+    nested: usize,
+    has_loops: bool,
+    has_defers: bool,
+}
+impl SExprVisitor for Body {
+    fn visit(&mut self, sexpr: &Located<SExpr>) {
+        self.size += 1;
+        match form(sexpr) {
+            Some(("recur", _)) if self.nested == 0 => self.recurs += 1,
+            Some(("defer", _)) => self.has_defers = true,
+            Some(("loop", _)) => {
+                self.has_loops = true;
+                self.nested += 1;
+                walk(self, sexpr);
+                self.nested -= 1;
+                return;
+            }
+            _ => {}
+        }
+        walk(self, sexpr)
+    }
+}
+
+/// the expressions in `sexprs` and every one nested in them
+fn size(sexprs: &[Located<SExpr>]) -> usize {
+    let mut body = Body::default();
+    body.visit_all(sexprs);
+    body.size
+}
+
+/// the `recur`s whose value is the value of `body`, which continue the loop right where the
+/// body ends
+fn tail_recurs(body: &[Located<SExpr>]) -> usize {
+    match body.last().and_then(form) {
+        Some(("recur", _)) => 1,
+        Some(("block", body)) => tail_recurs(body),
+        Some(("case" | "case-str", [_, arms @ ..])) => arms
+            .iter()
+            .map(|arm| match &arm.value {
+                SExpr::Expr(arm) => tail_recurs(arm.get(1..).unwrap_or_default()),
+                _ => 0,
+            })
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// replaces a word with an integer
+struct Substitute(Symbol, i32);
+impl SExprFolder for Substitute {
+    fn fold(&mut self, sexpr: Located<SExpr>) -> Located<SExpr> {
+        match sexpr.value {
+            SExpr::Word(word) if word == self.0 => Located {
+                value: SExpr::Int(self.1.into(), None),
+                pos: sexpr.pos,
+            },
+            _ => fold_children(self, sexpr),
+        }
+    }
+}
+
+impl Compiler {
+    /// how to unroll the loop binding `names` to `values` with `body` if it's worth it
+    ///
+    /// a counted loop ends in a `case` testing one of its variables, the counter, and changes
+    /// it by a constant at every `recur`; it is fully unrolled if the counter starts out
+    /// constant and the loop ends in a few iterations, which go through every arm of the
+    /// `case`, otherwise it is partially unrolled into `unroll_factor` copies if no loop is
+    /// nested in it, every copy still testing the counter
+    pub fn unroll(
+        &self,
+        names: &[Symbol],
+        values: &[Located<SExpr>],
+        body: &[Located<SExpr>],
+    ) -> Option<Unrolled> {
+        if !self.options.opt_level.unrolls_loops() {
+            return None;
+        }
+        let mut stats = Body::default();
+        stats.visit_all(body);
+        // copies can't `recur` from the middle of an expression, or run deferred code again
+        if stats.has_defers || stats.recurs != tail_recurs(body) {
+            return None;
+        }
+        let (last, prefix) = body.split_last()?;
+        let Some(("case", [scrutinee, arms @ ..])) = form(last) else {
+            return None;
+        };
+        let SExpr::Word(counter) = scrutinee.value else {
+            return None;
+        };
+        let idx = names.iter().rposition(|name| *name == counter)?;
+        let rebinds = prefix.iter().any(|sexpr| {
+            matches!(form(sexpr), Some(("let", [name, ..])) if name.value == SExpr::Word(counter))
+        });
+        if rebinds {
+            return None;
+        }
+        let arms: Vec<(Option<i64>, &[Located<SExpr>])> = arms
+            .iter()
+            .map(|arm| {
+                let SExpr::Expr(arm) = &arm.value else {
+                    return None;
+                };
+                let (key, body) = arm.split_first()?;
+                match &key.value {
+                    SExpr::Word(word) if word == "else" => Some((None, body)),
+                    _ => Some((Some(const_eval(self, key).ok()?), body)),
+                }
+            })
+            .collect::<Option<_>>()?;
+        let recur = |body: &[Located<SExpr>]| match body.last().and_then(form) {
+            Some(("recur", args)) if args.len() == names.len() => Some(args[idx].clone()),
+            _ => None,
+        };
+        let full = self
+            .unroll_fully(&arms, prefix, &recur, counter, &values[idx])
+            .filter(|unrolled| {
+                unrolled.bodies.iter().map(|body| size(body)).sum::<usize>() <= UNROLL_BUDGET
+            });
+        if full.is_some() {
+            return full;
+        }
+
+        // only a counter stepping by a constant makes a loop counted
+        let counted = arms.iter().filter_map(|(_, body)| recur(body)).any(|step| {
+            matches!(form(&step), Some(("+" | "-", [word, amount]))
+                if word.value == SExpr::Word(counter) && const_eval(self, amount).is_ok())
+        });
+        let factor = match self.options.unroll_factor {
+            0 => DEFAULT_FACTOR,
+            factor => factor,
+        }
+        .min(UNROLL_BUDGET / stats.size.max(1));
+        if !counted || stats.has_loops || factor < 2 {
+            return None;
+        }
+        Some(Unrolled {
+            full: false,
+            bodies: vec![body.to_vec(); factor],
+        })
+    }
+    /// the bodies of the iterations of a loop whose counter starts at `start`, picking the arm
+    /// of the `case` every iteration takes
+    fn unroll_fully(
+        &self,
+        arms: &[(Option<i64>, &[Located<SExpr>])],
+        prefix: &[Located<SExpr>],
+        recur: &impl Fn(&[Located<SExpr>]) -> Option<Located<SExpr>>,
+        counter: Symbol,
+        start: &Located<SExpr>,
+    ) -> Option<Unrolled> {
+        // `compile_case` rejects an `else` arm before others
+        if arms[..arms.len().saturating_sub(1)]
+            .iter()
+            .any(|(key, _)| key.is_none())
+        {
+            return None;
+        }
+        let mut value = const_eval(self, start).ok()?;
+        let mut taken = vec![false; arms.len()];
+        let mut bodies = vec![];
+        loop {
+            // the counter has the value at run time whether it is signed or not
+            let value32 = i32::try_from(value).ok().filter(|value| *value >= 0)?;
+            let arm = arms
+                .iter()
+                .position(|(key, _)| *key == Some(value))
+                .or_else(|| arms.iter().position(|(key, _)| key.is_none()))?;
+            taken[arm] = true;
+            let (_, arm_body) = arms[arm];
+            let mut body = prefix.to_vec();
+            let pos = arm_body.first().map_or(start.pos, |sexpr| sexpr.pos);
+            let block = Located {
+                value: SExpr::Word(Symbol::intern("block")),
+                pos,
+            };
+            body.push(Located {
+                value: SExpr::Expr(std::iter::once(block).chain(arm_body.to_vec()).collect()),
+                pos,
+            });
+            bodies.push(body);
+            let mut stats = Body::default();
+            stats.visit_all(arm_body);
+            match recur(arm_body) {
+                Some(step) if bodies.len() < FULL_UNROLL_LIMIT => {
+                    value = const_eval(self, &Substitute(counter, value32).fold(step)).ok()?;
+                }
+                // an arm recurring somewhere but at its end can't continue with the next body
+                None if stats.recurs == 0 => break,
+                _ => return None,
+            }
+        }
+        // the arms it never takes aren't compiled, so a type error in them would go unnoticed
+        taken
+            .iter()
+            .all(|taken| *taken)
+            .then_some(Unrolled { full: true, bodies })
+    }
+}
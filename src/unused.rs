@@ -0,0 +1,103 @@
+use crate::{
+    compiler::{parse_defn, CompileWarning, Compiler},
+    parser::{Located, SExpr},
+};
+
+/// the forms whose only effect is their value, if their arguments are such forms too
+///
+/// a call to any other form or function counts as having effects, even if it has none
+const PURE_FORMS: &[&str] = &[
+    "+",
+    "-",
+    "wrapping-add",
+    "wrapping-sub",
+    "wrapping-mul",
+    "/",
+    "saturating-add",
+    "saturating-sub",
+    "sizeof",
+    "embed-file",
+    "addr-of",
+    "str-len",
+    "str-eq",
+    "argc",
+    "argv",
+];
+
+/// whether evaluating `sexpr` does nothing but compute its value
+fn pure(sexpr: &Located<SExpr>) -> bool {
+    match &sexpr.value {
+        SExpr::Expr(sexprs) => match sexprs.split_first() {
+            Some((
+                Located {
+                    value: SExpr::Word(head),
+                    ..
+                },
+                args,
+            )) => PURE_FORMS.contains(&head.as_str()) && args.iter().all(pure),
+            _ => false,
+        },
+        SExpr::Int(..) | SExpr::String(_) | SExpr::ByteString(_) | SExpr::Word(_) => true,
+        _ => false,
+    }
+}
+
+impl Compiler {
+    /// warns about every expression of the program computing a value nobody uses, which is
+    /// still compiled
+    ///
+    /// this only looks at the syntax: an expression is pure if it is a literal, a word or one of
+    /// `PURE_FORMS`, it is unused if a body or `defer` drops its value. There is no dataflow
+    /// analysis, the stack slots written by the lowered code aren't looked at
+    ///
+    /// the last top-level expression is the exit code of the program
+    pub fn warn_unused_results(&mut self, program: &[Located<SExpr>]) {
+        self.unused_in_body(program, true);
+    }
+    /// checks the expressions of a body, whose last one is its value if `used`
+    fn unused_in_body(&mut self, body: &[Located<SExpr>], used: bool) {
+        for (idx, sexpr) in body.iter().enumerate() {
+            self.unused_in(sexpr, used && idx + 1 == body.len());
+        }
+    }
+    fn unused_in(&mut self, sexpr: &Located<SExpr>, used: bool) {
+        if !used && pure(sexpr) {
+            self.warnings.push(Located {
+                value: CompileWarning::UnusedResult,
+                pos: sexpr.pos,
+            });
+            return;
+        }
+        let SExpr::Expr(sexprs) = &sexpr.value else {
+            return;
+        };
+        let Some((
+            Located {
+                value: SExpr::Word(head),
+                ..
+            },
+            args,
+        )) = sexprs.split_first()
+        else {
+            return;
+        };
+        match (head.as_str(), args) {
+            ("block", body) => self.unused_in_body(body, used),
+            ("loop", [_, body @ ..]) => self.unused_in_body(body, used),
+            ("defn", args) => {
+                if let Ok(defn) = parse_defn(args.to_vec(), sexpr.pos) {
+                    self.unused_in_body(&defn.body, true);
+                }
+            }
+            ("spawn", [lambda]) => {
+                if let SExpr::Expr(lambda) = &lambda.value {
+                    if let [_, _, body @ ..] = lambda.as_slice() {
+                        self.unused_in_body(body, false);
+                    }
+                }
+            }
+            ("defer", [sexpr]) => self.unused_in(sexpr, false),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    builder::{check_operands, BuildErrorKind},
+    code::{Destination, Function, Instruction, Program, Register, RegisterName, Source},
+    intern::Symbol,
+};
+use std::{collections::HashSet, fmt::Display};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub function: String,
+    /// index of the offending instruction in the function body
+    pub index: usize,
+    pub kind: VerifyErrorKind,
+}
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    UnknownLabel(Symbol),
+    DuplicateLabel(Symbol),
+    UnknownFunction(Symbol),
+    InvalidOperands(BuildErrorKind),
+    /// bytes left on the stack when returning
+    StackImbalance(i64),
+}
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}+{}: {}", self.function, self.index, self.kind)
+    }
+}
+impl std::error::Error for VerifyError {}
+impl Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyErrorKind::UnknownLabel(label) => write!(f, "jump to unknown label {label}"),
+            VerifyErrorKind::DuplicateLabel(label) => write!(f, "label {label} defined twice"),
+            VerifyErrorKind::UnknownFunction(func) => {
+                write!(
+                    f,
+                    "call to {func} which is neither defined nor declared extern"
+                )
+            }
+            VerifyErrorKind::InvalidOperands(err) => write!(f, "{err}"),
+            VerifyErrorKind::StackImbalance(bytes) => {
+                write!(f, "returning with {bytes} bytes left on the stack")
+            }
+        }
+    }
+}
+impl std::error::Error for VerifyErrorKind {}
+
+fn is_stack_pointer(dest: &Destination) -> bool {
+    matches!(
+        dest,
+        Destination::Register(Register {
+            name: RegisterName::SP,
+            ..
+        })
+    )
+}
+/// a register or memory operand, as taken by `mul` and `div`
+fn check_operand(src: &Source) -> Result<(), BuildErrorKind> {
+    match src {
+        Source::Register(_) | Source::Memory(_) => Ok(()),
+        src => Err(BuildErrorKind::InvalidOperand(src.clone())),
+    }
+}
+
+impl Program {
+    /// checks the structural invariants of every function, returning all violations
+    pub fn verify(&self) -> Vec<VerifyError> {
+        let functions: HashSet<&str> = self
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .chain(self.externs.iter().map(|name| name.as_str()))
+            .collect();
+        self.functions
+            .iter()
+            .flat_map(|function| function.verify(&functions))
+            .collect()
+    }
+}
+impl Function {
+    /// checks this function, with `functions` being every callable name
+    pub fn verify(&self, functions: &HashSet<&str>) -> Vec<VerifyError> {
+        let mut errors = vec![];
+        let mut error = |index: usize, kind: VerifyErrorKind| {
+            errors.push(VerifyError {
+                function: self.name.clone(),
+                index,
+                kind,
+            })
+        };
+        let mut labels = HashSet::new();
+        for (index, instr) in self.body.iter().enumerate() {
+            if let Instruction::Label(label) = instr {
+                if !labels.insert(*label) {
+                    error(index, VerifyErrorKind::DuplicateLabel(*label));
+                }
+            }
+        }
+        for label in self.tables.iter().flatten() {
+            if !labels.contains(label) {
+                error(self.body.len(), VerifyErrorKind::UnknownLabel(*label));
+            }
+        }
+        // bytes pushed since entry, `None` once the stack pointer is changed in an untracked way
+        let mut depth = Some(0i64);
+        // depth when the frame pointer was set up
+        let mut frame = None;
+        for (index, instr) in self.body.iter().enumerate() {
+            let operands = match instr {
+                Instruction::Mov { dest, src }
+                | Instruction::Add { dest, src }
+                | Instruction::Sub { dest, src }
+                | Instruction::And { dest, src } => check_operands(dest, src),
+                Instruction::Cmp { a, b } => match Destination::try_from(a.clone()) {
+                    Ok(a) => check_operands(&a, b),
+                    Err(a) => Err(BuildErrorKind::InvalidOperand(a)),
+                },
+                Instruction::Cmov { dest, src, .. } => check_operands(&(*dest).into(), src),
+                Instruction::Xchg { dest, src }
+                | Instruction::Xadd { dest, src, .. }
+                | Instruction::Cmpxchg { dest, src, .. } => check_operands(dest, &(*src).into()),
+                Instruction::Mul { src } | Instruction::Div { src } | Instruction::Idiv { src } => {
+                    check_operand(src)
+                }
+                _ => Ok(()),
+            };
+            if let Err(err) = operands {
+                error(index, VerifyErrorKind::InvalidOperands(err));
+            }
+            match instr {
+                Instruction::Jmp { label } | Instruction::JOp { label, .. }
+                    if !labels.contains(label) =>
+                {
+                    error(index, VerifyErrorKind::UnknownLabel(*label))
+                }
+                Instruction::Call { func } if !functions.contains(func.as_str()) => {
+                    error(index, VerifyErrorKind::UnknownFunction(*func))
+                }
+                Instruction::Push { src } => {
+                    let bytes = src.size().map_or(4, |size| size.bytes()) as i64;
+                    depth = depth.map(|depth| depth + bytes);
+                }
+                Instruction::Pop { dest } => {
+                    depth = depth.map(|depth| depth - dest.size().bytes() as i64);
+                }
+                Instruction::Add {
+                    dest,
+                    src: Source::Int(int),
+                } if is_stack_pointer(dest) => depth = depth.map(|depth| depth - *int as i64),
+                Instruction::Add {
+                    dest,
+                    src: Source::Amount(amount),
+                } if is_stack_pointer(dest) => depth = depth.map(|depth| depth - *amount as i64),
+                Instruction::Sub {
+                    dest,
+                    src: Source::Int(int),
+                } if is_stack_pointer(dest) => depth = depth.map(|depth| depth + *int as i64),
+                Instruction::Mov {
+                    dest:
+                        Destination::Register(Register {
+                            name: RegisterName::BP,
+                            ..
+                        }),
+                    src:
+                        Source::Register(Register {
+                            name: RegisterName::SP,
+                            ..
+                        }),
+                } => frame = depth,
+                Instruction::Mov { dest, .. }
+                | Instruction::Add { dest, .. }
+                | Instruction::Sub { dest, .. }
+                | Instruction::And { dest, .. }
+                | Instruction::Sar { dest, .. }
+                | Instruction::Shl { dest, .. }
+                | Instruction::Shr { dest, .. }
+                    if is_stack_pointer(dest) =>
+                {
+                    depth = None
+                }
+                Instruction::Leave => {
+                    depth = frame.map(|frame| frame - 4);
+                }
+                Instruction::Ret => {
+                    if let Some(bytes) = depth.filter(|depth| *depth != 0) {
+                        error(index, VerifyErrorKind::StackImbalance(bytes));
+                    }
+                }
+                _ => {}
+            }
+        }
+        errors
+    }
+}
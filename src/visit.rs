@@ -0,0 +1,47 @@
+use crate::parser::{Located, SExpr};
+
+/// walks a `Located<SExpr>` tree by reference; override `visit` and call `walk` to recurse
+pub trait SExprVisitor {
+    fn visit(&mut self, sexpr: &Located<SExpr>) {
+        walk(self, sexpr)
+    }
+    fn visit_all(&mut self, sexprs: &[Located<SExpr>]) {
+        for sexpr in sexprs {
+            self.visit(sexpr);
+        }
+    }
+}
+/// visits the children of `sexpr`
+pub fn walk<V: SExprVisitor + ?Sized>(visitor: &mut V, sexpr: &Located<SExpr>) {
+    match &sexpr.value {
+        SExpr::Expr(sexprs) | SExpr::Bracket(sexprs) | SExpr::Brace(sexprs) => {
+            visitor.visit_all(sexprs)
+        }
+        SExpr::Quoted(_, sexpr) => visitor.visit(sexpr),
+        _ => {}
+    }
+}
+
+/// rebuilds a `Located<SExpr>` tree by value; override `fold` and call `fold_children` to recurse
+pub trait SExprFolder {
+    fn fold(&mut self, sexpr: Located<SExpr>) -> Located<SExpr> {
+        fold_children(self, sexpr)
+    }
+    fn fold_all(&mut self, sexprs: Vec<Located<SExpr>>) -> Vec<Located<SExpr>> {
+        sexprs.into_iter().map(|sexpr| self.fold(sexpr)).collect()
+    }
+}
+/// folds the children of `sexpr`, keeping its position
+pub fn fold_children<F: SExprFolder + ?Sized>(
+    folder: &mut F,
+    Located { value, pos }: Located<SExpr>,
+) -> Located<SExpr> {
+    let value = match value {
+        SExpr::Expr(sexprs) => SExpr::Expr(folder.fold_all(sexprs)),
+        SExpr::Bracket(sexprs) => SExpr::Bracket(folder.fold_all(sexprs)),
+        SExpr::Brace(sexprs) => SExpr::Brace(folder.fold_all(sexprs)),
+        SExpr::Quoted(kind, sexpr) => SExpr::Quoted(kind, Box::new(folder.fold(*sexpr))),
+        value => value,
+    };
+    Located { value, pos }
+}
@@ -0,0 +1,807 @@
+use crate::{
+    bytecode::{Bytecode, Op, DATA_BASE, EXTERN_BASE, FUNCTION_BASE},
+    code::{ComparisonOperator, Register, RegisterName, RegisterSize},
+    os::{SYS_CLOSE, SYS_EXIT, SYS_OPEN, SYS_READ, SYS_WRITE},
+};
+use std::{fmt::Display, io::Write};
+
+/// bytes available to `malloc`
+const HEAP_SIZE: u32 = 16 << 20;
+const STACK_SIZE: u32 = 1 << 20;
+/// the Linux error numbers the file calls fail with
+const ENOENT: i64 = 2;
+const EBADF: i64 = 9;
+
+#[derive(Debug)]
+pub enum VmError {
+    /// an access outside of the mapped memory, including null pointers
+    OutOfBounds(u64),
+    StackUnderflow,
+    DivideByZero,
+    /// a signed division with a quotient too big for its size
+    DivideOverflow,
+    OutOfMemory,
+    StackOverflow,
+    /// an indirect call to something which isn't a function
+    InvalidCall(u64),
+    NoMain,
+    UnsupportedExtern(String),
+    UnsupportedInterrupt(u8),
+    UnsupportedSyscall(u32),
+    Io(std::io::Error),
+}
+impl Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::OutOfBounds(address) => write!(f, "access to unmapped address {address:#x}"),
+            VmError::StackUnderflow => write!(f, "operand stack underflow"),
+            VmError::DivideByZero => write!(f, "division by zero"),
+            VmError::DivideOverflow => write!(f, "division overflow"),
+            VmError::OutOfMemory => write!(f, "out of memory"),
+            VmError::StackOverflow => write!(f, "stack overflow"),
+            VmError::InvalidCall(address) => write!(f, "call to non-function {address:#x}"),
+            VmError::NoMain => write!(f, "no main function"),
+            VmError::UnsupportedExtern(name) => write!(f, "extern {name} isn't supported"),
+            VmError::UnsupportedInterrupt(vector) => {
+                write!(f, "interrupt {vector:#x} isn't supported")
+            }
+            VmError::UnsupportedSyscall(number) => {
+                write!(f, "system call {number} isn't supported")
+            }
+            VmError::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+impl std::error::Error for VmError {}
+impl From<std::io::Error> for VmError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+fn mask(value: u64, size: RegisterSize) -> u64 {
+    match size {
+        RegisterSize::S64 => value,
+        size => value & ((1 << (size.bytes() * 8)) - 1),
+    }
+}
+fn sign_extend(value: u64, size: RegisterSize) -> i64 {
+    let shift = 64 - size.bytes() * 8;
+    ((value << shift) as i64) >> shift
+}
+
+/// the operands of the last op setting the flags, which are computed when they are read
+#[derive(Debug, Clone, Copy)]
+struct Flags {
+    a: u64,
+    b: u64,
+    size: RegisterSize,
+    /// set by `AddFlags` rather than `Cmp`
+    sum: bool,
+}
+
+/// how executing an op continues
+enum Flow {
+    Next,
+    Jump(usize),
+    Call(u32),
+    Return,
+    Exit(i32),
+}
+
+/// a stack machine running `Bytecode` in a 32-bit address space, writing program output to
+/// `output`
+pub struct Vm<'a, W: Write> {
+    bytecode: &'a Bytecode,
+    memory: Vec<u8>,
+    registers: [u64; 16],
+    stack: Vec<u64>,
+    /// the x87 register stack, top last
+    floats: Vec<f64>,
+    flags: Flags,
+    /// the function and op to return to
+    frames: Vec<(u32, usize)>,
+    /// next free byte of the heap
+    heap: u32,
+    /// the return values of the threads `pthread_create` started, which run to completion
+    /// before it returns
+    threads: Vec<u64>,
+    /// the command-line arguments `main` is called with
+    args: Vec<String>,
+    /// the environment variables `getenv` reads
+    env: Vec<(String, String)>,
+    output: W,
+}
+impl<'a, W: Write> Vm<'a, W> {
+    pub fn new(bytecode: &'a Bytecode, output: W) -> Self {
+        let data_end = DATA_BASE as usize + bytecode.data.len() + bytecode.bss as usize;
+        let heap = data_end.next_multiple_of(16) as u32;
+        let mut memory = vec![0; (heap + HEAP_SIZE + STACK_SIZE) as usize];
+        memory[DATA_BASE as usize..DATA_BASE as usize + bytecode.data.len()]
+            .copy_from_slice(&bytecode.data);
+        let mut registers = [0; 16];
+        registers[RegisterName::SP as usize] = memory.len() as u64;
+        Self {
+            bytecode,
+            memory,
+            registers,
+            stack: vec![],
+            floats: vec![],
+            flags: Flags {
+                a: 0,
+                b: 0,
+                size: RegisterSize::S32,
+                sum: false,
+            },
+            frames: vec![],
+            heap,
+            threads: vec![],
+            args: vec![],
+            env: vec![],
+            output,
+        }
+    }
+    /// passes `args` to `main`, the name of the program first
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+    /// makes `env` the environment variables of the program
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+    /// runs `main` until it returns, giving its return value as the exit code
+    pub fn run(&mut self) -> Result<i32, VmError> {
+        let main = self.bytecode.function("main").ok_or(VmError::NoMain)?;
+        let argv = self.push_args()?;
+        self.push(argv, RegisterSize::S32)?;
+        self.push(self.args.len() as u64, RegisterSize::S32)?;
+        // the return address of main
+        self.push(0, RegisterSize::S32)?;
+        match self.execute(main)? {
+            Some(code) => Ok(code),
+            None => Ok(self.register(RegisterName::A) as i32),
+        }
+    }
+    /// runs the function `entry`, whose return address is already pushed, until it returns,
+    /// or until the program exits with the code returned
+    fn execute(&mut self, entry: u32) -> Result<Option<i32>, VmError> {
+        let depth = self.frames.len();
+        let (mut function, mut pc) = (entry, 0);
+        loop {
+            let code = &self.bytecode.functions[function as usize].code;
+            // running off the end of a function returns like `ret`
+            let flow = match code.get(pc).copied() {
+                Some(op) => self.step(op)?,
+                None => Flow::Return,
+            };
+            match flow {
+                Flow::Next => pc += 1,
+                Flow::Jump(target) => pc = target,
+                Flow::Call(callee) => {
+                    if self.frames.len() >= STACK_SIZE as usize / 4 {
+                        return Err(VmError::StackOverflow);
+                    }
+                    self.push(0, RegisterSize::S32)?;
+                    self.frames.push((function, pc + 1));
+                    (function, pc) = (callee, 0);
+                }
+                Flow::Return => {
+                    self.pop(RegisterSize::S32)?;
+                    match self.frames.len() > depth {
+                        true => {
+                            (function, pc) = self.frames.pop().ok_or(VmError::StackUnderflow)?
+                        }
+                        false => return Ok(None),
+                    }
+                }
+                Flow::Exit(code) => return Ok(Some(code)),
+            }
+        }
+    }
+    /// runs the function at `address` with `arg` on its own registers like a thread, returning
+    /// its return value or the code the program exited with
+    fn run_thread(&mut self, address: u64, arg: u64) -> Result<Result<u64, i32>, VmError> {
+        let function = address
+            .checked_sub(FUNCTION_BASE as u64)
+            .filter(|index| *index < self.bytecode.functions.len() as u64)
+            .ok_or(VmError::InvalidCall(address))?;
+        let (registers, flags) = (self.registers, self.flags);
+        self.push(arg, RegisterSize::S32)?;
+        self.push(0, RegisterSize::S32)?;
+        if let Some(code) = self.execute(function as u32)? {
+            return Ok(Err(code));
+        }
+        let result = self.register(RegisterName::A);
+        (self.registers, self.flags) = (registers, flags);
+        Ok(Ok(result))
+    }
+
+    fn register(&self, name: RegisterName) -> u64 {
+        self.registers[name as usize]
+    }
+    fn set_register(&mut self, Register { name, size }: Register, value: u64) {
+        let old = self.registers[name as usize];
+        self.registers[name as usize] = match size {
+            RegisterSize::S64 | RegisterSize::S32 => mask(value, size),
+            size => old & !mask(u64::MAX, size) | mask(value, size),
+        };
+    }
+    fn operand(&mut self) -> Result<u64, VmError> {
+        self.stack.pop().ok_or(VmError::StackUnderflow)
+    }
+    fn range(&self, address: u64, len: usize) -> Result<std::ops::Range<usize>, VmError> {
+        let start = address as usize;
+        if address < DATA_BASE as u64 || start + len > self.memory.len() {
+            return Err(VmError::OutOfBounds(address));
+        }
+        Ok(start..start + len)
+    }
+    fn load(&self, address: u64, size: RegisterSize) -> Result<u64, VmError> {
+        let mut bytes = [0; 8];
+        let range = self.range(address, size.bytes())?;
+        bytes[..size.bytes()].copy_from_slice(&self.memory[range]);
+        Ok(u64::from_le_bytes(bytes))
+    }
+    fn store(&mut self, address: u64, value: u64, size: RegisterSize) -> Result<(), VmError> {
+        let range = self.range(address, size.bytes())?;
+        self.memory[range].copy_from_slice(&value.to_le_bytes()[..size.bytes()]);
+        Ok(())
+    }
+    fn push(&mut self, value: u64, size: RegisterSize) -> Result<(), VmError> {
+        let sp = self
+            .register(RegisterName::SP)
+            .wrapping_sub(size.bytes() as u64);
+        if sp < (self.memory.len() - STACK_SIZE as usize) as u64 {
+            return Err(VmError::StackOverflow);
+        }
+        self.registers[RegisterName::SP as usize] = sp;
+        self.store(sp, value, size)
+    }
+    /// copies the arguments onto the stack with the null-terminated array pointing to them
+    /// below, returning the address of the array
+    fn push_args(&mut self) -> Result<u64, VmError> {
+        let mut pointers = vec![];
+        for arg in self.args.clone().iter().rev() {
+            let sp = (self.register(RegisterName::SP) - arg.len() as u64 - 1) & !3;
+            if sp < (self.memory.len() - STACK_SIZE as usize) as u64 {
+                return Err(VmError::StackOverflow);
+            }
+            self.registers[RegisterName::SP as usize] = sp;
+            for (idx, byte) in arg.bytes().chain([0]).enumerate() {
+                self.store(sp + idx as u64, byte as u64, RegisterSize::S8)?;
+            }
+            pointers.push(sp);
+        }
+        self.push(0, RegisterSize::S32)?;
+        for pointer in pointers {
+            self.push(pointer, RegisterSize::S32)?;
+        }
+        Ok(self.register(RegisterName::SP))
+    }
+    fn pop(&mut self, size: RegisterSize) -> Result<u64, VmError> {
+        let sp = self.register(RegisterName::SP);
+        let value = self.load(sp, size)?;
+        self.registers[RegisterName::SP as usize] = sp + size.bytes() as u64;
+        Ok(value)
+    }
+    fn compare(&self, op: ComparisonOperator) -> bool {
+        let Flags { a, b, size, sum } = self.flags;
+        let (sa, sb) = (sign_extend(a, size) as i128, sign_extend(b, size) as i128);
+        let (ua, ub) = (mask(a, size) as i128, mask(b, size) as i128);
+        // the exact results, which the flags say how the truncated one differs from
+        let (signed, unsigned) = match sum {
+            true => (sa + sb, ua + ub),
+            false => (sa - sb, ua - ub),
+        };
+        let result = mask(unsigned as u64, size);
+        let zero = result == 0;
+        let sign = sign_extend(result, size) < 0;
+        let carry = unsigned != result as i128;
+        let overflow = signed != sign_extend(result, size) as i128;
+        match op {
+            ComparisonOperator::Equal => zero,
+            ComparisonOperator::NotEqual => !zero,
+            ComparisonOperator::Less => sign != overflow,
+            ComparisonOperator::Greater => !zero && sign == overflow,
+            ComparisonOperator::LessEqual => zero || sign != overflow,
+            ComparisonOperator::GreaterEqual => sign == overflow,
+            ComparisonOperator::LessUnsigned => carry,
+            ComparisonOperator::GreaterUnsigned => !carry && !zero,
+            ComparisonOperator::LessEqualUnsigned => carry || zero,
+            ComparisonOperator::GreaterEqualUnsigned => !carry,
+            ComparisonOperator::Overflow => overflow,
+            ComparisonOperator::NotOverflow => !overflow,
+        }
+    }
+
+    fn step(&mut self, op: Op) -> Result<Flow, VmError> {
+        match op {
+            Op::Int(int) => self.stack.push(int as u64),
+            Op::Reg(register) => {
+                let value = mask(self.register(register.name), register.size);
+                self.stack.push(value)
+            }
+            Op::SetReg(register) => {
+                let value = self.operand()?;
+                self.set_register(register, value)
+            }
+            Op::Dup => {
+                let value = *self.stack.last().ok_or(VmError::StackUnderflow)?;
+                self.stack.push(value)
+            }
+            Op::Add | Op::Sub | Op::And | Op::Mul => {
+                let (b, a) = (self.operand()?, self.operand()?);
+                self.stack.push(match op {
+                    Op::Add => a.wrapping_add(b),
+                    Op::Sub => a.wrapping_sub(b),
+                    Op::And => a & b,
+                    _ => a.wrapping_mul(b),
+                })
+            }
+            Op::Sar(size) => {
+                let (amount, value) = (self.operand()?, self.operand()?);
+                self.stack
+                    .push((sign_extend(value, size) >> (amount & 63)) as u64)
+            }
+            Op::Shl | Op::Shr => {
+                let (amount, value) = (self.operand()?, self.operand()?);
+                self.stack.push(match op {
+                    Op::Shl => value << (amount & 63),
+                    _ => value >> (amount & 63),
+                })
+            }
+            Op::SignExtend(size) => {
+                let value = self.operand()?;
+                self.stack.push(sign_extend(value, size) as u64)
+            }
+            Op::Load(size) => {
+                let address = self.operand()?;
+                let value = self.load(mask(address, RegisterSize::S32), size)?;
+                self.stack.push(value)
+            }
+            Op::Store(size) => {
+                let (value, address) = (self.operand()?, self.operand()?);
+                self.store(mask(address, RegisterSize::S32), value, size)?
+            }
+            Op::Push(size) => {
+                let value = self.operand()?;
+                self.push(value, size)?
+            }
+            Op::Pop(size) => {
+                let value = self.pop(size)?;
+                self.stack.push(value)
+            }
+            Op::Cmp(size) | Op::AddFlags(size) => {
+                let (b, a) = (self.operand()?, self.operand()?);
+                let sum = matches!(op, Op::AddFlags(_));
+                self.flags = Flags { a, b, size, sum }
+            }
+            Op::Select(op) => {
+                let (b, a) = (self.operand()?, self.operand()?);
+                self.stack.push(if self.compare(op) { b } else { a })
+            }
+            Op::Set(op) => {
+                let value = self.compare(op) as u64;
+                self.stack.push(value)
+            }
+            Op::Jmp(target) => return Ok(Flow::Jump(target as usize)),
+            Op::JumpIf(op, target) if self.compare(op) => return Ok(Flow::Jump(target as usize)),
+            Op::JumpIf(..) => {}
+            Op::JumpIndirect => {
+                let target = mask(self.operand()?, RegisterSize::S32);
+                return Ok(Flow::Jump(target as usize));
+            }
+            Op::WideMul(size) => {
+                let src = mask(self.operand()?, size) as u128;
+                let a = mask(self.register(RegisterName::A), size) as u128;
+                let product = a * src;
+                let bits = size.bytes() * 8;
+                self.set_register(register(RegisterName::A, size), product as u64);
+                self.set_register(register(RegisterName::D, size), (product >> bits) as u64);
+            }
+            Op::WideDiv(size) => {
+                let src = mask(self.operand()?, size) as u128;
+                if src == 0 {
+                    return Err(VmError::DivideByZero);
+                }
+                let bits = size.bytes() * 8;
+                let a = mask(self.register(RegisterName::A), size) as u128;
+                let d = mask(self.register(RegisterName::D), size) as u128;
+                let dividend = d << bits | a;
+                self.set_register(register(RegisterName::A, size), (dividend / src) as u64);
+                self.set_register(register(RegisterName::D, size), (dividend % src) as u64);
+            }
+            Op::FloatLoad(size) => {
+                let address = mask(self.operand()?, RegisterSize::S32);
+                let bits = self.load(address, size)?;
+                self.floats.push(match size {
+                    RegisterSize::S32 => f32::from_bits(bits as u32) as f64,
+                    _ => f64::from_bits(bits),
+                })
+            }
+            Op::FloatStore(size) => {
+                let address = mask(self.operand()?, RegisterSize::S32);
+                let value = self.floats.pop().ok_or(VmError::StackUnderflow)?;
+                let bits = match size {
+                    RegisterSize::S32 => (value as f32).to_bits() as u64,
+                    _ => value.to_bits(),
+                };
+                self.store(address, bits, size)?
+            }
+            Op::SignedDiv(size) => {
+                let src = sign_extend(self.operand()?, size) as i128;
+                if src == 0 {
+                    return Err(VmError::DivideByZero);
+                }
+                let bits = size.bytes() * 8;
+                let a = mask(self.register(RegisterName::A), size) as i128;
+                let d = sign_extend(self.register(RegisterName::D), size) as i128;
+                let dividend = d << bits | a;
+                let quotient = dividend / src;
+                if quotient != sign_extend(quotient as u64, size) as i128 {
+                    return Err(VmError::DivideOverflow);
+                }
+                self.set_register(register(RegisterName::A, size), quotient as u64);
+                self.set_register(register(RegisterName::D, size), (dividend % src) as u64);
+            }
+            Op::Call(index) => return Ok(Flow::Call(index)),
+            Op::CallExtern(index) => return self.call_extern(index),
+            Op::Interrupt(0x80) => return self.syscall(),
+            Op::Interrupt(vector) => return Err(VmError::UnsupportedInterrupt(vector)),
+            Op::CallIndirect => {
+                let address = mask(self.operand()?, RegisterSize::S32);
+                let (functions, externs) = (
+                    self.bytecode.functions.len() as u64,
+                    self.bytecode.externs.len() as u64,
+                );
+                return match address.checked_sub(FUNCTION_BASE as u64) {
+                    Some(index) if index < functions => Ok(Flow::Call(index as u32)),
+                    _ => match address.checked_sub(EXTERN_BASE as u64) {
+                        Some(index) if index < externs => self.call_extern(index as u32),
+                        _ => Err(VmError::InvalidCall(address)),
+                    },
+                };
+            }
+            Op::Ret => return Ok(Flow::Return),
+        }
+        Ok(Flow::Next)
+    }
+
+    /// the `n`th 4-byte argument of an extern call
+    fn arg(&self, n: u64) -> Result<u64, VmError> {
+        self.load(self.register(RegisterName::SP) + n * 4, RegisterSize::S32)
+    }
+    fn c_str(&self, address: u64) -> Result<&[u8], VmError> {
+        let start = self.range(address, 0)?.start;
+        let len = self.memory[start..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(VmError::OutOfBounds(self.memory.len() as u64))?;
+        Ok(&self.memory[start..start + len])
+    }
+    /// copies `bytes` to `address` followed by a null terminator
+    fn write_c_str(&mut self, address: u64, bytes: &[u8]) -> Result<(), VmError> {
+        let range = self.range(address, bytes.len() + 1)?;
+        self.memory[range.start..range.end - 1].copy_from_slice(bytes);
+        self.memory[range.end - 1] = 0;
+        Ok(())
+    }
+    fn malloc(&mut self, size: u64) -> Result<u64, VmError> {
+        // every block starts with its size, which `realloc` needs
+        let start = self.heap as u64 + 8;
+        let end = (start + size).next_multiple_of(8);
+        if end > self.heap_end() as u64 {
+            return Err(VmError::OutOfMemory);
+        }
+        self.store(start - 4, size, RegisterSize::S32)?;
+        self.heap = end as u32;
+        Ok(start)
+    }
+    fn heap_end(&self) -> usize {
+        self.memory.len() - STACK_SIZE as usize
+    }
+    fn call_extern(&mut self, index: u32) -> Result<Flow, VmError> {
+        let name = self.bytecode.externs[index as usize].as_str();
+        let result = match name {
+            "printf" => {
+                let text = self.format(self.arg(0)?, 1)?;
+                self.output.write_all(&text)?;
+                text.len() as u64
+            }
+            "sprintf" => {
+                let text = self.format(self.arg(1)?, 2)?;
+                self.write_c_str(self.arg(0)?, &text)?;
+                text.len() as u64
+            }
+            "snprintf" => {
+                let text = self.format(self.arg(2)?, 3)?;
+                let len = (self.arg(1)? as usize).min(text.len() + 1);
+                if len > 0 {
+                    self.write_c_str(self.arg(0)?, &text[..len - 1])?;
+                }
+                text.len() as u64
+            }
+            "puts" => {
+                let mut text = self.c_str(self.arg(0)?)?.to_vec();
+                text.push(b'\n');
+                self.output.write_all(&text)?;
+                text.len() as u64
+            }
+            "putchar" => {
+                let char = self.arg(0)?;
+                self.output.write_all(&[char as u8])?;
+                char
+            }
+            "malloc" => self.malloc(self.arg(0)?)?,
+            "calloc" => self.malloc(self.arg(0)? * self.arg(1)?)?,
+            "realloc" => {
+                let (old, size) = (self.arg(0)?, self.arg(1)?);
+                let new = self.malloc(size)?;
+                if old != 0 {
+                    let len = self.load(old - 4, RegisterSize::S32)?.min(size) as usize;
+                    let (from, to) = (self.range(old, len)?, self.range(new, len)?);
+                    self.memory.copy_within(from, to.start);
+                }
+                new
+            }
+            "free" => 0,
+            "strlen" => self.c_str(self.arg(0)?)?.len() as u64,
+            "strcmp" => {
+                let (a, b) = (self.c_str(self.arg(0)?)?, self.c_str(self.arg(1)?)?);
+                a.cmp(b) as i64 as u64
+            }
+            "strcpy" => {
+                let (dest, src) = (self.arg(0)?, self.arg(1)?);
+                let text = self.c_str(src)?.to_vec();
+                self.write_c_str(dest, &text)?;
+                dest
+            }
+            "strcat" => {
+                let (dest, src) = (self.arg(0)?, self.arg(1)?);
+                let end = dest + self.c_str(dest)?.len() as u64;
+                let text = self.c_str(src)?.to_vec();
+                self.write_c_str(end, &text)?;
+                dest
+            }
+            "memcmp" => {
+                let (a, b, len) = (self.arg(0)?, self.arg(1)?, self.arg(2)? as usize);
+                let (a, b) = (self.range(a, len)?, self.range(b, len)?);
+                self.memory[a].cmp(&self.memory[b]) as i64 as u64
+            }
+            "memcpy" | "memmove" => {
+                let (dest, src, len) = (self.arg(0)?, self.arg(1)?, self.arg(2)? as usize);
+                let (from, to) = (self.range(src, len)?, self.range(dest, len)?);
+                self.memory.copy_within(from, to.start);
+                dest
+            }
+            "memset" => {
+                let (dest, byte, len) = (self.arg(0)?, self.arg(1)?, self.arg(2)? as usize);
+                let range = self.range(dest, len)?;
+                self.memory[range].fill(byte as u8);
+                dest
+            }
+            "abs" => (self.arg(0)? as i32).unsigned_abs() as u64,
+            "pthread_create" => {
+                let (thread, start, arg) = (self.arg(0)?, self.arg(2)?, self.arg(3)?);
+                let result = match self.run_thread(start, arg)? {
+                    Ok(result) => result,
+                    Err(code) => return Ok(Flow::Exit(code)),
+                };
+                self.threads.push(result);
+                // handles start at one
+                self.store(thread, self.threads.len() as u64, RegisterSize::S32)?;
+                0
+            }
+            "pthread_join" => {
+                let (thread, result) = (self.arg(0)?, self.arg(1)?);
+                let Some(value) = self.threads.get((thread as usize).wrapping_sub(1)).copied()
+                else {
+                    // ESRCH
+                    return self.extern_result(3);
+                };
+                if result != 0 {
+                    self.store(result, value, RegisterSize::S32)?;
+                }
+                0
+            }
+            "getenv" => {
+                let name = self.c_str(self.arg(0)?)?;
+                let value = self
+                    .env
+                    .iter()
+                    .find(|(key, _)| key.as_bytes() == name)
+                    .map(|(_, value)| value.clone());
+                match value {
+                    Some(value) => {
+                        let address = self.malloc(value.len() as u64 + 1)?;
+                        self.write_c_str(address, value.as_bytes())?;
+                        address
+                    }
+                    None => 0,
+                }
+            }
+            "exit" => return Ok(Flow::Exit(self.arg(0)? as i32)),
+            "open" | "read" | "write" | "close" => {
+                let number = match name {
+                    "open" => SYS_OPEN,
+                    "read" => SYS_READ,
+                    "write" => SYS_WRITE,
+                    _ => SYS_CLOSE,
+                };
+                let args = [self.arg(0)?, self.arg(1)?, self.arg(2)?];
+                // libc returns -1 and sets `errno`, which isn't kept
+                self.file_call(number, args)?.max(-1) as u64
+            }
+            name => return Err(VmError::UnsupportedExtern(name.to_string())),
+        };
+        self.extern_result(result)
+    }
+    /// a Linux system call, with its number in the A register and its arguments in the B, C
+    /// and D registers
+    fn syscall(&mut self) -> Result<Flow, VmError> {
+        let args = [RegisterName::B, RegisterName::C, RegisterName::D]
+            .map(|name| mask(self.register(name), RegisterSize::S32));
+        let result = match mask(self.register(RegisterName::A), RegisterSize::S32) as u32 {
+            SYS_EXIT => return Ok(Flow::Exit(args[0] as i32)),
+            number @ (SYS_READ | SYS_WRITE | SYS_OPEN | SYS_CLOSE) => {
+                self.file_call(number, args)?
+            }
+            number => return Err(VmError::UnsupportedSyscall(number)),
+        };
+        self.extern_result(result as u64)
+    }
+    /// the file system call `number`, returning a negative error number if it fails
+    ///
+    /// there are only the standard streams: stdin is empty and stdout and stderr are the output
+    fn file_call(&mut self, number: u32, [fd, buf, count]: [u64; 3]) -> Result<i64, VmError> {
+        Ok(match (number, fd) {
+            (SYS_OPEN, _) => -ENOENT,
+            (SYS_READ, 0) => 0,
+            (SYS_WRITE, 1 | 2) => {
+                let range = self.range(buf, count as usize)?;
+                self.output.write_all(&self.memory[range])?;
+                count as i64
+            }
+            (SYS_CLOSE, 0..=2) => 0,
+            _ => -EBADF,
+        })
+    }
+    fn extern_result(&mut self, result: u64) -> Result<Flow, VmError> {
+        self.set_register(register(RegisterName::A, RegisterSize::S32), result);
+        Ok(Flow::Next)
+    }
+
+    /// formats like `printf`, with the variadic arguments starting at the `arg`th argument
+    fn format(&self, fmt: u64, mut arg: u64) -> Result<Vec<u8>, VmError> {
+        let fmt = self.c_str(fmt)?;
+        let mut out = vec![];
+        let mut chars = fmt.iter().copied().peekable();
+        while let Some(c) = chars.next() {
+            if c != b'%' {
+                out.push(c);
+                continue;
+            }
+            let (mut left, mut zero, mut plus, mut space) = (false, false, false, false);
+            while let Some(flag) = chars.next_if(|c| b"-0+ #".contains(c)) {
+                match flag {
+                    b'-' => left = true,
+                    b'0' => zero = true,
+                    b'+' => plus = true,
+                    b' ' => space = true,
+                    _ => {}
+                }
+            }
+            let mut width = 0;
+            if chars.next_if_eq(&b'*').is_some() {
+                width = self.arg(arg)? as i32 as i64;
+                arg += 1;
+                if width < 0 {
+                    left = true;
+                    width = -width;
+                }
+            }
+            while let Some(digit) = chars.next_if(u8::is_ascii_digit) {
+                width = width * 10 + (digit - b'0') as i64;
+            }
+            let mut precision = None;
+            if chars.next_if_eq(&b'.').is_some() {
+                let mut digits = 0;
+                if chars.next_if_eq(&b'*').is_some() {
+                    digits = self.arg(arg)? as i32 as i64;
+                    arg += 1;
+                }
+                while let Some(digit) = chars.next_if(u8::is_ascii_digit) {
+                    digits = digits * 10 + (digit - b'0') as i64;
+                }
+                precision = Some(digits.max(0) as usize);
+            }
+            let mut long = 0;
+            while let Some(length) = chars.next_if(|c| b"hlzjt".contains(c)) {
+                if length == b'l' {
+                    long += 1;
+                }
+            }
+            // reads the next integer argument, 8 bytes for `ll`
+            let mut int = |vm: &Self| -> Result<u64, VmError> {
+                let value = if long >= 2 {
+                    let value =
+                        vm.load(vm.register(RegisterName::SP) + arg * 4, RegisterSize::S64)?;
+                    arg += 2;
+                    value
+                } else {
+                    let value = vm.arg(arg)?;
+                    arg += 1;
+                    value
+                };
+                Ok(value)
+            };
+            let wide = if long >= 2 {
+                RegisterSize::S64
+            } else {
+                RegisterSize::S32
+            };
+            let (text, numeric) = match chars.next() {
+                Some(b'%') => (b"%".to_vec(), false),
+                Some(b'd' | b'i') => {
+                    let value = sign_extend(int(self)?, wide);
+                    let sign = if value < 0 {
+                        "-"
+                    } else if plus {
+                        "+"
+                    } else if space {
+                        " "
+                    } else {
+                        ""
+                    };
+                    (format!("{sign}{}", value.unsigned_abs()).into_bytes(), true)
+                }
+                Some(b'u') => (mask(int(self)?, wide).to_string().into_bytes(), true),
+                Some(b'x') => (format!("{:x}", mask(int(self)?, wide)).into_bytes(), true),
+                Some(b'X') => (format!("{:X}", mask(int(self)?, wide)).into_bytes(), true),
+                Some(b'o') => (format!("{:o}", mask(int(self)?, wide)).into_bytes(), true),
+                Some(b'p') => (format!("{:#x}", int(self)?).into_bytes(), false),
+                Some(b'c') => (vec![int(self)? as u8], false),
+                Some(b's') => {
+                    let text = self.c_str(int(self)?)?;
+                    let len = precision.map_or(text.len(), |max| max.min(text.len()));
+                    (text[..len].to_vec(), false)
+                }
+                Some(conversion @ (b'f' | b'F' | b'e' | b'g')) => {
+                    // floats are promoted to doubles when passed variadically
+                    let bits =
+                        self.load(self.register(RegisterName::SP) + arg * 4, RegisterSize::S64)?;
+                    arg += 2;
+                    let value = f64::from_bits(bits);
+                    let precision = precision.unwrap_or(6);
+                    let text = match conversion {
+                        b'e' => format!("{value:.precision$e}"),
+                        b'g' => format!("{value}"),
+                        _ => format!("{value:.precision$}"),
+                    };
+                    (text.into_bytes(), true)
+                }
+                Some(other) => (vec![b'%', other], false),
+                None => (b"%".to_vec(), false),
+            };
+            let padding = (width as usize).saturating_sub(text.len());
+            if left {
+                out.extend(text);
+                out.extend(std::iter::repeat_n(b' ', padding));
+            } else if zero && numeric {
+                let sign = text.first().filter(|c| b"+- ".contains(c)).map_or(0, |_| 1);
+                out.extend_from_slice(&text[..sign]);
+                out.extend(std::iter::repeat_n(b'0', padding));
+                out.extend_from_slice(&text[sign..]);
+            } else {
+                out.extend(std::iter::repeat_n(b' ', padding));
+                out.extend(text);
+            }
+        }
+        Ok(out)
+    }
+}
+fn register(name: RegisterName, size: RegisterSize) -> Register {
+    Register { name, size }
+}
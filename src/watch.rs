@@ -0,0 +1,139 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// how often the modification time is checked where there are no filesystem notifications
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// how long to wait for more writes after a change, saving often truncates before writing
+pub const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::{c_char, c_int, c_void};
+
+    pub const IN_CLOEXEC: c_int = 0o2000000;
+    pub const IN_MODIFY: u32 = 0x2;
+    pub const IN_CLOSE_WRITE: u32 = 0x8;
+    pub const IN_MOVED_TO: u32 = 0x80;
+    pub const IN_CREATE: u32 = 0x100;
+    extern "C" {
+        pub fn inotify_init1(flags: c_int) -> c_int;
+        pub fn inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int;
+        pub fn read(fd: c_int, buf: *mut c_void, count: usize) -> isize;
+        pub fn close(fd: c_int) -> c_int;
+    }
+    /// the fixed part of `struct inotify_event`, followed by `len` bytes of file name
+    pub const EVENT_HEADER: usize = 16;
+}
+
+/// waits for a file to change
+#[derive(Debug)]
+pub struct Watcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// an inotify instance watching the file's directory, editors often replace files instead
+    /// of writing to them
+    #[cfg(target_os = "linux")]
+    fd: std::ffi::c_int,
+}
+impl Watcher {
+    pub fn new(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let modified = modified(&path);
+        #[cfg(target_os = "linux")]
+        {
+            use std::{ffi::CString, os::unix::ffi::OsStrExt};
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let dir = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            let fd = unsafe { inotify::inotify_init1(inotify::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mask = inotify::IN_MODIFY
+                | inotify::IN_CLOSE_WRITE
+                | inotify::IN_MOVED_TO
+                | inotify::IN_CREATE;
+            if unsafe { inotify::inotify_add_watch(fd, dir.as_ptr(), mask) } < 0 {
+                let err = io::Error::last_os_error();
+                unsafe { inotify::close(fd) };
+                return Err(err);
+            }
+            Ok(Self { path, modified, fd })
+        }
+        #[cfg(not(target_os = "linux"))]
+        Ok(Self { path, modified })
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// blocks until the file's modification time changes
+    pub fn wait(&mut self) -> io::Result<()> {
+        loop {
+            self.wait_for_event()?;
+            std::thread::sleep(SETTLE_TIME);
+            let modified = modified(&self.path);
+            if modified.is_some() && modified != self.modified {
+                self.modified = modified;
+                return Ok(());
+            }
+        }
+    }
+    #[cfg(target_os = "linux")]
+    fn wait_for_event(&mut self) -> io::Result<()> {
+        let name = self.path.file_name().unwrap_or_default().as_encoded_bytes();
+        let mut buf = [0u8; 4096];
+        loop {
+            let len = unsafe { inotify::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+            if len < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            let events = &buf[..len as usize];
+            let mut offset = 0;
+            while offset + inotify::EVENT_HEADER <= events.len() {
+                let name_len =
+                    u32::from_ne_bytes(events[offset + 12..offset + 16].try_into().unwrap())
+                        as usize;
+                let start = offset + inotify::EVENT_HEADER;
+                let event_name = &events[start..(start + name_len).min(events.len())];
+                // the name is padded with nul bytes
+                let event_name = event_name
+                    .split(|byte| *byte == 0)
+                    .next()
+                    .unwrap_or_default();
+                if event_name == name {
+                    return Ok(());
+                }
+                offset = start + name_len;
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    fn wait_for_event(&mut self) -> io::Result<()> {
+        std::thread::sleep(POLL_INTERVAL);
+        Ok(())
+    }
+}
+#[cfg(target_os = "linux")]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe {
+            inotify::close(self.fd);
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
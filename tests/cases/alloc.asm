@@ -0,0 +1,26 @@
+extern malloc
+extern free
+global main
+section .text
+_Lmk:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	shl eax, 2
+	push eax
+	call malloc
+	add esp, 4
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 4
+	push eax
+	call _Lmk
+	add esp, 4
+	push eax
+	call free
+	add esp, 4
+	leave
+	ret
@@ -0,0 +1,40 @@
+extern puts
+global main
+section .text
+_Lfirst:
+	push ebp
+	mov ebp, esp
+	mov eax, 1
+	mov ecx, DWORD PTR [lerp_argv]
+	mov eax, DWORD PTR [ecx+eax*4]
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	mov DWORD PTR [lerp_argc], eax
+	mov eax, DWORD PTR [ebp+12]
+	mov DWORD PTR [lerp_argv], eax
+	mov eax, DWORD PTR [lerp_argc]
+	cmp eax, 1
+	je .case0_0
+	jmp .case0_else
+.case0_0:
+	lea eax, [main_c0]
+	push eax
+	call puts
+	add esp, 4
+	jmp .case0_end
+.case0_else:
+	call _Lfirst
+	push eax
+	call puts
+	add esp, 4
+.case0_end:
+	leave
+	ret
+main_c0 db `no arguments`, 0
+section .bss
+lerp_argc resb 4
+lerp_argv resb 4
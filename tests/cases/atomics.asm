@@ -0,0 +1,42 @@
+global main
+section .text
+_Ltake:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, 1
+	pop ecx
+	lock xadd DWORD PTR [ecx], eax
+	leave
+	ret
+_Lclaim:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+8]
+	movzx eax, BYTE PTR [eax]
+	pop ecx
+	xchg BYTE PTR [ecx], al
+	leave
+	ret
+_Lswap:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	push eax
+	mov eax, DWORD PTR [ebp+16]
+	mov edx, eax
+	pop eax
+	pop ecx
+	lock cmpxchg DWORD PTR [ecx], edx
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	leave
+	ret
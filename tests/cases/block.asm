@@ -0,0 +1,33 @@
+global main
+section .text
+_Larea:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+8]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	push eax
+	mov eax, DWORD PTR [ebp-4]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	add esp, 4
+	push eax
+	mov eax, DWORD PTR [ebp-4]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	leave
+	ret
@@ -0,0 +1,15 @@
+extern puts
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	lea eax, [main_c0]
+	push eax
+	lea eax, [puts]
+	call eax
+	add esp, 4
+	leave
+	ret
+main_c0 db `hi`, 0
@@ -0,0 +1,90 @@
+extern printf
+global main
+section .text
+_Lop:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	cmp eax, 4
+	ja .case0_else
+	jmp DWORD PTR [eax*4+_Lop_t0]
+.case0_0:
+	mov eax, 10
+	jmp .case0_end
+.case0_1:
+	mov eax, 20
+	jmp .case0_end
+.case0_2:
+	mov eax, 30
+	jmp .case0_end
+.case0_3:
+	mov eax, 50
+	jmp .case0_end
+.case0_else:
+	mov eax, 0
+.case0_end:
+	leave
+	ret
+_Lsparse:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	cmp eax, -100
+	je .case0_0
+	cmp eax, 7
+	je .case0_1
+	jmp .case0_else
+.case0_0:
+	mov eax, 1
+	jmp .case0_end
+.case0_1:
+	mov eax, 2
+	jmp .case0_end
+.case0_else:
+	mov eax, 3
+.case0_end:
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 2
+	push eax
+	call _Lop
+	add esp, 4
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	mov eax, 7
+	push eax
+	call _Lsparse
+	add esp, 4
+	push eax
+	lea eax, [main_c1]
+	push eax
+	call printf
+	add esp, 8
+	mov eax, 9
+	push eax
+	call _Lop
+	add esp, 4
+	cmp eax, 0
+	je .case0_0
+	jmp .case0_end
+.case0_0:
+	mov eax, 0
+	push eax
+	lea eax, [main_c2]
+	push eax
+	call printf
+	add esp, 8
+.case0_end:
+	leave
+	ret
+main_c0 db `%d\n`, 0
+main_c1 db `%d\n`, 0
+main_c2 db `%d\n`, 0
+section .rodata
+_Lop_t0 dd _Lop.case0_0, _Lop.case0_1, _Lop.case0_2, _Lop.case0_else, _Lop.case0_3
@@ -0,0 +1,133 @@
+extern strlen
+extern memcmp
+extern printf
+global main
+section .text
+_Leval:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	push eax
+	call strlen
+	add esp, 4
+	cmp eax, 0
+	je .case0_2
+	cmp eax, 3
+	jne .case0_not3
+	push 3
+	push _Leval_c0
+	push DWORD PTR [esp+8]
+	call memcmp
+	add esp, 12
+	cmp eax, 0
+	je .case0_0
+	push 3
+	push _Leval_c1
+	push DWORD PTR [esp+8]
+	call memcmp
+	add esp, 12
+	cmp eax, 0
+	je .case0_1
+	jmp .case0_else
+.case0_not3:
+	cmp eax, 5
+	jne .case0_not5
+	push 5
+	push _Leval_c2
+	push DWORD PTR [esp+8]
+	call memcmp
+	add esp, 12
+	cmp eax, 0
+	je .case0_3
+	jmp .case0_else
+.case0_not5:
+	jmp .case0_else
+.case0_0:
+	add esp, 4
+	mov eax, DWORD PTR [ebp+12]
+	push eax
+	mov eax, DWORD PTR [ebp+16]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	jmp .case0_end
+.case0_1:
+	add esp, 4
+	mov eax, DWORD PTR [ebp+12]
+	jmp .case0_end
+.case0_2:
+	add esp, 4
+	mov eax, 0
+	jmp .case0_end
+.case0_3:
+	add esp, 4
+	mov eax, DWORD PTR [ebp+12]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	jmp .case0_end
+.case0_else:
+	add esp, 4
+	mov eax, DWORD PTR [ebp+16]
+.case0_end:
+	leave
+	ret
+_Leval_c0 db `add`, 0
+_Leval_c1 db `and`, 0
+_Leval_c2 db `twice`, 0
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 2
+	push eax
+	mov eax, 1
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call _Leval
+	add esp, 12
+	push eax
+	lea eax, [main_c1]
+	push eax
+	call printf
+	add esp, 8
+	lea eax, [main_c2]
+	push eax
+	push eax
+	call strlen
+	add esp, 4
+	cmp eax, 4
+	jne .case0_not4
+	push 4
+	push main_c3
+	push DWORD PTR [esp+8]
+	call memcmp
+	add esp, 12
+	cmp eax, 0
+	je .case0_0
+	jmp .case0_else
+.case0_not4:
+	jmp .case0_else
+.case0_0:
+	add esp, 4
+	lea eax, [main_c4]
+	push eax
+	lea eax, [main_c5]
+	push eax
+	call printf
+	add esp, 8
+	jmp .case0_end
+.case0_else:
+	add esp, 4
+.case0_end:
+	leave
+	ret
+main_c0 db `add`, 0
+main_c1 db `%d\n`, 0
+main_c2 db `quit`, 0
+main_c3 db `quit`, 0
+main_c4 db `bye`, 0
+main_c5 db `%s\n`, 0
@@ -0,0 +1,50 @@
+extern printf
+extern puts
+extern exit
+global main
+section .text
+lerp_panic:
+	push ebp
+	mov ebp, esp
+	push DWORD PTR [ebp+8]
+	call puts
+	add esp, 4
+	push 101
+	call exit
+	add esp, 4
+	leave
+	ret
+_Ladd:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	jno .checked0
+	push _Ladd_c0
+	call lerp_panic
+	add esp, 4
+.checked0:
+	leave
+	ret
+_Ladd_c0 db `arithmetic overflow at 2:33`, 0
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 2
+	push eax
+	mov eax, 1
+	push eax
+	call _Ladd
+	add esp, 8
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%d\n`, 0
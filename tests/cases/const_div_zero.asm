@@ -0,0 +1 @@
+error 1:10: division by zero in constant expression
@@ -0,0 +1,44 @@
+extern printf
+extern free
+extern malloc
+global main
+section .text
+_Lconsume:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	mov eax, DWORD PTR [eax]
+	push edx
+	push eax
+	lea eax, [_Lconsume_c0]
+	push eax
+	call printf
+	add esp, 4
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	call free
+	add esp, 4
+	pop eax
+	pop edx
+	leave
+	ret
+_Lconsume_c0 db `consumed\n`, 0
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 1
+	shl eax, 2
+	push eax
+	call malloc
+	add esp, 4
+	push eax
+	call _Lconsume
+	add esp, 4
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%d\n`, 0
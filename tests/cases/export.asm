@@ -0,0 +1,31 @@
+extern printf
+global main
+global square
+section .text
+square:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	leave
+	ret
+_Lhelper:
+	push ebp
+	mov ebp, esp
+	mov eax, 2
+	push eax
+	call square
+	add esp, 4
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	call _Lhelper
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%d\n`, 0
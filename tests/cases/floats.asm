@@ -0,0 +1,36 @@
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	mov edx, DWORD PTR [main_f0+4]
+	mov eax, DWORD PTR [main_f0]
+	push edx
+	push eax
+	mov eax, DWORD PTR [main_f1]
+	push eax
+	fld DWORD PTR [esp]
+	sub esp, 4
+	fstp QWORD PTR [esp]
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 20
+	mov eax, DWORD PTR [main_f2]
+	push eax
+	fld DWORD PTR [esp]
+	sub esp, 4
+	fstp QWORD PTR [esp]
+	lea eax, [main_c1]
+	push eax
+	call printf
+	add esp, 12
+	leave
+	ret
+main_c0 db `%f %f\n`, 0
+main_c1 db `%f\n`, 0
+section .rodata
+main_f0 dq 0x4004000000000000
+main_f1 dd 0x3fc00000
+main_f2 dd 0x3e800000
@@ -0,0 +1,40 @@
+extern snprintf
+extern malloc
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	lea eax, [main_c0]
+	push eax
+	mov eax, 1
+	push eax
+	lea eax, [main_c1]
+	push eax
+	push 0
+	push 0
+	call snprintf
+	add esp, 8
+	add eax, 1
+	push eax
+	push eax
+	call malloc
+	add esp, 4
+	pop ecx
+	mov ebx, eax
+	push ecx
+	push eax
+	call snprintf
+	add esp, 20
+	mov eax, ebx
+	push eax
+	lea eax, [main_c2]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `a`, 0
+main_c1 db `x=%d y=%s%%`, 0
+main_c2 db `%s\n`, 0
@@ -0,0 +1,67 @@
+extern printf
+global main
+section .text
+_Ladd:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	leave
+	ret
+_Lid_28i32_29:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	leave
+	ret
+_Lid_28u8_5b2_5d_29:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 2
+	push eax
+	mov eax, 1
+	push eax
+	call _Ladd
+	add esp, 8
+	push eax
+	call _Lid_28i32_29
+	add esp, 4
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	mov eax, 5
+	push eax
+	call _Lid_28i32_29
+	add esp, 4
+	push eax
+	lea eax, [main_c1]
+	push eax
+	call printf
+	add esp, 8
+	lea eax, [main_c2]
+	push eax
+	call _Lid_28u8_5b2_5d_29
+	add esp, 4
+	push eax
+	lea eax, [main_c3]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%d\n`, 0
+main_c1 db `%d\n`, 0
+main_c2 db `s`, 0
+main_c3 db `%s\n`, 0
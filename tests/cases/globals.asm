@@ -0,0 +1,28 @@
+extern puts
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 16384
+	push eax
+	mov eax, 4096
+	push eax
+	mov eax, DWORD PTR [counter]
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 16
+	lea eax, [buf]
+	push eax
+	call puts
+	add esp, 4
+	leave
+	ret
+main_c0 db `%d %d %u\n`, 0
+section .data
+counter dd 4097
+section .bss
+buf resb 2048
@@ -0,0 +1,17 @@
+; link m
+; link c
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 1
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%d\n`, 0
@@ -0,0 +1,46 @@
+global main
+section .text
+_Lfact:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, 1
+	push eax
+.loop0:
+	mov eax, DWORD PTR [ebp-4]
+	cmp eax, 0
+	je .case1_0
+	jmp .case1_else
+.case1_0:
+	mov eax, DWORD PTR [ebp-8]
+	jmp .case1_end
+.case1_else:
+	mov eax, DWORD PTR [ebp-4]
+	push eax
+	mov eax, 1
+	mov ebx, eax
+	pop eax
+	sub eax, ebx
+	push eax
+	mov eax, DWORD PTR [ebp-8]
+	push eax
+	mov eax, DWORD PTR [ebp-4]
+	mov ebx, eax
+	pop eax
+	mul ebx
+	push eax
+	pop eax
+	mov DWORD PTR [ebp-8], eax
+	pop eax
+	mov DWORD PTR [ebp-4], eax
+	jmp .loop0
+.case1_end:
+	add esp, 8
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	leave
+	ret
@@ -0,0 +1,30 @@
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 2
+	push eax
+	mov eax, 2
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	push eax
+	mov eax, 2
+	push eax
+	mov eax, 2
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%d\n`, 0
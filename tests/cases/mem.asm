@@ -0,0 +1,72 @@
+global main
+section .text
+_Lclear:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, 0
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ecx, eax
+	pop eax
+	pop edx
+	push edi
+	mov edi, edx
+	rep stosb
+	pop edi
+	leave
+	ret
+_Lpair:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	push eax
+	pop eax
+	pop ecx
+	mov edx, DWORD PTR [eax]
+	mov DWORD PTR [ecx], edx
+	mov dx, WORD PTR [eax+4]
+	mov WORD PTR [ecx+4], dx
+	mov dl, BYTE PTR [eax+6]
+	mov BYTE PTR [ecx+6], dl
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, 255
+	push eax
+	pop eax
+	movzx eax, al
+	mov ecx, 16843009
+	mul ecx
+	pop ecx
+	mov WORD PTR [ecx], ax
+	leave
+	ret
+_Lcopy:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	push eax
+	movzx eax, WORD PTR [ebp+16]
+	movzx eax, ax
+	mov ecx, eax
+	pop eax
+	pop edx
+	push esi
+	push edi
+	mov esi, eax
+	mov edi, edx
+	rep movsb
+	pop edi
+	pop esi
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	leave
+	ret
@@ -0,0 +1,2 @@
+error 2:10: unclosed string
+error 4:1: unclosed '('
@@ -0,0 +1,44 @@
+extern printf
+global main
+section .text
+_Lskip:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	leave
+	ret
+_Lcount:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+12]
+	push eax
+	mov eax, DWORD PTR [ebp+8]
+	mov ebx, eax
+	pop eax
+	sub eax, ebx
+	sar eax, 3
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 3
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call _Lskip
+	add esp, 8
+	push eax
+	lea eax, [main_c1]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `pointer`, 0
+main_c1 db `%s\n`, 0
@@ -0,0 +1,35 @@
+extern printf
+extern strlen
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 1
+	push eax
+	mov eax, 2
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	push eax
+	lea eax, [main_c0]
+	push eax
+	lea eax, [main_c1]
+	push eax
+	call printf
+	add esp, 12
+	lea eax, [main_c2]
+	push eax
+	call strlen
+	add esp, 4
+	push eax
+	lea eax, [main_c3]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `x =`, 0
+main_c1 db `%s %d\n`, 0
+main_c2 db `ab`, 0
+main_c3 db `%u`, 0
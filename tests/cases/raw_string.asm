@@ -0,0 +1,24 @@
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	lea eax, [main_c0]
+	push eax
+	lea eax, [main_c1]
+	push eax
+	call printf
+	add esp, 8
+	lea eax, [main_c2]
+	push eax
+	lea eax, [main_c3]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `a\n"b"\nc`, 0
+main_c1 db `%s\n`, 0
+main_c2 db `x`, 0
+main_c3 db `%s\n`, 0
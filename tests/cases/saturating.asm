@@ -0,0 +1,56 @@
+extern printf
+global main
+section .text
+_Ladd:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	mov ecx, 2147483647
+	mov edx, -2147483648
+	cmp eax, 0
+	cmovl ecx, edx
+	add eax, ebx
+	cmovo eax, ecx
+	leave
+	ret
+_Lsub:
+	push ebp
+	mov ebp, esp
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	mov eax, DWORD PTR [ebp+12]
+	mov ebx, eax
+	pop eax
+	mov ecx, 0
+	sub eax, ebx
+	cmovb eax, ecx
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 1
+	push eax
+	mov eax, 4
+	push eax
+	call _Lsub
+	add esp, 8
+	push eax
+	mov eax, 2
+	push eax
+	mov eax, 1
+	push eax
+	call _Ladd
+	add esp, 8
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 12
+	leave
+	ret
+main_c0 db `%d %u\n`, 0
@@ -0,0 +1,145 @@
+extern printf
+extern free
+extern malloc
+extern pthread_create
+extern puts
+extern exit
+extern pthread_join
+global main
+section .text
+_Lstart_l0:
+	push ebp
+	mov ebp, esp
+	sub esp, 8
+	mov ecx, DWORD PTR [ebp+8]
+	mov edx, DWORD PTR [ecx]
+	mov DWORD PTR [ebp-8], edx
+	mov edx, DWORD PTR [ecx+4]
+	mov DWORD PTR [ebp-4], edx
+	push ecx
+	call free
+	add esp, 4
+	mov eax, DWORD PTR [ebp-8]
+	push eax
+	lea eax, [_Lstart_l0_c0]
+	push eax
+	call printf
+	add esp, 8
+	mov eax, DWORD PTR [ebp-4]
+	push eax
+	mov eax, DWORD PTR [ebp-8]
+	pop ecx
+	lock xadd DWORD PTR [ecx], eax
+	mov eax, 0
+	leave
+	ret
+_Lstart_l0_c0 db `thread %d\n`, 0
+lerp_panic:
+	push ebp
+	mov ebp, esp
+	push DWORD PTR [ebp+8]
+	call puts
+	add esp, 4
+	push 101
+	call exit
+	add esp, 4
+	leave
+	ret
+_Lstart:
+	push ebp
+	mov ebp, esp
+	push 8
+	call malloc
+	add esp, 4
+	mov edx, DWORD PTR [ebp+12]
+	mov DWORD PTR [eax], edx
+	mov edx, DWORD PTR [ebp+8]
+	mov DWORD PTR [eax+4], edx
+	push 0
+	mov ecx, esp
+	push eax
+	lea eax, [_Lstart_l0]
+	push eax
+	push 0
+	push ecx
+	call pthread_create
+	add esp, 16
+	cmp eax, 0
+	je .spawned1
+	push _Lstart_c0
+	call lerp_panic
+	add esp, 4
+.spawned1:
+	pop eax
+	leave
+	ret
+_Lstart_c0 db `couldn't spawn a thread at 3:3`, 0
+_Lrun_l0:
+	push ebp
+	mov ebp, esp
+	lea eax, [_Lrun_l0_c0]
+	push eax
+	call printf
+	add esp, 4
+	mov eax, 0
+	leave
+	ret
+_Lrun_l0_c0 db `no captures\n`, 0
+_Lrun:
+	push ebp
+	mov ebp, esp
+	mov eax, 3
+	push eax
+	mov eax, DWORD PTR [ebp+8]
+	push eax
+	call _Lstart
+	add esp, 8
+	push 0
+	push eax
+	call pthread_join
+	add esp, 8
+	mov eax, 0
+	push 0
+	mov ecx, esp
+	push eax
+	lea eax, [_Lrun_l0]
+	push eax
+	push 0
+	push ecx
+	call pthread_create
+	add esp, 16
+	cmp eax, 0
+	je .spawned1
+	push _Lrun_c0
+	call lerp_panic
+	add esp, 4
+.spawned1:
+	pop eax
+	push 0
+	push eax
+	call pthread_join
+	add esp, 8
+	mov eax, DWORD PTR [ebp+8]
+	mov eax, DWORD PTR [eax]
+	leave
+	ret
+_Lrun_c0 db `couldn't spawn a thread at 6:9`, 0
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 1
+	shl eax, 2
+	push eax
+	call malloc
+	add esp, 4
+	push eax
+	call _Lrun
+	add esp, 4
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%d\n`, 0
@@ -0,0 +1,27 @@
+extern puts
+extern printf
+global main
+section .text
+main:
+	push ebp
+	mov ebp, esp
+	lea eax, [greeting]
+	push eax
+	call puts
+	add esp, 4
+	mov eax, 16
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 8
+	leave
+	ret
+main_c0 db `%u\n`, 0
+section .data
+primes dd 2, 3, 5, 7
+grid db 1, 2, 0, 3, 0, 0
+greeting db 104, 105, 0, 0, 0, 0, 0, 0
+section .bss
+zeroes resb 8
+total resb 8
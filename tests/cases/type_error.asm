@@ -0,0 +1 @@
+error 1:6: expected i32, got u8[2]
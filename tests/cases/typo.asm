@@ -0,0 +1 @@
+error 2:1: "defun" not found, did you mean `defn`?
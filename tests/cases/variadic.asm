@@ -0,0 +1,62 @@
+extern printf
+global main
+section .text
+_Lsum:
+	push ebp
+	mov ebp, esp
+	lea eax, [ebp+12]
+	push eax
+	mov eax, DWORD PTR [ebp+8]
+	cmp eax, 1
+	je .case0_0
+	cmp eax, 2
+	je .case0_1
+	jmp .case0_else
+.case0_0:
+	mov ecx, DWORD PTR [ebp-4]
+	mov eax, DWORD PTR [ecx]
+	add DWORD PTR [ebp-4], 4
+	jmp .case0_end
+.case0_1:
+	mov ecx, DWORD PTR [ebp-4]
+	mov eax, DWORD PTR [ecx]
+	add DWORD PTR [ebp-4], 4
+	push eax
+	mov ecx, DWORD PTR [ebp-4]
+	mov eax, DWORD PTR [ecx]
+	add DWORD PTR [ebp-4], 4
+	mov ebx, eax
+	pop eax
+	add eax, ebx
+	jmp .case0_end
+.case0_else:
+	mov eax, 0
+.case0_end:
+	leave
+	ret
+main:
+	push ebp
+	mov ebp, esp
+	mov eax, 4
+	push eax
+	mov eax, 3
+	push eax
+	mov eax, 2
+	push eax
+	call _Lsum
+	add esp, 12
+	push eax
+	mov eax, 5
+	push eax
+	mov eax, 1
+	push eax
+	call _Lsum
+	add esp, 8
+	push eax
+	lea eax, [main_c0]
+	push eax
+	call printf
+	add esp, 12
+	leave
+	ret
+main_c0 db `%d %d\n`, 0